@@ -0,0 +1,45 @@
+//! Compare the single traversal of [`surface_passes`] with the separate passes it replaces.
+//!
+//! Run with `cargo bench --no-default-features --bench surface_passes`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use pullauta::contours::{contour_band, slope_grid, surface_passes, SMOOTH_KERNEL};
+use pullauta::io::heightmap::HeightMap;
+use pullauta::vec2d::Vec2D;
+
+/// A 1000 x 1000 cell DEM of rolling terrain, like a tile at the default resolution.
+fn fixture() -> HeightMap {
+    HeightMap {
+        xoffset: 0.0,
+        yoffset: 0.0,
+        scale: 2.0,
+        grid: Vec2D::from_fn(1000, 1000, |x, y| {
+            let (x, y) = (x as f64, y as f64);
+            100.0 + 8.0 * (x / 40.0).sin() * (y / 55.0).cos() + 0.05 * y
+        }),
+    }
+}
+
+fn bench_surface_passes(c: &mut Criterion) {
+    let hmap = fixture();
+    let interval = 2.5;
+
+    let mut group = c.benchmark_group("surface_passes");
+    group.sample_size(20);
+    group.bench_function("fused", |b| {
+        b.iter(|| surface_passes(black_box(&hmap), interval))
+    });
+    group.bench_function("separate", |b| {
+        b.iter(|| {
+            let smoothed = black_box(&hmap).grid.convolve3x3(&SMOOTH_KERNEL);
+            let slope = slope_grid(&hmap);
+            let (w, h) = smoothed.dimensions();
+            let band = Vec2D::from_fn(w, h, |x, y| contour_band(smoothed[(x, y)], interval));
+            (smoothed, slope, band)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_surface_passes);
+criterion_main!(benches);
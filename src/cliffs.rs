@@ -28,6 +28,10 @@ pub fn makecliffs(
         steep_factor,
         flat_place,
         mut no_small_ciffs,
+        cliff_min_slope,
+        cliff_steep_slope,
+        cliff_min_length,
+        cliff_skeleton,
         ..
     } = config;
 
@@ -76,6 +80,24 @@ pub fn makecliffs(
         }
     }
 
+    let mut cliff_cells = slope_mask(&hmap, cliff_min_slope, cliff_min_length);
+    let mut steep_cells = slope_mask(&hmap, cliff_steep_slope, cliff_min_length);
+    if cliff_skeleton {
+        // without a slope limit the masks are all set, and there is no area to thin
+        if cliff_min_slope > 0.0 {
+            cliff_cells = thin_mask(&cliff_cells);
+        }
+        if cliff_steep_slope > 0.0 {
+            steep_cells = thin_mask(&steep_cells);
+        }
+    }
+    // check if the middle of a cliff is on a cell of the mask, cells outside the mask are allowed
+    let on_mask = |mask: &Vec2D<bool>, x: f64, y: f64| {
+        let i = ((x - xstart) / size + 0.5).floor();
+        let j = ((y - ystart) / size + 0.5).floor();
+        i < 0.0 || j < 0.0 || *mask.get(i as usize, j as usize).unwrap_or(&true)
+    };
+
     let mut img = RgbImage::from_pixel(
         (xmax - xmin).floor() as u32,
         (ymax - ymin).floor() as u32,
@@ -93,7 +115,7 @@ pub fn makecliffs(
 
     let xyz_file_in = tmpfolder.join("xyztemp.xyz.bin");
 
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(config.random_seed);
     let randdist = distributions::Bernoulli::new(cliff_thin).unwrap();
 
     let mut reader = XyzInternalReader::new(BufReader::new(fs.open(&xyz_file_in)?))?;
@@ -234,6 +256,7 @@ pub fn makecliffs(
                                 && temp > (limit + (dist - limit) * 0.85)
                                 && imgx < img.width()
                                 && imgy < img.height()
+                                && on_mask(&cliff_cells, (x0 + xt) / 2.0, (y0 + yt) / 2.0)
                             {
                                 let p = img.get_pixel(imgx, imgy);
                                 if p[0] == 255 {
@@ -253,7 +276,10 @@ pub fn makecliffs(
                                 }
                             }
 
-                            if temp > limit2 && temp > (limit2 + (dist - limit2) * 0.85) {
+                            if temp > limit2
+                                && temp > (limit2 + (dist - limit2) * 0.85)
+                                && on_mask(&steep_cells, (x0 + xt) / 2.0, (y0 + yt) / 2.0)
+                            {
                                 f3.write_all(b"POLYLINE\r\n 66\r\n1\r\n  8\r\ncliff3\r\n  0\r\n")
                                     .expect("Cannot write dxf file");
                                 write!(
@@ -336,7 +362,11 @@ pub fn makecliffs(
                     for &(xt, yt, ht) in t.iter() {
                         let temp = h0 - ht;
                         let dist = ((x0 - xt).powi(2) + (y0 - yt).powi(2)).sqrt();
-                        if dist > 0.0 && temp > limit && temp > (limit + (dist - limit) * 0.85) {
+                        if dist > 0.0
+                            && temp > limit
+                            && temp > (limit + (dist - limit) * 0.85)
+                            && on_mask(&steep_cells, (x0 + xt) / 2.0, (y0 + yt) / 2.0)
+                        {
                             f3.write_all(b"POLYLINE\r\n 66\r\n1\r\n  8\r\ncliff4\r\n  0\r\n")
                                 .expect("Cannot write dxf file");
                             write!(
@@ -369,3 +399,220 @@ pub fn makecliffs(
     info!("Done");
     Ok(())
 }
+
+/// Mark the cells of the heightmap where the steepest slope (rise/run) to any of the 8 neighbors
+/// is at least `min_slope`. Connected areas of such cells that are shorter than `min_length`
+/// meters are dropped, to ignore single steep cells in rough terrain. A `min_slope` of 0 marks all
+/// cells.
+pub fn slope_mask(hmap: &HeightMap, min_slope: f64, min_length: f64) -> Vec2D<bool> {
+    let grid = &hmap.grid;
+    let (w, h) = grid.dimensions();
+    if min_slope <= 0.0 {
+        return Vec2D::new(w, h, true);
+    }
+
+    let mut mask = Vec2D::new(w, h, false);
+    for x in 0..w {
+        for y in 0..h {
+            let z = grid[(x, y)];
+            if z.is_nan() {
+                continue;
+            }
+            let mut steepest: f64 = 0.0;
+            for dx in -1isize..=1 {
+                for dy in -1isize..=1 {
+                    let (xx, yy) = (x as isize + dx, y as isize + dy);
+                    if (dx, dy) == (0, 0)
+                        || xx < 0
+                        || yy < 0
+                        || xx >= w as isize
+                        || yy >= h as isize
+                    {
+                        continue;
+                    }
+                    let zz = grid[(xx as usize, yy as usize)];
+                    let dist = hmap.scale * ((dx * dx + dy * dy) as f64).sqrt();
+                    if !zz.is_nan() {
+                        steepest = steepest.max((z - zz).abs() / dist);
+                    }
+                }
+            }
+            mask[(x, y)] = steepest >= min_slope;
+        }
+    }
+
+    // remove the connected areas (8-connected) that are shorter than the minimum length
+    let min_cells = (min_length / hmap.scale).ceil() as usize;
+    if min_cells > 1 {
+        let mut visited = Vec2D::new(w, h, false);
+        let mut stack = Vec::new();
+        let mut area = Vec::new();
+        for x in 0..w {
+            for y in 0..h {
+                if !mask[(x, y)] || visited[(x, y)] {
+                    continue;
+                }
+                area.clear();
+                visited[(x, y)] = true;
+                stack.push((x, y));
+                let (mut xmin, mut xmax, mut ymin, mut ymax) = (x, x, y, y);
+                while let Some((cx, cy)) = stack.pop() {
+                    area.push((cx, cy));
+                    xmin = xmin.min(cx);
+                    xmax = xmax.max(cx);
+                    ymin = ymin.min(cy);
+                    ymax = ymax.max(cy);
+                    for nx in cx.saturating_sub(1)..(cx + 2).min(w) {
+                        for ny in cy.saturating_sub(1)..(cy + 2).min(h) {
+                            if mask[(nx, ny)] && !visited[(nx, ny)] {
+                                visited[(nx, ny)] = true;
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+                // the length of the area is the longer side of its bounding box
+                if (xmax - xmin).max(ymax - ymin) + 1 < min_cells {
+                    for &(ax, ay) in &area {
+                        mask[(ax, ay)] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    mask
+}
+
+/// Thin the set areas of the mask to 8-connected lines one cell wide along their middle, with the
+/// Zhang-Suen algorithm. The cells outside the grid count as unset.
+pub fn thin_mask(mask: &Vec2D<bool>) -> Vec2D<bool> {
+    let mut mask = mask.clone();
+    let (w, h) = mask.dimensions();
+    let mut remove = Vec::new();
+    loop {
+        let mut changed = false;
+        for pass in 0..2 {
+            remove.clear();
+            for x in 0..w {
+                for y in 0..h {
+                    if !mask[(x, y)] {
+                        continue;
+                    }
+                    let at = |dx: isize, dy: isize| {
+                        x.checked_add_signed(dx)
+                            .zip(y.checked_add_signed(dy))
+                            .and_then(|(x, y)| mask.get(x, y))
+                            .copied()
+                            .unwrap_or(false)
+                    };
+                    // the neighbors clockwise from north
+                    let n = [
+                        at(0, 1),
+                        at(1, 1),
+                        at(1, 0),
+                        at(1, -1),
+                        at(0, -1),
+                        at(-1, -1),
+                        at(-1, 0),
+                        at(-1, 1),
+                    ];
+                    let set = n.iter().filter(|&&v| v).count();
+                    let transitions = (0..8).filter(|&i| !n[i] && n[(i + 1) % 8]).count();
+                    if !(2..=6).contains(&set) || transitions != 1 {
+                        continue;
+                    }
+                    let (north, east, south, west) = (n[0], n[2], n[4], n[6]);
+                    let removable = if pass == 0 {
+                        !(east && south && (north || west))
+                    } else {
+                        !(north && west && (east || south))
+                    };
+                    if removable {
+                        remove.push((x, y));
+                    }
+                }
+            }
+            for &cell in &remove {
+                mask[cell] = false;
+            }
+            changed |= !remove.is_empty();
+        }
+        if !changed {
+            return mask;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn heightmap(w: usize, h: usize, f: impl Fn(usize, usize) -> f64) -> HeightMap {
+        let mut grid = Vec2D::new(w, h, 0.0);
+        for (x, y, v) in grid.iter_mut() {
+            *v = f(x, y);
+        }
+        HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid,
+        }
+    }
+
+    #[test]
+    fn test_slope_mask_step_and_ramp() {
+        // a 4 m vertical face along x = 10 over the whole height of the grid
+        let step = heightmap(20, 20, |x, _| if x < 10 { 104.0 } else { 100.0 });
+        let mask = slope_mask(&step, 1.0, 10.0);
+        for y in 0..20 {
+            assert!(
+                mask[(9, y)] && mask[(10, y)],
+                "face not flagged at y = {}",
+                y
+            );
+            assert!(!mask[(5, y)] && !mask[(15, y)]);
+        }
+
+        // a gentle 20 % ramp is never a cliff
+        let ramp = heightmap(20, 20, |x, _| 100.0 + 0.4 * x as f64);
+        let mask = slope_mask(&ramp, 1.0, 10.0);
+        assert!(mask.iter().all(|(_, _, m)| !m));
+
+        // the face is 4 m over 2 m, so a slope of 2
+        assert!(slope_mask(&step, 1.0, 0.0)[(10, 0)]);
+        assert!(!slope_mask(&step, 2.5, 0.0)[(10, 0)]);
+    }
+
+    #[test]
+    fn test_slope_mask_min_length() {
+        // a single 3 m high block is steep, but shorter than the minimum length
+        let block = heightmap(
+            20,
+            20,
+            |x, y| if (x, y) == (10, 10) { 103.0 } else { 100.0 },
+        );
+        assert!(slope_mask(&block, 1.0, 0.0)[(10, 10)]);
+        assert!(slope_mask(&block, 1.0, 10.0).iter().all(|(_, _, m)| !m));
+
+        // no slope limit keeps everything
+        assert!(slope_mask(&block, 0.0, 10.0).iter().all(|(_, _, m)| m));
+    }
+
+    #[test]
+    fn test_thin_mask_band() {
+        // a ramp rising 4 m per cell from x = 9 to x = 13, which is steep over 5 cells
+        let band = heightmap(24, 30, |x, _| 100.0 + 4.0 * (x.clamp(9, 13) - 9) as f64);
+        let mask = slope_mask(&band, 1.0, 0.0);
+        assert_eq!(mask.row(15).filter(|&&m| m).count(), 5);
+
+        let thinned = thin_mask(&mask);
+        for y in 3..27 {
+            let cells = (0..24).filter(|&x| thinned[(x, y)]).collect::<Vec<_>>();
+            assert_eq!(cells.len(), 1, "row {}: {:?}", y, cells);
+            assert!((10..=12).contains(&cells[0]), "row {}: {:?}", y, cells);
+        }
+        assert!(thinned.iter().all(|(x, y, m)| !m || mask[(x, y)]));
+    }
+}
@@ -1,11 +1,19 @@
-use std::{path::Path, str::FromStr};
+use std::{fmt, path::Path, str::FromStr};
 
 use ini::Ini;
+use rustc_hash::FxHashMap as HashMap;
+use serde::{Deserialize, Serialize};
 
-/// The config parsed from the .ini configuration file.
+/// The config parsed from the .ini configuration file, or deserialized from TOML or JSON with the
+/// same keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub batch: bool,
     pub processes: u64,
+    #[serde(rename = "memory_budget")]
+    pub memory_budget_mb: usize,
+    pub contour_block_size: usize,
 
     pub experimental_use_in_memory_fs: bool,
 
@@ -14,77 +22,188 @@ pub struct Config {
     pub cliffsonly: bool,
     pub contoursonly: bool,
 
+    #[serde(rename = "northlinesangle")]
     pub pnorthlinesangle: f64,
+    #[serde(rename = "northlineswidth")]
     pub pnorthlineswidth: usize,
 
     pub lazfolder: String,
     pub batchoutfolder: String,
+    pub tile_buffer: f64,
+    pub tile_size: f64,
+    pub tile_name: Option<TileName>,
     pub savetempfiles: bool,
     pub savetempfolders: bool,
+    pub manifest: bool,
 
     pub scalefactor: f64,
+    /// Left out or 0 in TOML and JSON for the default, like an empty value in the .ini file.
+    #[serde(default)]
+    pub pixels_per_meter: f64,
+    /// The corner of the rendered maps to draw the scale bar and north arrow in, if any.
+    pub legend: Option<Corner>,
+    pub legend_margin: bool,
     pub vege_bitmode: bool,
+    #[serde(rename = "zoffset")]
     pub zoff: f64,
     pub thinfactor: f64,
+    pub max_points_per_cell: usize,
+    pub max_points_cell_size: f64,
+    #[serde(with = "large_integer")]
+    pub random_seed: u64,
 
     pub skipknolldetection: bool,
     pub vegemode: bool,
 
+    #[serde(rename = "coordxfactor")]
     pub xfactor: f64,
+    #[serde(rename = "coordyfactor")]
     pub yfactor: f64,
+    #[serde(rename = "coordzfactor")]
     pub zfactor: f64,
 
     pub contour_interval: f64,
+    pub index_contour_every: usize,
+    pub contour_geojson: bool,
+    pub geojson_precision: usize,
+    pub contour_layered_dxf: bool,
+    pub contour_shapefile: bool,
+    /// Left out or 0 in TOML and JSON for the default, like in the .ini file.
+    #[serde(default)]
+    pub dem_binning_cell: f64,
+    /// Left out or 0 in TOML and JSON for the default, like in the .ini file.
+    #[serde(default)]
+    pub dem_resolution: f64,
+    pub ground_model: GroundModel,
+    /// The radius in cells of the inverse-distance filling of the cells without ground points, 0
+    /// to only interpolate them along the rows and columns.
+    pub dem_fill_radius: usize,
+    pub dem_smoothing: DemSmoothing,
+    pub dem_smoothing_iterations: usize,
+    pub dem_geotiff: bool,
+    pub hillshade: bool,
+    pub hillshade_azimuth: f64,
+    pub hillshade_altitude: f64,
+    #[serde(rename = "intensity")]
+    pub intensity_png: bool,
+    pub dsm: bool,
+    pub density: bool,
+    pub density_max: f64,
+    pub crs_epsg: Option<u32>,
+    /// The EPSG code of the coordinate system of the input, if it is reprojected to `crs_epsg`.
+    pub source_epsg: Option<u32>,
+    pub dem_cache: bool,
+    pub cachefolder: String,
+    #[serde(rename = "basemapinterval")]
     pub basemapcontours: f64,
 
     pub detectbuildings: bool,
 
-    pub water_class: u8,
+    #[serde(rename = "groundclasses")]
+    pub ground_classes: Vec<u8>,
+    /// The class of the water points used for the elevation model together with
+    /// `ground_classes`, 0 for none. See [`Config::dem_water_class`] for the default.
+    #[serde(rename = "dem_waterclass")]
+    pub dem_water_class: Option<u8>,
+    /// The classifications of the input points to replace, applied as the points are read.
+    #[serde(with = "class_remap")]
+    pub class_remap: HashMap<u8, u8>,
 
     // merge
+    #[serde(rename = "knolls")]
     pub inidotknolls: f64,
+    pub knoll_min_prominence: f64,
+    pub knoll_min_area: f64,
     pub smoothing: f64,
     pub curviness: f64,
+    #[serde(rename = "contoursmoothing")]
+    pub contour_smoothing: ContourSmoothing,
+    pub contour_simplify: f64,
+    pub min_contour_length: f64,
+    pub min_ring_area: f64,
     pub indexcontours: f64,
     pub formline: f64,
+    pub formline_by_slope: bool,
+    pub formline_max_slope: f64,
+    pub formline_min_distance: f64,
     pub depression_length: usize,
 
     // cliffs
+    #[serde(rename = "cliff1")]
     pub c1_limit: f64,
+    #[serde(rename = "cliff2")]
     pub c2_limit: f64,
+    #[serde(rename = "cliffthin")]
     pub cliff_thin: f64,
+    #[serde(rename = "cliffsteepfactor")]
     pub steep_factor: f64,
+    #[serde(rename = "cliffflatplace")]
     pub flat_place: f64,
+    #[serde(rename = "cliffnosmallciffs")]
     pub no_small_ciffs: f64,
+    /// Minimum slope (rise/run) of the heightmap for a cliff, 0 = no limit.
+    pub cliff_min_slope: f64,
+    /// Minimum slope (rise/run) of the heightmap for an impassable cliff, 0 = no limit.
+    pub cliff_steep_slope: f64,
+    /// Minimum length in meters of a connected area exceeding the slope limits.
+    pub cliff_min_length: f64,
+    /// Thin the areas exceeding the slope limits to lines one cell wide.
+    pub cliff_skeleton: bool,
 
     // vegetation
     pub zones: Vec<Zone>,
     pub thresholds: Vec<(f64, f64, f64)>,
-    pub greenshades: Vec<f64>,
+    /// Ordered `(threshold, color)` pairs for the green shades, a cell gets the color of the last
+    /// shade whose threshold its vegetation value exceeds.
+    pub greenshades: Vec<(f64, [u8; 3])>,
+    pub background: Background,
     pub yellowheight: f64,
+    #[serde(rename = "yellowthresold")]
     pub yellowthreshold: f64,
     pub greenground: f64,
     pub pointvolumefactor: f64,
     pub pointvolumeexponent: f64,
     pub greenhigh: f64,
     pub topweight: f64,
-    pub greentone: f64,
     pub vegezoffset: f64,
+    #[serde(rename = "undergrowth")]
     pub uglimit: f64,
+    #[serde(rename = "undergrowth2")]
     pub uglimit2: f64,
+    #[serde(rename = "greendotsize")]
     pub addition: i32,
     pub firstandlastreturnasground: u64,
+    #[serde(rename = "firstandlastreturnfactor")]
     pub firstandlastfactor: f64,
+    #[serde(rename = "lastreturnfactor")]
     pub lastfactor: f64,
     pub yellowfirstlast: u64,
     pub vegethin: u32,
     pub greendetectsize: f64,
+    #[serde(rename = "yellow_smoothing")]
     pub proceed_yellows: bool,
+    #[serde(rename = "medianboxsize")]
     pub med: u32,
+    #[serde(rename = "medianboxsize2")]
     pub med2: u32,
+    pub vege_despeckle_window: usize,
+    pub vege_despeckle_min_cells: usize,
+    pub vege_height_band: Option<(f64, f64)>,
+    pub vege_exclude_buildings: bool,
+    pub vege_canopy: bool,
+    pub canopy_rough_height: f64,
+    pub canopy_forest_height: f64,
+    pub canopy_min_cover: f64,
+    #[serde(rename = "waterclass")]
     pub water: u8,
+    #[serde(rename = "buildingsclass")]
     pub buildings: u8,
+    #[serde(rename = "waterelevation")]
     pub waterele: f64,
+    pub water_detection: bool,
+    pub water_max_slope: f64,
+    pub water_max_density: f64,
+    pub water_min_area: f64,
 
     // render
     pub buildingcolor: (u8, u8, u8),
@@ -98,9 +217,16 @@ pub struct Config {
     pub dashlength: f64,
     pub gaplength: f64,
     pub minimumgap: u32,
+    #[serde(rename = "label_formlines_depressions")]
     pub label_depressions: bool,
+    pub contour_antialias: bool,
+
+    /// The settings as read from the configuration file, in file order.
+    #[serde(skip)]
+    pub settings: Vec<(String, String)>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Zone {
     pub low: f64,
     pub high: f64,
@@ -108,33 +234,634 @@ pub struct Zone {
     pub factor: f64,
 }
 
+/// The algorithm used to smooth the contour lines.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ContourSmoothing {
+    /// The original weighted smoothing, tuned with `smoothing` and `curviness`.
+    #[default]
+    Weighted,
+    /// No smoothing at all.
+    None,
+    /// Chaikin corner cutting, each iteration doubles the number of vertices.
+    Chaikin { iterations: u32 },
+    /// Moving average of the vertex positions over a window of vertices.
+    MovingAverage { window: usize },
+}
+
+impl FromStr for ContourSmoothing {
+    type Err = String;
+
+    /// Parse from the `contoursmoothing` ini format, e.g. `weighted`, `none`, `chaikin|2` or
+    /// `movingaverage|5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('|');
+        let name = parts.next().unwrap_or("").trim();
+        let param = parts.next().map(str::trim);
+
+        match (name, param) {
+            ("weighted", None) => Ok(Self::Weighted),
+            ("none", None) => Ok(Self::None),
+            ("chaikin", p) => Ok(Self::Chaikin {
+                iterations: p
+                    .unwrap_or("1")
+                    .parse()
+                    .map_err(|_| format!("Invalid chaikin iterations in `{}`", s))?,
+            }),
+            ("movingaverage", p) => Ok(Self::MovingAverage {
+                window: p
+                    .unwrap_or("3")
+                    .parse()
+                    .map_err(|_| format!("Invalid moving average window in `{}`", s))?,
+            }),
+            _ => Err(format!("Unknown contour smoothing `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ContourSmoothing {
+    /// Format in the `contoursmoothing` ini format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Weighted => write!(f, "weighted"),
+            Self::None => write!(f, "none"),
+            Self::Chaikin { iterations } => write!(f, "chaikin|{}", iterations),
+            Self::MovingAverage { window } => write!(f, "movingaverage|{}", window),
+        }
+    }
+}
+
+/// How the ground heightmap is built from the ground points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum GroundModel {
+    /// The average of the points in each `dem_binning_cell` cell.
+    #[default]
+    Binning,
+    /// A Delaunay triangulation of the points, sampled at the center of each cell.
+    Tin,
+}
+
+impl FromStr for GroundModel {
+    type Err = String;
+
+    /// Parse from the `ground_model` ini format, either `binning` or `tin`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "binning" => Ok(Self::Binning),
+            "tin" => Ok(Self::Tin),
+            _ => Err(format!("Unknown ground model `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for GroundModel {
+    /// Format in the `ground_model` ini format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Binning => write!(f, "binning"),
+            Self::Tin => write!(f, "tin"),
+        }
+    }
+}
+
+/// The kernel used to smooth the ground heightmap, see [`crate::contours::smooth_dem`].
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DemSmoothing {
+    /// The mean of the 3x3 neighborhood.
+    #[default]
+    Mean,
+    /// A Gaussian with the standard deviation `sigma` in cells, cut off at 3 sigma.
+    Gaussian { sigma: f64 },
+}
+
+impl FromStr for DemSmoothing {
+    type Err = String;
+
+    /// Parse from the `dem_smoothing` ini format, either `mean` or `gaussian|sigma`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('|');
+        let name = parts.next().unwrap_or("").trim();
+        let param = parts.next().map(str::trim);
+
+        match (name, param) {
+            ("mean", None) => Ok(Self::Mean),
+            ("gaussian", p) => {
+                let sigma: f64 = p
+                    .unwrap_or("1")
+                    .parse()
+                    .map_err(|_| format!("Invalid gaussian sigma in `{}`", s))?;
+                if sigma.is_nan() || sigma <= 0.0 {
+                    return Err(format!("Gaussian sigma in `{}` must be positive", s));
+                }
+                Ok(Self::Gaussian { sigma })
+            }
+            _ => Err(format!("Unknown DEM smoothing `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for DemSmoothing {
+    /// Format in the `dem_smoothing` ini format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mean => write!(f, "mean"),
+            Self::Gaussian { sigma } => write!(f, "gaussian|{}", sigma),
+        }
+    }
+}
+
+/// A part of a [`TileName`] template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TileNamePart {
+    Text(String),
+    X,
+    Y,
+    Name,
+    Scale,
+}
+
+/// A template for the names of the output files of the tiles in batch mode, with the tokens `{x}`
+/// and `{y}` for the lower-left corner of the tile, `{name}` for the input file name without its
+/// extension and `{scale}` for the `scalefactor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TileName {
+    parts: Vec<TileNamePart>,
+}
+
+impl TileName {
+    /// Whether the template has both `{x}` and `{y}`, so that the tiles of a file get different
+    /// names.
+    pub fn has_coordinates(&self) -> bool {
+        self.parts.contains(&TileNamePart::X) && self.parts.contains(&TileNamePart::Y)
+    }
+
+    /// The name of the tile with the lower-left corner `(x, y)` (rounded down to whole meters) of
+    /// the input file `name`.
+    pub fn expand(&self, name: &str, x: f64, y: f64, scale: f64) -> String {
+        let stem = Path::new(name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(name);
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TileNamePart::Text(text) => text.clone(),
+                TileNamePart::X => (x.floor() as i64).to_string(),
+                TileNamePart::Y => (y.floor() as i64).to_string(),
+                TileNamePart::Name => stem.to_string(),
+                TileNamePart::Scale => scale.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for TileName {
+    type Err = String;
+
+    /// Parse from the ini format, for example `tile_{x}_{y}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s.trim();
+        while !rest.is_empty() {
+            if let Some(token) = rest.strip_prefix('{') {
+                let (token, after) = token
+                    .split_once('}')
+                    .ok_or_else(|| format!("Unclosed `{{` in tile_name `{}`", s))?;
+                parts.push(match token {
+                    "x" => TileNamePart::X,
+                    "y" => TileNamePart::Y,
+                    "name" => TileNamePart::Name,
+                    "scale" => TileNamePart::Scale,
+                    _ => {
+                        return Err(format!(
+                            "Unknown token `{{{}}}` in tile_name `{}`",
+                            token, s
+                        ))
+                    }
+                });
+                rest = after;
+            } else {
+                let end = rest.find('{').unwrap_or(rest.len());
+                let text = &rest[..end];
+                if text.contains('}') {
+                    return Err(format!("Unmatched `}}` in tile_name `{}`", s));
+                }
+                if text.contains(['/', '\\']) {
+                    return Err(format!(
+                        "tile_name `{}` must not contain a path separator",
+                        s
+                    ));
+                }
+                parts.push(TileNamePart::Text(text.to_string()));
+                rest = &rest[end..];
+            }
+        }
+        let tile_name = TileName { parts };
+        if !tile_name.has_coordinates() && !tile_name.parts.contains(&TileNamePart::Name) {
+            return Err(format!(
+                "tile_name `{}` must contain {{x}} and {{y}} or {{name}} to tell the tiles apart",
+                s
+            ));
+        }
+        Ok(tile_name)
+    }
+}
+
+impl fmt::Display for TileName {
+    /// Format as the template it was parsed from.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                TileNamePart::Text(text) => write!(f, "{}", text)?,
+                TileNamePart::X => write!(f, "{{x}}")?,
+                TileNamePart::Y => write!(f, "{{y}}")?,
+                TileNamePart::Name => write!(f, "{{name}}")?,
+                TileNamePart::Scale => write!(f, "{{scale}}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A corner of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    /// Parse from the ini format, one of `topleft`, `topright`, `bottomleft` or `bottomright`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "topleft" => Ok(Self::TopLeft),
+            "topright" => Ok(Self::TopRight),
+            "bottomleft" => Ok(Self::BottomLeft),
+            "bottomright" => Ok(Self::BottomRight),
+            _ => Err(format!("Unknown corner `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    /// Format in the ini format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TopLeft => write!(f, "topleft"),
+            Self::TopRight => write!(f, "topright"),
+            Self::BottomLeft => write!(f, "bottomleft"),
+            Self::BottomRight => write!(f, "bottomright"),
+        }
+    }
+}
+
+/// The background of the rendered maps where there is no vegetation or other map feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Background {
+    /// An opaque color, white by default.
+    Color([u8; 3]),
+    /// Transparent, so that the maps are written as RGBA images that can be laid over other
+    /// imagery.
+    Transparent,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::Color([255, 255, 255])
+    }
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    /// Parse from the `background` ini format, either `transparent` or a `#rrggbb` color.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "transparent" {
+            Ok(Self::Transparent)
+        } else {
+            parse_color(s).map(Self::Color)
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    /// Format in the `background` ini format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Color(color) => write!(f, "{}", format_color(*color)),
+            Self::Transparent => write!(f, "transparent"),
+        }
+    }
+}
+
+/// Parse a slope given either as a rise/run ratio (`1.5`) or in degrees (`56deg`), returning the
+/// rise/run ratio.
+fn parse_slope(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let (value, degrees) = match s.strip_suffix("deg") {
+        Some(value) => (value.trim(), true),
+        None => (s, false),
+    };
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("Invalid slope `{}`", s))?;
+    if degrees {
+        if !(0.0..90.0).contains(&value) {
+            return Err(format!("Slope `{}` must be between 0 and 90 degrees", s));
+        }
+        Ok(value.to_radians().tan())
+    } else if value < 0.0 {
+        Err(format!("Slope `{}` must not be negative", s))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Resolve the cell sizes the ground points are binned at and the heightmap is resampled to, 0
+/// meaning the default of 2 m times the scale factor. A binning cell larger than an explicitly set
+/// resolution would lose detail that cannot be recovered, so it is an error.
+fn parse_dem_cells(binning: f64, resolution: f64, scalefactor: f64) -> Result<(f64, f64), String> {
+    if binning < 0.0 || resolution < 0.0 {
+        return Err(format!(
+            "dem_binning_cell and dem_resolution must not be negative, got {} and {}",
+            binning, resolution
+        ));
+    }
+    match (binning > 0.0, resolution > 0.0) {
+        (true, true) if binning > resolution => Err(format!(
+            "dem_binning_cell {} must not be larger than dem_resolution {}",
+            binning, resolution
+        )),
+        (true, true) => Ok((binning, resolution)),
+        (true, false) => Ok((binning, binning.max(2.0 * scalefactor))),
+        (false, true) => Ok((2.0 * scalefactor, resolution)),
+        (false, false) => Ok((2.0 * scalefactor, 2.0 * scalefactor)),
+    }
+}
+
+/// Parse an EPSG code, optionally prefixed with `EPSG:`. Empty and 0 mean no code.
+fn parse_epsg(value: Option<&str>, key: &str) -> Result<Option<u32>, String> {
+    match value.map(str::trim) {
+        None | Some("") | Some("0") => Ok(None),
+        Some(value) => value
+            .trim_start_matches("EPSG:")
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid {} `{}`", key, value)),
+    }
+}
+
+/// Parse the output resolution, which defaults to 600 dpi at 1:10000 (divided by the scale factor).
+fn parse_pixels_per_meter(value: Option<&str>, scalefactor: f64) -> Result<f64, String> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(600.0 / 254.0 / scalefactor),
+        Some(value) => match value.parse::<f64>() {
+            Ok(pixels_per_meter) if pixels_per_meter > 0.0 && pixels_per_meter.is_finite() => {
+                Ok(pixels_per_meter)
+            }
+            _ => Err(format!(
+                "Invalid pixels_per_meter `{}`, expected a positive number",
+                value
+            )),
+        },
+    }
+}
+
+/// Parse a color given as hex `#rrggbb` (the `#` is optional).
+fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("Invalid color `{}`, expected #rrggbb", s));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| format!("Invalid color `{}`, expected #rrggbb", s))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Parse the class remapping, comma separated `from:to` pairs like `1:2,17:2`. Empty for none.
+fn parse_class_remap(s: &str) -> Result<HashMap<u8, u8>, String> {
+    s.split(',')
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| {
+            let (from, to) = v.split_once(':')?;
+            Some((from.trim().parse().ok()?, to.trim().parse().ok()?))
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            format!(
+                "Invalid value for `class_remap`, expected from:to pairs like 1:2,17:2: {}",
+                s
+            )
+        })
+}
+
+/// Format a color as hex `#rrggbb`, see [`parse_color`].
+fn format_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Serialize the types parsed from strings in the .ini file as the same strings.
+macro_rules! serde_as_string {
+    ($($t:ty),*) => {$(
+        impl TryFrom<String> for $t {
+            type Error = String;
+
+            fn try_from(s: String) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+
+        impl From<$t> for String {
+            fn from(value: $t) -> Self {
+                value.to_string()
+            }
+        }
+    )*};
+}
+
+serde_as_string!(
+    ContourSmoothing,
+    GroundModel,
+    DemSmoothing,
+    TileName,
+    Corner,
+    Background
+);
+
+/// An unsigned integer as a TOML integer, or as a string if it does not fit in the signed 64 bit
+/// integers of TOML.
+mod large_integer {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Integer(i64),
+        String(String),
+    }
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        match i64::try_from(*value) {
+            Ok(i) => Repr::Integer(i),
+            Err(_) => Repr::String(value.to_string()),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        match Repr::deserialize(deserializer)? {
+            Repr::Integer(i) => u64::try_from(i).map_err(serde::de::Error::custom),
+            Repr::String(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// The class remapping as the `from:to` pairs of the .ini file, see [`parse_class_remap`].
+mod class_remap {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::HashMap;
+
+    pub fn serialize<S: Serializer>(
+        remap: &HashMap<u8, u8>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut pairs = remap.iter().collect::<Vec<_>>();
+        pairs.sort();
+        let pairs = pairs
+            .iter()
+            .map(|(from, to)| format!("{}:{}", from, to))
+            .collect::<Vec<_>>();
+        serializer.serialize_str(&pairs.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<u8, u8>, D::Error> {
+        super::parse_class_remap(&String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 const DEFAULT_CONFIG_FILE: &str = "pullauta.ini";
+const TOML_CONFIG_FILE: &str = "pullauta.toml";
+const JSON_CONFIG_FILE: &str = "pullauta.json";
+const DEFAULT_CONFIG_TEMPLATE: &str = include_str!("../pullauta.default.ini");
 
 impl Config {
     pub fn load_or_create_default() -> Result<Self, Box<dyn std::error::Error>> {
+        for path in [TOML_CONFIG_FILE, JSON_CONFIG_FILE].map(Path::new) {
+            if path.exists() {
+                return Self::from_file(path);
+            }
+        }
         let path = Path::new(DEFAULT_CONFIG_FILE);
         // populate the default if no file was found
         if !path.exists() {
-            std::fs::write(path, include_bytes!("../pullauta.default.ini"))?;
+            std::fs::write(path, DEFAULT_CONFIG_TEMPLATE)?;
         }
         Self::from_file(path)
     }
 
-    fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
-        let conf = Ini::load_from_file(path)?;
+    /// Load the configuration from an .ini file, or from a TOML or JSON file (see
+    /// [`Config::from_toml_str`] and [`Config::from_json_str`]) if the extension is `.toml` or
+    /// `.json`.
+    pub(crate) fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&std::fs::read_to_string(path)?),
+            Some("json") => Self::from_json_str(&std::fs::read_to_string(path)?),
+            _ => Self::from_ini(&Ini::load_from_file(path)?),
+        }
+    }
 
-        let gs = conf.general_section();
+    /// Parse a TOML configuration with the same keys as the .ini file. The keys that are not set
+    /// keep their values in the default configuration template.
+    pub fn from_toml_str(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        toml::from_str::<Self>(s)?.resolve()
+    }
 
-        // only one can be set at a time
-        let vegeonly: bool = gs.get("vegeonly").unwrap_or("0") == "1";
-        let cliffsonly: bool = gs.get("cliffsonly").unwrap_or("0") == "1";
-        let contoursonly: bool = gs.get("contoursonly").unwrap_or("0") == "1";
+    /// Parse a JSON configuration, a single object with the same keys and values as the TOML
+    /// configuration of [`Config::from_toml_str`].
+    pub fn from_json_str(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        serde_json::from_str::<Self>(s)?.resolve()
+    }
+
+    /// Whether the points of a classification are used for the ground heightmap, either one of
+    /// `ground_classes` or [`Config::dem_water_class`].
+    pub fn is_dem_class(&self, classification: u8) -> bool {
+        let water = self.dem_water_class();
+        self.ground_classes.contains(&classification) || (water > 0 && classification == water)
+    }
+
+    /// The class of the water points used for the elevation model, `dem_waterclass` if it is set
+    /// and otherwise `waterclass` if that is set, or 9.
+    pub fn dem_water_class(&self) -> u8 {
+        match self.dem_water_class {
+            Some(class) => class,
+            None if self.water > 0 => self.water,
+            None => 9,
+        }
+    }
+
+    /// The classification of an input point after `class_remap`.
+    pub fn remap_class(&self, classification: u8) -> u8 {
+        self.class_remap
+            .get(&classification)
+            .copied()
+            .unwrap_or(classification)
+    }
+
+    /// Write the configuration as TOML, which [`Config::from_toml_str`] reads back to an equal
+    /// configuration.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Could not serialize the config")
+    }
+
+    /// Write the configuration as JSON, which [`Config::from_json_str`] reads back to an equal
+    /// configuration.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Could not serialize the config")
+    }
+
+    /// Fill in the defaults that depend on other values of a deserialized configuration and check
+    /// it. The settings are the deserialized values, as there is no file to list them from.
+    fn resolve(mut self) -> Result<Self, Box<dyn std::error::Error>> {
+        if self.pixels_per_meter == 0.0 {
+            self.pixels_per_meter = parse_pixels_per_meter(None, self.scalefactor)?;
+        }
+        (self.dem_binning_cell, self.dem_resolution) =
+            parse_dem_cells(self.dem_binning_cell, self.dem_resolution, self.scalefactor)?;
+        self.validate()?;
+
+        self.settings = toml::Table::try_from(&self)?
+            .into_iter()
+            .map(|(key, value)| match value {
+                toml::Value::String(s) => (key, s),
+                value => (key, value.to_string()),
+            })
+            .collect();
+        Ok(self)
+    }
 
+    /// Check the values that are not checked as they are parsed.
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
         // clippy complains about this, but we want it like this for understandability
         #[allow(clippy::nonminimal_bool)]
-        if (vegeonly && (cliffsonly || contoursonly))
-            || (cliffsonly && (vegeonly || contoursonly))
-            || (contoursonly && (vegeonly || cliffsonly))
+        if (self.vegeonly && (self.cliffsonly || self.contoursonly))
+            || (self.cliffsonly && (self.vegeonly || self.contoursonly))
+            || (self.contoursonly && (self.vegeonly || self.cliffsonly))
         {
             return Err(
                 "Only one of vegeonly, cliffsonly, or contoursonly can be set!"
@@ -142,6 +869,110 @@ impl Config {
                     .into(),
             );
         }
+        if self.contour_block_size == 1 {
+            return Err("contour_block_size must be 0 or at least 2, got 1".into());
+        }
+        if self.tile_buffer < 0.0 {
+            return Err(
+                format!("tile_buffer must not be negative, got {}", self.tile_buffer).into(),
+            );
+        }
+        if self.tile_size < 0.0 {
+            return Err(format!("tile_size must not be negative, got {}", self.tile_size).into());
+        }
+        if let Some(tile_name) = &self.tile_name {
+            if self.tile_size > 0.0 && !tile_name.has_coordinates() {
+                return Err(
+                    "tile_name must contain {x} and {y} when the files are split with tile_size"
+                        .into(),
+                );
+            }
+        }
+        if !(self.pixels_per_meter > 0.0 && self.pixels_per_meter.is_finite()) {
+            return Err(format!(
+                "Invalid pixels_per_meter `{}`, expected a positive number",
+                self.pixels_per_meter
+            )
+            .into());
+        }
+        if !(0.0..=1.0).contains(&self.thinfactor) {
+            return Err(format!(
+                "Value {} of `thinfactor` is outside the allowed range of 0.0 to 1.0",
+                self.thinfactor
+            )
+            .into());
+        }
+        if self.max_points_cell_size <= 0.0 {
+            return Err(format!(
+                "max_points_cell_size must be positive, got {}",
+                self.max_points_cell_size
+            )
+            .into());
+        }
+        if self.vegemode {
+            return Err("vegemode=1 not implemented, use perl version"
+                .to_string()
+                .into());
+        }
+        if !(0.0..=90.0).contains(&self.hillshade_altitude) {
+            return Err(format!(
+                "hillshade_altitude must be between 0 and 90 degrees, got {}",
+                self.hillshade_altitude
+            )
+            .into());
+        }
+        if self.density_max.is_nan() || self.density_max <= 0.0 {
+            return Err(format!("density_max must be positive, got {}", self.density_max).into());
+        }
+        match (self.source_epsg, self.crs_epsg) {
+            (Some(_), _) if !cfg!(feature = "reproject") => {
+                return Err("source_epsg needs pullauta built with the `reproject` feature".into());
+            }
+            (Some(_), None) => {
+                return Err("source_epsg needs crs_epsg to reproject the input to".into());
+            }
+            #[cfg(feature = "reproject")]
+            (Some(source_epsg), Some(crs_epsg)) => {
+                crate::io::reproject::Transform::new(source_epsg, crs_epsg)?;
+            }
+            _ => {}
+        }
+        if self.contour_simplify < 0.0 {
+            return Err(format!(
+                "contour_simplify must not be negative, got {}",
+                self.contour_simplify
+            )
+            .into());
+        }
+        if !(0.0..=1.0).contains(&self.cliff_thin) {
+            return Err(format!(
+                "Value {} of `cliffthin` is outside the allowed range of 0.0 to 1.0",
+                self.cliff_thin
+            )
+            .into());
+        }
+        if self.cliff_min_slope < 0.0 || self.cliff_steep_slope < 0.0 {
+            return Err("cliff_min_slope and cliff_steep_slope must not be negative".into());
+        }
+        if let Some((low, high)) = self.vege_height_band {
+            if low >= high {
+                return Err(format!(
+                    "Invalid `vege_height_band` {}|{}, expected `min|max` with min below max",
+                    low, high
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn from_ini(conf: &Ini) -> Result<Self, Box<dyn std::error::Error>> {
+        let gs = conf.general_section();
+
+        // only one can be set at a time
+        let vegeonly: bool = gs.get("vegeonly").unwrap_or("0") == "1";
+        let cliffsonly: bool = gs.get("cliffsonly").unwrap_or("0") == "1";
+        let contoursonly: bool = gs.get("contoursonly").unwrap_or("0") == "1";
 
         fn parse_typed<T: FromStr>(props: &ini::Properties, name: &str, default: T) -> T {
             props
@@ -154,36 +985,42 @@ impl Config {
         let pnorthlineswidth: usize = parse_typed(gs, "northlineswidth", 0);
 
         let processes: u64 = gs.get("processes").unwrap().parse::<u64>().unwrap();
+        let memory_budget_mb: usize = parse_typed(gs, "memory_budget", 0);
+        let contour_block_size: usize = parse_typed(gs, "contour_block_size", 0);
         let experimental_use_in_memory_fs: bool =
             gs.get("experimental_use_in_memory_fs").unwrap_or("0") == "1";
 
         let lazfolder = gs.get("lazfolder").unwrap_or("").to_string();
         let batchoutfolder = gs.get("batchoutfolder").unwrap_or("").to_string();
+        let tile_buffer: f64 = parse_typed(gs, "tile_buffer", 127.0);
+        let tile_size: f64 = parse_typed(gs, "tile_size", 0.0);
+        let tile_name: Option<TileName> = match gs.get("tile_name").map(str::trim) {
+            None | Some("") => None,
+            Some(template) => Some(template.parse()?),
+        };
         let savetempfiles: bool = gs.get("savetempfiles").unwrap() == "1";
         let savetempfolders: bool = gs.get("savetempfolders").unwrap() == "1";
+        let manifest: bool = gs.get("manifest").unwrap_or("0") == "1";
 
         let scalefactor: f64 = parse_typed(gs, "scalefactor", 1.0);
+        let pixels_per_meter = parse_pixels_per_meter(gs.get("pixels_per_meter"), scalefactor)?;
+        let legend: Option<Corner> = match gs.get("legend").map(str::trim) {
+            None | Some("") => None,
+            Some(corner) => Some(corner.parse()?),
+        };
+        let legend_margin: bool = gs.get("legend_margin").unwrap_or("0") == "1";
         let vege_bitmode: bool = gs.get("vege_bitmode").unwrap_or("0") == "1";
         let zoff = parse_typed(gs, "zoffset", 0.0);
         let mut thinfactor: f64 = parse_typed(gs, "thinfactor", 1.0);
-        if !(0.0..=1.0).contains(&thinfactor) {
-            return Err(format!(
-                "Value {} of `thinfactor` is outside the allowed range of 0.0 to 1.0",
-                thinfactor
-            )
-            .into());
-        }
         if thinfactor == 0.0 {
             thinfactor = 1.0;
         }
+        let max_points_per_cell: usize = parse_typed(gs, "max_points_per_cell", 0);
+        let max_points_cell_size: f64 = parse_typed(gs, "max_points_cell_size", 1.0);
+        let random_seed: u64 = parse_typed(gs, "random_seed", 0);
 
         let skipknolldetection = gs.get("skipknolldetection").unwrap_or("0") == "1";
         let vegemode: bool = gs.get("vegemode").unwrap_or("0") == "1";
-        if vegemode {
-            return Err("vegemode=1 not implemented, use perl version"
-                .to_string()
-                .into());
-        }
 
         let mut xfactor: f64 = parse_typed(gs, "coordxfactor", 1.0);
         let mut yfactor: f64 = parse_typed(gs, "coordyfactor", 1.0);
@@ -199,18 +1036,72 @@ impl Config {
         }
 
         let contour_interval: f64 = parse_typed(gs, "contour_interval", 5.0);
+        let index_contour_every: usize = parse_typed(gs, "index_contour_every", 0);
+        let contour_geojson: bool = gs.get("contour_geojson").unwrap_or("0") == "1";
+        let geojson_precision: usize = parse_typed(gs, "geojson_precision", 2);
+        let contour_layered_dxf: bool = gs.get("contour_layered_dxf").unwrap_or("0") == "1";
+        let contour_shapefile: bool = gs.get("contour_shapefile").unwrap_or("0") == "1";
+        let (dem_binning_cell, dem_resolution) = parse_dem_cells(
+            parse_typed(gs, "dem_binning_cell", 0.0),
+            parse_typed(gs, "dem_resolution", 0.0),
+            scalefactor,
+        )?;
+        let ground_model: GroundModel = gs
+            .get("ground_model")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let dem_smoothing: DemSmoothing = gs
+            .get("dem_smoothing")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let dem_fill_radius: usize = parse_typed(gs, "dem_fill_radius", 0);
+        let dem_smoothing_iterations: usize = parse_typed(gs, "dem_smoothing_iterations", 0);
+        let dem_geotiff: bool = gs.get("dem_geotiff").unwrap_or("0") == "1";
+        let hillshade: bool = gs.get("hillshade").unwrap_or("0") == "1";
+        let hillshade_azimuth: f64 = parse_typed(gs, "hillshade_azimuth", 315.0);
+        let hillshade_altitude: f64 = parse_typed(gs, "hillshade_altitude", 45.0);
+        let intensity_png: bool = gs.get("intensity").unwrap_or("0") == "1";
+        let dsm: bool = gs.get("dsm").unwrap_or("0") == "1";
+        let density: bool = gs.get("density").unwrap_or("0") == "1";
+        let density_max: f64 = parse_typed(gs, "density_max", 4.0);
+        let crs_epsg = parse_epsg(gs.get("crs_epsg"), "crs_epsg")?;
+        let source_epsg = parse_epsg(gs.get("source_epsg"), "source_epsg")?;
+        let dem_cache: bool = gs.get("dem_cache").unwrap_or("0") == "1";
+        let cachefolder = gs.get("cachefolder").unwrap_or("cache").to_string();
 
         let basemapcontours: f64 = parse_typed(gs, "basemapinterval", 0.0);
 
         let detectbuildings: bool = gs.get("detectbuildings").unwrap_or("0") == "1";
 
-        let water_class = parse_typed(gs, "waterclass", 9);
+        let ground_classes: Vec<u8> = gs
+            .get("groundclasses")
+            .unwrap_or("2")
+            .split(',')
+            .map(|v| v.trim().parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Invalid value for `groundclasses`: {}", e))?;
+        let class_remap = parse_class_remap(gs.get("class_remap").unwrap_or(""))?;
 
         let inidotknolls: f64 = parse_typed(gs, "knolls", 0.8);
+        let knoll_min_prominence: f64 = parse_typed(gs, "knoll_min_prominence", 0.0);
+        let knoll_min_area: f64 = parse_typed(gs, "knoll_min_area", 0.0);
         let smoothing: f64 = parse_typed(gs, "smoothing", 1.0);
         let curviness: f64 = parse_typed(gs, "curviness", 1.0);
+        let contour_smoothing: ContourSmoothing = gs
+            .get("contoursmoothing")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let contour_simplify: f64 = parse_typed(gs, "contour_simplify", 0.0);
+        let min_contour_length: f64 = parse_typed(gs, "min_contour_length", 0.0);
+        let min_ring_area: f64 = parse_typed(gs, "min_ring_area", 0.0);
         let indexcontours: f64 = parse_typed(gs, "indexcontours", 12.5);
         let formline: f64 = parse_typed(gs, "formline", 2.0);
+        let formline_by_slope: bool = gs.get("formline_by_slope").unwrap_or("0") == "1";
+        let formline_max_slope: f64 = parse_typed(gs, "formline_max_slope", 0.15);
+        let formline_min_distance: f64 = parse_typed(gs, "formline_min_distance", 10.0);
 
         let depression_length: usize = parse_typed(gs, "depression_length", 181);
 
@@ -218,16 +1109,13 @@ impl Config {
         let c1_limit: f64 = parse_typed(gs, "cliff1", 1.0);
         let c2_limit: f64 = parse_typed(gs, "cliff2", 1.0);
         let cliff_thin: f64 = parse_typed(gs, "cliffthin", 1.0);
-        if !(0.0..=1.0).contains(&cliff_thin) {
-            return Err(format!(
-                "Value {} of `cliffthin` is outside the allowed range of 0.0 to 1.0",
-                cliff_thin
-            )
-            .into());
-        }
         let steep_factor: f64 = parse_typed(gs, "cliffsteepfactor", 0.33);
         let flat_place: f64 = parse_typed(gs, "cliffflatplace", 6.6);
         let no_small_ciffs: f64 = parse_typed(gs, "cliffnosmallciffs", 0.0);
+        let cliff_min_slope = parse_slope(gs.get("cliff_min_slope").unwrap_or("0"))?;
+        let cliff_steep_slope = parse_slope(gs.get("cliff_steep_slope").unwrap_or("0"))?;
+        let cliff_min_length: f64 = parse_typed(gs, "cliff_min_length", 0.0);
+        let cliff_skeleton: bool = gs.get("cliff_skeleton").unwrap_or("0") == "1";
 
         // vegetation
 
@@ -269,12 +1157,43 @@ impl Config {
             thresholds
         };
 
-        let greenshades = gs
+        let greenshade_limits = gs
             .get("greenshades")
             .unwrap_or("")
             .split('|')
             .map(|v| v.parse::<f64>().unwrap())
             .collect::<Vec<f64>>();
+        let greentone: f64 = parse_typed(gs, "lightgreentone", 200.0);
+        let greencolors = match gs.get("greencolors") {
+            Some(colors) if !colors.trim().is_empty() => colors
+                .split('|')
+                .map(parse_color)
+                .collect::<Result<Vec<_>, _>>()?,
+            // by default fade from the light green tone to the darkest green
+            _ => {
+                let steps = greenshade_limits.len().saturating_sub(1).max(1) as f64;
+                (0..greenshade_limits.len())
+                    .map(|i| {
+                        let tone = (greentone - greentone / steps * i as f64) as u8;
+                        [tone, (254.0 - 74.0 / steps * i as f64) as u8, tone]
+                    })
+                    .collect()
+            }
+        };
+        if greencolors.len() != greenshade_limits.len() {
+            return Err(format!(
+                "`greencolors` has {} colors but `greenshades` has {} values",
+                greencolors.len(),
+                greenshade_limits.len()
+            )
+            .into());
+        }
+        let greenshades = greenshade_limits.into_iter().zip(greencolors).collect();
+        let background: Background = gs
+            .get("background")
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
         let yellowheight: f64 = parse_typed(gs, "yellowheight", 0.9);
         let yellowthreshold: f64 = parse_typed(gs, "yellowthresold", 0.9);
         let greenground: f64 = parse_typed(gs, "greenground", 0.9);
@@ -282,7 +1201,6 @@ impl Config {
         let pointvolumeexponent: f64 = parse_typed(gs, "pointvolumeexponent", 1.0);
         let greenhigh: f64 = parse_typed(gs, "greenhigh", 2.0);
         let topweight: f64 = parse_typed(gs, "topweight", 0.8);
-        let greentone: f64 = parse_typed(gs, "lightgreentone", 200.0);
         let vegezoffset: f64 = parse_typed(gs, "vegezoffset", 0.0);
         let uglimit: f64 = parse_typed(gs, "undergrowth", 0.35);
         let uglimit2: f64 = parse_typed(gs, "undergrowth2", 0.56);
@@ -298,9 +1216,39 @@ impl Config {
         let proceed_yellows: bool = gs.get("yellow_smoothing").unwrap_or("0") == "1";
         let med: u32 = parse_typed(gs, "medianboxsize", 0);
         let med2: u32 = parse_typed(gs, "medianboxsize2", 0);
+        let vege_despeckle_window: usize = parse_typed(gs, "vege_despeckle_window", 0);
+        let vege_despeckle_min_cells: usize = parse_typed(gs, "vege_despeckle_min_cells", 4);
+        let vege_height_band: Option<(f64, f64)> = match gs.get("vege_height_band").map(str::trim) {
+            None | Some("") => None,
+            Some(band) => {
+                let parsed = band.split_once('|').and_then(|(low, high)| {
+                    Some((low.trim().parse().ok()?, high.trim().parse().ok()?))
+                });
+                match parsed {
+                    Some((low, high)) => Some((low, high)),
+                    None => {
+                        return Err(format!(
+                            "Invalid `vege_height_band` {}, expected `min|max` with min below max",
+                            band
+                        )
+                        .into())
+                    }
+                }
+            }
+        };
+        let vege_exclude_buildings: bool = gs.get("vege_exclude_buildings").unwrap_or("0") == "1";
+        let vege_canopy: bool = gs.get("vege_canopy").unwrap_or("0") == "1";
+        let canopy_rough_height: f64 = parse_typed(gs, "canopy_rough_height", 0.5);
+        let canopy_forest_height: f64 = parse_typed(gs, "canopy_forest_height", 2.0);
+        let canopy_min_cover: f64 = parse_typed(gs, "canopy_min_cover", 0.3);
         let water = parse_typed(gs, "waterclass", 0);
+        let dem_water_class: Option<u8> = gs.get("dem_waterclass").and_then(|s| s.parse().ok());
         let buildings = parse_typed(gs, "buildingsclass", 0);
         let waterele = parse_typed(gs, "waterelevation", -999999.0);
+        let water_detection: bool = gs.get("water_detection").unwrap_or("0") == "1";
+        let water_max_slope: f64 = parse_typed(gs, "water_max_slope", 0.05);
+        let water_max_density: f64 = parse_typed(gs, "water_max_density", 0.2);
+        let water_min_area: f64 = parse_typed(gs, "water_min_area", 400.0);
 
         // render
         let buildingcolor: (u8, u8, u8) = {
@@ -328,9 +1276,12 @@ impl Config {
         let gaplength: f64 = parse_typed(gs, "gaplength", 12.0);
         let minimumgap: u32 = parse_typed(gs, "minimumgap", 30);
         let label_depressions: bool = gs.get("label_formlines_depressions").unwrap_or("0") == "1";
-        Ok(Self {
+        let contour_antialias: bool = gs.get("contour_antialias").unwrap_or("0") == "1";
+        let config = Self {
             batch: gs.get("batch").unwrap() == "1",
             processes,
+            memory_budget_mb,
+            contour_block_size,
             experimental_use_in_memory_fs,
             vegeonly,
             cliffsonly,
@@ -339,26 +1290,70 @@ impl Config {
             pnorthlineswidth,
             lazfolder,
             batchoutfolder,
+            tile_buffer,
+            tile_size,
+            tile_name,
             savetempfolders,
             savetempfiles,
+            manifest,
             scalefactor,
+            pixels_per_meter,
+            legend,
+            legend_margin,
             vege_bitmode,
             zoff,
             thinfactor,
+            max_points_per_cell,
+            max_points_cell_size,
+            random_seed,
             skipknolldetection,
             vegemode,
             xfactor,
             yfactor,
             zfactor,
             contour_interval,
+            index_contour_every,
+            contour_geojson,
+            geojson_precision,
+            contour_layered_dxf,
+            contour_shapefile,
+            dem_binning_cell,
+            dem_resolution,
+            ground_model,
+            dem_fill_radius,
+            dem_smoothing,
+            dem_smoothing_iterations,
+            dem_geotiff,
+            hillshade,
+            hillshade_azimuth,
+            hillshade_altitude,
+            intensity_png,
+            dsm,
+            density,
+            density_max,
+            crs_epsg,
+            source_epsg,
+            dem_cache,
+            cachefolder,
             basemapcontours,
             detectbuildings,
-            water_class,
+            ground_classes,
+            dem_water_class,
+            class_remap,
             inidotknolls,
+            knoll_min_prominence,
+            knoll_min_area,
             smoothing,
             curviness,
+            contour_smoothing,
+            contour_simplify,
+            min_contour_length,
+            min_ring_area,
             indexcontours,
             formline,
+            formline_by_slope,
+            formline_max_slope,
+            formline_min_distance,
             depression_length,
             c1_limit,
             c2_limit,
@@ -366,9 +1361,14 @@ impl Config {
             steep_factor,
             flat_place,
             no_small_ciffs,
+            cliff_min_slope,
+            cliff_steep_slope,
+            cliff_min_length,
+            cliff_skeleton,
             zones,
             thresholds,
             greenshades,
+            background,
             yellowheight,
             yellowthreshold,
             greenground,
@@ -376,7 +1376,6 @@ impl Config {
             pointvolumeexponent,
             greenhigh,
             topweight,
-            greentone,
             vegezoffset,
             uglimit,
             uglimit2,
@@ -390,9 +1389,21 @@ impl Config {
             proceed_yellows,
             med,
             med2,
+            vege_despeckle_window,
+            vege_despeckle_min_cells,
+            vege_height_band,
+            vege_exclude_buildings,
+            vege_canopy,
+            canopy_rough_height,
+            canopy_forest_height,
+            canopy_min_cover,
             water,
             buildings,
             waterele,
+            water_detection,
+            water_max_slope,
+            water_max_density,
+            water_min_area,
             buildingcolor,
             vectorconf,
             mtkskiplayers,
@@ -403,7 +1414,22 @@ impl Config {
             gaplength,
             minimumgap,
             label_depressions,
-        })
+            contour_antialias,
+            settings: gs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    /// The configuration of the default configuration template.
+    fn default() -> Self {
+        Self::from_ini(&Ini::load_from_str(DEFAULT_CONFIG_TEMPLATE).expect("Invalid template"))
+            .expect("Invalid template")
     }
 }
 
@@ -411,11 +1437,409 @@ impl Config {
 mod test {
     use std::path::Path;
 
-    use super::Config;
+    use super::{
+        parse_color, parse_dem_cells, parse_pixels_per_meter, parse_slope, Background, Config,
+        ContourSmoothing, Corner, DemSmoothing, GroundModel, TileName, Zone,
+    };
 
     #[test]
     fn should_load_config_template_successfully() {
         Config::from_file(Path::new("pullauta.default.ini"))
             .expect("Could not load and parse the default config template");
     }
+
+    /// A configuration with every field changed from the default, that passes the validation.
+    fn changed_config() -> Config {
+        let mut config = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        config.batch = true;
+        config.processes = 3;
+        config.memory_budget_mb = 512;
+        config.contour_block_size = 64;
+        config.experimental_use_in_memory_fs = true;
+        // only one of these can be set
+        config.vegeonly = true;
+        config.pnorthlinesangle = 12.5;
+        config.pnorthlineswidth = 3;
+        config.lazfolder = "in".to_string();
+        config.batchoutfolder = "out".to_string();
+        config.tile_buffer = 50.0;
+        config.tile_size = 500.0;
+        config.tile_name = Some("N{y}E{x}_{scale}".parse().unwrap());
+        config.savetempfiles = true;
+        config.savetempfolders = true;
+        config.manifest = true;
+        config.scalefactor = 1.5;
+        config.pixels_per_meter = 3.25;
+        config.legend = Some(Corner::BottomRight);
+        config.legend_margin = true;
+        config.vege_bitmode = true;
+        config.zoff = 1.25;
+        config.thinfactor = 0.5;
+        config.max_points_per_cell = 4;
+        config.max_points_cell_size = 2.0;
+        // does not fit in a TOML integer
+        config.random_seed = u64::MAX;
+        config.skipknolldetection = true;
+        config.xfactor = 0.5;
+        config.yfactor = 0.25;
+        config.zfactor = 0.125;
+        config.contour_interval = 2.5;
+        config.index_contour_every = 5;
+        config.contour_geojson = true;
+        config.geojson_precision = 3;
+        config.contour_layered_dxf = true;
+        config.contour_shapefile = true;
+        config.dem_binning_cell = 1.0;
+        config.dem_resolution = 2.5;
+        config.ground_model = GroundModel::Tin;
+        config.dem_fill_radius = 3;
+        config.dem_smoothing = DemSmoothing::Gaussian { sigma: 1.5 };
+        config.dem_smoothing_iterations = 2;
+        config.dem_geotiff = true;
+        config.hillshade = true;
+        config.hillshade_azimuth = 270.0;
+        config.hillshade_altitude = 30.0;
+        config.intensity_png = true;
+        config.dsm = true;
+        config.density = true;
+        config.density_max = 8.0;
+        config.crs_epsg = Some(3067);
+        config.source_epsg = cfg!(feature = "reproject").then_some(32635);
+        config.dem_cache = true;
+        config.cachefolder = "dems".to_string();
+        config.basemapcontours = 10.0;
+        config.detectbuildings = true;
+        config.ground_classes = vec![2, 8];
+        config.dem_water_class = Some(7);
+        config.class_remap = [(1, 2), (17, 2)].into_iter().collect();
+        config.inidotknolls = 0.6;
+        config.knoll_min_prominence = 0.5;
+        config.knoll_min_area = 20.0;
+        config.smoothing = 0.7;
+        config.curviness = 1.1;
+        config.contour_smoothing = ContourSmoothing::MovingAverage { window: 5 };
+        config.contour_simplify = 0.2;
+        config.min_contour_length = 15.0;
+        config.min_ring_area = 30.0;
+        config.indexcontours = 25.0;
+        config.formline = 1.0;
+        config.formline_by_slope = true;
+        config.formline_max_slope = 0.3;
+        config.formline_min_distance = 20.0;
+        config.depression_length = 90;
+        config.c1_limit = 1.5;
+        config.c2_limit = 2.5;
+        config.cliff_thin = 0.5;
+        config.steep_factor = 0.4;
+        config.flat_place = 5.5;
+        config.no_small_ciffs = 3.0;
+        config.cliff_min_slope = 1.2;
+        config.cliff_steep_slope = 2.4;
+        config.cliff_min_length = 4.0;
+        config.cliff_skeleton = true;
+        config.zones = vec![
+            Zone {
+                low: 1.0,
+                high: 2.5,
+                roof: 99.0,
+                factor: 1.0,
+            },
+            Zone {
+                low: 2.5,
+                high: 5.0,
+                roof: 8.0,
+                factor: 0.5,
+            },
+        ];
+        config.thresholds = vec![(0.1, 3.0, 0.2), (3.0, 99.0, 0.3)];
+        config.greenshades = vec![(0.2, [1, 2, 3]), (0.5, [4, 5, 6])];
+        config.background = Background::Transparent;
+        config.yellowheight = 0.8;
+        config.yellowthreshold = 0.7;
+        config.greenground = 0.8;
+        config.pointvolumefactor = 0.2;
+        config.pointvolumeexponent = 1.5;
+        config.greenhigh = 3.0;
+        config.topweight = 0.7;
+        config.vegezoffset = 0.5;
+        config.uglimit = 0.3;
+        config.uglimit2 = 0.6;
+        config.addition = -1;
+        config.firstandlastreturnasground = 2;
+        config.firstandlastfactor = 0.5;
+        config.lastfactor = 0.5;
+        config.yellowfirstlast = 2;
+        config.vegethin = 2;
+        config.greendetectsize = 4.0;
+        config.proceed_yellows = true;
+        config.med = 3;
+        config.med2 = 5;
+        config.vege_despeckle_window = 3;
+        config.vege_despeckle_min_cells = 6;
+        config.vege_height_band = Some((1.5, 30.0));
+        config.vege_exclude_buildings = true;
+        config.vege_canopy = true;
+        config.canopy_rough_height = 0.6;
+        config.canopy_forest_height = 2.5;
+        config.canopy_min_cover = 0.4;
+        config.water = 9;
+        config.buildings = 6;
+        config.waterele = 0.15;
+        config.water_detection = true;
+        config.water_max_slope = 0.1;
+        config.water_max_density = 0.3;
+        config.water_min_area = 500.0;
+        config.buildingcolor = (10, 20, 30);
+        config.vectorconf = "vectors.txt".to_string();
+        config.mtkskiplayers = vec!["12111".to_string(), "12112".to_string()];
+        config.cliffdebug = true;
+        config.formlinesteepness = 0.4;
+        config.formlineaddition = 15.0;
+        config.dashlength = 50.0;
+        config.gaplength = 10.0;
+        config.minimumgap = 25;
+        config.label_depressions = true;
+        config.contour_antialias = true;
+        config
+    }
+
+    #[test]
+    fn should_round_trip_through_toml() {
+        let config = changed_config();
+        let toml = config.to_toml();
+        let mut parsed = Config::from_toml_str(&toml).unwrap();
+        // the settings are the ones of the file that was read
+        parsed.settings = config.settings.clone();
+        assert_eq!(parsed, config);
+        assert_eq!(parsed.to_toml(), toml);
+
+        let defaults = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        let mut parsed = Config::from_toml_str(&defaults.to_toml()).unwrap();
+        parsed.settings = defaults.settings.clone();
+        assert_eq!(parsed, defaults);
+    }
+
+    #[test]
+    fn should_round_trip_through_json() {
+        let config = changed_config();
+        let mut parsed = Config::from_json_str(&config.to_json()).unwrap();
+        parsed.settings = config.settings.clone();
+        assert_eq!(parsed, config);
+
+        let config = Config::from_json_str(r#"{"contour_interval": 2.5, "dsm": true}"#).unwrap();
+        assert_eq!(config.contour_interval, 2.5);
+        assert!(config.dsm);
+        assert!(Config::from_json_str(r#"{"contour_interval": null}"#).is_err());
+    }
+
+    #[test]
+    fn should_merge_partial_toml_onto_defaults() {
+        let defaults = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        let config = Config::from_toml_str(
+            "contour_interval = 2.5\ncontoursmoothing = \"chaikin|2\"\ncontour_geojson = true\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.contour_interval, 2.5);
+        assert_eq!(
+            config.contour_smoothing,
+            ContourSmoothing::Chaikin { iterations: 2 }
+        );
+        assert!(config.contour_geojson);
+        assert_eq!(config.scalefactor, defaults.scalefactor);
+        assert_eq!(config.processes, defaults.processes);
+        assert!(config
+            .settings
+            .contains(&("contour_interval".to_string(), "2.5".to_string())));
+
+        assert!(Config::from_toml_str("contour_interval = [1, 2]").is_err());
+    }
+
+    #[test]
+    fn should_parse_source_epsg() {
+        assert!(Config::from_toml_str("source_epsg = 4326").is_err());
+        let config = Config::from_toml_str("source_epsg = 32635\ncrs_epsg = 3067");
+        if cfg!(feature = "reproject") {
+            let config = config.unwrap();
+            assert_eq!(
+                (config.source_epsg, config.crs_epsg),
+                (Some(32635), Some(3067))
+            );
+            assert!(Config::from_toml_str("source_epsg = 2393\ncrs_epsg = 3067").is_err());
+        } else {
+            assert!(config.is_err());
+        }
+    }
+
+    #[test]
+    fn should_parse_contour_smoothing() {
+        assert_eq!("weighted".parse(), Ok(ContourSmoothing::Weighted));
+        assert_eq!("none".parse(), Ok(ContourSmoothing::None));
+        assert_eq!(
+            "chaikin|2".parse(),
+            Ok(ContourSmoothing::Chaikin { iterations: 2 })
+        );
+        assert_eq!(
+            "movingaverage|5".parse(),
+            Ok(ContourSmoothing::MovingAverage { window: 5 })
+        );
+        assert!("chaikin|x".parse::<ContourSmoothing>().is_err());
+        assert!("bezier".parse::<ContourSmoothing>().is_err());
+    }
+
+    #[test]
+    fn should_parse_dem_smoothing() {
+        assert_eq!("mean".parse(), Ok(DemSmoothing::Mean));
+        assert_eq!(
+            "gaussian|1.5".parse(),
+            Ok(DemSmoothing::Gaussian { sigma: 1.5 })
+        );
+        assert_eq!(
+            "gaussian".parse(),
+            Ok(DemSmoothing::Gaussian { sigma: 1.0 })
+        );
+        assert!("gaussian|0".parse::<DemSmoothing>().is_err());
+        assert!("median".parse::<DemSmoothing>().is_err());
+    }
+
+    #[test]
+    fn should_parse_slope() {
+        assert_eq!(parse_slope("1.5"), Ok(1.5));
+        assert!((parse_slope("45deg").unwrap() - 1.0).abs() < 1e-12);
+        assert!((parse_slope(" 30 deg").unwrap() - 30f64.to_radians().tan()).abs() < 1e-12);
+        assert!(parse_slope("90deg").is_err());
+        assert!(parse_slope("-1").is_err());
+        assert!(parse_slope("steep").is_err());
+    }
+
+    #[test]
+    fn should_parse_pixels_per_meter() {
+        assert_eq!(parse_pixels_per_meter(None, 1.0), Ok(600.0 / 254.0));
+        assert_eq!(
+            parse_pixels_per_meter(Some(""), 2.0),
+            Ok(600.0 / 254.0 / 2.0)
+        );
+        assert_eq!(parse_pixels_per_meter(Some("5"), 1.0), Ok(5.0));
+        assert!(parse_pixels_per_meter(Some("0"), 1.0).is_err());
+        assert!(parse_pixels_per_meter(Some("-2"), 1.0).is_err());
+        assert!(parse_pixels_per_meter(Some("fine"), 1.0).is_err());
+    }
+
+    #[test]
+    fn should_parse_dem_cells() {
+        assert_eq!(parse_dem_cells(0.0, 0.0, 1.0), Ok((2.0, 2.0)));
+        assert_eq!(parse_dem_cells(0.5, 1.0, 1.0), Ok((0.5, 1.0)));
+        assert_eq!(parse_dem_cells(0.5, 0.0, 1.0), Ok((0.5, 2.0)));
+        // without an explicit binning cell the heightmap can still be made finer
+        assert_eq!(parse_dem_cells(0.0, 1.0, 1.0), Ok((2.0, 1.0)));
+        assert!(parse_dem_cells(2.0, 1.0, 1.0).is_err());
+        assert!(parse_dem_cells(-1.0, 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn should_parse_ground_model() {
+        assert_eq!("binning".parse(), Ok(GroundModel::Binning));
+        assert_eq!("tin".parse(), Ok(GroundModel::Tin));
+        assert!("kriging".parse::<GroundModel>().is_err());
+    }
+
+    #[test]
+    fn should_parse_dem_classes() {
+        let config = Config::from_toml_str("").unwrap();
+        assert_eq!(config.ground_classes, vec![2]);
+        assert_eq!(config.water, 0);
+        assert!(config.is_dem_class(2));
+        // the water points are used for the elevation model even when they are not drawn
+        assert!(config.is_dem_class(9));
+        assert!(!config.is_dem_class(0));
+
+        let config = Config::from_toml_str("groundclasses = [2, 8]\nwaterclass = 7").unwrap();
+        assert_eq!(config.ground_classes, vec![2, 8]);
+        assert_eq!(config.dem_water_class(), 7);
+        assert!(config.is_dem_class(8));
+        assert!(config.is_dem_class(7));
+        assert!(!config.is_dem_class(9));
+        assert!(!config.is_dem_class(5));
+
+        let config = Config::from_toml_str("dem_waterclass = 0").unwrap();
+        assert!(!config.is_dem_class(9));
+        assert!(!config.is_dem_class(0));
+
+        assert!(Config::from_toml_str("groundclasses = [2, 256]").is_err());
+    }
+
+    #[test]
+    fn should_parse_class_remap() {
+        let config = Config::from_toml_str("").unwrap();
+        assert!(config.class_remap.is_empty());
+        assert_eq!(config.remap_class(1), 1);
+
+        let config = Config::from_toml_str("class_remap = \"1:2, 17:2\"").unwrap();
+        assert_eq!(config.class_remap.len(), 2);
+        assert_eq!(
+            (
+                config.remap_class(1),
+                config.remap_class(17),
+                config.remap_class(3)
+            ),
+            (2, 2, 3)
+        );
+        assert!(Config::from_toml_str("class_remap = \"1-2\"").is_err());
+        assert!(Config::from_toml_str("class_remap = \"1:256\"").is_err());
+    }
+
+    #[test]
+    fn should_parse_vege_height_band() {
+        assert_eq!(Config::from_toml_str("").unwrap().vege_height_band, None);
+        let config = Config::from_toml_str("vege_height_band = [1.5, 30.0]").unwrap();
+        assert_eq!(config.vege_height_band, Some((1.5, 30.0)));
+        assert!(Config::from_toml_str("vege_height_band = [30.0, 1.5]").is_err());
+        assert!(Config::from_toml_str("vege_height_band = [30.0]").is_err());
+    }
+
+    #[test]
+    fn should_parse_tile_name() {
+        assert_eq!(Config::from_toml_str("").unwrap().tile_name, None);
+        let config = Config::from_toml_str("tile_name = \"N{y}E{x}\"").unwrap();
+        assert_eq!(
+            config
+                .tile_name
+                .unwrap()
+                .expand("a.laz", 412000.0, 6721000.0, 1.0),
+            "N6721000E412000"
+        );
+        for invalid in [
+            "tile_{x",
+            "tile_{z}_{y}",
+            "tile}_{x}_{y}",
+            "a/{x}_{y}",
+            "tile",
+        ] {
+            assert!(invalid.parse::<TileName>().is_err(), "{}", invalid);
+        }
+        assert!(Config::from_toml_str("tile_name = \"{name}\"\ntile_size = 500").is_err());
+    }
+
+    #[test]
+    fn should_parse_legend() {
+        assert_eq!(Config::from_toml_str("").unwrap().legend, None);
+        let config = Config::from_toml_str("legend = \"bottomright\"").unwrap();
+        assert_eq!(config.legend, Some(Corner::BottomRight));
+        assert!(Config::from_toml_str("legend = \"middle\"").is_err());
+    }
+
+    #[test]
+    fn should_parse_background() {
+        assert_eq!("transparent".parse(), Ok(Background::Transparent));
+        assert_eq!("#f0f0e0".parse(), Ok(Background::Color([240, 240, 224])));
+        assert!("clear".parse::<Background>().is_err());
+    }
+
+    #[test]
+    fn should_parse_color() {
+        assert_eq!(parse_color("#c8fec8"), Ok([200, 254, 200]));
+        assert_eq!(parse_color("00b400"), Ok([0, 180, 0]));
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#gg0000").is_err());
+    }
 }
@@ -4,16 +4,32 @@ use std::error::Error;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
 
-use crate::config::Config;
+use crate::cancel::CancellationToken;
+use crate::config::{Config, DemSmoothing, GroundModel};
 use crate::io::fs::FileSystem;
-use crate::io::heightmap::HeightMap;
+use crate::io::heightmap::{HeightMap, HeightMapStrips};
 use crate::io::xyz::XyzInternalReader;
+use crate::progress::{report, report_steps, Stage};
+use crate::tin::Tin;
 use crate::util::read_lines_no_alloc;
-use crate::vec2d::Vec2D;
+use crate::vec2d::{distance_transform, AccumGrid, Vec2D};
+
+/// A single contour line in world coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    pub points: Vec<(f64, f64)>,
+    pub elevation: f64,
+    /// Whether this is an index contour.
+    pub index: bool,
+    /// Whether this is a form line, drawn dashed between the regular contours.
+    pub form_line: bool,
+}
 
 /// Create a heightmap from a point cloud file.
 ///
-/// Loads all the points and uses those that are classified as ground or water to create a heightmap using averages.
+/// Loads all the points and uses those that are classified as ground (any of `groundclasses`) or water to create a
+/// heightmap using averages, or by sampling their triangulation with `ground_model=tin`. Cells without any such points
+/// are filled with [`fill_nodata`] within `dem_fill_radius` and interpolated from their neighbors otherwise.
 pub fn xyz2heightmap(
     fs: &impl FileSystem,
     config: &Config,
@@ -22,8 +38,7 @@ pub fn xyz2heightmap(
 ) -> Result<HeightMap, Box<dyn Error>> {
     info!("Generating curves...");
 
-    let scalefactor = config.scalefactor;
-    let water_class = config.water_class;
+    let cell = config.dem_binning_cell;
 
     let mut xmin: f64 = f64::MAX;
     let mut xmax: f64 = f64::MIN;
@@ -37,7 +52,7 @@ pub fn xyz2heightmap(
     let xyz_file_in = tmpfolder.join(xyzfilein);
     let mut reader = XyzInternalReader::new(BufReader::new(fs.open(&xyz_file_in)?))?;
     while let Some(r) = reader.next()? {
-        if r.classification == 2 || r.classification == water_class {
+        if config.is_dem_class(r.classification) {
             let x: f64 = r.x;
             let y: f64 = r.y;
             let h: f64 = r.z;
@@ -69,46 +84,55 @@ pub fn xyz2heightmap(
     }
     drop(reader);
 
-    xmin = (xmin / 2.0 / scalefactor).floor() * 2.0 * scalefactor;
-    ymin = (ymin / 2.0 / scalefactor).floor() * 2.0 * scalefactor;
+    if xmin > xmax {
+        return Err(crate::error::Error::EmptyTile.into());
+    }
 
-    let w: usize = ((xmax - xmin).ceil() / 2.0 / scalefactor) as usize;
-    let h: usize = ((ymax - ymin).ceil() / 2.0 / scalefactor) as usize;
+    xmin = (xmin / cell).floor() * cell;
+    ymin = (ymin / cell).floor() * cell;
 
-    // a two-dimensional vector of (sum, count) pairs for computing averages
-    let mut list_alt = Vec2D::new(w + 2, h + 2, (0f64, 0usize));
+    let w: usize = ((xmax - xmin).ceil() / cell) as usize;
+    let h: usize = ((ymax - ymin).ceil() / cell) as usize;
 
     let mut reader = XyzInternalReader::new(BufReader::new(fs.open(&xyz_file_in)?))?;
-    while let Some(r) = reader.next()? {
-        if r.classification == 2 || r.classification == water_class {
-            let x: f64 = r.x;
-            let y: f64 = r.y;
-            let h: f64 = r.z;
-
-            let idx_x = ((x - xmin).floor() / 2.0 / scalefactor) as usize;
-            let idx_y = ((y - ymin).floor() / 2.0 / scalefactor) as usize;
-
-            let (sum, count) = &mut list_alt[(idx_x, idx_y)];
-            *sum += h;
-            *count += 1;
+    let mut avg_alt = match config.ground_model {
+        GroundModel::Binning => {
+            let mut list_alt = AccumGrid::new(w + 2, h + 2);
+            while let Some(r) = reader.next()? {
+                if config.is_dem_class(r.classification) {
+                    let x: f64 = r.x;
+                    let y: f64 = r.y;
+                    let h: f64 = r.z;
+
+                    let idx_x = ((x - xmin) / cell).floor() as usize;
+                    let idx_y = ((y - ymin) / cell).floor() as usize;
+
+                    list_alt.add(idx_x, idx_y, h);
+                }
+            }
+            list_alt.finalize().subgrid(0, 0, w + 1, h + 1)
         }
-    }
+        GroundModel::Tin => {
+            let mut points = Vec::new();
+            while let Some(r) = reader.next()? {
+                if config.is_dem_class(r.classification) {
+                    points.push((r.x, r.y, r.z));
+                }
+            }
+            // the cells outside the triangulation are interpolated below like empty bins
+            Tin::new(points).rasterize(xmin + cell / 2.0, ymin + cell / 2.0, cell, w + 1, h + 1)
+        }
+    };
 
     drop(reader);
 
-    let mut avg_alt = Vec2D::new(w + 1, h + 1, f64::NAN);
-
-    for x in 0..w + 1 {
-        for y in 0..h + 1 {
-            let (sum, count) = &list_alt[(x, y)];
-
-            if *count > 0 {
-                avg_alt[(x, y)] = *sum / *count as f64;
-            }
-        }
+    // the cells farther than dem_fill_radius from any ground point are interpolated below
+    if config.dem_fill_radius > 0 {
+        fill_nodata(&mut avg_alt, config.dem_fill_radius);
     }
 
     for x in 0..w + 1 {
+        report_steps(Stage::Heightmap, x, w + 1);
         for y in 0..h + 1 {
             if avg_alt[(x, y)].is_nan() {
                 // interpolate altitude of pixel
@@ -201,31 +225,317 @@ pub fn xyz2heightmap(
         }
     }
 
-    xmin += 1.0;
-    ymin += 1.0;
+    // the heightmap values are at the cell centers
+    xmin += cell / 2.0;
+    ymin += cell / 2.0;
 
     // make sure we do not have any NaNs
-    for x in 0..avg_alt.width() {
-        for y in 0..avg_alt.height() {
-            if avg_alt[(x, y)].is_nan() {
-                panic!(
-                    "heightmap should not have any nans, found NaN at ({}, {})",
-                    x, y
-                );
-            }
-        }
-    }
+    crate::error::Error::check_no_data(&avg_alt)?;
 
     let hmap = HeightMap {
         xoffset: xmin,
         yoffset: ymin,
-        scale: 2.0 * scalefactor,
+        scale: cell,
         grid: avg_alt.clone(),
     };
+    report(Stage::Heightmap, 1.0);
 
     Ok(hmap)
 }
 
+/// Fills the NaN (no-data) cells of a grid using inverse-distance weighting of the valid cells
+/// within `max_radius` cells. Only cells that were valid before the call are used as sources, and
+/// cells without any valid neighbor within range are left as NaN.
+pub fn fill_nodata(grid: &mut Vec2D<f64>, max_radius: usize) {
+    let source = grid.clone();
+    let r = max_radius as isize;
+    let (w, h) = (grid.width() as isize, grid.height() as isize);
+
+    for (x, y, v) in grid.iter_mut() {
+        if !v.is_nan() {
+            continue;
+        }
+
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        for dx in -r..=r {
+            for dy in -r..=r {
+                let (xx, yy) = (x as isize + dx, y as isize + dy);
+                if xx < 0 || yy < 0 || xx >= w || yy >= h {
+                    continue;
+                }
+                let d2 = (dx * dx + dy * dy) as f64;
+                if d2 > (r * r) as f64 {
+                    continue;
+                }
+                let value = source[(xx as usize, yy as usize)];
+                if !value.is_nan() {
+                    sum += value / d2;
+                    weights += 1.0 / d2;
+                }
+            }
+        }
+
+        if weights > 0.0 {
+            *v = sum / weights;
+        }
+    }
+}
+
+/// Smooths the heightmap `iterations` times with the kernel of `smoothing`. Each cell becomes the
+/// weighted mean of the valid cells around it, so the NaN (no-data) cells stay NaN and do not
+/// spread, and the cells at the edges are the mean of the neighbors inside the grid.
+pub fn smooth_dem(grid: &Vec2D<f64>, smoothing: DemSmoothing, iterations: usize) -> Vec2D<f64> {
+    let kernel = match smoothing {
+        DemSmoothing::Mean => Vec2D::new(3, 3, 1.0),
+        DemSmoothing::Gaussian { sigma } => {
+            let size = 2 * (3.0 * sigma).ceil() as usize + 1;
+            Vec2D::from_fn(size, size, |i, j| {
+                let (dx, dy) = (i as f64 - (size / 2) as f64, j as f64 - (size / 2) as f64);
+                (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+            })
+        }
+    };
+    let radius = (kernel.width() / 2) as isize;
+
+    let (w, h) = (grid.width() as isize, grid.height() as isize);
+    let mut result = grid.clone();
+    for _ in 0..iterations {
+        let source = result.clone();
+        for (x, y, v) in result.iter_mut() {
+            if v.is_nan() {
+                continue;
+            }
+            let mut sum = 0.0;
+            let mut weights = 0.0;
+            for (i, j, k) in kernel.iter() {
+                let xx = x as isize + i as isize - radius;
+                let yy = y as isize + j as isize - radius;
+                if xx < 0 || yy < 0 || xx >= w || yy >= h {
+                    continue;
+                }
+                let value = source[(xx as usize, yy as usize)];
+                if !value.is_nan() {
+                    sum += k * value;
+                    weights += k;
+                }
+            }
+            *v = sum / weights;
+        }
+    }
+    result
+}
+
+/// Returns the contour band an elevation falls in, i.e. the number of whole intervals below it.
+pub fn contour_band(z: f64, interval: f64) -> i64 {
+    (z / interval).floor() as i64
+}
+
+/// Returns the contour levels from the band containing `hmin` up to, but not including, `hmax`.
+/// Each level is computed from its band number instead of by repeated addition, so non-integer
+/// intervals do not accumulate floating point drift over many bands.
+pub fn contour_levels(hmin: f64, hmax: f64, interval: f64) -> impl Iterator<Item = f64> {
+    (contour_band(hmin, interval)..)
+        .map(move |band| band as f64 * interval)
+        .take_while(move |&level| level < hmax)
+}
+
+/// The 3x3 mean used to smooth the heightmap in [`surface_passes`].
+pub const SMOOTH_KERNEL: [[f64; 3]; 3] = [[1.0 / 9.0; 3]; 3];
+
+/// The 3x3 kernels of the gradient along x and y (Horn's method), like the hillshade. Their
+/// results are 8 times the rise over one cell.
+const DZDX_KERNEL: [[f64; 3]; 3] = [
+    [-1.0, 0.0, 1.0], //
+    [-2.0, 0.0, 2.0],
+    [-1.0, 0.0, 1.0],
+];
+const DZDY_KERNEL: [[f64; 3]; 3] = [
+    [-1.0, -2.0, -1.0], //
+    [0.0, 0.0, 0.0],
+    [1.0, 2.0, 1.0],
+];
+
+/// Returns the slope (rise/run) of each cell of the heightmap, from the gradient over its 3x3
+/// neighborhood.
+pub fn slope_grid(hmap: &HeightMap) -> Vec2D<f64> {
+    let dzdx = hmap.grid.convolve3x3(&DZDX_KERNEL);
+    let dzdy = hmap.grid.convolve3x3(&DZDY_KERNEL);
+    Vec2D::from_fn(hmap.grid.width(), hmap.grid.height(), |x, y| {
+        dzdx[(x, y)].hypot(dzdy[(x, y)]) / (8.0 * hmap.scale)
+    })
+}
+
+/// The per cell results of [`surface_passes`], on the grid of the heightmap.
+#[derive(Debug, Clone)]
+pub struct SurfacePasses {
+    /// The heightmap smoothed with [`SMOOTH_KERNEL`].
+    pub smoothed: Vec2D<f64>,
+    /// The slope of the (unsmoothed) heightmap, see [`slope_grid`].
+    pub slope: Vec2D<f64>,
+    /// The [`contour_band`] of the smoothed elevation.
+    pub band: Vec2D<i64>,
+}
+
+/// Compute the smoothed elevation, the slope and the contour band of every cell in a single
+/// traversal of the heightmap, in the order the grid is stored. The results are the same as
+/// those of the separate passes ([`Vec2D::convolve3x3`] with [`SMOOTH_KERNEL`], [`slope_grid`]
+/// and [`contour_band`]), but each 3x3 neighborhood is read once instead of three times.
+pub fn surface_passes(hmap: &HeightMap, interval: f64) -> SurfacePasses {
+    let grid = &hmap.grid;
+    let (w, h) = grid.dimensions();
+    let mut smoothed = Vec2D::new(w, h, 0.0);
+    let mut slope = Vec2D::new(w, h, 0.0);
+    let mut band = Vec2D::new(w, h, 0);
+
+    for x in 0..w {
+        for y in 0..h {
+            // same neighborhood and summation order as convolve3x3, so the results match exactly
+            let (mut mean, mut dzdx, mut dzdy) = (0.0, 0.0, 0.0);
+            for j in 0..3 {
+                let yy = (y + j).saturating_sub(1).min(h - 1);
+                for i in 0..3 {
+                    let xx = (x + i).saturating_sub(1).min(w - 1);
+                    let z = grid[(xx, yy)];
+                    mean += SMOOTH_KERNEL[j][i] * z;
+                    dzdx += DZDX_KERNEL[j][i] * z;
+                    dzdy += DZDY_KERNEL[j][i] * z;
+                }
+            }
+            smoothed[(x, y)] = mean;
+            slope[(x, y)] = dzdx.hypot(dzdy) / (8.0 * hmap.scale);
+            band[(x, y)] = contour_band(mean, interval);
+        }
+    }
+
+    SurfacePasses {
+        smoothed,
+        slope,
+        band,
+    }
+}
+
+/// Selects the vertices of the intermediate contours (half an interval between the regular
+/// contours) that are kept as form lines: where the slope (rise/run) of the heightmap is below
+/// `max_slope` and the nearest regular contour is farther than `min_distance`, as the regular
+/// contours are far apart there.
+pub struct FormLineSelector {
+    slope: Vec2D<f64>,
+    /// The distance in meters from each cell of the heightmap to the nearest regular contour,
+    /// within half a cell as the contours are rasterized onto the grid.
+    contour_distance: Vec2D<f64>,
+    xoffset: f64,
+    yoffset: f64,
+    scale: f64,
+    max_slope: f64,
+    min_distance: f64,
+}
+
+impl FormLineSelector {
+    pub fn new(regular: &[Contour], hmap: &HeightMap, max_slope: f64, min_distance: f64) -> Self {
+        let (w, h) = hmap.grid.dimensions();
+        let mut on_contour = Vec2D::new(w, h, false);
+        let mut mark = |(x, y): (f64, f64)| {
+            let xx = ((x - hmap.xoffset) / hmap.scale).round();
+            let yy = ((y - hmap.yoffset) / hmap.scale).round();
+            if xx >= 0.0 && yy >= 0.0 && xx < w as f64 && yy < h as f64 {
+                on_contour[(xx as usize, yy as usize)] = true;
+            }
+        };
+        for contour in regular {
+            for s in contour.points.windows(2) {
+                // every half cell along the segment, so that no cell it crosses is skipped
+                let (dx, dy) = (s[1].0 - s[0].0, s[1].1 - s[0].1);
+                let steps = (2.0 * dx.hypot(dy) / hmap.scale).ceil().max(1.0) as usize;
+                for i in 0..=steps {
+                    let t = i as f64 / steps as f64;
+                    mark((s[0].0 + t * dx, s[0].1 + t * dy));
+                }
+            }
+            if let [point] = contour.points[..] {
+                mark(point);
+            }
+        }
+        let mut contour_distance = distance_transform(&on_contour);
+        for (_, _, d) in contour_distance.iter_mut() {
+            *d *= hmap.scale;
+        }
+
+        Self {
+            slope: slope_grid(hmap),
+            contour_distance,
+            xoffset: hmap.xoffset,
+            yoffset: hmap.yoffset,
+            scale: hmap.scale,
+            max_slope,
+            min_distance,
+        }
+    }
+
+    /// Whether the vertex `(x, y)` of an intermediate contour is kept as a form line.
+    pub fn keep(&self, x: f64, y: f64) -> bool {
+        let (xx, yy) = (
+            (x - self.xoffset) / self.scale,
+            (y - self.yoffset) / self.scale,
+        );
+        !x.is_nan()
+            && self.slope.sample_bilinear(xx, yy) < self.max_slope
+            && self.contour_distance[(
+                (xx.round().max(0.0) as usize).min(self.contour_distance.width() - 1),
+                (yy.round().max(0.0) as usize).min(self.contour_distance.height() - 1),
+            )] > self.min_distance
+    }
+}
+
+/// Select the parts of the intermediate contours that are kept as form lines, the runs of vertices
+/// kept by [`FormLineSelector`]. Runs of a single vertex are dropped.
+pub fn form_lines(
+    intermediate: &[Contour],
+    regular: &[Contour],
+    hmap: &HeightMap,
+    max_slope: f64,
+    min_distance: f64,
+) -> Vec<Contour> {
+    let selector = FormLineSelector::new(regular, hmap, max_slope, min_distance);
+
+    let mut result = Vec::new();
+    for contour in intermediate {
+        let mut run = Vec::new();
+        for &(x, y) in contour
+            .points
+            .iter()
+            .chain(std::iter::once(&(f64::NAN, f64::NAN)))
+        {
+            if selector.keep(x, y) {
+                run.push((x, y));
+                continue;
+            }
+            if run.len() > 1 {
+                result.push(Contour {
+                    points: std::mem::take(&mut run),
+                    elevation: contour.elevation,
+                    index: false,
+                    form_line: true,
+                });
+            }
+            run.clear();
+        }
+    }
+    result
+}
+
+/// Distance from the point `p` to the segment from `a` to `b`.
+pub(crate) fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (p.0 - a.0 - t * dx).hypot(p.1 - a.1 - t * dy)
+}
+
 /// Creates contour lines from a heightmap.
 pub fn heightmap2contours(
     fs: &impl FileSystem,
@@ -233,16 +543,120 @@ pub fn heightmap2contours(
     cinterval: f64,
     heightmap: &HeightMap,
     dxffile: &str,
+    cancel: &CancellationToken,
 ) -> Result<(), Box<dyn Error>> {
     let mut avg_alt = heightmap.grid.clone();
-    let w = heightmap.grid.width() - 1;
-    let h = heightmap.grid.height() - 1;
-    let xmin = heightmap.xoffset;
-    let ymin = heightmap.yoffset;
-    let xmax = heightmap.maxx();
-    let ymax = heightmap.maxy();
-    let size = heightmap.scale;
+    nudge_off_levels(&mut avg_alt, cinterval);
+
+    // compute hmin and hmax
+    let mut hmin: f64 = f64::MAX;
+    let mut hmax: f64 = f64::MIN;
+    for (_, _, h) in avg_alt.iter() {
+        if h < hmin {
+            hmin = h;
+        }
+        if h > hmax {
+            hmax = h;
+        }
+    }
+
+    let levels = contour_levels(hmin, hmax, cinterval).collect::<Vec<_>>();
+    let lines = trace_contours(&avg_alt, &levels, cinterval, cancel)?;
+    write_contours(
+        fs,
+        tmpfolder,
+        &lines,
+        (heightmap.xoffset, heightmap.yoffset),
+        (heightmap.maxx(), heightmap.maxy()),
+        heightmap.scale,
+        dxffile,
+    )
+}
+
+/// Creates contour lines from the heightmap file at `path` like [`heightmap2contours`], but reads
+/// the grid in strips of `block_size` columns so that only one strip is in memory at a time. The
+/// strips overlap by one column, which holds the corners of the cells on both sides of the seam,
+/// and the traced segments are joined into lines over the whole heightmap, so the contours are the
+/// same as from the whole grid. The segments of all levels are kept until the end.
+#[allow(clippy::too_many_arguments)]
+pub fn heightmap_file2contours(
+    fs: &impl FileSystem,
+    tmpfolder: &Path,
+    cinterval: f64,
+    path: &Path,
+    block_size: usize,
+    dxffile: &str,
+    cancel: &CancellationToken,
+) -> Result<(), Box<dyn Error>> {
+    // a first pass for the range of the elevations
+    let mut hmin: f64 = f64::MAX;
+    let mut hmax: f64 = f64::MIN;
+    let mut hmap = HeightMapStrips::new(BufReader::new(fs.open(path)?), block_size)?;
+    while let Some((_, mut strip)) = hmap.strips.next_strip()? {
+        cancel.check()?;
+        nudge_off_levels(&mut strip, cinterval);
+        hmin = hmin.min(strip.min_ignore_nan().unwrap_or(f64::MAX));
+        hmax = hmax.max(strip.max_ignore_nan().unwrap_or(f64::MIN));
+    }
+    let levels = contour_levels(hmin, hmax, cinterval).collect::<Vec<_>>();
+
+    let mut hmap = HeightMapStrips::new(BufReader::new(fs.open(path)?), block_size)?;
+    let (w, h) = hmap.strips.dimensions();
+    let mut tracers = levels
+        .iter()
+        .map(|_| LevelTracer::default())
+        .collect::<Vec<_>>();
+    while let Some((x0, mut strip)) = hmap.strips.next_strip()? {
+        cancel.check()?;
+        nudge_off_levels(&mut strip, cinterval);
+        // the cells of the strip that the whole grid traces, see `trace_level`
+        let start = 1.max(x0) - x0;
+        let end = w.saturating_sub(2).min(x0 + strip.width() - 1).max(x0) - x0;
+        let trace = |(tracer, &level): (&mut LevelTracer, &f64)| {
+            tracer.trace_cells(&strip, x0, start..end, level, cinterval)
+        };
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            tracers
+                .par_iter_mut()
+                .zip(levels.par_iter())
+                .for_each(trace);
+        }
+        #[cfg(not(feature = "rayon"))]
+        tracers.iter_mut().zip(levels.iter()).for_each(trace);
+        report_steps(Stage::Contours, x0 + strip.width(), w);
+    }
 
+    let mut lines = tracers
+        .into_iter()
+        .enumerate()
+        .flat_map(|(level_index, tracer)| {
+            tracer
+                .into_lines()
+                .into_iter()
+                .map(move |line| (level_index, line))
+        })
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|(level_index, line)| (*level_index, line[0]));
+
+    let scale = hmap.scale;
+    write_contours(
+        fs,
+        tmpfolder,
+        &lines,
+        (hmap.xoffset, hmap.yoffset),
+        (
+            hmap.xoffset + scale * w.saturating_sub(1) as f64,
+            hmap.yoffset + scale * h.saturating_sub(1) as f64,
+        ),
+        scale,
+        dxffile,
+    )
+}
+
+/// Move the elevations that are (almost) exactly at a contour level slightly off it.
+fn nudge_off_levels(avg_alt: &mut Vec2D<f64>, cinterval: f64) {
     // As per https://github.com/karttapullautin/karttapullautin/discussions/154#discussioncomment-11393907
     // If elevation grid point elavion equals with contour interval steps you will get contour topology issues
     // (crossing/touching contours). This was implemented to avoid that. 0.02 (two centimeters) is just a random
@@ -259,41 +673,167 @@ pub fn heightmap2contours(
             }
         }
     }
+}
 
-    // compute hmin and hmax
-    let mut hmin: f64 = f64::MAX;
-    let mut hmax: f64 = f64::MIN;
-    for (_, _, h) in avg_alt.iter() {
-        if h < hmin {
-            hmin = h;
-        }
-        if h > hmax {
-            hmax = h;
+/// Write the traced lines to `temp_polylines.txt` and as polylines to the dxf file `dxffile`, with
+/// the cells scaled by `size` from `min`.
+fn write_contours(
+    fs: &impl FileSystem,
+    tmpfolder: &Path,
+    lines: &[(usize, TracedLine)],
+    (xmin, ymin): (f64, f64),
+    (xmax, ymax): (f64, f64),
+    size: f64,
+    dxffile: &str,
+) -> Result<(), Box<dyn Error>> {
+    let polyline_out = tmpfolder.join("temp_polylines.txt");
+
+    let f = fs.create(&polyline_out).expect("Unable to create file");
+    let mut f = BufWriter::new(f);
+
+    for (_, line) in lines {
+        for &(x, y) in line {
+            write!(&mut f, "{},{};", x as f64 / 100.0, y as f64 / 100.0)
+                .expect("Cannot write to output file");
         }
+        f.write_all("\r\n".as_bytes())
+            .expect("Cannot write to output file");
     }
+    // explicitly flush and drop to close the file
+    drop(f);
 
-    let v = cinterval;
+    let f = fs
+        .create(tmpfolder.join(dxffile))
+        .expect("Unable to create file");
+    let mut f = BufWriter::new(f);
 
-    let mut level: f64 = (hmin / v).floor() * v;
-    let polyline_out = tmpfolder.join("temp_polylines.txt");
+    write!(
+        &mut f,
+        "  0\r\nSECTION\r\n  2\r\nHEADER\r\n  9\r\n$EXTMIN\r\n 10\r\n{}\r\n 20\r\n{}\r\n  9\r\n$EXTMAX\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\nENDSEC\r\n  0\r\nSECTION\r\n  2\r\nENTITIES\r\n  0\r\n",
+        xmin, ymin, xmax, ymax,
+    ).expect("Cannot write dxf file");
 
-    let f = fs.create(&polyline_out).expect("Unable to create file");
-    let mut f = BufWriter::new(f);
+    read_lines_no_alloc(fs, polyline_out, |line| {
+        let parts = line.trim().split(';');
+        let r = parts.collect::<Vec<&str>>();
+        f.write_all("POLYLINE\r\n 66\r\n1\r\n  8\r\ncont\r\n  0\r\n".as_bytes())
+            .expect("Cannot write dxf file");
+        for (i, d) in r.iter().enumerate() {
+            if d != &"" {
+                let ii = i + 1;
+                let ldata = r.len() - 2;
+                if ii > 5 && ii < ldata - 5 && ldata > 12 && ii % 2 == 0 {
+                    continue;
+                }
+                let mut xy_raw = d.split(',');
+                let x: f64 = xy_raw.next().unwrap().parse::<f64>().unwrap() * size + xmin;
+                let y: f64 = xy_raw.next().unwrap().parse::<f64>().unwrap() * size + ymin;
+                write!(
+                    &mut f,
+                    "VERTEX\r\n  8\r\ncont\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\n",
+                    x, y
+                )
+                .expect("Cannot write dxf file");
+            }
+        }
+        f.write_all("SEQEND\r\n  0\r\n".as_bytes())
+            .expect("Cannot write dxf file");
+    })
+    .expect("Cannot read file");
+    f.write_all("ENDSEC\r\n  0\r\nEOF\r\n".as_bytes())
+        .expect("Cannot write dxf file");
+    report(Stage::Contours, 1.0);
+    info!("Done");
 
-    loop {
-        if level >= hmax {
-            break;
+    Ok(())
+}
+
+/// A contour line as traced from the grid, with the coordinates in hundredths of a cell.
+pub type TracedLine = Vec<(i64, i64)>;
+
+/// Trace the contour lines of `grid` at each of `levels`, returning them with the index of their
+/// level. The levels are independent, so with the `rayon` feature they are traced in parallel. The
+/// lines are sorted by level and then by their first point, so the order does not depend on the
+/// thread scheduling. The remaining levels are skipped once `cancel` is cancelled.
+pub fn trace_contours(
+    grid: &Vec2D<f64>,
+    levels: &[f64],
+    interval: f64,
+    cancel: &CancellationToken,
+) -> Result<Vec<(usize, TracedLine)>, crate::error::Error> {
+    // the levels are traced a chunk at a time, so that the progress is reported and the token is
+    // checked on the calling thread between the chunks
+    #[cfg(feature = "rayon")]
+    let chunk_size = rayon::current_num_threads();
+    #[cfg(not(feature = "rayon"))]
+    let chunk_size = 1;
+
+    let mut lines = Vec::new();
+    for (chunk_index, chunk) in levels.chunks(chunk_size).enumerate() {
+        cancel.check()?;
+        let first = chunk_index * chunk_size;
+        let trace = |(i, &level): (usize, &f64)| {
+            trace_level(grid, level, interval)
+                .into_iter()
+                .map(move |line| (first + i, line))
+        };
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            lines.par_extend(chunk.par_iter().enumerate().flat_map_iter(trace));
         }
+        #[cfg(not(feature = "rayon"))]
+        lines.extend(chunk.iter().enumerate().flat_map(trace));
+        report_steps(Stage::Contours, first + chunk.len(), levels.len());
+    }
+
+    cancel.check()?;
+
+    lines.sort_by_key(|(level_index, line)| (*level_index, line[0]));
+    Ok(lines)
+}
 
-        let mut obj = Vec::<(i64, i64, u8)>::new();
-        let mut curves: HashMap<(i64, i64, u8), (i64, i64)> = HashMap::default();
+/// Trace the contour lines of `avg_alt` at `level` through the cells away from the edges.
+fn trace_level(avg_alt: &Vec2D<f64>, level: f64, v: f64) -> Vec<TracedLine> {
+    let mut tracer = LevelTracer::default();
+    tracer.trace_cells(avg_alt, 0, 1..(avg_alt.width() - 2), level, v);
+    tracer.into_lines()
+}
 
-        for i in 1..(w - 1) {
+/// The contour segments of one level, collected from one or more strips of the grid and joined into
+/// lines by [`LevelTracer::into_lines`].
+#[derive(Default)]
+struct LevelTracer {
+    obj: Vec<(i64, i64, u8)>,
+    curves: HashMap<(i64, i64, u8), (i64, i64)>,
+}
+
+impl LevelTracer {
+    /// Trace the segments through the cells with the x index in `cells` of `avg_alt`, a strip of
+    /// the whole grid that starts at column `x0`.
+    fn trace_cells(
+        &mut self,
+        avg_alt: &Vec2D<f64>,
+        x0: usize,
+        cells: std::ops::Range<usize>,
+        level: f64,
+        v: f64,
+    ) {
+        let h = avg_alt.height() - 1;
+        assert!(cells.end < avg_alt.width());
+        for i in cells {
+            let x = (x0 + i) as f64;
             for j in 2..(h - 1) {
-                let mut a = avg_alt[(i, j)];
-                let mut b = avg_alt[(i, j + 1)];
-                let mut c = avg_alt[(i + 1, j)];
-                let mut d = avg_alt[(i + 1, j + 1)];
+                // SAFETY: i + 1 is at most cells.end, which is checked above to be less than the
+                // width, and j + 1 is less than h - 1 and so less than the height
+                let (mut a, mut b, mut c, mut d) = unsafe {
+                    (
+                        *avg_alt.index_unchecked(i, j),
+                        *avg_alt.index_unchecked(i, j + 1),
+                        *avg_alt.index_unchecked(i + 1, j),
+                        *avg_alt.index_unchecked(i + 1, j + 1),
+                    )
+                };
 
                 if a < level && b < level && c < level && d < level
                     || a > level && b > level && c > level && d > level
@@ -338,113 +878,116 @@ pub fn heightmap2contours(
 
                     if a < b {
                         if level < b && level > a {
-                            let x1: f64 = i as f64;
+                            let x1: f64 = x;
                             let y1: f64 = j as f64 + (level - a) / (b - a);
                             if level > c {
-                                let x2: f64 = i as f64 + (b - level) / (b - c);
+                                let x2: f64 = x + (b - level) / (b - c);
                                 let y2: f64 = j as f64 + (level - c) / (b - c);
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             } else if level < c {
-                                let x2: f64 = i as f64 + (level - a) / (c - a);
+                                let x2: f64 = x + (level - a) / (c - a);
                                 let y2: f64 = j as f64;
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             }
                         }
                     } else if b < a && level < a && level > b {
-                        let x1: f64 = i as f64;
+                        let x1: f64 = x;
                         let y1: f64 = j as f64 + (a - level) / (a - b);
                         if level < c {
-                            let x2: f64 = i as f64 + (level - b) / (c - b);
+                            let x2: f64 = x + (level - b) / (c - b);
                             let y2: f64 = j as f64 + (c - level) / (c - b);
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         } else if level > c {
-                            let x2: f64 = i as f64 + (a - level) / (a - c);
+                            let x2: f64 = x + (a - level) / (a - c);
                             let y2: f64 = j as f64;
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         }
                     }
 
                     if a < c {
                         if level < c && level > a {
-                            let x1: f64 = i as f64 + (level - a) / (c - a);
+                            let x1: f64 = x + (level - a) / (c - a);
                             let y1: f64 = j as f64;
                             if level > b {
-                                let x2: f64 = i as f64 + (level - b) / (c - b);
+                                let x2: f64 = x + (level - b) / (c - b);
                                 let y2: f64 = j as f64 + (c - level) / (c - b);
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             }
                         }
                     } else if a > c && level < a && level > c {
-                        let x1: f64 = i as f64 + (a - level) / (a - c);
+                        let x1: f64 = x + (a - level) / (a - c);
                         let y1: f64 = j as f64;
                         if level < b {
-                            let x2: f64 = i as f64 + (b - level) / (b - c);
+                            let x2: f64 = x + (b - level) / (b - c);
                             let y2: f64 = j as f64 + (level - c) / (b - c);
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         }
                     }
 
                     if c < d {
                         if level < d && level > c {
-                            let x1: f64 = i as f64 + 1.0;
+                            let x1: f64 = x + 1.0;
                             let y1: f64 = j as f64 + (level - c) / (d - c);
                             if level < b {
-                                let x2: f64 = i as f64 + (b - level) / (b - c);
+                                let x2: f64 = x + (b - level) / (b - c);
                                 let y2: f64 = j as f64 + (level - c) / (b - c);
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             } else if level > b {
-                                let x2: f64 = i as f64 + (level - b) / (d - b);
+                                let x2: f64 = x + (level - b) / (d - b);
                                 let y2: f64 = j as f64 + 1.0;
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             }
                         }
                     } else if c > d && level < c && level > d {
-                        let x1: f64 = i as f64 + 1.0;
+                        let x1: f64 = x + 1.0;
                         let y1: f64 = j as f64 + (c - level) / (c - d);
                         if level > b {
-                            let x2: f64 = i as f64 + (level - b) / (c - b);
+                            let x2: f64 = x + (level - b) / (c - b);
                             let y2: f64 = j as f64 + (c - level) / (c - b);
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         } else if level < b {
-                            let x2: f64 = i as f64 + (b - level) / (b - d);
+                            let x2: f64 = x + (b - level) / (b - d);
                             let y2: f64 = j as f64 + 1.0;
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         }
                     }
 
                     if d < b {
                         if level < b && level > d {
-                            let x1: f64 = i as f64 + (b - level) / (b - d);
+                            let x1: f64 = x + (b - level) / (b - d);
                             let y1: f64 = j as f64 + 1.0;
                             if level > c {
-                                let x2: f64 = i as f64 + (b - level) / (b - c);
+                                let x2: f64 = x + (b - level) / (b - c);
                                 let y2: f64 = j as f64 + (level - c) / (b - c);
-                                check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                                check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                             }
                         }
                     } else if b < d && level < d && level > b {
-                        let x1: f64 = i as f64 + (level - b) / (d - b);
+                        let x1: f64 = x + (level - b) / (d - b);
                         let y1: f64 = j as f64 + 1.0;
                         if level < c {
-                            let x2: f64 = i as f64 + (level - b) / (c - b);
+                            let x2: f64 = x + (level - b) / (c - b);
                             let y2: f64 = j as f64 + (c - level) / (c - b);
-                            check_obj_in(&mut obj, &mut curves, x1, x2, y1, y2);
+                            check_obj_in(&mut self.obj, &mut self.curves, x1, x2, y1, y2);
                         }
                     }
                 }
             }
         }
+    }
 
+    /// Join the segments into lines.
+    fn into_lines(self) -> Vec<TracedLine> {
+        let LevelTracer { obj, mut curves } = self;
+        let mut lines = Vec::new();
         for k in obj.iter() {
             if curves.contains_key(k) {
                 let (x, y, _) = *k;
-                write!(&mut f, "{},{};", x as f64 / 100.0, y as f64 / 100.0)
-                    .expect("Cannot write to output file");
+                let mut line = vec![(x, y)];
                 let mut res = (x, y);
 
                 let (x, y) = *curves.get(k).unwrap();
-                write!(&mut f, "{},{};", x as f64 / 100.0, y as f64 / 100.0)
-                    .expect("Cannot write to output file");
+                line.push((x, y));
                 curves.remove(k);
 
                 let mut head = (x, y);
@@ -460,8 +1003,7 @@ pub fn heightmap2contours(
                         res = head;
 
                         let (x, y) = *curves.get(&(head.0, head.1, 1)).unwrap();
-                        write!(&mut f, "{},{};", x as f64 / 100.0, y as f64 / 100.0)
-                            .expect("Cannot write to output file");
+                        line.push((x, y));
                         curves.remove(&(head.0, head.1, 1));
 
                         head = (x, y);
@@ -475,8 +1017,7 @@ pub fn heightmap2contours(
                         res = head;
 
                         let (x, y) = *curves.get(&(head.0, head.1, 2)).unwrap();
-                        write!(&mut f, "{},{};", x as f64 / 100.0, y as f64 / 100.0)
-                            .expect("Cannot write to output file");
+                        line.push((x, y));
                         curves.remove(&(head.0, head.1, 2));
 
                         head = (x, y);
@@ -487,61 +1028,14 @@ pub fn heightmap2contours(
                             curves.remove(&(head.0, head.1, 2));
                         }
                     } else {
-                        f.write_all("\r\n".as_bytes())
-                            .expect("Cannot write to output file");
+                        lines.push(line);
                         break;
                     }
                 }
             }
         }
-        level += v;
+        lines
     }
-    // explicitly flush and drop to close the file
-    drop(f);
-
-    let f = fs
-        .create(tmpfolder.join(dxffile))
-        .expect("Unable to create file");
-    let mut f = BufWriter::new(f);
-
-    write!(
-        &mut f,
-        "  0\r\nSECTION\r\n  2\r\nHEADER\r\n  9\r\n$EXTMIN\r\n 10\r\n{}\r\n 20\r\n{}\r\n  9\r\n$EXTMAX\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\nENDSEC\r\n  0\r\nSECTION\r\n  2\r\nENTITIES\r\n  0\r\n",
-        xmin, ymin, xmax, ymax,
-    ).expect("Cannot write dxf file");
-
-    read_lines_no_alloc(fs, polyline_out, |line| {
-        let parts = line.trim().split(';');
-        let r = parts.collect::<Vec<&str>>();
-        f.write_all("POLYLINE\r\n 66\r\n1\r\n  8\r\ncont\r\n  0\r\n".as_bytes())
-            .expect("Cannot write dxf file");
-        for (i, d) in r.iter().enumerate() {
-            if d != &"" {
-                let ii = i + 1;
-                let ldata = r.len() - 2;
-                if ii > 5 && ii < ldata - 5 && ldata > 12 && ii % 2 == 0 {
-                    continue;
-                }
-                let mut xy_raw = d.split(',');
-                let x: f64 = xy_raw.next().unwrap().parse::<f64>().unwrap() * size + xmin;
-                let y: f64 = xy_raw.next().unwrap().parse::<f64>().unwrap() * size + ymin;
-                write!(
-                    &mut f,
-                    "VERTEX\r\n  8\r\ncont\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\n",
-                    x, y
-                )
-                .expect("Cannot write dxf file");
-            }
-        }
-        f.write_all("SEQEND\r\n  0\r\n".as_bytes())
-            .expect("Cannot write dxf file");
-    })
-    .expect("Cannot read file");
-    f.write_all("ENDSEC\r\n  0\r\nEOF\r\n".as_bytes())
-        .expect("Cannot write dxf file");
-    info!("Done");
-
-    Ok(())
 }
 
 fn check_obj_in(
@@ -579,3 +1073,372 @@ fn check_obj_in(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_contour_band() {
+        assert_eq!(contour_band(0.0, 2.5), 0);
+        assert_eq!(contour_band(2.49, 2.5), 0);
+        assert_eq!(contour_band(2.5, 2.5), 1);
+        assert_eq!(contour_band(7.5, 2.5), 3);
+        assert_eq!(contour_band(9.99, 2.5), 3);
+        assert_eq!(contour_band(10.0, 2.5), 4);
+        assert_eq!(contour_band(-0.1, 2.5), -1);
+    }
+
+    #[test]
+    fn test_contour_levels() {
+        let levels: Vec<f64> = contour_levels(0.0, 10.0, 2.5).collect();
+        assert_eq!(levels, vec![0.0, 2.5, 5.0, 7.5]);
+
+        let levels: Vec<f64> = contour_levels(1.0, 10.1, 2.5).collect();
+        assert_eq!(levels, vec![0.0, 2.5, 5.0, 7.5, 10.0]);
+
+        // no drift after many bands
+        let last = contour_levels(0.0, 1000.0, 0.1).last().unwrap();
+        assert_eq!(last, 9999.0 * 0.1);
+    }
+
+    #[test]
+    fn test_fill_nodata_plane() {
+        let mut grid = Vec2D::new(7, 7, 0.0);
+        for (x, y, v) in grid.iter_mut() {
+            *v = 10.0 + 2.0 * x as f64 - 0.5 * y as f64;
+        }
+        let expected = grid[(3, 3)];
+        grid[(3, 3)] = f64::NAN;
+
+        fill_nodata(&mut grid, 2);
+        assert!((grid[(3, 3)] - expected).abs() < 1e-9);
+        assert!(!grid.is_any_nan());
+    }
+
+    #[test]
+    fn test_fill_nodata_out_of_range() {
+        let mut grid = Vec2D::new(9, 9, 1.0);
+        for x in 1..8 {
+            for y in 1..8 {
+                grid[(x, y)] = f64::NAN;
+            }
+        }
+
+        fill_nodata(&mut grid, 2);
+        // the center is more than 2 cells away from any valid cell
+        assert!(grid[(4, 4)].is_nan());
+        // but the ring next to the border is filled
+        assert_eq!(grid[(1, 1)], 1.0);
+        assert_eq!(grid[(2, 4)], 1.0);
+    }
+
+    #[test]
+    fn test_heightmap_fills_holes_within_radius() {
+        use crate::io::fs::memory::MemoryFileSystem;
+        use crate::io::xyz::{XyzInternalWriter, XyzRecord};
+
+        let surface = |x: f64, y: f64| 100.0 + 5.0 * (x / 4.0).sin() + 0.01 * y * y;
+        let in_hole = |i: usize, j: usize| (8..11).contains(&i) && (6..9).contains(&j);
+        let fs = MemoryFileSystem::new();
+        let tmpfolder = Path::new("temp");
+        fs.create_dir_all(tmpfolder).unwrap();
+        let mut writer = XyzInternalWriter::new(BufWriter::new(
+            fs.create(tmpfolder.join("ground.xyz.bin")).unwrap(),
+        ));
+        // one point in the middle of each 2 m cell, except in the hole
+        for i in 0..20 {
+            for j in 0..16 {
+                if in_hole(i, j) {
+                    continue;
+                }
+                let (x, y) = (1.0 + 2.0 * i as f64, 1.0 + 2.0 * j as f64);
+                writer
+                    .write_record(&XyzRecord {
+                        x,
+                        y,
+                        z: surface(x, y),
+                        classification: 2,
+                        number_of_returns: 1,
+                        return_number: 1,
+                    })
+                    .unwrap();
+            }
+        }
+        writer.finish().unwrap();
+
+        let mut expected = Vec2D::from_fn(20, 16, |i, j| {
+            if in_hole(i, j) {
+                f64::NAN
+            } else {
+                surface(1.0 + 2.0 * i as f64, 1.0 + 2.0 * j as f64)
+            }
+        });
+        fill_nodata(&mut expected, 3);
+
+        let config = Config::from_toml_str("dem_fill_radius = 3").unwrap();
+        let filled = xyz2heightmap(&fs, &config, tmpfolder, "ground.xyz.bin").unwrap();
+        assert_eq!(filled.grid.dimensions(), expected.dimensions());
+        for (x, y, v) in expected.iter() {
+            assert!((filled.grid[(x, y)] - v).abs() < 1e-9, "at ({x}, {y})");
+        }
+
+        // without the filling, the hole is interpolated along the rows and columns instead
+        let config = Config::from_toml_str("").unwrap();
+        let interpolated = xyz2heightmap(&fs, &config, tmpfolder, "ground.xyz.bin").unwrap();
+        assert!((interpolated.grid[(9, 7)] - filled.grid[(9, 7)]).abs() > 1e-6);
+    }
+
+    #[test]
+    fn test_smooth_dem() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut grid = Vec2D::from_fn(40, 30, |x, y| {
+            100.0 + 0.5 * x as f64 - 0.2 * y as f64 + rng.gen_range(-1.0..1.0)
+        });
+        grid[(10, 10)] = f64::NAN;
+        grid[(11, 10)] = f64::NAN;
+
+        // the sum of the squared differences between neighboring cells
+        let roughness = |grid: &Vec2D<f64>| {
+            let mut sum = 0.0;
+            for (x, y, v) in grid.iter() {
+                if x > 0 && !v.is_nan() && !grid[(x - 1, y)].is_nan() {
+                    sum += (v - grid[(x - 1, y)]).powi(2);
+                }
+                if y > 0 && !v.is_nan() && !grid[(x, y - 1)].is_nan() {
+                    sum += (v - grid[(x, y - 1)]).powi(2);
+                }
+            }
+            sum
+        };
+
+        for smoothing in [DemSmoothing::Mean, DemSmoothing::Gaussian { sigma: 1.5 }] {
+            let unchanged = smooth_dem(&grid, smoothing, 0);
+            for (_, _, a, b) in unchanged.zip(&grid) {
+                assert!(a == b || a.is_nan() && b.is_nan());
+            }
+
+            let mut previous = roughness(&grid);
+            for iterations in 1..5 {
+                let smoothed = smooth_dem(&grid, smoothing, iterations);
+                assert!(smoothed[(10, 10)].is_nan() && smoothed[(11, 10)].is_nan());
+                assert_eq!(smoothed.iter().filter(|(_, _, v)| v.is_nan()).count(), 2);
+                let r = roughness(&smoothed);
+                assert!(
+                    r < previous,
+                    "{:?} {} {} {}",
+                    smoothing,
+                    iterations,
+                    r,
+                    previous
+                );
+                previous = r;
+            }
+        }
+    }
+
+    #[test]
+    fn test_surface_passes_match_separate_passes() {
+        let mut grid = Vec2D::from_fn(37, 23, |x, y| {
+            100.0 + 3.0 * (x as f64 * 0.3).sin() + 0.7 * y as f64 + 0.01 * (x * y) as f64
+        });
+        grid[(10, 5)] = f64::NAN;
+        let hmap = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid,
+        };
+
+        let passes = surface_passes(&hmap, 2.5);
+        let smoothed = hmap.grid.convolve3x3(&SMOOTH_KERNEL);
+        let slope = slope_grid(&hmap);
+        let same = |a: f64, b: f64| a == b || (a.is_nan() && b.is_nan());
+        for (x, y, v) in smoothed.iter() {
+            assert!(same(passes.smoothed[(x, y)], v), "smoothed at ({x}, {y})");
+            assert!(
+                same(passes.slope[(x, y)], slope[(x, y)]),
+                "slope at ({x}, {y})"
+            );
+            assert_eq!(passes.band[(x, y)], contour_band(v, 2.5));
+        }
+        assert!(passes.smoothed[(11, 6)].is_nan());
+        assert!(!passes.smoothed[(12, 6)].is_nan());
+    }
+
+    #[test]
+    fn test_trace_contours_match_serial_trace() {
+        // two hills, so that most levels have several lines
+        let grid = Vec2D::from_fn(60, 50, |x, y| {
+            let hill = |cx: f64, cy: f64, r: f64| {
+                let d2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                10.0 * (-d2 / (r * r)).exp()
+            };
+            100.0 + hill(18.0, 20.0, 9.0) + hill(42.0, 28.0, 12.0) + 0.01 * x as f64
+        });
+        let levels = contour_levels(100.0, 111.0, 1.25).collect::<Vec<_>>();
+
+        let cancel = CancellationToken::new();
+        let traced = trace_contours(&grid, &levels, 1.25, &cancel).unwrap();
+        let mut serial = Vec::new();
+        for (level_index, &level) in levels.iter().enumerate() {
+            for line in trace_level(&grid, level, 1.25) {
+                serial.push((level_index, line));
+            }
+        }
+        serial.sort_by_key(|(level_index, line)| (*level_index, line[0]));
+        assert!(serial.iter().filter(|(i, _)| *i == 5).count() >= 2);
+        assert_eq!(traced, serial);
+        assert_eq!(
+            trace_contours(&grid, &levels, 1.25, &cancel).unwrap(),
+            traced
+        );
+
+        cancel.cancel();
+        assert!(matches!(
+            trace_contours(&grid, &levels, 1.25, &cancel),
+            Err(crate::error::Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_tin_ground_model_follows_tilted_plane() {
+        use crate::io::fs::memory::MemoryFileSystem;
+        use crate::io::xyz::{XyzInternalWriter, XyzRecord};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let plane = |x: f64, y: f64| 200.0 + 0.8 * (x - 500000.0) + 0.3 * (y - 6700000.0);
+        let fs = MemoryFileSystem::new();
+        let tmpfolder = Path::new("temp");
+        fs.create_dir_all(tmpfolder).unwrap();
+        let mut writer = XyzInternalWriter::new(BufWriter::new(
+            fs.create(tmpfolder.join("ground.xyz.bin")).unwrap(),
+        ));
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..2400 {
+            let x = 500000.0 + rng.gen_range(0.0..60.0);
+            let y = 6700000.0 + rng.gen_range(0.0..40.0);
+            writer
+                .write_record(&XyzRecord {
+                    x,
+                    y,
+                    z: plane(x, y),
+                    classification: 2,
+                    number_of_returns: 1,
+                    return_number: 1,
+                })
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mean_error = |ground_model: &str| {
+            let config =
+                Config::from_toml_str(&format!("ground_model = \"{}\"", ground_model)).unwrap();
+            let hmap = xyz2heightmap(&fs, &config, tmpfolder, "ground.xyz.bin").unwrap();
+            let (w, h) = hmap.grid.dimensions();
+            let mut errors = Vec::new();
+            // away from the edges, where the cells are only partly covered
+            for x in 2..w - 2 {
+                for y in 2..h - 2 {
+                    let (cx, cy) = (
+                        hmap.xoffset + x as f64 * hmap.scale,
+                        hmap.yoffset + y as f64 * hmap.scale,
+                    );
+                    errors.push((hmap.grid[(x, y)] - plane(cx, cy)).abs());
+                }
+            }
+            errors.iter().sum::<f64>() / errors.len() as f64
+        };
+        let binning = mean_error("binning");
+        let tin = mean_error("tin");
+        assert!(tin < 1e-6, "{}", tin);
+        assert!(binning > 100.0 * tin, "{} {}", binning, tin);
+    }
+
+    #[test]
+    fn test_heightmap_file2contours_match_whole_grid() {
+        use crate::io::fs::memory::MemoryFileSystem;
+
+        // hills that span several strips, on a plane with cells exactly at the contour levels
+        let grid = Vec2D::from_fn(150, 120, |x, y| {
+            let hill = |cx: f64, cy: f64, r: f64| {
+                let d2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                12.0 * (-d2 / (r * r)).exp()
+            };
+            100.0 + hill(40.0, 50.0, 25.0) + hill(100.0, 70.0, 30.0) + 0.025 * x as f64
+        });
+        let hmap = HeightMap {
+            xoffset: 1000.0,
+            yoffset: 2000.0,
+            scale: 2.0,
+            grid,
+        };
+        let fs = MemoryFileSystem::new();
+        let tmpfolder = Path::new("temp");
+        fs.create_dir_all(tmpfolder).unwrap();
+        hmap.to_file(&fs, tmpfolder.join("xyz.hmap")).unwrap();
+        let cancel = CancellationToken::new();
+
+        heightmap2contours(&fs, tmpfolder, 1.25, &hmap, "whole.dxf", &cancel).unwrap();
+        let whole = fs.read_to_string(tmpfolder.join("whole.dxf")).unwrap();
+        assert!(whole.matches("POLYLINE").count() > 10);
+        for block_size in [2, 16, 149, 500] {
+            heightmap_file2contours(
+                &fs,
+                tmpfolder,
+                1.25,
+                &tmpfolder.join("xyz.hmap"),
+                block_size,
+                "chunked.dxf",
+                &cancel,
+            )
+            .unwrap();
+            let chunked = fs.read_to_string(tmpfolder.join("chunked.dxf")).unwrap();
+            assert!(chunked == whole, "block size {}", block_size);
+        }
+    }
+
+    /// A north-south line at `x` across the 120 m square of the test heightmaps.
+    fn line(x: f64, elevation: f64) -> Contour {
+        Contour {
+            points: (0..60).map(|i| (x, 2.0 * i as f64)).collect(),
+            elevation,
+            index: false,
+            form_line: false,
+        }
+    }
+
+    fn ramp(slope: f64) -> HeightMap {
+        HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid: Vec2D::from_fn(60, 60, |x, _| slope * 2.0 * x as f64),
+        }
+    }
+
+    #[test]
+    fn test_form_lines_on_gentle_slope() {
+        let hmap = ramp(0.05);
+        let regular = [line(10.0, 0.5), line(110.0, 5.5)];
+        let form = form_lines(&[line(60.0, 3.0)], &regular, &hmap, 0.15, 10.0);
+        assert_eq!(form.len(), 1);
+        assert!(form[0].form_line);
+        assert_eq!(form[0].elevation, 3.0);
+        assert_eq!(form[0].points.len(), 60);
+        assert!(form[0].points.iter().all(|&(x, _)| x == 60.0));
+
+        // too close to a regular contour
+        let regular = [line(55.0, 2.75)];
+        assert!(form_lines(&[line(60.0, 3.0)], &regular, &hmap, 0.15, 10.0).is_empty());
+    }
+
+    #[test]
+    fn test_no_form_lines_on_steep_slope() {
+        let hmap = ramp(0.5);
+        let regular = [line(10.0, 5.0), line(110.0, 55.0)];
+        assert!(form_lines(&[line(60.0, 30.0)], &regular, &hmap, 0.15, 10.0).is_empty());
+    }
+}
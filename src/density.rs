@@ -0,0 +1,137 @@
+use std::{
+    error::Error,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use image::{Rgb, RgbImage};
+
+use crate::config::Config;
+use crate::io::crs::write_prj;
+use crate::io::fs::FileSystem;
+use crate::io::heightmap::HeightMap;
+use crate::io::worldfile::write_world_file;
+use crate::io::xyz::{XyzInternalReader, XyzRecord};
+use crate::vec2d::Vec2D;
+
+/// The colors of the density ramp from no points to `density_max`, evenly spaced.
+const RAMP: [[u8; 3]; 5] = [
+    [0, 0, 255],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 255, 0],
+    [255, 0, 0],
+];
+
+/// Count the ground and water points (see [`Config::is_dem_class`]) binned into each cell of the
+/// heightmap `dem`, like the points used to build it. Points outside the grid are ignored.
+pub fn ground_point_counts(
+    records: impl IntoIterator<Item = XyzRecord>,
+    dem: &HeightMap,
+    config: &Config,
+) -> Vec2D<u32> {
+    let (w, h) = dem.grid.dimensions();
+    let mut counts = Vec2D::new(w, h, 0);
+    for r in records {
+        if !config.is_dem_class(r.classification) {
+            continue;
+        }
+        let idx_x = ((r.x - dem.xoffset) / dem.scale).floor();
+        let idx_y = ((r.y - dem.yoffset) / dem.scale).floor();
+        if idx_x < 0.0 || idx_y < 0.0 || idx_x >= w as f64 || idx_y >= h as f64 {
+            continue;
+        }
+        counts[(idx_x as usize, idx_y as usize)] += 1;
+    }
+    counts
+}
+
+/// Color of a point density (points per square meter) on a blue to red ramp, red from
+/// `density_max` up. The ramp does not depend on the tile so that tiles processed separately
+/// share the same colors.
+pub fn density_color(density: f64, density_max: f64) -> Rgb<u8> {
+    let t = (density / density_max).clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let i = (t.floor() as usize).min(RAMP.len() - 2);
+    let f = t - i as f64;
+    let (a, b) = (RAMP[i], RAMP[i + 1]);
+    Rgb([0, 1, 2].map(|c| (a[c] as f64 * (1.0 - f) + b[c] as f64 * f).round() as u8))
+}
+
+/// Write the density of the ground points of an internal xyz file in each cell of the heightmap
+/// `dem` as a north-up PNG on the ramp of [`density_color`], with a world file (and a `.prj` if
+/// `crs_epsg` is configured), to show the voids and the gaps between flightlines.
+pub fn write_density_png(
+    fs: &impl FileSystem,
+    config: &Config,
+    tmpfolder: &Path,
+    xyzfilein: &str,
+    dem: &HeightMap,
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut reader = XyzInternalReader::new(BufReader::new(fs.open(tmpfolder.join(xyzfilein))?))?;
+    let mut read_error = None;
+    let records = std::iter::from_fn(|| match reader.next() {
+        Ok(r) => r,
+        Err(e) => {
+            read_error = Some(e);
+            None
+        }
+    });
+    let counts = ground_point_counts(records, dem, config);
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
+
+    let (w, h) = counts.dimensions();
+    let area = dem.scale * dem.scale;
+    let img = RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let count = counts[(x as usize, h - 1 - y as usize)];
+        density_color(count as f64 / area, config.density_max)
+    });
+    img.write_to(
+        &mut BufWriter::new(fs.create(path)?),
+        image::ImageFormat::Png,
+    )?;
+
+    write_world_file(fs, path, dem.minx(), dem.maxy(), dem.scale)?;
+    write_prj(fs, path, config.crs_epsg)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_more_points_are_hotter() {
+        let dem = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 1.0,
+            grid: Vec2D::new(3, 1, 100.0),
+        };
+        let record = |x: f64, classification: u8| XyzRecord {
+            x,
+            y: 0.5,
+            z: 100.0,
+            classification,
+            number_of_returns: 1,
+            return_number: 1,
+        };
+        // 10 ground points in the first cell, 1 in the second and only vegetation in the third
+        let records = (0..10)
+            .map(|i| record(0.05 + 0.09 * i as f64, 2))
+            .chain([record(1.5, 2), record(2.5, 5)]);
+        let config = Config::from_toml_str("").unwrap();
+        let counts = ground_point_counts(records, &dem, &config);
+        assert_eq!((counts[(0, 0)], counts[(1, 0)], counts[(2, 0)]), (10, 1, 0));
+
+        let density_max = config.density_max;
+        let [ten, one, none] =
+            [0, 1, 2].map(|x| density_color(counts[(x, 0)] as f64, density_max).0);
+        assert!(ten[0] > one[0] && ten[2] < one[2], "{:?} {:?}", ten, one);
+        assert_eq!(none, [0, 0, 255]);
+        assert_eq!(density_color(1000.0, density_max).0, [255, 0, 0]);
+    }
+}
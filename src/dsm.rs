@@ -0,0 +1,108 @@
+use std::{error::Error, io::BufReader, path::Path};
+
+use crate::io::fs::FileSystem;
+use crate::io::heightmap::HeightMap;
+use crate::io::xyz::{XyzInternalReader, XyzRecord};
+use crate::vec2d::Vec2D;
+
+/// Build a digital surface model (the top of the canopy and buildings) on the same grid as the
+/// heightmap `dem`: each cell is the highest z of the first returns binned into it, NaN for cells
+/// without first returns. Points outside the grid are ignored.
+pub fn surface_model(records: impl IntoIterator<Item = XyzRecord>, dem: &HeightMap) -> HeightMap {
+    let (w, h) = dem.grid.dimensions();
+    let mut grid = Vec2D::new(w, h, f64::NAN);
+    for r in records {
+        if r.return_number != 1 {
+            continue;
+        }
+        let idx_x = ((r.x - dem.xoffset) / dem.scale).floor();
+        let idx_y = ((r.y - dem.yoffset) / dem.scale).floor();
+        if idx_x < 0.0 || idx_y < 0.0 || idx_x >= w as f64 || idx_y >= h as f64 {
+            continue;
+        }
+        let v = &mut grid[(idx_x as usize, idx_y as usize)];
+        if v.is_nan() || r.z > *v {
+            *v = r.z;
+        }
+    }
+    HeightMap {
+        xoffset: dem.xoffset,
+        yoffset: dem.yoffset,
+        scale: dem.scale,
+        grid,
+    }
+}
+
+/// Height of the surface above the ground, `dsm - dem` for each cell. Cells where either is NaN
+/// are NaN.
+pub fn canopy_height(dsm: &HeightMap, dem: &HeightMap) -> HeightMap {
+    let mut height = dsm.clone();
+    for (x, y, v) in height.grid.iter_mut() {
+        *v -= dem.grid[(x, y)];
+    }
+    height
+}
+
+/// Read the points of an internal xyz file and build its surface model on the grid of `dem`, see
+/// [`surface_model`].
+pub fn xyz2dsm(
+    fs: &impl FileSystem,
+    tmpfolder: &Path,
+    xyzfilein: &str,
+    dem: &HeightMap,
+) -> Result<HeightMap, Box<dyn Error>> {
+    let mut reader = XyzInternalReader::new(BufReader::new(fs.open(tmpfolder.join(xyzfilein))?))?;
+    let mut read_error = None;
+    let records = std::iter::from_fn(|| match reader.next() {
+        Ok(r) => r,
+        Err(e) => {
+            read_error = Some(e);
+            None
+        }
+    });
+    let dsm = surface_model(records, dem);
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
+    Ok(dsm)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(x: f64, y: f64, z: f64, classification: u8, return_number: u8) -> XyzRecord {
+        XyzRecord {
+            x,
+            y,
+            z,
+            classification,
+            number_of_returns: 2,
+            return_number,
+        }
+    }
+
+    #[test]
+    fn test_canopy_height() {
+        let dem = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid: Vec2D::new(2, 1, 10.0),
+        };
+        let records = [
+            record(0.5, 0.5, 10.0, 2, 2),
+            record(1.0, 1.0, 25.0, 5, 1),
+            record(1.5, 0.5, 20.0, 5, 1),
+            // not a first return
+            record(0.5, 1.5, 30.0, 5, 2),
+        ];
+        let dsm = surface_model(records, &dem);
+        assert_eq!(dsm.grid[(0, 0)], 25.0);
+        assert!(dsm.grid[(1, 0)].is_nan());
+
+        let height = canopy_height(&dsm, &dem);
+        assert_eq!(height.grid[(0, 0)], 15.0);
+        assert!(height.grid[(1, 0)].is_nan());
+    }
+}
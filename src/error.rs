@@ -0,0 +1,132 @@
+use std::{fmt, path::PathBuf};
+
+use crate::vec2d::Vec2D;
+
+/// Errors that make processing a single tile fail, so that a batch can skip the tile and continue
+/// with the others.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// The input file is not a valid LAS/LAZ file.
+    InvalidLasHeader(String),
+    /// The input file format is not supported.
+    UnsupportedInput(PathBuf),
+    /// The tile has no ground points to build a heightmap from.
+    EmptyTile,
+    /// The points of the tile cover too small an area to process.
+    InsufficientPoints { points: usize },
+    /// The heightmap has no valid cells.
+    AllNoData,
+    /// The given DEM does not overlap the point cloud.
+    DemOutsideTile,
+    /// Some cells of the heightmap are still missing after interpolation.
+    PartialNoData { cells: usize },
+    /// Processing was cancelled with a [`crate::cancel::CancellationToken`].
+    Cancelled,
+    /// Any other error from a processing stage.
+    Other(Box<dyn std::error::Error>),
+}
+
+impl Error {
+    /// Check that a grid has no NaN (no-data) cells, returning [`Error::AllNoData`] if all cells are
+    /// NaN or [`Error::PartialNoData`] if some are.
+    pub fn check_no_data(grid: &Vec2D<f64>) -> Result<(), Error> {
+        if !grid.is_any_nan() {
+            return Ok(());
+        }
+        let cells = grid.iter().filter(|(_, _, v)| v.is_nan()).count();
+        if cells == grid.len() {
+            Err(Error::AllNoData)
+        } else {
+            Err(Error::PartialNoData { cells })
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::InvalidLasHeader(e) => write!(f, "invalid LAS/LAZ file: {}", e),
+            Error::UnsupportedInput(path) => {
+                write!(f, "unsupported input file: {}", path.display())
+            }
+            Error::EmptyTile => write!(f, "the tile has no ground points"),
+            Error::InsufficientPoints { points } => write!(
+                f,
+                "the {} point(s) of the tile cover too small an area to process",
+                points
+            ),
+            Error::AllNoData => write!(f, "the heightmap has no valid cells"),
+            Error::DemOutsideTile => write!(f, "the DEM does not overlap the point cloud"),
+            Error::PartialNoData { cells } => {
+                write!(f, "the heightmap has {} cells without data", cells)
+            }
+            Error::Cancelled => write!(f, "processing was cancelled"),
+            Error::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    // errors that were boxed on the way up keep their variant
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        match e.downcast::<Error>() {
+            Ok(e) => *e,
+            Err(e) => match e.downcast::<std::io::Error>() {
+                Ok(e) => Error::Io(*e),
+                Err(e) => Error::Other(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_no_data() {
+        let mut grid = Vec2D::new(3, 2, 1.0);
+        assert!(Error::check_no_data(&grid).is_ok());
+
+        grid[(1, 1)] = f64::NAN;
+        grid[(2, 0)] = f64::NAN;
+        assert!(matches!(
+            Error::check_no_data(&grid),
+            Err(Error::PartialNoData { cells: 2 })
+        ));
+
+        grid.fill(f64::NAN);
+        assert!(matches!(Error::check_no_data(&grid), Err(Error::AllNoData)));
+    }
+
+    #[test]
+    fn test_boxed_error_keeps_variant() {
+        let boxed: Box<dyn std::error::Error> = Error::EmptyTile.into();
+        assert!(matches!(Error::from(boxed), Error::EmptyTile));
+
+        let boxed: Box<dyn std::error::Error> = std::io::Error::other("disk full").into();
+        assert!(matches!(Error::from(boxed), Error::Io(_)));
+
+        let boxed: Box<dyn std::error::Error> = "something else".into();
+        assert!(matches!(Error::from(boxed), Error::Other(_)));
+    }
+}
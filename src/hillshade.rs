@@ -0,0 +1,132 @@
+use std::{error::Error, io::BufWriter, path::Path};
+
+use image::{GrayAlphaImage, LumaA};
+
+use crate::io::crs::write_prj;
+use crate::io::fs::FileSystem;
+use crate::io::heightmap::HeightMap;
+use crate::io::worldfile::write_world_file;
+use crate::vec2d::Vec2D;
+
+/// Value of the cells without data in the hillshade layer. Valid cells are shaded in `1..=255`.
+pub const HILLSHADE_NO_DATA: u8 = 0;
+
+/// Compute the illumination of each cell of a DEM lit by a sun at `azimuth_deg` (clockwise from
+/// north) and `altitude_deg` (above the horizon). The gradient is taken over the 3x3 neighborhood
+/// (Horn's method), with x growing east and y growing north like in [`HeightMap`]. Fully lit cells
+/// are 255, cells in full shadow are 1 and cells whose neighborhood has NaN are
+/// [`HILLSHADE_NO_DATA`].
+pub fn hillshade(
+    grid: &Vec2D<f64>,
+    cell_size: f64,
+    azimuth_deg: f64,
+    altitude_deg: f64,
+) -> Vec2D<u8> {
+    let dzdx = grid.convolve3x3(&[
+        [-1.0, 0.0, 1.0], //
+        [-2.0, 0.0, 2.0],
+        [-1.0, 0.0, 1.0],
+    ]);
+    let dzdy = grid.convolve3x3(&[
+        [-1.0, -2.0, -1.0], //
+        [0.0, 0.0, 0.0],
+        [1.0, 2.0, 1.0],
+    ]);
+
+    let azimuth = azimuth_deg.to_radians();
+    let altitude = altitude_deg.to_radians();
+    let sun = [
+        azimuth.sin() * altitude.cos(),
+        azimuth.cos() * altitude.cos(),
+        altitude.sin(),
+    ];
+
+    let mut result = Vec2D::new(grid.width(), grid.height(), HILLSHADE_NO_DATA);
+    for (x, y, v) in result.iter_mut() {
+        let gx = dzdx[(x, y)] / (8.0 * cell_size);
+        let gy = dzdy[(x, y)] / (8.0 * cell_size);
+        if gx.is_nan() || gy.is_nan() {
+            continue;
+        }
+        // the surface normal is (-gx, -gy, 1)
+        let light = (-gx * sun[0] - gy * sun[1] + sun[2]) / (gx * gx + gy * gy + 1.0).sqrt();
+        *v = (1.0 + light.max(0.0) * 254.0).round() as u8;
+    }
+    result
+}
+
+/// Write the hillshade of a heightmap as a north-up grayscale PNG with a world file (and a `.prj`
+/// if `crs_epsg` is given). Cells without data are transparent.
+pub fn write_hillshade_png(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    heightmap: &HeightMap,
+    azimuth_deg: f64,
+    altitude_deg: f64,
+    crs_epsg: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let shade = hillshade(&heightmap.grid, heightmap.scale, azimuth_deg, altitude_deg);
+
+    let (w, h) = shade.dimensions();
+    let img = GrayAlphaImage::from_fn(w as u32, h as u32, |x, y| {
+        match shade[(x as usize, h - 1 - y as usize)] {
+            HILLSHADE_NO_DATA => LumaA([0, 0]),
+            v => LumaA([v, 255]),
+        }
+    });
+    img.write_to(
+        &mut BufWriter::new(fs.create(path)?),
+        image::ImageFormat::Png,
+    )?;
+
+    write_world_file(
+        fs,
+        path,
+        heightmap.minx(),
+        heightmap.maxy(),
+        heightmap.scale,
+    )?;
+    write_prj(fs, path, crs_epsg)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flat_grid_is_uniform() {
+        let grid = Vec2D::new(5, 4, 100.0);
+        let shade = hillshade(&grid, 2.0, 315.0, 45.0);
+        let expected = (1.0 + 45f64.to_radians().sin() * 254.0).round() as u8;
+        assert!(shade.iter().all(|(_, _, v)| v == expected));
+    }
+
+    #[test]
+    fn test_slope_is_lit_from_the_sun_side() {
+        // rises towards the east, so the slope faces west
+        let mut grid = Vec2D::new(5, 5, 0.0);
+        for (x, _, v) in grid.iter_mut() {
+            *v = x as f64;
+        }
+        let flat = hillshade(&Vec2D::new(5, 5, 0.0), 1.0, 270.0, 45.0)[(2, 2)];
+        let west_sun = hillshade(&grid, 1.0, 270.0, 45.0)[(2, 2)];
+        let east_sun = hillshade(&grid, 1.0, 90.0, 45.0)[(2, 2)];
+        let north_sun = hillshade(&grid, 1.0, 0.0, 45.0)[(2, 2)];
+        assert!(west_sun > flat);
+        assert!(east_sun < flat);
+        assert!(east_sun < north_sun && north_sun < west_sun);
+    }
+
+    #[test]
+    fn test_nan_is_no_data() {
+        let mut grid = Vec2D::new(5, 5, 10.0);
+        grid[(0, 0)] = f64::NAN;
+        let shade = hillshade(&grid, 1.0, 315.0, 45.0);
+        assert_eq!(shade[(0, 0)], HILLSHADE_NO_DATA);
+        assert_eq!(shade[(1, 1)], HILLSHADE_NO_DATA);
+        assert_ne!(shade[(2, 2)], HILLSHADE_NO_DATA);
+        assert_ne!(shade[(4, 4)], HILLSHADE_NO_DATA);
+    }
+}
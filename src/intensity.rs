@@ -0,0 +1,122 @@
+use std::{error::Error, io::BufWriter, path::Path};
+
+use image::{GrayAlphaImage, LumaA};
+
+use crate::config::Config;
+use crate::io::crs::{write_prj, InputTransform};
+use crate::io::fs::FileSystem;
+use crate::io::worldfile::write_world_file;
+use crate::vec2d::{AccumGrid, Vec2D};
+
+/// Bin the intensities of the points `(x, y, intensity)` into a `w` x `h` grid of `cell` sized
+/// cells starting at `(xmin, ymin)`, with the same cell indexing as the heightmap. Each cell is the
+/// mean intensity of its points, NaN for cells without points. Points outside the grid are
+/// ignored.
+pub fn intensity_grid(
+    points: impl IntoIterator<Item = (f64, f64, u16)>,
+    xmin: f64,
+    ymin: f64,
+    cell: f64,
+    w: usize,
+    h: usize,
+) -> Vec2D<f64> {
+    let mut accum = AccumGrid::new(w, h);
+    for (x, y, intensity) in points {
+        let idx_x = ((x - xmin) / cell).floor();
+        let idx_y = ((y - ymin) / cell).floor();
+        if idx_x < 0.0 || idx_y < 0.0 || idx_x >= w as f64 || idx_y >= h as f64 {
+            continue;
+        }
+        accum.add(idx_x as usize, idx_y as usize, intensity as f64);
+    }
+    accum.finalize()
+}
+
+/// Gray level of a mean intensity, scaled over the full 16 bit range so that tiles processed
+/// separately share the same scale. NaN (no data) has no gray level.
+pub fn intensity_gray(intensity: f64) -> Option<u8> {
+    if intensity.is_nan() {
+        return None;
+    }
+    Some((intensity / u16::MAX as f64 * 255.0).round() as u8)
+}
+
+/// Write the mean intensity of the points of a LAS/LAZ file as a north-up grayscale PNG with a
+/// world file (and a `.prj` if `crs_epsg` is configured). The grid covers `bounds`
+/// (`[min_x, min_y, max_x, max_y]` after the coordinate factors) in cells of `dem_resolution`.
+/// Cells without points are transparent.
+pub fn write_intensity_png(
+    fs: &impl FileSystem,
+    config: &Config,
+    input_file: &Path,
+    bounds: [f64; 4],
+    path: impl AsRef<Path>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let cell = config.dem_resolution;
+    let xmin = (bounds[0] / cell).floor() * cell;
+    let ymin = (bounds[1] / cell).floor() * cell;
+    let w = ((bounds[2] - xmin) / cell).floor() as usize + 1;
+    let h = ((bounds[3] - ymin) / cell).floor() as usize + 1;
+
+    let transform = InputTransform::new(config)?;
+    let mut read_error = None;
+    let points = crate::io::las::points(fs, input_file, None)?.map_while(|p| match p {
+        Ok(p) => {
+            let (x, y) = transform.apply(p.x * config.xfactor, p.y * config.yfactor);
+            Some((x, y, p.intensity))
+        }
+        Err(e) => {
+            read_error = Some(e);
+            None
+        }
+    });
+    let grid = intensity_grid(points, xmin, ymin, cell, w, h);
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
+
+    let img = GrayAlphaImage::from_fn(w as u32, h as u32, |x, y| {
+        match intensity_gray(grid[(x as usize, h - 1 - y as usize)]) {
+            Some(v) => LumaA([v, 255]),
+            None => LumaA([0, 0]),
+        }
+    });
+    img.write_to(
+        &mut BufWriter::new(fs.create(path)?),
+        image::ImageFormat::Png,
+    )?;
+
+    write_world_file(fs, path, xmin, ymin + cell * (h - 1) as f64, cell)?;
+    write_prj(fs, path, config.crs_epsg)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cell_mean_is_mid_gray() {
+        let grid = intensity_grid(
+            [(0.5, 0.5, 0), (1.5, 1.5, u16::MAX), (1.2, 1.8, 0)],
+            0.0,
+            0.0,
+            2.0,
+            2,
+            2,
+        );
+        assert_eq!(grid[(0, 0)], u16::MAX as f64 / 3.0);
+        assert!(grid[(1, 1)].is_nan());
+
+        let grid = intensity_grid([(0.5, 0.5, 0), (1.5, 1.5, u16::MAX)], 0.0, 0.0, 2.0, 1, 1);
+        assert_eq!(intensity_gray(grid[(0, 0)]), Some(128));
+        assert_eq!(intensity_gray(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_points_outside_are_ignored() {
+        let grid = intensity_grid([(-0.5, 0.5, 10), (4.5, 0.5, 10)], 0.0, 0.0, 2.0, 2, 1);
+        assert!(grid[(0, 0)].is_nan() && grid[(1, 0)].is_nan());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{fs::FileSystem, heightmap::HeightMap};
+use crate::vec2d::Vec2D;
+
+/// The no-data value written by [`write_asc`] for the NaN cells.
+pub const ASC_NODATA: f64 = -9999.0;
+
+/// Read an ESRI ASCII grid (.asc) as a heightmap. The offsets of the heightmap are the center of
+/// the south-western cell, given either as `xllcenter`/`yllcenter` or as the corner of the cell
+/// with `xllcorner`/`yllcorner`. Cells with the `NODATA_value` become NaN.
+pub fn read_asc(fs: &impl FileSystem, path: impl AsRef<Path>) -> std::io::Result<HeightMap> {
+    decode_asc(&fs.read_to_string(path)?)
+}
+
+/// Write a heightmap as an ESRI ASCII grid (.asc), with the NaN cells as [`ASC_NODATA`].
+pub fn write_asc(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    heightmap: &HeightMap,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(fs.create(path)?);
+    let (w, h) = heightmap.grid.dimensions();
+    let half = heightmap.scale / 2.0;
+    writeln!(writer, "ncols {}", w)?;
+    writeln!(writer, "nrows {}", h)?;
+    writeln!(writer, "xllcorner {}", heightmap.xoffset - half)?;
+    writeln!(writer, "yllcorner {}", heightmap.yoffset - half)?;
+    writeln!(writer, "cellsize {}", heightmap.scale)?;
+    writeln!(writer, "NODATA_value {}", ASC_NODATA)?;
+    // the first row is the northern edge, which is the last row of the grid
+    for y in (0..h).rev() {
+        let row = heightmap
+            .grid
+            .row(y)
+            .map(|&v| if v.is_nan() { ASC_NODATA } else { v }.to_string())
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", row.join(" "))?;
+    }
+    writer.flush()
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Decode the text of an ESRI ASCII grid, see [`read_asc`].
+fn decode_asc(text: &str) -> std::io::Result<HeightMap> {
+    let mut lines = text.lines().peekable();
+
+    // the header lines are `key value` pairs, the data starts at the first line starting with a
+    // number
+    let mut header = Vec::new();
+    while let Some(line) = lines.peek() {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) if key.parse::<f64>().is_err() => {
+                header.push((key.to_ascii_lowercase(), value));
+                lines.next();
+            }
+            _ => break,
+        }
+    }
+    let value = |key: &str| -> std::io::Result<Option<f64>> {
+        header
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| {
+                v.parse::<f64>()
+                    .map_err(|_| invalid(format!("invalid {} in the ASCII grid: {}", key, v)))
+            })
+            .transpose()
+    };
+    let required = |key: &str| {
+        value(key)?.ok_or_else(|| invalid(format!("missing {} in the ASCII grid", key)))
+    };
+
+    let w = required("ncols")? as usize;
+    let h = required("nrows")? as usize;
+    let scale = required("cellsize")?;
+    let nodata = value("nodata_value")?;
+    let (xoffset, yoffset) = match (value("xllcenter")?, value("yllcenter")?) {
+        (Some(x), Some(y)) => (x, y),
+        _ => (
+            required("xllcorner")? + scale / 2.0,
+            required("yllcorner")? + scale / 2.0,
+        ),
+    };
+
+    let mut values = lines.flat_map(str::split_whitespace).map(|v| {
+        v.parse::<f64>()
+            .map(|v| if Some(v) == nodata { f64::NAN } else { v })
+            .map_err(|_| invalid(format!("invalid value in the ASCII grid: {}", v)))
+    });
+    let mut rows = Vec::with_capacity(h);
+    for _ in 0..h {
+        let row = values
+            .by_ref()
+            .take(w)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        if row.len() < w {
+            return Err(invalid("unexpected end of the ASCII grid"));
+        }
+        rows.push(row);
+    }
+    // the first row is the northern edge, which is the last row of the grid
+    rows.reverse();
+
+    Ok(HeightMap {
+        xoffset,
+        yoffset,
+        scale,
+        grid: Vec2D::from_rows(rows).map_err(|e| invalid(e.to_string()))?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    #[test]
+    fn test_asc_roundtrip() {
+        let fs = MemoryFileSystem::new();
+        fs.create("dem.asc")
+            .unwrap()
+            .write_all(
+                b"ncols 3\nNROWS 2\nxllcorner 500000.0\nyllcorner 6700000\ncellsize 2\n\
+                  NODATA_value -9999\n1.5 2 -9999\n4 5.25 6\n",
+            )
+            .unwrap();
+
+        let hmap = read_asc(&fs, "dem.asc").unwrap();
+        assert_eq!(
+            (hmap.xoffset, hmap.yoffset, hmap.scale),
+            (500001.0, 6700001.0, 2.0)
+        );
+        assert_eq!(hmap.grid.dimensions(), (3, 2));
+        assert_eq!(hmap.grid[(0, 0)], 4.0);
+        assert_eq!(hmap.grid[(0, 1)], 1.5);
+        assert_eq!(hmap.grid[(1, 0)], 5.25);
+        assert!(hmap.grid[(2, 1)].is_nan());
+
+        write_asc(&fs, "out.asc", &hmap).unwrap();
+        assert_eq!(
+            fs.read_to_string("out.asc").unwrap(),
+            "ncols 3\nnrows 2\nxllcorner 500000\nyllcorner 6700000\ncellsize 2\n\
+             NODATA_value -9999\n1.5 2 -9999\n4 5.25 6\n"
+        );
+        let read = read_asc(&fs, "out.asc").unwrap();
+        assert_eq!(
+            (read.xoffset, read.yoffset, read.scale),
+            (hmap.xoffset, hmap.yoffset, hmap.scale)
+        );
+        for (_, _, a, b) in read.grid.zip(&hmap.grid) {
+            assert!(a == b || a.is_nan() && b.is_nan());
+        }
+    }
+
+    #[test]
+    fn test_read_asc_rejects_truncated_grid() {
+        let fs = MemoryFileSystem::new();
+        fs.create("dem.asc")
+            .unwrap()
+            .write_all(b"ncols 3\nnrows 2\nxllcenter 0\nyllcenter 0\ncellsize 1\n1 2 3\n4 5\n")
+            .unwrap();
+        assert!(read_asc(&fs, "dem.asc").is_err());
+    }
+}
@@ -41,6 +41,28 @@ impl FromToBytes for u64 {
     }
 }
 
+impl FromToBytes for u8 {
+    fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut buff = [0; 1];
+        reader.read_exact(&mut buff)?;
+        Ok(buff[0])
+    }
+    fn to_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[*self])
+    }
+}
+
+impl FromToBytes for i16 {
+    fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut buff = [0; 2];
+        reader.read_exact(&mut buff)?;
+        Ok(i16::from_ne_bytes(buff))
+    }
+    fn to_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&self.to_ne_bytes())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -65,4 +87,19 @@ mod test {
         42usize.to_bytes(&mut buff).unwrap();
         assert_eq!(usize::from_bytes(&mut buff.as_slice()).unwrap(), 42);
     }
+
+    #[test]
+    fn test_u8() {
+        let mut buff = Vec::new();
+        42u8.to_bytes(&mut buff).unwrap();
+        assert_eq!(buff.len(), 1);
+        assert_eq!(u8::from_bytes(&mut buff.as_slice()).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_i16() {
+        let mut buff = Vec::new();
+        (-42i16).to_bytes(&mut buff).unwrap();
+        assert_eq!(i16::from_bytes(&mut buff.as_slice()).unwrap(), -42);
+    }
 }
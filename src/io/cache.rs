@@ -0,0 +1,137 @@
+use std::{
+    hash::{Hash, Hasher},
+    io::{BufReader, BufWriter},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use log::{debug, warn};
+use rustc_hash::FxHasher;
+
+use super::{bytes::FromToBytes, fs::FileSystem};
+
+/// Compute a cache key from the path and modification time of the input file together with any
+/// other values (such as config parameters) that affect the cached result.
+pub fn cache_key(
+    fs: &impl FileSystem,
+    input: impl AsRef<Path>,
+    params: impl Hash,
+) -> std::io::Result<u64> {
+    let input = input.as_ref();
+    let modified = fs
+        .modified(input)?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut hasher = FxHasher::default();
+    input.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    params.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Load a value from `{folder}/{name}_{key}.bin` if it exists and can be read, otherwise compute
+/// it with `build` and store it there for the next run. A cache file that cannot be read (for
+/// example written by an older version) is ignored and replaced.
+pub fn load_or_build<T, E>(
+    fs: &impl FileSystem,
+    folder: impl AsRef<Path>,
+    name: &str,
+    key: u64,
+    build: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E>
+where
+    T: FromToBytes,
+    E: From<std::io::Error>,
+{
+    let folder = folder.as_ref();
+    let path = folder.join(format!("{}_{:016x}.bin", name, key));
+
+    if fs.exists(&path) {
+        let mut reader = BufReader::new(fs.open(&path)?);
+        match T::from_bytes(&mut reader) {
+            Ok(value) => {
+                debug!("Loaded {} from cache {}", name, path.display());
+                return Ok(value);
+            }
+            Err(e) => warn!("Ignoring invalid cache file {}: {}", path.display(), e),
+        }
+    }
+
+    let value = build()?;
+    fs.create_dir_all(folder)?;
+    let mut writer = BufWriter::new(fs.create(&path)?);
+    value.to_bytes(&mut writer)?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, io::Write};
+
+    use crate::{
+        io::{fs::memory::MemoryFileSystem, heightmap::HeightMap},
+        vec2d::Vec2D,
+    };
+
+    use super::*;
+
+    fn heightmap() -> HeightMap {
+        let grid = Vec2D::from_fn(4, 3, |x, y| (x * 10 + y) as f64);
+        HeightMap {
+            xoffset: 100.0,
+            yoffset: 200.0,
+            scale: 2.0,
+            grid,
+        }
+    }
+
+    #[test]
+    fn test_second_run_reads_from_cache() {
+        let fs = MemoryFileSystem::new();
+        fs.create("points.laz").unwrap().write_all(b"LASF").unwrap();
+
+        let builds = Cell::new(0);
+        let build = || -> std::io::Result<HeightMap> {
+            builds.set(builds.get() + 1);
+            Ok(heightmap())
+        };
+
+        let key = cache_key(&fs, "points.laz", 1.0f64.to_bits()).unwrap();
+        let first = load_or_build(&fs, "cache", "dem", key, build).unwrap();
+        let second = load_or_build(&fs, "cache", "dem", key, build).unwrap();
+        assert_eq!(builds.get(), 1);
+        assert_eq!(first, second);
+
+        // different parameters miss the cache
+        let other = cache_key(&fs, "points.laz", 2.0f64.to_bits()).unwrap();
+        assert_ne!(key, other);
+        load_or_build(&fs, "cache", "dem", other, build).unwrap();
+        assert_eq!(builds.get(), 2);
+    }
+
+    #[test]
+    fn test_invalid_cache_file_is_rebuilt() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("cache").unwrap();
+        fs.create(format!("cache/dem_{:016x}.bin", 42))
+            .unwrap()
+            .write_all(b"garbage")
+            .unwrap();
+
+        let builds = Cell::new(0);
+        let build = || -> std::io::Result<HeightMap> {
+            builds.set(builds.get() + 1);
+            Ok(heightmap())
+        };
+        assert_eq!(
+            load_or_build(&fs, "cache", "dem", 42, build).unwrap(),
+            heightmap()
+        );
+        assert_eq!(
+            load_or_build(&fs, "cache", "dem", 42, build).unwrap(),
+            heightmap()
+        );
+        assert_eq!(builds.get(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use log::warn;
+
+use super::fs::FileSystem;
+use crate::config::Config;
+
+/// A spheroid of a datum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spheroid {
+    pub name: &'static str,
+    pub semi_major_axis: f64,
+    pub inverse_flattening: f64,
+}
+
+const GRS_1980: Spheroid = Spheroid {
+    name: "GRS_1980",
+    semi_major_axis: 6378137.0,
+    inverse_flattening: 298.257222101,
+};
+const WGS_1984: Spheroid = Spheroid {
+    name: "WGS_1984",
+    semi_major_axis: 6378137.0,
+    inverse_flattening: 298.257223563,
+};
+
+/// The scale on the central meridian of all the known transverse Mercator projections.
+pub const TM_SCALE_FACTOR: f64 = 0.9996;
+/// The false easting of all the known transverse Mercator projections.
+pub const TM_FALSE_EASTING: f64 = 500000.0;
+
+/// A geographic coordinate system, named as in ESRI WKT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geographic {
+    pub name: &'static str,
+    pub datum: &'static str,
+    pub spheroid: Spheroid,
+}
+
+/// A coordinate system known to [`definition`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    Geographic(Geographic),
+    TransverseMercator {
+        name: String,
+        geographic: Geographic,
+        /// In degrees.
+        central_meridian: f64,
+        false_northing: f64,
+    },
+}
+
+/// The OGC URN naming an EPSG coordinate reference system, as used in the GeoJSON `crs` member.
+pub fn urn(epsg: u32) -> String {
+    format!("urn:ogc:def:crs:EPSG::{}", epsg)
+}
+
+/// Check if the EPSG code is a geographic (latitude/longitude) coordinate system that is known to
+/// [`wkt`].
+pub fn is_geographic(epsg: u32) -> bool {
+    matches!(definition(epsg), Some(Definition::Geographic(_)))
+}
+
+/// The definition of the coordinate system. Only the systems commonly used for orienteering maps
+/// are known: ETRS89 / TM35FIN, SWEREF99 TM, the ETRS89 and WGS 84 UTM zones, and ETRS89 and
+/// WGS 84 geographic coordinates.
+pub fn definition(epsg: u32) -> Option<Definition> {
+    let etrs89 = Geographic {
+        name: "GCS_ETRS_1989",
+        datum: "D_ETRS_1989",
+        spheroid: GRS_1980,
+    };
+    let wgs84 = Geographic {
+        name: "GCS_WGS_1984",
+        datum: "D_WGS_1984",
+        spheroid: WGS_1984,
+    };
+    let tm = |name: String, geographic, central_meridian, false_northing| {
+        Some(Definition::TransverseMercator {
+            name,
+            geographic,
+            central_meridian,
+            false_northing,
+        })
+    };
+    match epsg {
+        3067 => tm("ETRS_1989_TM35FIN".into(), etrs89, 27.0, 0.0),
+        3006 => tm(
+            "SWEREF99_TM".into(),
+            Geographic {
+                name: "GCS_SWEREF99",
+                datum: "D_SWEREF99",
+                spheroid: GRS_1980,
+            },
+            15.0,
+            0.0,
+        ),
+        25828..=25838 => tm(
+            format!("ETRS_1989_UTM_Zone_{}N", epsg - 25800),
+            etrs89,
+            utm_central_meridian(epsg - 25800),
+            0.0,
+        ),
+        32601..=32660 => tm(
+            format!("WGS_1984_UTM_Zone_{}N", epsg - 32600),
+            wgs84,
+            utm_central_meridian(epsg - 32600),
+            0.0,
+        ),
+        32701..=32760 => tm(
+            format!("WGS_1984_UTM_Zone_{}S", epsg - 32700),
+            wgs84,
+            utm_central_meridian(epsg - 32700),
+            10000000.0,
+        ),
+        4258 => Some(Definition::Geographic(etrs89)),
+        4326 => Some(Definition::Geographic(wgs84)),
+        _ => None,
+    }
+}
+
+/// The ESRI flavoured WKT of the coordinate system, as written to `.prj` files, for the systems
+/// known to [`definition`].
+pub fn wkt(epsg: u32) -> Option<String> {
+    Some(match definition(epsg)? {
+        Definition::Geographic(geographic) => geogcs(&geographic),
+        Definition::TransverseMercator {
+            name,
+            geographic,
+            central_meridian,
+            false_northing,
+        } => format!(
+            concat!(
+                r#"PROJCS["{}",{},PROJECTION["Transverse_Mercator"],"#,
+                r#"PARAMETER["False_Easting",{:.1}],PARAMETER["False_Northing",{:.1}],"#,
+                r#"PARAMETER["Central_Meridian",{:.1}],PARAMETER["Scale_Factor",{}],"#,
+                r#"PARAMETER["Latitude_Of_Origin",0.0],UNIT["Meter",1.0]]"#
+            ),
+            name,
+            geogcs(&geographic),
+            TM_FALSE_EASTING,
+            false_northing,
+            central_meridian,
+            TM_SCALE_FACTOR
+        ),
+    })
+}
+
+fn utm_central_meridian(zone: u32) -> f64 {
+    zone as f64 * 6.0 - 183.0
+}
+
+fn geogcs(geographic: &Geographic) -> String {
+    format!(
+        concat!(
+            r#"GEOGCS["{}",DATUM["{}",SPHEROID["{}",{:?},{:?}]],PRIMEM["Greenwich",0.0],"#,
+            r#"UNIT["Degree",0.0174532925199433]]"#
+        ),
+        geographic.name,
+        geographic.datum,
+        geographic.spheroid.name,
+        geographic.spheroid.semi_major_axis,
+        geographic.spheroid.inverse_flattening
+    )
+}
+
+/// Write the `.prj` sidecar declaring the coordinate system of an output file, next to the file
+/// itself. Nothing is written without an EPSG code, and a warning is logged if the code is not
+/// known to [`wkt`].
+pub fn write_prj(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    epsg: Option<u32>,
+) -> std::io::Result<()> {
+    let Some(epsg) = epsg else {
+        return Ok(());
+    };
+    let Some(wkt) = wkt(epsg) else {
+        warn!("No WKT known for EPSG:{}, not writing a .prj file", epsg);
+        return Ok(());
+    };
+    let mut writer = BufWriter::new(fs.create(path.as_ref().with_extension("prj"))?);
+    writer.write_all(wkt.as_bytes())?;
+    writer.flush()
+}
+
+/// The transform of the input coordinates from the `source_epsg` to the `crs_epsg` of the config,
+/// see [`super::reproject::Transform`]. Without a `source_epsg` the coordinates are kept as they are.
+pub struct InputTransform {
+    #[cfg(feature = "reproject")]
+    transform: Option<super::reproject::Transform>,
+}
+
+impl InputTransform {
+    pub fn new(config: &Config) -> Result<InputTransform, String> {
+        #[cfg(feature = "reproject")]
+        let transform = match (config.source_epsg, config.crs_epsg) {
+            (Some(from), Some(to)) => Some(super::reproject::Transform::new(from, to)?),
+            _ => None,
+        };
+        #[cfg(not(feature = "reproject"))]
+        if config.source_epsg.is_some() {
+            return Err("source_epsg needs pullauta built with the `reproject` feature".into());
+        }
+        Ok(InputTransform {
+            #[cfg(feature = "reproject")]
+            transform,
+        })
+    }
+
+    /// Transform the point `(x, y)`.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        #[cfg(feature = "reproject")]
+        if let Some(transform) = &self.transform {
+            return transform.transform(x, y);
+        }
+        (x, y)
+    }
+
+    /// Transform the `[min_x, min_y, max_x, max_y]` bounds, to the bounds of the transformed
+    /// edges. The edges are sampled, as they are curved after the transform.
+    pub fn apply_bounds(&self, bounds: [f64; 4]) -> [f64; 4] {
+        const STEPS: usize = 16;
+        let [min_x, min_y, max_x, max_y] = bounds;
+        let mut result = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+        for i in 0..=STEPS {
+            let t = i as f64 / STEPS as f64;
+            let (x, y) = (min_x + t * (max_x - min_x), min_y + t * (max_y - min_y));
+            for (x, y) in [(x, min_y), (x, max_y), (min_x, y), (max_x, y)] {
+                let (x, y) = self.apply(x, y);
+                result = [
+                    result[0].min(x),
+                    result[1].min(y),
+                    result[2].max(x),
+                    result[3].max(y),
+                ];
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    #[test]
+    fn test_write_prj_tm35fin() {
+        let fs = MemoryFileSystem::new();
+        write_prj(&fs, "tile.png", Some(3067)).unwrap();
+        assert_eq!(
+            fs.read_to_string("tile.prj").unwrap(),
+            concat!(
+                r#"PROJCS["ETRS_1989_TM35FIN",GEOGCS["GCS_ETRS_1989",DATUM["D_ETRS_1989","#,
+                r#"SPHEROID["GRS_1980",6378137.0,298.257222101]],PRIMEM["Greenwich",0.0],"#,
+                r#"UNIT["Degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],"#,
+                r#"PARAMETER["False_Easting",500000.0],PARAMETER["False_Northing",0.0],"#,
+                r#"PARAMETER["Central_Meridian",27.0],PARAMETER["Scale_Factor",0.9996],"#,
+                r#"PARAMETER["Latitude_Of_Origin",0.0],UNIT["Meter",1.0]]"#
+            )
+        );
+        assert_eq!(urn(3067), "urn:ogc:def:crs:EPSG::3067");
+    }
+
+    #[test]
+    fn test_write_prj_without_known_crs() {
+        let fs = MemoryFileSystem::new();
+        write_prj(&fs, "none.png", None).unwrap();
+        write_prj(&fs, "unknown.png", Some(2393)).unwrap();
+        assert!(!fs.exists("none.prj"));
+        assert!(!fs.exists("unknown.prj"));
+    }
+
+    #[test]
+    fn test_utm_zones() {
+        let prj = wkt(25835).unwrap();
+        assert!(prj.contains(r#"PROJCS["ETRS_1989_UTM_Zone_35N""#));
+        assert!(prj.contains(r#"PARAMETER["Central_Meridian",27.0]"#));
+
+        let prj = wkt(32733).unwrap();
+        assert!(prj.contains(r#"PARAMETER["Central_Meridian",15.0]"#));
+        assert!(prj.contains(r#"PARAMETER["False_Northing",10000000.0]"#));
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::Write;
+
+use crate::contours::Contour;
+
+/// Layer used for index contours by [`write_contours_dxf`].
+pub const CONTOUR_INDEX_LAYER: &str = "contour_index";
+/// Layer used for all other contours by [`write_contours_dxf`].
+pub const CONTOUR_LAYER: &str = "contour";
+/// Layer used for form lines by [`write_contours_dxf`].
+pub const FORM_LINE_LAYER: &str = "formline";
+
+/// Write contours as a minimal R12 DXF file. Every contour becomes a `POLYLINE` entity with its
+/// elevation in the entity elevation field (group code 38), and index contours are placed on
+/// [`CONTOUR_INDEX_LAYER`], form lines on [`FORM_LINE_LAYER`] and the others on
+/// [`CONTOUR_LAYER`].
+pub fn write_contours_dxf<W: Write>(writer: &mut W, contours: &[Contour]) -> std::io::Result<()> {
+    let mut xmin = f64::MAX;
+    let mut ymin = f64::MAX;
+    let mut xmax = f64::MIN;
+    let mut ymax = f64::MIN;
+    for &(x, y) in contours.iter().flat_map(|c| c.points.iter()) {
+        xmin = xmin.min(x);
+        ymin = ymin.min(y);
+        xmax = xmax.max(x);
+        ymax = ymax.max(y);
+    }
+    if xmin > xmax {
+        // no points at all
+        (xmin, ymin, xmax, ymax) = (0.0, 0.0, 0.0, 0.0);
+    }
+
+    write!(
+        writer,
+        "  0\r\nSECTION\r\n  2\r\nHEADER\r\n  9\r\n$ACADVER\r\n  1\r\nAC1009\r\n  9\r\n$EXTMIN\r\n 10\r\n{}\r\n 20\r\n{}\r\n  9\r\n$EXTMAX\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\nENDSEC\r\n",
+        xmin, ymin, xmax, ymax,
+    )?;
+
+    write!(
+        writer,
+        "  0\r\nSECTION\r\n  2\r\nTABLES\r\n  0\r\nTABLE\r\n  2\r\nLAYER\r\n 70\r\n3\r\n"
+    )?;
+    for layer in [CONTOUR_LAYER, CONTOUR_INDEX_LAYER, FORM_LINE_LAYER] {
+        write!(
+            writer,
+            "  0\r\nLAYER\r\n  2\r\n{}\r\n 70\r\n0\r\n 62\r\n7\r\n  6\r\nCONTINUOUS\r\n",
+            layer
+        )?;
+    }
+    write!(writer, "  0\r\nENDTAB\r\n  0\r\nENDSEC\r\n")?;
+
+    write!(writer, "  0\r\nSECTION\r\n  2\r\nENTITIES\r\n")?;
+    for contour in contours {
+        let layer = if contour.form_line {
+            FORM_LINE_LAYER
+        } else if contour.index {
+            CONTOUR_INDEX_LAYER
+        } else {
+            CONTOUR_LAYER
+        };
+        write!(
+            writer,
+            "  0\r\nPOLYLINE\r\n  8\r\n{}\r\n 66\r\n1\r\n 10\r\n0.0\r\n 20\r\n0.0\r\n 30\r\n{}\r\n 38\r\n{}\r\n",
+            layer, contour.elevation, contour.elevation
+        )?;
+        for (x, y) in contour.points.iter() {
+            write!(
+                writer,
+                "  0\r\nVERTEX\r\n  8\r\n{}\r\n 10\r\n{}\r\n 20\r\n{}\r\n 30\r\n{}\r\n",
+                layer, x, y, contour.elevation
+            )?;
+        }
+        write!(writer, "  0\r\nSEQEND\r\n  8\r\n{}\r\n", layer)?;
+    }
+    write!(writer, "  0\r\nENDSEC\r\n  0\r\nEOF\r\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parse a DXF file into its (group code, value) pairs.
+    fn parse_pairs(dxf: &str) -> Vec<(i32, &str)> {
+        let lines: Vec<&str> = dxf.split("\r\n").collect();
+        lines
+            .chunks_exact(2)
+            .map(|pair| (pair[0].trim().parse().unwrap(), pair[1]))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_contours_dxf() {
+        let contours = vec![
+            Contour {
+                points: vec![(1.0, 2.0), (3.0, 4.0)],
+                elevation: 102.5,
+                index: false,
+                form_line: false,
+            },
+            Contour {
+                points: vec![(-1.0, 5.0), (2.0, 6.0), (-1.0, 5.0)],
+                elevation: 125.0,
+                index: true,
+                form_line: false,
+            },
+            Contour {
+                points: vec![(0.0, 3.0), (1.0, 3.0)],
+                elevation: 105.0,
+                index: false,
+                form_line: true,
+            },
+        ];
+
+        let mut buff = Vec::new();
+        write_contours_dxf(&mut buff, &contours).unwrap();
+        let dxf = String::from_utf8(buff).unwrap();
+        let pairs = parse_pairs(&dxf);
+
+        // header
+        let version = pairs.iter().position(|p| *p == (9, "$ACADVER")).unwrap();
+        assert_eq!(pairs[version + 1], (1, "AC1009"));
+        let extmin = pairs.iter().position(|p| *p == (9, "$EXTMIN")).unwrap();
+        assert_eq!(pairs[extmin + 1], (10, "-1"));
+        assert_eq!(pairs[extmin + 2], (20, "2"));
+        let extmax = pairs.iter().position(|p| *p == (9, "$EXTMAX")).unwrap();
+        assert_eq!(pairs[extmax + 1], (10, "3"));
+        assert_eq!(pairs[extmax + 2], (20, "6"));
+
+        // entities
+        let polylines: Vec<usize> = pairs
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p == (0, "POLYLINE"))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(polylines.len(), 3);
+        assert_eq!(pairs.iter().filter(|p| **p == (0, "VERTEX")).count(), 7);
+        assert_eq!(pairs[polylines[0] + 1], (8, CONTOUR_LAYER));
+        assert_eq!(pairs[polylines[1] + 1], (8, CONTOUR_INDEX_LAYER));
+        assert_eq!(pairs[polylines[2] + 1], (8, FORM_LINE_LAYER));
+        assert!(pairs[polylines[1]..].contains(&(38, "125")));
+        assert_eq!(pairs.last(), Some(&(0, "EOF")));
+    }
+}
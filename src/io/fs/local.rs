@@ -1,5 +1,6 @@
 use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use super::FileSystem;
 
@@ -47,6 +48,10 @@ impl FileSystem for LocalFileSystem {
         Ok(metadata.len())
     }
 
+    fn modified(&self, path: impl AsRef<Path>) -> Result<SystemTime, io::Error> {
+        std::fs::metadata(path)?.modified()
+    }
+
     fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), io::Error> {
         std::fs::copy(from, to)?;
         Ok(())
@@ -5,6 +5,7 @@ use core::str;
 use std::io::{self, Read, Seek, Write};
 use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 
 /// An in-memory implementation of [`FileSystem`] for use whenever there is no access to a local
 /// file system (such as on WASM), or to speed up the processing when there is a lot of RAM available.
@@ -195,17 +196,18 @@ impl Drop for WritableFile {
     fn drop(&mut self) {
         let data = core::mem::replace(&mut self.data, io::Cursor::new(Vec::new()));
         let mut data_link = self.data_link.write().expect("file data lock poisoned");
-        *data_link = FileData(Arc::new(data.into_inner()));
+        *data_link = FileData(Arc::new(data.into_inner()), SystemTime::now());
     }
 }
 
-/// Holds the data of a file. Cheap to clone because the data is behind an [`Arc`].
+/// Holds the data of a file and the time it was last written. Cheap to clone because the data is
+/// behind an [`Arc`].
 #[derive(Clone)]
-struct FileData(Arc<Vec<u8>>);
+struct FileData(Arc<Vec<u8>>, SystemTime);
 
 impl FileData {
     fn new() -> Self {
-        Self(Arc::new(Vec::new()))
+        Self(Arc::new(Vec::new()), SystemTime::now())
     }
 }
 
@@ -386,6 +388,28 @@ impl FileSystem for MemoryFileSystem {
         Ok(data.0.len() as u64)
     }
 
+    fn modified(&self, path: impl AsRef<Path>) -> Result<SystemTime, io::Error> {
+        let root = self.root.read().expect("root lock poisoned");
+        let path = path.as_ref();
+
+        let parent = file_parent(path)?;
+
+        // find the directory
+        let dir = root.get_directory(parent)?;
+
+        // get file name
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        // get the file entry
+        let file = match dir.files.get(&name) {
+            Some(file) => file,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "file not found")),
+        };
+
+        let data = file.data.read().expect("file data lock poisoned");
+        Ok(data.1)
+    }
+
     fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), io::Error> {
         let mut root = self.root.write().expect("root lock poisoned");
         let from = from.as_ref();
@@ -415,9 +439,9 @@ impl FileSystem for MemoryFileSystem {
         // get file names
         let to_name = to.file_name().unwrap().to_string_lossy().to_string();
         let to_file = to_dir.files.entry(to_name).or_insert(FileEntry::new());
-        // copy the data
+        // copy the data, the copy counts as a new modification like on the local file system
         let mut to_data = to_file.data.write().expect("file data lock poisoned");
-        *to_data = from_data;
+        *to_data = FileData(from_data.0, SystemTime::now());
 
         Ok(())
     }
@@ -573,6 +597,22 @@ mod test {
         assert_eq!(buff, content.as_bytes());
     }
 
+    #[test]
+    fn test_modified() {
+        let fs = super::MemoryFileSystem::new();
+        let path = "file.txt";
+
+        fs.create(path).unwrap().write_all(b"first").unwrap();
+        let first = fs.modified(path).unwrap();
+        fs.create(path).unwrap().write_all(b"second").unwrap();
+        assert!(fs.modified(path).unwrap() >= first);
+
+        match fs.modified("missing.txt") {
+            Ok(_) => panic!("file should not exist"),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+        }
+    }
+
     #[test]
     fn test_create_not_found() {
         let fs = super::MemoryFileSystem::new();
@@ -1,6 +1,7 @@
 use std::{
     io::{self, Read, Seek, Write},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 pub mod local;
@@ -32,6 +33,9 @@ pub trait FileSystem: std::fmt::Debug {
     /// Get the size of a file in bytes.
     fn file_size(&self, path: impl AsRef<Path>) -> Result<u64, io::Error>;
 
+    /// Get the time a file was last modified.
+    fn modified(&self, path: impl AsRef<Path>) -> Result<SystemTime, io::Error>;
+
     /// Copy a file.
     fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), io::Error>;
 
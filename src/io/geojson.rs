@@ -0,0 +1,108 @@
+use std::io::Write;
+
+use crate::contours::Contour;
+
+use super::crs;
+
+/// Write contours as a GeoJSON `FeatureCollection` with one `LineString` feature per contour,
+/// carrying `elevation`, `index` and `form_line` properties. Coordinates are written as-is, i.e.
+/// in the same projected CRS as the input, rounded to `precision` decimals. If `crs_epsg` is
+/// given, it is declared in the (pre-RFC 7946) `crs` member.
+pub fn write_geojson<W: Write>(
+    writer: &mut W,
+    contours: &[Contour],
+    precision: usize,
+    crs_epsg: Option<u32>,
+) -> std::io::Result<()> {
+    write!(writer, "{{\"type\":\"FeatureCollection\",")?;
+    if let Some(epsg) = crs_epsg {
+        write!(
+            writer,
+            "\"crs\":{{\"type\":\"name\",\"properties\":{{\"name\":\"{}\"}}}},",
+            crs::urn(epsg)
+        )?;
+    }
+    write!(writer, "\"features\":[")?;
+    for (i, contour) in contours.iter().enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        write!(
+            writer,
+            "{{\"type\":\"Feature\",\"properties\":{{\"elevation\":{},\"index\":{},\"form_line\":{}}},\"geometry\":{{\"type\":\"LineString\",\"coordinates\":[",
+            contour.elevation, contour.index, contour.form_line
+        )?;
+        for (j, (x, y)) in contour.points.iter().enumerate() {
+            if j > 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "[{:.*},{:.*}]", precision, x, precision, y)?;
+        }
+        write!(writer, "]}}}}")?;
+    }
+    writeln!(writer, "]}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_geojson() {
+        let contours = vec![
+            Contour {
+                points: vec![(1.0, 2.0), (3.14159, 4.5)],
+                elevation: 102.5,
+                index: false,
+                form_line: false,
+            },
+            Contour {
+                points: vec![(10.0, 20.0), (30.0, 40.0), (10.0, 20.0)],
+                elevation: 125.0,
+                index: true,
+                form_line: false,
+            },
+        ];
+
+        let mut buff = Vec::new();
+        write_geojson(&mut buff, &contours, 2, None).unwrap();
+        let json = String::from_utf8(buff).unwrap();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"type":"FeatureCollection","features":["#,
+                r#"{"type":"Feature","properties":{"elevation":102.5,"index":false,"form_line":false},"#,
+                r#""geometry":{"type":"LineString","coordinates":[[1.00,2.00],[3.14,4.50]]}},"#,
+                r#"{"type":"Feature","properties":{"elevation":125,"index":true,"form_line":false},"#,
+                r#""geometry":{"type":"LineString","coordinates":[[10.00,20.00],[30.00,40.00],[10.00,20.00]]}}"#,
+                "]}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_geojson_empty() {
+        let mut buff = Vec::new();
+        write_geojson(&mut buff, &[], 2, None).unwrap();
+        assert_eq!(
+            String::from_utf8(buff).unwrap(),
+            "{\"type\":\"FeatureCollection\",\"features\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_geojson_crs() {
+        let mut buff = Vec::new();
+        write_geojson(&mut buff, &[], 2, Some(3067)).unwrap();
+        assert_eq!(
+            String::from_utf8(buff).unwrap(),
+            concat!(
+                r#"{"type":"FeatureCollection","#,
+                r#""crs":{"type":"name","properties":{"name":"urn:ogc:def:crs:EPSG::3067"}},"#,
+                r#""features":[]}"#,
+                "\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{crs, fs::FileSystem, heightmap::HeightMap};
+use crate::vec2d::Vec2D;
+
+/// Width and height of the tiles in the written GeoTIFF, must be a multiple of 16.
+const TILE_SIZE: usize = 256;
+
+// TIFF field types
+const BYTE: u16 = 1;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const ASCII: u16 = 2;
+const FLOAT: u16 = 11;
+const DOUBLE: u16 = 12;
+
+/// The value of a single IFD entry.
+enum TagValue {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Double(Vec<f64>),
+    Ascii(&'static str),
+}
+
+impl TagValue {
+    fn field_type(&self) -> u16 {
+        match self {
+            TagValue::Short(_) => SHORT,
+            TagValue::Long(_) => LONG,
+            TagValue::Double(_) => DOUBLE,
+            TagValue::Ascii(_) => ASCII,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            TagValue::Short(v) => v.len() as u32,
+            TagValue::Long(v) => v.len() as u32,
+            TagValue::Double(v) => v.len() as u32,
+            // includes the NUL terminator
+            TagValue::Ascii(s) => s.len() as u32 + 1,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            TagValue::Short(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            TagValue::Long(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            TagValue::Double(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            TagValue::Ascii(s) => s.bytes().chain(std::iter::once(0)).collect(),
+        }
+    }
+}
+
+/// Write a heightmap as a single-band, tiled, float32 GeoTIFF. NaN cells are kept as NaN, which is
+/// also recorded as the no-data value (GDAL_NODATA tag). If `epsg` is given it is written as the
+/// coordinate system of the file, codes that do not fit in a GeoKey are skipped.
+pub fn write_geotiff(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    heightmap: &HeightMap,
+    epsg: Option<u32>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(fs.create(path)?);
+    writer.write_all(&encode_geotiff(heightmap, epsg))?;
+    writer.flush()
+}
+
+/// Encode the heightmap as a little-endian GeoTIFF file, see [`write_geotiff`].
+fn encode_geotiff(heightmap: &HeightMap, epsg: Option<u32>) -> Vec<u8> {
+    let grid = &heightmap.grid;
+    let (w, h) = grid.dimensions();
+    let tiles_across = w.div_ceil(TILE_SIZE);
+    let tiles_down = h.div_ceil(TILE_SIZE);
+
+    // header, the offset of the IFD is patched in at the end
+    let mut out = Vec::new();
+    out.extend_from_slice(b"II");
+    out.extend_from_slice(&42u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+
+    // tiles, row 0 of the image is the northern edge, which is the last row of the grid
+    let tile_bytes = TILE_SIZE * TILE_SIZE * 4;
+    let mut tile_offsets = Vec::with_capacity(tiles_across * tiles_down);
+    for ty in 0..tiles_down {
+        for tx in 0..tiles_across {
+            tile_offsets.push(out.len() as u32);
+            for row in ty * TILE_SIZE..(ty + 1) * TILE_SIZE {
+                for col in tx * TILE_SIZE..(tx + 1) * TILE_SIZE {
+                    let v = if row < h && col < w {
+                        grid[(col, h - 1 - row)] as f32
+                    } else {
+                        f32::NAN
+                    };
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    let epsg = epsg.and_then(|epsg| Some((epsg, u16::try_from(epsg).ok()?)));
+    let geographic = matches!(epsg, Some((epsg, _)) if crs::is_geographic(epsg));
+    let mut geokeys: Vec<u16> = vec![
+        1,
+        1,
+        0,
+        0, // header, number of keys is patched in below
+        1024,
+        0,
+        1,
+        if geographic { 2 } else { 1 }, // GTModelTypeGeoKey = Geographic or Projected
+        1025,
+        0,
+        1,
+        2, // GTRasterTypeGeoKey = RasterPixelIsPoint
+    ];
+    match epsg {
+        // GeographicTypeGeoKey
+        Some((_, code)) if geographic => geokeys.extend_from_slice(&[2048, 0, 1, code]),
+        // ProjectedCSTypeGeoKey
+        Some((_, code)) => geokeys.extend_from_slice(&[3072, 0, 1, code]),
+        None => {}
+    }
+    geokeys[3] = (geokeys.len() / 4 - 1) as u16;
+
+    // must be sorted by tag
+    let tags = vec![
+        (256, TagValue::Long(vec![w as u32])),
+        (257, TagValue::Long(vec![h as u32])),
+        (258, TagValue::Short(vec![32])),
+        (259, TagValue::Short(vec![1])),
+        (262, TagValue::Short(vec![1])),
+        (277, TagValue::Short(vec![1])),
+        (284, TagValue::Short(vec![1])),
+        (322, TagValue::Long(vec![TILE_SIZE as u32])),
+        (323, TagValue::Long(vec![TILE_SIZE as u32])),
+        (324, TagValue::Long(tile_offsets)),
+        (
+            325,
+            TagValue::Long(vec![tile_bytes as u32; tiles_across * tiles_down]),
+        ),
+        (339, TagValue::Short(vec![3])),
+        (
+            33550,
+            TagValue::Double(vec![heightmap.scale, heightmap.scale, 0.0]),
+        ),
+        (
+            33922,
+            TagValue::Double(vec![0.0, 0.0, 0.0, heightmap.minx(), heightmap.maxy(), 0.0]),
+        ),
+        (34735, TagValue::Short(geokeys)),
+        (42113, TagValue::Ascii("nan")),
+    ];
+
+    // values that do not fit in the 4 byte value field are written after the IFD
+    let ifd_offset = out.len() + out.len() % 2;
+    out.resize(ifd_offset, 0);
+    let mut extra_offset = ifd_offset + 2 + tags.len() * 12 + 4;
+    let mut extra = Vec::new();
+
+    out.extend_from_slice(&(tags.len() as u16).to_le_bytes());
+    for (tag, value) in tags.iter() {
+        out.extend_from_slice(&(*tag as u16).to_le_bytes());
+        out.extend_from_slice(&value.field_type().to_le_bytes());
+        out.extend_from_slice(&value.count().to_le_bytes());
+        let mut bytes = value.bytes();
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            out.extend_from_slice(&bytes);
+        } else {
+            out.extend_from_slice(&(extra_offset as u32).to_le_bytes());
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            extra_offset += bytes.len();
+            extra.extend_from_slice(&bytes);
+        }
+    }
+    // no further IFDs
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&extra);
+
+    out[4..8].copy_from_slice(&(ifd_offset as u32).to_le_bytes());
+    out
+}
+
+/// Read a single band GeoTIFF of 32 or 64 bit floats as a heightmap, for example a DEM written by
+/// [`write_geotiff`]. Only uncompressed little-endian files with square, unrotated cells are
+/// supported. Cells with the GDAL no-data value become NaN.
+pub fn read_geotiff(fs: &impl FileSystem, path: impl AsRef<Path>) -> std::io::Result<HeightMap> {
+    let mut data = Vec::new();
+    fs.open(path)?.read_to_end(&mut data)?;
+    decode_geotiff(&data)
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Decode a GeoTIFF file, see [`read_geotiff`].
+fn decode_geotiff(data: &[u8]) -> std::io::Result<HeightMap> {
+    let bytes = |offset: usize, len: usize| {
+        data.get(offset..offset + len)
+            .ok_or_else(|| invalid("unexpected end of the GeoTIFF file"))
+    };
+    let u16_at = |o: usize| bytes(o, 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()));
+    let u32_at = |o: usize| bytes(o, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+
+    if bytes(0, 4)? != b"II\x2a\x00" {
+        return Err(invalid("not a little-endian TIFF file"));
+    }
+
+    // the numeric values of the tags of the first IFD, and the text of the ASCII ones
+    let mut values: HashMap<u16, Vec<f64>> = HashMap::new();
+    let mut texts: HashMap<u16, String> = HashMap::new();
+    let ifd = u32_at(4)? as usize;
+    for i in 0..u16_at(ifd)? as usize {
+        let entry = ifd + 2 + i * 12;
+        let (tag, field_type, count) = (u16_at(entry)?, u16_at(entry + 2)?, u32_at(entry + 4)?);
+        let size = match field_type {
+            BYTE | ASCII => 1,
+            SHORT => 2,
+            LONG | FLOAT => 4,
+            DOUBLE => 8,
+            // not needed here
+            _ => continue,
+        };
+        let len = size * count as usize;
+        let offset = if len <= 4 {
+            entry + 8
+        } else {
+            u32_at(entry + 8)? as usize
+        };
+        let raw = bytes(offset, len)?;
+        if field_type == ASCII {
+            let text = String::from_utf8_lossy(raw);
+            texts.insert(tag, text.trim_end_matches('\0').to_string());
+            continue;
+        }
+        let parsed = raw
+            .chunks_exact(size)
+            .map(|c| match field_type {
+                BYTE => c[0] as f64,
+                SHORT => u16::from_le_bytes(c.try_into().unwrap()) as f64,
+                LONG => u32::from_le_bytes(c.try_into().unwrap()) as f64,
+                FLOAT => f32::from_le_bytes(c.try_into().unwrap()) as f64,
+                _ => f64::from_le_bytes(c.try_into().unwrap()),
+            })
+            .collect();
+        values.insert(tag, parsed);
+    }
+    let value = |tag: u16, default: Option<f64>| {
+        values
+            .get(&tag)
+            .and_then(|v| v.first().copied())
+            .or(default)
+            .ok_or_else(|| invalid(format!("missing TIFF tag {}", tag)))
+    };
+    let list = |tag: u16| {
+        values
+            .get(&tag)
+            .ok_or_else(|| invalid(format!("missing TIFF tag {}", tag)))
+    };
+
+    let w = value(256, None)? as usize;
+    let h = value(257, None)? as usize;
+    let bits = value(258, Some(1.0))?;
+    if value(259, Some(1.0))? != 1.0 {
+        return Err(invalid("compressed GeoTIFF files are not supported"));
+    }
+    if value(277, Some(1.0))? != 1.0 {
+        return Err(invalid("only single band GeoTIFF files are supported"));
+    }
+    if value(339, Some(1.0))? != 3.0 || (bits != 32.0 && bits != 64.0) {
+        return Err(invalid(
+            "only 32 and 64 bit float GeoTIFF files are supported",
+        ));
+    }
+    let sample_size = bits as usize / 8;
+
+    let scale = list(33550)?;
+    if scale.len() < 2 || scale[0] != scale[1] {
+        return Err(invalid(
+            "only GeoTIFF files with square cells are supported",
+        ));
+    }
+    let scale = scale[0];
+    let tiepoint = list(33922)?;
+    if tiepoint.len() < 6 {
+        return Err(invalid("invalid GeoTIFF tie point"));
+    }
+    // GTRasterTypeGeoKey, the tie point is the corner of the pixel unless it is RasterPixelIsPoint
+    let pixel_is_point = values.get(&34735).is_some_and(|keys| {
+        keys.chunks_exact(4)
+            .skip(1)
+            .any(|k| k[0] == 1025.0 && k[3] == 2.0)
+    });
+    let corner = if pixel_is_point { 0.0 } else { 0.5 };
+    let left = tiepoint[3] + (corner - tiepoint[0]) * scale;
+    let top = tiepoint[4] - (corner - tiepoint[1]) * scale;
+    let nodata = texts.get(&42113).and_then(|t| t.trim().parse::<f64>().ok());
+
+    // the byte offset of each image pixel, in tiles or in strips of rows
+    let pixel_offset: Box<dyn Fn(usize, usize) -> std::io::Result<usize>> =
+        if values.contains_key(&322) {
+            let (tile_w, tile_h) = (value(322, None)? as usize, value(323, None)? as usize);
+            let offsets = list(324)?;
+            let tiles_across = w.div_ceil(tile_w);
+            Box::new(move |col, row| {
+                let tile = (row / tile_h) * tiles_across + col / tile_w;
+                let offset = offsets
+                    .get(tile)
+                    .ok_or_else(|| invalid("missing GeoTIFF tile"))?;
+                Ok(*offset as usize + ((row % tile_h) * tile_w + col % tile_w) * sample_size)
+            })
+        } else {
+            let rows_per_strip = value(278, Some(h as f64))? as usize;
+            let offsets = list(273)?;
+            Box::new(move |col, row| {
+                let offset = offsets
+                    .get(row / rows_per_strip)
+                    .ok_or_else(|| invalid("missing GeoTIFF strip"))?;
+                Ok(*offset as usize + ((row % rows_per_strip) * w + col) * sample_size)
+            })
+        };
+
+    let mut grid = Vec2D::new(w, h, f64::NAN);
+    for row in 0..h {
+        for col in 0..w {
+            let sample = bytes(pixel_offset(col, row)?, sample_size)?;
+            let v = if sample_size == 4 {
+                f32::from_le_bytes(sample.try_into().unwrap()) as f64
+            } else {
+                f64::from_le_bytes(sample.try_into().unwrap())
+            };
+            // row 0 of the image is the northern edge, which is the last row of the grid
+            grid[(col, h - 1 - row)] = if Some(v) == nodata { f64::NAN } else { v };
+        }
+    }
+
+    Ok(HeightMap {
+        xoffset: left,
+        yoffset: top - scale * h.saturating_sub(1) as f64,
+        scale,
+        grid,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    /// Minimal reader for the first IFD of a little-endian TIFF, returning the raw bytes of the
+    /// values of the given tag.
+    fn read_tag(data: &[u8], tag: u16) -> Option<Vec<u8>> {
+        let u16_at = |o: usize| u16::from_le_bytes(data[o..o + 2].try_into().unwrap());
+        let u32_at = |o: usize| u32::from_le_bytes(data[o..o + 4].try_into().unwrap());
+
+        let ifd = u32_at(4) as usize;
+        for i in 0..u16_at(ifd) as usize {
+            let entry = ifd + 2 + i * 12;
+            if u16_at(entry) != tag {
+                continue;
+            }
+            let size = match u16_at(entry + 2) {
+                ASCII => 1,
+                SHORT => 2,
+                LONG => 4,
+                DOUBLE => 8,
+                t => panic!("unexpected field type {}", t),
+            };
+            let len = size * u32_at(entry + 4) as usize;
+            let offset = if len <= 4 {
+                entry + 8
+            } else {
+                u32_at(entry + 8) as usize
+            };
+            return Some(data[offset..offset + len].to_vec());
+        }
+        None
+    }
+
+    fn read_u32(data: &[u8], tag: u16) -> Vec<u32> {
+        read_tag(data, tag)
+            .unwrap()
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_geotiff() {
+        let (w, h) = (300, 20);
+        let mut grid = Vec2D::new(w, h, 0.0);
+        for (x, y, v) in grid.iter_mut() {
+            *v = 100.0 + x as f64 + 0.5 * y as f64;
+        }
+        grid[(0, 0)] = f64::NAN;
+        let hmap = HeightMap {
+            xoffset: 500000.0,
+            yoffset: 6700000.0,
+            scale: 2.0,
+            grid,
+        };
+
+        let fs = MemoryFileSystem::new();
+        write_geotiff(&fs, "dem.tif", &hmap, Some(3067)).unwrap();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut fs.open("dem.tif").unwrap(), &mut data).unwrap();
+
+        assert_eq!(&data[0..4], b"II\x2a\x00");
+        assert_eq!(read_u32(&data, 256), vec![w as u32]);
+        assert_eq!(read_u32(&data, 257), vec![h as u32]);
+        assert_eq!(read_tag(&data, 42113).unwrap(), b"nan\0");
+
+        // two tiles across, one down
+        let offsets = read_u32(&data, 324);
+        assert_eq!(offsets.len(), 2);
+
+        let sample = |tile: usize, col: usize, row: usize| {
+            let o = offsets[tile] as usize + (row * TILE_SIZE + col) * 4;
+            f32::from_le_bytes(data[o..o + 4].try_into().unwrap())
+        };
+        // the first image row is the northern edge of the grid
+        assert_eq!(sample(0, 3, 0), 100.0 + 3.0 + 0.5 * 19.0);
+        assert_eq!(sample(1, 10, 5), 100.0 + 266.0 + 0.5 * 14.0);
+        assert!(sample(0, 0, h - 1).is_nan());
+        // padding outside the grid
+        assert!(sample(1, 100, 0).is_nan());
+
+        let tiepoint: Vec<f64> = read_tag(&data, 33922)
+            .unwrap()
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(tiepoint, vec![0.0, 0.0, 0.0, 500000.0, 6700038.0, 0.0]);
+
+        let geokeys: Vec<u16> = read_tag(&data, 34735)
+            .unwrap()
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(&geokeys[0..4], &[1, 1, 0, 3]);
+        assert_eq!(&geokeys[12..16], &[3072, 0, 1, 3067]);
+    }
+
+    #[test]
+    fn test_read_geotiff_roundtrip() {
+        let mut grid = Vec2D::new(300, 20, 0.0);
+        for (x, y, v) in grid.iter_mut() {
+            *v = 100.0 + x as f64 + 0.5 * y as f64;
+        }
+        grid[(0, 0)] = f64::NAN;
+        let hmap = HeightMap {
+            xoffset: 500000.0,
+            yoffset: 6700000.0,
+            scale: 2.0,
+            grid,
+        };
+
+        let fs = MemoryFileSystem::new();
+        write_geotiff(&fs, "dem.tif", &hmap, Some(3067)).unwrap();
+        let read = read_geotiff(&fs, "dem.tif").unwrap();
+        assert_eq!(
+            (read.xoffset, read.yoffset, read.scale),
+            (500000.0, 6700000.0, 2.0)
+        );
+        assert_eq!(read.grid.width(), 300);
+        assert_eq!(read.grid.height(), 20);
+        assert!(read.grid[(0, 0)].is_nan());
+        assert_eq!(read.grid[(266, 14)], 100.0 + 266.0 + 0.5 * 14.0);
+    }
+
+    #[test]
+    fn test_read_geotiff_rejects_other_files() {
+        let fs = MemoryFileSystem::new();
+        fs.create("dem.tif")
+            .unwrap()
+            .write_all(b"MM\x00\x2a")
+            .unwrap();
+        let err = read_geotiff(&fs, "dem.tif").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::vec2d::Vec2D;
+use crate::vec2d::{ColumnStrips, Vec2D};
 
 use super::{bytes::FromToBytes, fs::FileSystem};
 
@@ -33,6 +33,48 @@ impl HeightMap {
         self.yoffset + self.scale * (self.grid.height().saturating_sub(1)) as f64
     }
 
+    /// Resample the heightmap to the given cell size, see [`Vec2D::resample`]. When the grid is made
+    /// coarser, the offsets move to the center of the first block of cells.
+    pub fn resample(&self, cell_size: f64) -> HeightMap {
+        let factor = cell_size / self.scale;
+        let shift = if factor > 1.0 {
+            (factor - 1.0) / 2.0 * self.scale
+        } else {
+            0.0
+        };
+        HeightMap {
+            xoffset: self.xoffset + shift,
+            yoffset: self.yoffset + shift,
+            scale: cell_size,
+            grid: self.grid.resample(factor),
+        }
+    }
+
+    /// Crop the heightmap to the cells covering `[minx, maxx] × [miny, maxy]`, including one cell
+    /// beyond each edge where there is one. Returns `None` if the heightmap does not overlap the
+    /// rectangle.
+    pub fn crop(&self, minx: f64, miny: f64, maxx: f64, maxy: f64) -> Option<HeightMap> {
+        let (w, h) = (self.grid.width() as f64, self.grid.height() as f64);
+        let x0 = ((minx - self.xoffset) / self.scale).floor() - 1.0;
+        let y0 = ((miny - self.yoffset) / self.scale).floor() - 1.0;
+        let x1 = ((maxx - self.xoffset) / self.scale).ceil() + 1.0;
+        let y1 = ((maxy - self.yoffset) / self.scale).ceil() + 1.0;
+        let (x0, y0) = (x0.max(0.0), y0.max(0.0));
+        let (x1, y1) = (x1.min(w - 1.0), y1.min(h - 1.0));
+        if x0 > x1 || y0 > y1 {
+            return None;
+        }
+        let (x0, y0) = (x0 as usize, y0 as usize);
+        Some(HeightMap {
+            xoffset: self.xoffset + self.scale * x0 as f64,
+            yoffset: self.yoffset + self.scale * y0 as f64,
+            scale: self.scale,
+            grid: self
+                .grid
+                .subgrid(x0, y0, x1 as usize - x0 + 1, y1 as usize - y0 + 1),
+        })
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (f64, f64, f64)> + '_ {
         self.grid.iter().map(|(x, y, v)| {
             (
@@ -67,6 +109,29 @@ impl HeightMap {
     }
 }
 
+/// A serialized HeightMap with the grid read in strips of columns, see [`ColumnStrips`].
+pub struct HeightMapStrips<R> {
+    pub xoffset: f64,
+    pub yoffset: f64,
+    pub scale: f64,
+    pub strips: ColumnStrips<R, f64>,
+}
+
+impl<R: std::io::Read> HeightMapStrips<R> {
+    /// Read the offsets and the scale, leaving the grid to be read in strips of `columns` columns.
+    pub fn new(mut reader: R, columns: usize) -> std::io::Result<Self> {
+        let xoffset = f64::from_bytes(&mut reader)?;
+        let yoffset = f64::from_bytes(&mut reader)?;
+        let scale = f64::from_bytes(&mut reader)?;
+        Ok(HeightMapStrips {
+            xoffset,
+            yoffset,
+            scale,
+            strips: ColumnStrips::new(reader, columns)?,
+        })
+    }
+}
+
 impl FromToBytes for HeightMap {
     fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
         let xoffset = f64::from_bytes(reader)?;
@@ -115,4 +180,60 @@ mod test {
 
         assert_eq!(heightmap, heightmap2);
     }
+
+    #[test]
+    fn test_crop() {
+        let hmap = HeightMap {
+            xoffset: 100.0,
+            yoffset: 200.0,
+            scale: 2.0,
+            grid: Vec2D::from_fn(10, 10, |x, y| (10 * x + y) as f64),
+        };
+
+        let cropped = hmap.crop(105.0, 201.0, 109.0, 250.0).unwrap();
+        assert_eq!((cropped.xoffset, cropped.yoffset), (102.0, 200.0));
+        assert_eq!(cropped.grid.dimensions(), (6, 10));
+        assert_eq!(cropped.grid[(4, 9)], hmap.grid[(5, 9)]);
+
+        let cropped = hmap.crop(110.0, 210.0, 111.0, 211.0).unwrap();
+        assert_eq!((cropped.xoffset, cropped.yoffset), (108.0, 208.0));
+        assert_eq!(cropped.grid[(0, 0)], hmap.grid[(4, 4)]);
+
+        assert!(hmap.crop(0.0, 0.0, 50.0, 50.0).is_none());
+    }
+
+    #[test]
+    fn test_resample_fine_binning_to_coarser_output() {
+        // 0.5 m cells of a tilted surface, binned from (100, 200)
+        let surface = |x: f64, y: f64| 0.3 * x - 0.1 * y + 0.01 * x * y;
+        let fine = HeightMap {
+            xoffset: 100.25,
+            yoffset: 200.25,
+            scale: 0.5,
+            grid: Vec2D::from_fn(8, 6, |x, y| {
+                surface(100.25 + 0.5 * x as f64, 200.25 + 0.5 * y as f64)
+            }),
+        };
+
+        let coarse = fine.resample(1.0);
+        assert_eq!(coarse.scale, 1.0);
+        assert_eq!(coarse.grid.dimensions(), (4, 3));
+        assert_eq!((coarse.xoffset, coarse.yoffset), (100.5, 200.5));
+        for (x, y, v) in coarse.iter() {
+            // the mean of the four 0.5 m cells around the 1 m cell center
+            let expected = [(-0.25, -0.25), (0.25, -0.25), (-0.25, 0.25), (0.25, 0.25)]
+                .iter()
+                .map(|(dx, dy)| surface(x + dx, y + dy))
+                .sum::<f64>()
+                / 4.0;
+            assert!(
+                (v - expected).abs() < 1e-9,
+                "{} {} {} {}",
+                x,
+                y,
+                v,
+                expected
+            );
+        }
+    }
 }
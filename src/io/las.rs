@@ -0,0 +1,424 @@
+use std::{
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use super::fs::FileSystem;
+
+/// The file signature that starts every LAS file, both compressed (LAZ) and uncompressed.
+const LAS_SIGNATURE: &[u8] = b"LASF";
+
+/// Check if a file starts with the LAS file signature, regardless of its extension.
+pub fn has_las_signature(fs: &impl FileSystem, path: impl AsRef<Path>) -> std::io::Result<bool> {
+    let mut file = fs.open(path)?;
+    let mut buff = [0; LAS_SIGNATURE.len()];
+    match file.read_exact(&mut buff) {
+        Ok(()) => Ok(buff == LAS_SIGNATURE),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Open a LAS or LAZ file for reading. Compression is detected from the point format in the
+/// header, so compressed files are decompressed transparently. An invalid header is reported as
+/// [`std::io::ErrorKind::InvalidData`].
+pub fn open_reader(fs: &impl FileSystem, path: impl AsRef<Path>) -> std::io::Result<las::Reader> {
+    reader_from(BufReader::new(fs.open(path)?))
+}
+
+/// Read a LAS or LAZ file from any seekable source, such as an in-memory buffer or a stream from
+/// an object store, like [`open_reader`]. The source should be buffered, since it is read in
+/// small pieces.
+pub fn reader_from<R: Read + Seek + Send + 'static>(read: R) -> std::io::Result<las::Reader> {
+    las::Reader::new(read).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// A single decoded point from a LAS or LAZ file, with coordinates in real-world units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LasPoint {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub classification: u8,
+    pub intensity: u16,
+    pub number_of_returns: u8,
+    pub return_number: u8,
+}
+
+impl From<las::Point> for LasPoint {
+    fn from(p: las::Point) -> Self {
+        Self {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            classification: u8::from(p.classification),
+            intensity: p.intensity,
+            number_of_returns: p.number_of_returns,
+            return_number: p.return_number,
+        }
+    }
+}
+
+/// Iterator that lazily decodes the points of a LAS or LAZ file, see [`points`].
+pub struct LasPoints {
+    reader: las::Reader,
+    bbox: Option<(f64, f64, f64, f64)>,
+}
+
+impl LasPoints {
+    /// The total number of points in the file according to the header, including points outside
+    /// the bounding box.
+    pub fn total(&self) -> u64 {
+        self.reader.header().number_of_points()
+    }
+}
+
+impl Iterator for LasPoints {
+    type Item = std::io::Result<LasPoint>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.read_point() {
+                Ok(Some(p)) => {
+                    if let Some((minx, miny, maxx, maxy)) = self.bbox {
+                        // include the min edge and exclude the max edge, so that points on the
+                        // border between two adjacent boxes are only counted once
+                        if p.x < minx || p.x >= maxx || p.y < miny || p.y >= maxy {
+                            continue;
+                        }
+                    }
+                    return Some(Ok(p.into()));
+                }
+                Ok(None) => return None,
+                Err(e) => return Some(Err(std::io::Error::other(e))),
+            }
+        }
+    }
+}
+
+/// Lazily iterate over the points of a LAS or LAZ file without loading them all into memory.
+/// The scale and offset from the header are applied, so coordinates are in real-world units.
+/// The points are read from the offset to the point data and with the record length in the
+/// header, so variable-length records and extra bytes per point are skipped.
+///
+/// If `bbox` is given as `(minx, miny, maxx, maxy)`, only points within `[minx, maxx) × [miny, maxy)`
+/// are yielded.
+pub fn points(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    bbox: Option<(f64, f64, f64, f64)>,
+) -> std::io::Result<LasPoints> {
+    Ok(LasPoints {
+        reader: open_reader(fs, path)?,
+        bbox,
+    })
+}
+
+/// Lazily iterate over the points of a LAS or LAZ file read from a seekable source, like
+/// [`points`].
+pub fn points_from<R: Read + Seek + Send + 'static>(
+    read: R,
+    bbox: Option<(f64, f64, f64, f64)>,
+) -> std::io::Result<LasPoints> {
+    Ok(LasPoints {
+        reader: reader_from(read)?,
+        bbox,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use las::{point::Format, Builder, Header, Point, Transform, Vector, Writer};
+
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    /// Write the points to a LAS file with the given point format in the file system.
+    fn write_las(
+        fs: &MemoryFileSystem,
+        path: &str,
+        format: u8,
+        compressed: bool,
+        points: &[Point],
+    ) {
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(format).unwrap();
+        builder.point_format.is_compressed = compressed;
+        write_las_with_header(fs, path, builder.into_header().unwrap(), points);
+    }
+
+    fn write_las_with_header(fs: &MemoryFileSystem, path: &str, header: Header, points: &[Point]) {
+        let mut writer = Writer::new(Cursor::new(Vec::new()), header).unwrap();
+        for point in points {
+            writer.write_point(point.clone()).unwrap();
+        }
+        let data = writer.into_inner().unwrap().into_inner();
+        fs.create(path).unwrap().write_all(&data).unwrap();
+    }
+
+    fn test_points(format: u8) -> Vec<Point> {
+        let format = Format::new(format).unwrap();
+        (0..10)
+            .map(|i| Point {
+                x: i as f64,
+                y: 2.0 * i as f64,
+                z: 100.0 + i as f64 / 4.0,
+                classification: las::point::Classification::Ground,
+                return_number: 1,
+                number_of_returns: 2,
+                gps_time: format.has_gps_time.then_some(0.0),
+                color: format.has_color.then(las::Color::default),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn read_all(fs: &MemoryFileSystem, path: &str) -> Vec<(f64, f64, f64, u8)> {
+        let mut reader = open_reader(fs, path).unwrap();
+        reader
+            .points()
+            .map(|p| {
+                let p = p.unwrap();
+                (p.x, p.y, p.z, u8::from(p.classification))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_laz_matches_las() {
+        let fs = MemoryFileSystem::new();
+        for format in [1, 3, 6] {
+            let points = test_points(format);
+            write_las(&fs, "points.las", format, false, &points);
+            write_las(&fs, "points.laz", format, true, &points);
+
+            let las = read_all(&fs, "points.las");
+            let laz = read_all(&fs, "points.laz");
+            assert_eq!(las.len(), points.len());
+            assert_eq!(las, laz, "point format {}", format);
+        }
+    }
+
+    #[test]
+    fn test_has_las_signature() {
+        let fs = MemoryFileSystem::new();
+        write_las(&fs, "points.bin", 1, true, &test_points(1));
+        fs.create("other.bin").unwrap().write_all(b"XYZB").unwrap();
+        fs.create("short.bin").unwrap().write_all(b"LA").unwrap();
+
+        assert!(has_las_signature(&fs, "points.bin").unwrap());
+        assert!(!has_las_signature(&fs, "other.bin").unwrap());
+        assert!(!has_las_signature(&fs, "short.bin").unwrap());
+    }
+
+    #[test]
+    fn test_extended_point_format() {
+        let fs = MemoryFileSystem::new();
+
+        // LAS 1.4 extended formats have 4 bit return numbers, a full classification byte and a
+        // 2 byte scan angle, make sure they are decoded the same way as the legacy formats
+        let mut extended = test_points(6);
+        extended[0].return_number = 9;
+        extended[0].number_of_returns = 12;
+        extended[0].scan_angle = -100.0;
+        extended[1].classification = las::point::Classification::new(64).unwrap();
+        write_las(&fs, "extended.las", 6, false, &extended);
+        write_las(&fs, "legacy.las", 1, false, &test_points(1));
+
+        let mut reader = open_reader(&fs, "extended.las").unwrap();
+        let points = reader.points().map(|p| p.unwrap()).collect::<Vec<_>>();
+        assert_eq!(points[0].return_number, 9);
+        assert_eq!(points[0].number_of_returns, 12);
+        // stored in steps of 0.006 degrees
+        assert!((points[0].scan_angle + 100.0).abs() < 0.01);
+        assert_eq!(u8::from(points[1].classification), 64);
+
+        // ground classification is equivalent between the formats
+        let extended = read_all(&fs, "extended.las");
+        let legacy = read_all(&fs, "legacy.las");
+        assert_eq!(extended[0], legacy[0]);
+        assert_eq!(extended[2..], legacy[2..]);
+        assert!(legacy.iter().all(|&(_, _, _, c)| c == 2));
+    }
+
+    #[test]
+    fn test_points_iterator() {
+        let fs = MemoryFileSystem::new();
+        let mut written = test_points(1);
+        written[3].intensity = 1234;
+        written[4].classification = las::point::Classification::LowVegetation;
+        write_las(&fs, "points.laz", 1, true, &written);
+
+        let read = points(&fs, "points.laz", None)
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read.len(), written.len());
+        assert_eq!(
+            points(&fs, "points.laz", None).unwrap().total(),
+            written.len() as u64
+        );
+        assert_eq!(
+            read[3],
+            LasPoint {
+                x: 3.0,
+                y: 6.0,
+                z: 100.75,
+                classification: 2,
+                intensity: 1234,
+                number_of_returns: 2,
+                return_number: 1,
+            }
+        );
+        assert_eq!(read[4].classification, 3);
+    }
+
+    #[test]
+    fn test_scale_and_offset() {
+        let fs = MemoryFileSystem::new();
+        let mut builder = Builder::from((1, 2));
+        builder.point_format = Format::new(1).unwrap();
+        builder.transforms = Vector {
+            x: Transform {
+                scale: 0.01,
+                offset: 500000.0,
+            },
+            y: Transform {
+                scale: 0.01,
+                offset: 6700000.0,
+            },
+            z: Transform {
+                scale: 0.001,
+                offset: -10.0,
+            },
+        };
+        let point = Point {
+            x: 500123.45,
+            y: 6700067.89,
+            z: 91.234,
+            gps_time: Some(0.0),
+            ..Default::default()
+        };
+        write_las_with_header(&fs, "scaled.las", builder.into_header().unwrap(), &[point]);
+
+        // the raw integer coordinates are stored relative to the offset
+        let mut file = fs.open("scaled.las").unwrap();
+        let header = las::raw::Header::read_from(&mut file).unwrap();
+        let mut raw = vec![0; header.offset_to_point_data as usize + 12];
+        let mut file = fs.open("scaled.las").unwrap();
+        file.read_exact(&mut raw).unwrap();
+        let raw = &raw[header.offset_to_point_data as usize..];
+        assert_eq!(i32::from_le_bytes(raw[0..4].try_into().unwrap()), 12345);
+        assert_eq!(i32::from_le_bytes(raw[4..8].try_into().unwrap()), 6789);
+        assert_eq!(i32::from_le_bytes(raw[8..12].try_into().unwrap()), 101234);
+
+        // and are transformed back to real-world units when read
+        let read = points(&fs, "scaled.las", None)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!((read.x - 500123.45).abs() < 1e-6);
+        assert!((read.y - 6700067.89).abs() < 1e-6);
+        assert!((read.z - 91.234).abs() < 1e-6);
+
+        // which puts the point in the expected 2 m grid cell of a tile starting at (500000, 6700000)
+        assert_eq!(((read.x - 500000.0) / 2.0).floor() as usize, 61);
+        assert_eq!(((read.y - 6700000.0) / 2.0).floor() as usize, 33);
+    }
+
+    #[test]
+    fn test_points_from_cursor_matches_path() {
+        let fs = MemoryFileSystem::new();
+        for (path, compressed) in [("points.las", false), ("points.laz", true)] {
+            write_las(&fs, path, 1, compressed, &test_points(1));
+
+            let mut data = Vec::new();
+            fs.open(path).unwrap().read_to_end(&mut data).unwrap();
+            let from_cursor = points_from(Cursor::new(data), None)
+                .unwrap()
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap();
+            let from_path = points(&fs, path, None)
+                .unwrap()
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(from_cursor.len(), 10);
+            assert_eq!(from_cursor, from_path);
+        }
+
+        let err = points_from(Cursor::new(b"LASF".to_vec()), None)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_vlrs_and_extra_bytes() {
+        let fs = MemoryFileSystem::new();
+        write_las(&fs, "plain.las", 1, false, &test_points(1));
+
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = Format::new(1).unwrap();
+        builder.point_format.extra_bytes = 2;
+        builder.vlrs.push(las::Vlr {
+            user_id: "vendor".to_string(),
+            record_id: 42,
+            description: "vendor specific".to_string(),
+            data: vec![7; 100],
+        });
+        let with_extra_bytes = test_points(1)
+            .into_iter()
+            .map(|p| Point {
+                extra_bytes: vec![0xAB, 0xCD],
+                ..p
+            })
+            .collect::<Vec<_>>();
+        write_las_with_header(
+            &fs,
+            "vendor.las",
+            builder.into_header().unwrap(),
+            &with_extra_bytes,
+        );
+
+        // the points start after the VLR and every record is 2 bytes longer than the minimum
+        let header = las::raw::Header::read_from(&mut fs.open("vendor.las").unwrap()).unwrap();
+        let plain = las::raw::Header::read_from(&mut fs.open("plain.las").unwrap()).unwrap();
+        assert!(header.offset_to_point_data > plain.offset_to_point_data);
+        assert_eq!(
+            header.point_data_record_length,
+            plain.point_data_record_length + 2
+        );
+
+        let read = |path| {
+            points(&fs, path, None)
+                .unwrap()
+                .collect::<std::io::Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert_eq!(read("vendor.las").len(), 10);
+        assert_eq!(read("vendor.las"), read("plain.las"));
+    }
+
+    #[test]
+    fn test_points_bbox() {
+        let fs = MemoryFileSystem::new();
+        write_las(&fs, "points.las", 1, false, &test_points(1));
+
+        // points are at (i, 2i) for i in 0..10
+        let read = points(&fs, "points.las", Some((2.0, 0.0, 5.0, 100.0)))
+            .unwrap()
+            .map(|p| p.unwrap().x)
+            .collect::<Vec<_>>();
+        assert_eq!(read, vec![2.0, 3.0, 4.0]);
+
+        let read = points(&fs, "points.las", Some((0.0, 4.0, 100.0, 8.0)))
+            .unwrap()
+            .map(|p| p.unwrap().y)
+            .collect::<Vec<_>>();
+        assert_eq!(read, vec![4.0, 6.0]);
+    }
+}
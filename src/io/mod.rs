@@ -1,14 +1,30 @@
 use std::{
-    io::{BufReader, BufWriter, Write},
+    error::Error,
+    io::{BufReader, BufWriter, Read, Write},
     path::Path,
 };
 
+use bytes::FromToBytes;
 use fs::FileSystem;
 use heightmap::HeightMap;
+use image::{Rgb, RgbImage};
 
+use crate::vec2d::Vec2D;
+
+pub mod asc;
 pub mod bytes;
+pub mod cache;
+pub mod crs;
+pub mod dxf;
 pub mod fs;
+pub mod geojson;
+pub mod geotiff;
 pub mod heightmap;
+pub mod las;
+#[cfg(feature = "reproject")]
+pub mod reproject;
+pub mod shp;
+pub mod worldfile;
 pub mod xyz;
 
 /// Helper function to convert an internal xyz file to a regular xyz file.
@@ -42,3 +58,99 @@ pub fn internal2xyz(fs: &impl FileSystem, input: &str, output: &str) -> std::io:
 
     Ok(())
 }
+
+/// Color of the NaN (no data) cells in [`grid_image`].
+pub const NO_DATA_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+
+/// Draw a grid as a grayscale image scaled from its smallest (black) to its largest (white) value,
+/// ignoring NaN cells, which are drawn in [`NO_DATA_COLOR`]. The image is north-up like the
+/// heightmap, i.e. the last row of the grid is the top row of the image. A grid with a single
+/// value is drawn black.
+pub fn grid_image(grid: &Vec2D<f64>) -> RgbImage {
+    let (w, h) = grid.dimensions();
+    let min = grid.min_ignore_nan().unwrap_or(0.0);
+    let range = grid.max_ignore_nan().unwrap_or(0.0) - min;
+    RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let v = grid[(x as usize, h - 1 - y as usize)];
+        if v.is_nan() {
+            return NO_DATA_COLOR;
+        }
+        let gray = if range > 0.0 {
+            ((v - min) / range * 255.0).round() as u8
+        } else {
+            0
+        };
+        Rgb([gray; 3])
+    })
+}
+
+/// Helper function to render a serialized `Vec2D<f64>` or `Vec2D<u8>` (such as the grids in the
+/// temp folder) as a PNG, see [`grid_image`].
+pub fn debug_render(
+    fs: &impl FileSystem,
+    input: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    fs.open(input)?.read_to_end(&mut data)?;
+
+    // the element type is not stored, it is the one whose grid uses up all the bytes
+    let mut reader = data.as_slice();
+    let grid = match Vec2D::<f64>::from_bytes(&mut reader) {
+        Ok(grid) if reader.is_empty() => grid,
+        _ => {
+            let mut reader = data.as_slice();
+            match Vec2D::<u8>::from_bytes(&mut reader) {
+                Ok(grid) if reader.is_empty() => grid.map(|&v| v as f64),
+                Ok(_) => {
+                    return Err(
+                        format!("{} is not a Vec2D<f64> or Vec2D<u8>", input.display()).into(),
+                    )
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    grid_image(&grid).write_to(
+        &mut BufWriter::new(fs.create(output)?),
+        image::ImageFormat::Png,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    #[test]
+    fn test_debug_render_f64() {
+        let fs = MemoryFileSystem::new();
+        let mut grid = Vec2D::from_fn(3, 2, |x, y| 10.0 + x as f64 + 3.0 * y as f64);
+        grid[(2, 1)] = f64::NAN;
+        grid.to_bytes(&mut fs.create("grid.bin").unwrap()).unwrap();
+
+        debug_render(&fs, Path::new("grid.bin"), Path::new("grid.png")).unwrap();
+        let img = fs.read_image_png("grid.png").unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (3, 2));
+        // values from 10 to 14, north-up
+        assert_eq!(img[(0, 1)], Rgb([0, 0, 0]));
+        assert_eq!(img[(1, 0)], Rgb([255, 255, 255]));
+        assert_eq!(img[(0, 0)], Rgb([191, 191, 191]));
+        assert_eq!(img[(2, 0)], NO_DATA_COLOR);
+    }
+
+    #[test]
+    fn test_debug_render_u8() {
+        let fs = MemoryFileSystem::new();
+        let grid = Vec2D::from_fn(4, 3, |x, _| (x * 20) as u8);
+        grid.to_bytes(&mut fs.create("grid.bin").unwrap()).unwrap();
+
+        debug_render(&fs, Path::new("grid.bin"), Path::new("grid.png")).unwrap();
+        let img = fs.read_image_png("grid.png").unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (4, 3));
+        assert_eq!(img[(0, 0)], Rgb([0, 0, 0]));
+        assert_eq!(img[(3, 2)], Rgb([255, 255, 255]));
+    }
+}
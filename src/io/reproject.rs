@@ -0,0 +1,208 @@
+//! Transform coordinates between the coordinate systems known to [`super::crs::definition`]: the
+//! transverse Mercator projections (TM35FIN, SWEREF99 TM and the UTM zones) and geographic
+//! coordinates (longitude as x, latitude as y, in degrees). The ETRS89, SWEREF99 and WGS 84 datums
+//! are treated as the same, which they are to within a meter.
+
+use super::crs::{definition, Definition, TM_FALSE_EASTING, TM_SCALE_FACTOR};
+
+/// A coordinate system as its ellipsoid and projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Crs {
+    Geographic,
+    TransverseMercator(TransverseMercator),
+}
+
+fn crs(epsg: u32) -> Option<Crs> {
+    Some(match definition(epsg)? {
+        Definition::Geographic(_) => Crs::Geographic,
+        Definition::TransverseMercator {
+            geographic,
+            central_meridian,
+            false_northing,
+            ..
+        } => Crs::TransverseMercator(TransverseMercator::new(
+            geographic.spheroid.semi_major_axis,
+            1.0 / geographic.spheroid.inverse_flattening,
+            central_meridian.to_radians(),
+            TM_SCALE_FACTOR,
+            TM_FALSE_EASTING,
+            false_northing,
+        )),
+    })
+}
+
+/// A transform of coordinates from one EPSG coordinate system to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    from: Crs,
+    to: Crs,
+}
+
+impl Transform {
+    /// The transform from `from` to `to`, fails if either of the EPSG codes is not known.
+    pub fn new(from: u32, to: u32) -> Result<Transform, String> {
+        let known = |epsg| crs(epsg).ok_or_else(|| format!("Cannot reproject EPSG:{}", epsg));
+        Ok(Transform {
+            from: known(from)?,
+            to: known(to)?,
+        })
+    }
+
+    /// Transform the point `(x, y)`.
+    pub fn transform(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.from == self.to {
+            return (x, y);
+        }
+        let (lon, lat) = match self.from {
+            Crs::Geographic => (x.to_radians(), y.to_radians()),
+            Crs::TransverseMercator(tm) => tm.inverse(x, y),
+        };
+        match self.to {
+            Crs::Geographic => (lon.to_degrees(), lat.to_degrees()),
+            Crs::TransverseMercator(tm) => tm.forward(lon, lat),
+        }
+    }
+}
+
+/// A transverse Mercator projection with the 4th order Krüger series, which is accurate to well
+/// below a millimeter within a few thousand kilometers of the central meridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TransverseMercator {
+    eccentricity: f64,
+    central_meridian: f64,
+    false_easting: f64,
+    false_northing: f64,
+    /// The radius of the rectifying sphere times the scale on the central meridian.
+    radius: f64,
+    alpha: [f64; 4],
+    beta: [f64; 4],
+}
+
+impl TransverseMercator {
+    fn new(
+        semi_major_axis: f64,
+        flattening: f64,
+        central_meridian: f64,
+        scale: f64,
+        false_easting: f64,
+        false_northing: f64,
+    ) -> TransverseMercator {
+        let n = flattening / (2.0 - flattening);
+        let (n2, n3, n4) = (n * n, n * n * n, n * n * n * n);
+        TransverseMercator {
+            eccentricity: (flattening * (2.0 - flattening)).sqrt(),
+            central_meridian,
+            false_easting,
+            false_northing,
+            radius: scale * semi_major_axis / (1.0 + n) * (1.0 + n2 / 4.0 + n4 / 64.0),
+            alpha: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0,
+                13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0,
+                61.0 * n3 / 240.0 - 103.0 * n4 / 140.0,
+                49561.0 * n4 / 161280.0,
+            ],
+            beta: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0,
+                n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0,
+                17.0 * n3 / 480.0 - 37.0 * n4 / 840.0,
+                4397.0 * n4 / 161280.0,
+            ],
+        }
+    }
+
+    /// Project the longitude and latitude (in radians) to easting and northing.
+    fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let e = self.eccentricity;
+        let dlon = lon - self.central_meridian;
+        // the tangent of the conformal latitude
+        let t = (lat.sin().atanh() - e * (e * lat.sin()).atanh()).sinh();
+        let xi0 = t.atan2(dlon.cos());
+        let eta0 = (dlon.sin() / (1.0 + t * t).sqrt()).atanh();
+        let (mut xi, mut eta) = (xi0, eta0);
+        for (j, alpha) in self.alpha.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            xi += alpha * (k * xi0).sin() * (k * eta0).cosh();
+            eta += alpha * (k * xi0).cos() * (k * eta0).sinh();
+        }
+        (
+            self.false_easting + self.radius * eta,
+            self.false_northing + self.radius * xi,
+        )
+    }
+
+    /// Find the longitude and latitude (in radians) of the easting and northing.
+    fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let e = self.eccentricity;
+        let xi = (y - self.false_northing) / self.radius;
+        let eta = (x - self.false_easting) / self.radius;
+        let (mut xi0, mut eta0) = (xi, eta);
+        for (j, beta) in self.beta.iter().enumerate() {
+            let k = 2.0 * (j + 1) as f64;
+            xi0 -= beta * (k * xi).sin() * (k * eta).cosh();
+            eta0 -= beta * (k * xi).cos() * (k * eta).sinh();
+        }
+        let lon = self.central_meridian + eta0.sinh().atan2(xi0.cos());
+        // the tangent of the conformal latitude, solved for the tangent of the latitude with
+        // Newton's method
+        let t0 = xi0.sin() / (eta0.sinh().powi(2) + xi0.cos().powi(2)).sqrt();
+        let mut t = t0;
+        for _ in 0..5 {
+            let sigma = (e * (e * t / (1.0 + t * t).sqrt()).atanh()).sinh();
+            let ti = t * (1.0 + sigma * sigma).sqrt() - sigma * (1.0 + t * t).sqrt();
+            t += (t0 - ti) / (1.0 + ti * ti).sqrt() * (1.0 + (1.0 - e * e) * t * t)
+                / ((1.0 - e * e) * (1.0 + t * t).sqrt());
+        }
+        (lon, t.atan())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_geographic_to_utm() {
+        // on the central meridian the northing is the scaled length of the meridian arc
+        let t = Transform::new(4326, 32635).unwrap();
+        let (x, y) = t.transform(27.0, 60.0);
+        assert!((x - 500000.0).abs() < 0.001, "{}", x);
+        assert!((y - 6651411.190).abs() < 0.001, "{}", y);
+
+        // the worked example of Snyder, Map Projections: A Working Manual, p. 269 (Clarke 1866)
+        let tm = TransverseMercator::new(
+            6378206.4,
+            1.0 - (1.0f64 - 0.00676866).sqrt(),
+            (-75.0f64).to_radians(),
+            0.9996,
+            0.0,
+            0.0,
+        );
+        let (x, y) = tm.forward((-73.5f64).to_radians(), 40.5f64.to_radians());
+        assert!((x - 127106.5).abs() < 0.1, "{}", x);
+        assert!((y - 4484124.4).abs() < 0.1, "{}", y);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let there = Transform::new(4326, 3067).unwrap();
+        let back = Transform::new(3067, 4326).unwrap();
+        for &(lon, lat) in &[(24.94, 60.17), (21.5, 69.0), (31.5, 59.8)] {
+            let (x, y) = there.transform(lon, lat);
+            let (lon2, lat2) = back.transform(x, y);
+            assert!((lon - lon2).abs() < 1e-9 && (lat - lat2).abs() < 1e-9);
+        }
+
+        // between zones through geographic coordinates
+        let (x, y) = Transform::new(3067, 32634)
+            .unwrap()
+            .transform(385000.0, 6672000.0);
+        let (x2, y2) = Transform::new(32634, 3067).unwrap().transform(x, y);
+        assert!((x2 - 385000.0).abs() < 0.001 && (y2 - 6672000.0).abs() < 0.001);
+
+        // the southern hemisphere
+        let (x, y) = Transform::new(4326, 32733).unwrap().transform(15.0, -30.0);
+        assert!((x - 500000.0).abs() < 0.001 && y < 10000000.0 && y > 6000000.0);
+
+        assert!(Transform::new(4326, 2393).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{crs::write_prj, fs::FileSystem};
+use crate::contours::Contour;
+
+/// The shape type of line strings in the main file and the index.
+const SHAPE_TYPE_POLYLINE: i32 = 3;
+/// The length in bytes of the header of the main file and the index.
+const HEADER_LENGTH: usize = 100;
+/// The attribute fields of each contour in the .dbf file: name, type, length and decimals.
+const FIELDS: [(&str, u8, u8, u8); 3] = [
+    ("ELEVATION", b'N', 12, 3),
+    ("INDEX", b'L', 1, 0),
+    ("FORMLINE", b'L', 1, 0),
+];
+
+/// Write contours as a Shapefile of PolyLine shapes: the main file at `path` (`.shp`), its index
+/// (`.shx`) and the attributes (`.dbf`) with the elevation and whether the contour is an index
+/// contour or a form line, plus a `.prj` for `epsg`, see [`write_prj`].
+pub fn write_contours_shapefile(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    contours: &[Contour],
+    epsg: Option<u32>,
+) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let content_lengths = contours
+        .iter()
+        .map(|c| 44 + 4 + 16 * c.points.len())
+        .collect::<Vec<_>>();
+    let bbox = bounding_box(contours.iter().flat_map(|c| c.points.iter()));
+
+    let mut shp = BufWriter::new(fs.create(path.with_extension("shp"))?);
+    let shp_length = HEADER_LENGTH + content_lengths.iter().map(|l| 8 + l).sum::<usize>();
+    write_header(&mut shp, shp_length, bbox)?;
+    for (i, (contour, &length)) in contours.iter().zip(&content_lengths).enumerate() {
+        shp.write_all(&(i as i32 + 1).to_be_bytes())?;
+        shp.write_all(&((length / 2) as i32).to_be_bytes())?;
+        shp.write_all(&SHAPE_TYPE_POLYLINE.to_le_bytes())?;
+        for v in bounding_box(contour.points.iter()) {
+            shp.write_all(&v.to_le_bytes())?;
+        }
+        // a single part starting at the first point
+        shp.write_all(&1i32.to_le_bytes())?;
+        shp.write_all(&(contour.points.len() as i32).to_le_bytes())?;
+        shp.write_all(&0i32.to_le_bytes())?;
+        for &(x, y) in &contour.points {
+            shp.write_all(&x.to_le_bytes())?;
+            shp.write_all(&y.to_le_bytes())?;
+        }
+    }
+    shp.flush()?;
+
+    let mut shx = BufWriter::new(fs.create(path.with_extension("shx"))?);
+    write_header(&mut shx, HEADER_LENGTH + 8 * contours.len(), bbox)?;
+    let mut offset = HEADER_LENGTH;
+    for &length in &content_lengths {
+        shx.write_all(&((offset / 2) as i32).to_be_bytes())?;
+        shx.write_all(&((length / 2) as i32).to_be_bytes())?;
+        offset += 8 + length;
+    }
+    shx.flush()?;
+
+    let mut dbf = BufWriter::new(fs.create(path.with_extension("dbf"))?);
+    let record_length = 1 + FIELDS.iter().map(|f| f.2 as usize).sum::<usize>();
+    // dBASE III, dated 1900-01-01 so that the output does not depend on when it was written
+    dbf.write_all(&[0x03, 0, 1, 1])?;
+    dbf.write_all(&(contours.len() as u32).to_le_bytes())?;
+    dbf.write_all(&((32 + 32 * FIELDS.len() + 1) as u16).to_le_bytes())?;
+    dbf.write_all(&(record_length as u16).to_le_bytes())?;
+    dbf.write_all(&[0; 20])?;
+    for (name, kind, length, decimals) in FIELDS {
+        let mut descriptor = [0u8; 32];
+        descriptor[..name.len()].copy_from_slice(name.as_bytes());
+        descriptor[11] = kind;
+        descriptor[16] = length;
+        descriptor[17] = decimals;
+        dbf.write_all(&descriptor)?;
+    }
+    dbf.write_all(&[0x0d])?;
+    let flag = |b: bool| if b { "T" } else { "F" };
+    for contour in contours {
+        write!(
+            dbf,
+            " {:>12.3}{}{}",
+            contour.elevation,
+            flag(contour.index),
+            flag(contour.form_line)
+        )?;
+    }
+    dbf.write_all(&[0x1a])?;
+    dbf.flush()?;
+
+    write_prj(fs, path, epsg)
+}
+
+/// The `[xmin, ymin, xmax, ymax]` of the points, all zero without any.
+fn bounding_box<'a>(points: impl Iterator<Item = &'a (f64, f64)>) -> [f64; 4] {
+    let mut bbox = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    for &(x, y) in points {
+        bbox = [
+            bbox[0].min(x),
+            bbox[1].min(y),
+            bbox[2].max(x),
+            bbox[3].max(y),
+        ];
+    }
+    if bbox[0] > bbox[2] {
+        return [0.0; 4];
+    }
+    bbox
+}
+
+/// Write the header shared by the main file and the index, for a file of `length` bytes.
+fn write_header<W: Write>(writer: &mut W, length: usize, bbox: [f64; 4]) -> std::io::Result<()> {
+    writer.write_all(&9994i32.to_be_bytes())?;
+    writer.write_all(&[0; 20])?;
+    writer.write_all(&((length / 2) as i32).to_be_bytes())?;
+    writer.write_all(&1000i32.to_le_bytes())?;
+    writer.write_all(&SHAPE_TYPE_POLYLINE.to_le_bytes())?;
+    for v in bbox {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    // the z and m ranges, unused for PolyLine
+    writer.write_all(&[0; 32])
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    fn i32_be(data: &[u8], at: usize) -> i32 {
+        i32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    fn i32_le(data: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    fn f64_le(data: &[u8], at: usize) -> f64 {
+        f64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_write_contours_shapefile() {
+        let fs = MemoryFileSystem::new();
+        let contours = vec![
+            Contour {
+                points: vec![
+                    (500000.0, 6700000.0),
+                    (500010.0, 6700005.0),
+                    (500020.0, 6700000.0),
+                ],
+                elevation: 102.5,
+                index: false,
+                form_line: false,
+            },
+            Contour {
+                points: vec![(500005.0, 6699990.0), (500015.0, 6700020.0)],
+                elevation: 105.0,
+                index: true,
+                form_line: false,
+            },
+        ];
+        write_contours_shapefile(&fs, "contours.shp", &contours, Some(3067)).unwrap();
+
+        let mut shp = Vec::new();
+        fs.open("contours.shp")
+            .unwrap()
+            .read_to_end(&mut shp)
+            .unwrap();
+        assert_eq!(i32_be(&shp, 0), 9994);
+        assert_eq!(i32_be(&shp, 24) as usize * 2, shp.len());
+        assert_eq!(i32_le(&shp, 32), SHAPE_TYPE_POLYLINE);
+        assert_eq!(
+            [
+                f64_le(&shp, 36),
+                f64_le(&shp, 44),
+                f64_le(&shp, 52),
+                f64_le(&shp, 60)
+            ],
+            [500000.0, 6699990.0, 500020.0, 6700020.0]
+        );
+
+        // walk the records and read back the points
+        let mut offset = HEADER_LENGTH;
+        let mut shapes = Vec::new();
+        while offset < shp.len() {
+            assert_eq!(i32_be(&shp, offset), shapes.len() as i32 + 1);
+            let content = &shp[offset + 8..offset + 8 + i32_be(&shp, offset + 4) as usize * 2];
+            assert_eq!(i32_le(content, 0), SHAPE_TYPE_POLYLINE);
+            assert_eq!(i32_le(content, 36), 1);
+            let points = (0..i32_le(content, 40) as usize)
+                .map(|i| (f64_le(content, 48 + 16 * i), f64_le(content, 56 + 16 * i)))
+                .collect::<Vec<_>>();
+            shapes.push(points);
+            offset += 8 + content.len();
+        }
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0], contours[0].points);
+        assert_eq!(shapes[1], contours[1].points);
+
+        let mut shx = Vec::new();
+        fs.open("contours.shx")
+            .unwrap()
+            .read_to_end(&mut shx)
+            .unwrap();
+        assert_eq!(shx.len(), HEADER_LENGTH + 2 * 8);
+        assert_eq!(i32_be(&shx, 100), 50);
+        assert_eq!(
+            i32_be(&shx, 108) as usize * 2,
+            HEADER_LENGTH + 8 + 44 + 4 + 3 * 16
+        );
+
+        let mut dbf = Vec::new();
+        fs.open("contours.dbf")
+            .unwrap()
+            .read_to_end(&mut dbf)
+            .unwrap();
+        assert_eq!(u32::from_le_bytes(dbf[4..8].try_into().unwrap()), 2);
+        let header_length = u16::from_le_bytes(dbf[8..10].try_into().unwrap()) as usize;
+        let record_length = u16::from_le_bytes(dbf[10..12].try_into().unwrap()) as usize;
+        assert_eq!(&dbf[32..41], b"ELEVATION");
+        let records = dbf[header_length..header_length + 2 * record_length]
+            .chunks(record_length)
+            .map(|r| String::from_utf8(r.to_vec()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records, vec!["      102.500FF", "      105.000TF"]);
+        assert_eq!(dbf.last(), Some(&0x1a));
+
+        assert!(fs.exists("contours.prj"));
+    }
+}
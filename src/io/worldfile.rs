@@ -0,0 +1,73 @@
+use std::{
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::fs::FileSystem;
+
+/// Write the world file (.pgw) that georeferences a PNG image, next to the image itself.
+///
+/// `x` and `y` are the world coordinates of the center of the upper-left pixel and `pixel_size`
+/// is the size of a (square) pixel in world units. The image is assumed to be north-up, so the
+/// rotation terms are zero and the y pixel size is negative.
+pub fn write_world_file(
+    fs: &impl FileSystem,
+    png: impl AsRef<Path>,
+    x: f64,
+    y: f64,
+    pixel_size: f64,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(fs.create(png.as_ref().with_extension("pgw"))?);
+    write!(
+        writer,
+        "{}\r\n0\r\n0\r\n{}\r\n{}\r\n{}\r\n",
+        pixel_size, -pixel_size, x, y
+    )?;
+    writer.flush()
+}
+
+/// Read the world file (.pgw) next to a PNG image, returning `(x, y, pixel_size)` like they are
+/// passed to [`write_world_file`]. Rotation terms are ignored.
+pub fn read_world_file(
+    fs: &impl FileSystem,
+    png: impl AsRef<Path>,
+) -> std::io::Result<(f64, f64, f64)> {
+    let pgw = png.as_ref().with_extension("pgw");
+    let data = fs.read_to_string(&pgw)?;
+    let values = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|values| values.len() == 6)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid world file {}", pgw.display()),
+            )
+        })?;
+    Ok((values[4], values[5], values[0]))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    #[test]
+    fn test_write_world_file() {
+        let fs = MemoryFileSystem::new();
+        write_world_file(&fs, "tile.png", 500000.5, 6700999.5, 1.0).unwrap();
+
+        let pgw = fs.read_to_string("tile.pgw").unwrap();
+        let lines: Vec<&str> = pgw.lines().collect();
+        assert_eq!(lines, vec!["1", "0", "0", "-1", "500000.5", "6700999.5"]);
+
+        assert_eq!(
+            read_world_file(&fs, "tile.png").unwrap(),
+            (500000.5, 6700999.5, 1.0)
+        );
+    }
+}
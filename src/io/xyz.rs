@@ -102,6 +102,11 @@ impl<W: Write + Seek> XyzInternalWriter<W> {
             )
         })?;
 
+        // the header is written with the first record, so an empty file still needs it
+        if self.records_written == 0 {
+            inner.write_all(XYZ_MAGIC)?;
+        }
+
         // seek to the beginning of the file and write the number of records
         inner.seek(std::io::SeekFrom::Start(XYZ_MAGIC.len() as u64))?;
         self.records_written.to_bytes(&mut inner)?;
@@ -238,4 +243,13 @@ mod test {
         assert_eq!(reader.next().unwrap().unwrap(), record);
         assert_eq!(reader.next().unwrap(), None);
     }
+
+    #[test]
+    fn test_writer_reader_empty() {
+        let mut writer = XyzInternalWriter::new(Cursor::new(Vec::new()));
+
+        let data = writer.finish().unwrap().into_inner();
+        let mut reader = super::XyzInternalReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(reader.next().unwrap(), None);
+    }
 }
@@ -1,7 +1,9 @@
 use image::{GrayImage, Luma};
 use imageproc::drawing::draw_line_segment_mut;
 use log::info;
-use rustc_hash::FxHashMap as HashMap;
+use rustc_hash::{FxHashMap as HashMap, FxHashSet as HashSet};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::error::Error;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
@@ -11,6 +13,130 @@ use crate::io::bytes::FromToBytes;
 use crate::io::fs::FileSystem;
 use crate::io::heightmap::HeightMap;
 use crate::util::read_lines_no_alloc;
+use crate::vec2d::Vec2D;
+
+/// A knoll or depression small enough to be drawn as a dot, in grid coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DotKnoll {
+    pub x: usize,
+    pub y: usize,
+    pub depression: bool,
+}
+
+/// Find the local maxima (knolls) and minima (depressions) of a DEM that rise at least
+/// `min_prominence` above (or sink below) the saddle to any higher (lower) terrain, and whose area
+/// above (below) the level `min_prominence` from the top is at least `min_area` cells.
+///
+/// Extrema whose surroundings run into the edge of the grid or NaN cells before they qualify are
+/// skipped, since their prominence cannot be known.
+pub fn detect_dot_knolls(grid: &Vec2D<f64>, min_prominence: f64, min_area: usize) -> Vec<DotKnoll> {
+    let (w, h) = grid.dimensions();
+    let mut dots = Vec::new();
+    for x in 1..w.saturating_sub(1) {
+        for y in 1..h.saturating_sub(1) {
+            let v = grid[(x, y)];
+            if v.is_nan() {
+                continue;
+            }
+            let mut higher = false;
+            let mut lower = false;
+            for (nx, ny) in neighbors8(x, y, w, h) {
+                let n = grid[(nx, ny)];
+                // NaN neighbors rule out both
+                higher |= n >= v || n.is_nan();
+                lower |= n <= v || n.is_nan();
+            }
+            for depression in [false, true] {
+                let extremum = if depression { !lower } else { !higher };
+                if extremum && is_prominent(grid, x, y, depression, min_prominence, min_area) {
+                    dots.push(DotKnoll { x, y, depression });
+                }
+            }
+        }
+    }
+    dots
+}
+
+fn neighbors8(x: usize, y: usize, w: usize, h: usize) -> impl Iterator<Item = (usize, usize)> {
+    (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&d| d != (0, 0))
+        .filter_map(move |(dx, dy)| {
+            let nx = x.checked_add_signed(dx).filter(|&nx| nx < w)?;
+            let ny = y.checked_add_signed(dy).filter(|&ny| ny < h)?;
+            Some((nx, ny))
+        })
+}
+
+/// Flood out from an extremum, always taking the highest (for depressions the lowest) cell next to
+/// the region. If the flood drops `min_prominence` below the top before reaching higher terrain,
+/// every way out of the region goes through a saddle at least that low.
+fn is_prominent(
+    grid: &Vec2D<f64>,
+    x: usize,
+    y: usize,
+    depression: bool,
+    min_prominence: f64,
+    min_area: usize,
+) -> bool {
+    // flip depressions upside down so that both are searched as knolls
+    let sign = if depression { -1.0 } else { 1.0 };
+    let top = sign * grid[(x, y)];
+    let (w, h) = grid.dimensions();
+
+    // only the neighborhood of the extremum is usually visited, so avoid a full size grid
+    let mut visited = HashSet::default();
+    let mut frontier = BinaryHeap::new();
+    frontier.push(Cell(top, x, y));
+    visited.insert((x, y));
+    let mut area = 0;
+    while let Some(Cell(v, cx, cy)) = frontier.pop() {
+        if v > top {
+            return false;
+        }
+        if v <= top - min_prominence {
+            return area >= min_area;
+        }
+        // the terrain beyond the edge is unknown
+        if cx == 0 || cy == 0 || cx == w - 1 || cy == h - 1 {
+            return false;
+        }
+        area += 1;
+        for (nx, ny) in neighbors8(cx, cy, w, h) {
+            let n = sign * grid[(nx, ny)];
+            if n.is_nan() {
+                return false;
+            }
+            if visited.insert((nx, ny)) {
+                frontier.push(Cell(n, nx, ny));
+            }
+        }
+    }
+    false
+}
+
+/// A cell of the flood frontier, ordered by its height.
+struct Cell(f64, usize, usize);
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.total_cmp(&other.0).is_eq()
+    }
+}
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
 
 pub fn dotknolls(
     fs: &impl FileSystem,
@@ -95,49 +221,67 @@ pub fn dotknolls(
         }
     }
 
-    let input = tmpfolder.join("dotknolls.txt");
-    read_lines_no_alloc(fs, input, |line| {
-        let parts = line.split(' ');
-        let r = parts.collect::<Vec<&str>>();
-        if r.len() >= 3 {
-            let depression: bool = r[0] == "1";
-            let x: f64 = r[1].parse::<f64>().unwrap();
-            let y: f64 = r[2].parse::<f64>().unwrap();
-            let mut ok = true;
-            let mut i = (x - xstart) / scalefactor - 3.0;
-            while i < (x - xstart) / scalefactor + 4.0 && ok {
-                let mut j = (y - ystart) / scalefactor - 3.0;
-                while j < (y - ystart) / scalefactor + 4.0 && ok {
-                    if (i as u32) >= im.width() || (j as u32) >= im.height() {
-                        ok = false;
-                        break;
-                    }
-                    let pix = im.get_pixel(i as u32, j as u32);
-                    if pix[0] == 0 {
-                        ok = false;
-                        break;
-                    }
-                    j += 1.0;
-                }
-                i += 1.0;
+    // (knoll, x, y) of each dot, where knoll is false for depressions
+    let mut dots: Vec<(bool, f64, f64)> = Vec::new();
+    if config.knoll_min_prominence > 0.0 {
+        let min_area = (config.knoll_min_area / (size * size)).ceil() as usize;
+        for dot in detect_dot_knolls(&hmap.grid, config.knoll_min_prominence, min_area) {
+            dots.push((
+                !dot.depression,
+                xstart + dot.x as f64 * size,
+                ystart + dot.y as f64 * size,
+            ));
+        }
+    } else {
+        let input = tmpfolder.join("dotknolls.txt");
+        read_lines_no_alloc(fs, input, |line| {
+            let parts = line.split(' ');
+            let r = parts.collect::<Vec<&str>>();
+            if r.len() >= 3 {
+                dots.push((
+                    r[0] == "1",
+                    r[1].parse::<f64>().unwrap(),
+                    r[2].parse::<f64>().unwrap(),
+                ));
             }
+        })
+        .expect("Could not read file");
+    }
 
-            let layer = match (ok, depression) {
-                (true, true) => "dotknoll",
-                (true, false) => "udepression",
-                (false, true) => "uglydotknoll",
-                (false, false) => "uglyudepression",
-            };
-
-            write!(
-                &mut f,
-                "POINT\r\n  8\r\n{}\r\n 10\r\n{}\r\n 20\r\n{}\r\n 50\r\n0\r\n  0\r\n",
-                layer, x, y
-            )
-            .expect("Can not write to file");
+    for (knoll, x, y) in dots {
+        let mut ok = true;
+        let mut i = (x - xstart) / scalefactor - 3.0;
+        while i < (x - xstart) / scalefactor + 4.0 && ok {
+            let mut j = (y - ystart) / scalefactor - 3.0;
+            while j < (y - ystart) / scalefactor + 4.0 && ok {
+                if (i as u32) >= im.width() || (j as u32) >= im.height() {
+                    ok = false;
+                    break;
+                }
+                let pix = im.get_pixel(i as u32, j as u32);
+                if pix[0] == 0 {
+                    ok = false;
+                    break;
+                }
+                j += 1.0;
+            }
+            i += 1.0;
         }
-    })
-    .expect("Could not read file");
+
+        let layer = match (ok, knoll) {
+            (true, true) => "dotknoll",
+            (true, false) => "udepression",
+            (false, true) => "uglydotknoll",
+            (false, false) => "uglyudepression",
+        };
+
+        write!(
+            &mut f,
+            "POINT\r\n  8\r\n{}\r\n 10\r\n{}\r\n 20\r\n{}\r\n 50\r\n0\r\n  0\r\n",
+            layer, x, y
+        )
+        .expect("Can not write to file");
+    }
 
     f.write_all("ENDSEC\r\n  0\r\nEOF\r\n".as_bytes())
         .expect("Can not write to file");
@@ -1105,3 +1249,45 @@ pub fn xyzknolls(
     info!("Done");
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect_dot_knolls() {
+        // a gentle slope with one tall bump and one shallow pit
+        let mut grid = Vec2D::new(30, 20, 0.0);
+        for (x, y, v) in grid.iter_mut() {
+            *v = 100.0 + 0.01 * x as f64;
+            let bump =
+                (((x as f64 - 8.0).powi(2) + (y as f64 - 10.0).powi(2)).sqrt() / 6.0).min(1.0);
+            *v += 4.0 * (1.0 - bump);
+            let pit =
+                (((x as f64 - 22.0).powi(2) + (y as f64 - 10.0).powi(2)).sqrt() / 3.0).min(1.0);
+            *v -= 0.4 * (1.0 - pit);
+        }
+
+        let dots = detect_dot_knolls(&grid, 1.0, 4);
+        assert_eq!(
+            dots,
+            vec![DotKnoll {
+                x: 8,
+                y: 10,
+                depression: false
+            }]
+        );
+
+        // with a lower threshold the pit shows up as a depression
+        let dots = detect_dot_knolls(&grid, 0.2, 1);
+        assert_eq!(dots.len(), 2);
+        assert!(dots.contains(&DotKnoll {
+            x: 22,
+            y: 10,
+            depression: true
+        }));
+
+        // the bump is too small for a large minimum area
+        assert!(detect_dot_knolls(&grid, 1.0, 100).is_empty());
+    }
+}
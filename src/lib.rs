@@ -4,18 +4,28 @@
 #![deny(clippy::undocumented_unsafe_blocks)]
 
 pub mod blocks;
+pub mod cancel;
 pub mod cliffs;
 pub mod config;
 pub mod contours;
 pub mod crop;
+pub mod density;
+pub mod dsm;
+pub mod error;
+pub mod hillshade;
+pub mod intensity;
 pub mod io;
 pub mod knolls;
+pub mod manifest;
 pub mod merge;
 pub mod process;
+pub mod progress;
 pub mod render;
+pub mod tin;
 pub mod util;
 pub mod vec2d;
 pub mod vegetation;
+pub mod water;
 
 #[cfg(feature = "shapefile")]
 pub mod shapefile;
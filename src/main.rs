@@ -8,7 +8,6 @@ use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::{thread, time};
 
 fn main() {
     // setup and configure logging, default to INFO when RUST_LOG is not set
@@ -30,10 +29,23 @@ fn main() {
         })
         .init();
 
+    // show the progress of the long running stages as a bar in steps of 10 %, for each thread
+    pullauta::progress::set_progress_handler(|stage, fraction| {
+        thread_local! {
+            static LAST: std::cell::Cell<Option<(pullauta::progress::Stage, usize)>> =
+                const { std::cell::Cell::new(None) };
+        }
+        let step = (fraction * 10.0).floor() as usize;
+        if LAST.get() != Some((stage, step)) {
+            LAST.set(Some((stage, step)));
+            info!("{:?} [{:<10}] {:>3}%", stage, "#".repeat(step), step * 10);
+        }
+    });
+
     let mut thread: String = String::new();
 
-    let config =
-        Arc::new(Config::load_or_create_default().expect("Could not open or create config file"));
+    let mut config =
+        Config::load_or_create_default().expect("Could not open or create config file");
 
     let fs = pullauta::io::fs::local::LocalFileSystem;
 
@@ -41,6 +53,28 @@ fn main() {
 
     args.remove(0); // program name
 
+    // --no-cache bypasses the heightmap cache for this run
+    if let Some(i) = args.iter().position(|a| a == "--no-cache") {
+        args.remove(i);
+        config.dem_cache = false;
+    }
+    let config = Arc::new(config);
+
+    // --threads N overrides the number of parallel batch processes from the config, 0 = one per core.
+    // processes 0 and 1 both process the tiles one at a time
+    let mut threads = (config.processes as usize).max(1);
+    if let Some(i) = args.iter().position(|a| a == "--threads") {
+        args.remove(i);
+        if i >= args.len() {
+            eprintln!("--threads requires the number of threads");
+            std::process::exit(1);
+        }
+        threads = args
+            .remove(i)
+            .parse()
+            .expect("--threads requires the number of threads");
+    }
+
     if !args.is_empty() && args[0].trim().parse::<usize>().is_ok() {
         thread = args.remove(0);
     }
@@ -163,6 +197,18 @@ fn main() {
         return;
     }
 
+    if command == "debug-render" {
+        if args.len() < 2 {
+            info!("USAGE: debug-render [cache file] [png file]");
+            return;
+        }
+
+        let input = Path::new(&args[0]);
+        let output = Path::new(&args[1]);
+        pullauta::io::debug_render(&fs, input, output).unwrap();
+        return;
+    }
+
     if command == "blocks" {
         pullauta::blocks::blocks(&fs, &tmpfolder).unwrap();
         return;
@@ -269,8 +315,15 @@ fn main() {
             hmap.to_file(&fs, xyzfileout).unwrap();
         }
 
-        pullauta::contours::heightmap2contours(&fs, &tmpfolder, cinterval, &hmap, &dxffile)
-            .unwrap();
+        pullauta::contours::heightmap2contours(
+            &fs,
+            &tmpfolder,
+            cinterval,
+            &hmap,
+            &dxffile,
+            &pullauta::cancel::CancellationToken::new(),
+        )
+        .unwrap();
         return;
     }
 
@@ -290,32 +343,7 @@ fn main() {
         .unwrap();
         return;
     }
-    let proc = config.processes;
-    if command.is_empty() && batch && proc > 1 {
-        // inner function to reduce code duplication
-        fn launch_threads<F: FileSystem + Send + Clone + 'static>(
-            fs: F,
-            proc: u64,
-            config: &Arc<Config>,
-        ) {
-            // do the processing
-            let mut handles: Vec<thread::JoinHandle<()>> = Vec::with_capacity((proc + 1) as usize);
-            for i in 0..proc {
-                let config = config.clone();
-                let fs = fs.clone();
-                let handle = thread::spawn(move || {
-                    info!("Starting thread");
-                    pullauta::process::batch_process(&config, &fs, &format!("{}", i + 1));
-                    info!("Thread complete");
-                });
-                thread::sleep(time::Duration::from_millis(100));
-                handles.push(handle);
-            }
-            for handle in handles {
-                handle.join().unwrap();
-            }
-        }
-
+    if command.is_empty() && batch && threads != 1 {
         if config.experimental_use_in_memory_fs {
             // copy all the input files into the memory file system
             let fs = pullauta::io::fs::memory::MemoryFileSystem::new();
@@ -327,7 +355,7 @@ fn main() {
                 fs.load_from_disk(&path, &path).unwrap();
             }
 
-            launch_threads(fs.clone(), proc, &config);
+            pullauta::process::batch_process_parallel(&config, &fs, threads);
 
             // copy the output files back to disk
             std::fs::create_dir_all(&config.batchoutfolder).unwrap();
@@ -336,12 +364,12 @@ fn main() {
                 fs.save_to_disk(&path, &path).unwrap();
             }
         } else {
-            launch_threads(fs, proc, &config);
+            pullauta::process::batch_process_parallel(&config, &fs, threads);
         }
         return;
     }
 
-    if (command.is_empty() && batch && proc < 2) || (command == "startthread" && batch) {
+    if (command.is_empty() && batch && threads == 1) || (command == "startthread" && batch) {
         thread = String::from("0");
         if !args.is_empty() {
             thread.clone_from(&args[0]);
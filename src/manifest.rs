@@ -0,0 +1,194 @@
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::io::fs::FileSystem;
+
+/// A record of what a batch run read and produced, written as JSON with [`Manifest::write`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// The input files of the processed tiles.
+    pub inputs: Vec<PathBuf>,
+    /// The files written to the output folder.
+    pub outputs: Vec<PathBuf>,
+    /// The input files that could not be read, skipping the tiles that needed them.
+    pub skipped: Vec<PathBuf>,
+    /// The total time spent in each processing stage, in the order the stages first ran.
+    pub timings: Vec<(String, Duration)>,
+}
+
+impl Manifest {
+    /// Add the time spent in the stages of one tile to the totals.
+    pub fn add_timings<'a>(&mut self, sections: impl IntoIterator<Item = (&'a str, Duration)>) {
+        for (name, duration) in sections {
+            match self.timings.iter_mut().find(|(n, _)| n == name) {
+                Some((_, total)) => *total += duration,
+                None => self.timings.push((name.to_string(), duration)),
+            }
+        }
+    }
+
+    /// Add the inputs, outputs and timings of another (worker's) manifest to this one.
+    pub fn merge(&mut self, other: Manifest) {
+        self.inputs.extend(other.inputs);
+        self.outputs.extend(other.outputs);
+        self.skipped.extend(other.skipped);
+        self.add_timings(other.timings.iter().map(|(name, d)| (name.as_str(), *d)));
+    }
+
+    /// Set the outputs to the files in `folder` modified at or after `since`, i.e. written by the
+    /// run that started then.
+    pub fn collect_outputs(
+        &mut self,
+        fs: &impl FileSystem,
+        folder: impl AsRef<Path>,
+        since: SystemTime,
+    ) -> std::io::Result<()> {
+        let mut outputs = Vec::new();
+        for path in fs.list(folder)? {
+            if fs.modified(&path)? >= since {
+                outputs.push(path);
+            }
+        }
+        outputs.sort();
+        self.outputs = outputs;
+        Ok(())
+    }
+
+    /// Write the manifest as JSON, together with the crate version, the settings of `config`,
+    /// their [`config_hash`] and the CRS.
+    pub fn write(
+        &self,
+        fs: &impl FileSystem,
+        config: &Config,
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(fs.create(path)?);
+        writeln!(writer, "{{")?;
+        writeln!(
+            writer,
+            "  \"version\": {},",
+            json_string(env!("CARGO_PKG_VERSION"))
+        )?;
+        writeln!(
+            writer,
+            "  \"config_hash\": \"{:016x}\",",
+            config_hash(&config.settings)
+        )?;
+        writeln!(writer, "  \"config\": {{")?;
+        for (i, (key, value)) in config.settings.iter().enumerate() {
+            let separator = if i + 1 < config.settings.len() {
+                ","
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "    {}: {}{}",
+                json_string(key),
+                json_string(value),
+                separator
+            )?;
+        }
+        writeln!(writer, "  }},")?;
+        match config.crs_epsg {
+            Some(epsg) => writeln!(writer, "  \"crs_epsg\": {},", epsg)?,
+            None => writeln!(writer, "  \"crs_epsg\": null,")?,
+        }
+        for (name, paths) in [
+            ("inputs", &self.inputs),
+            ("outputs", &self.outputs),
+            ("skipped", &self.skipped),
+        ] {
+            let paths = paths
+                .iter()
+                .map(|p| json_string(&p.to_string_lossy()))
+                .collect::<Vec<_>>();
+            writeln!(writer, "  \"{}\": [{}],", name, paths.join(", "))?;
+        }
+        let timings = self
+            .timings
+            .iter()
+            .map(|(name, duration)| format!("{}: {:.3}", json_string(name), duration.as_secs_f64()))
+            .collect::<Vec<_>>();
+        writeln!(writer, "  \"timings\": {{{}}}", timings.join(", "))?;
+        writeln!(writer, "}}")?;
+        writer.flush()
+    }
+}
+
+/// A hash of the settings that identifies the effective configuration together with the crate
+/// version (which determines the defaults of the unset keys). The settings are hashed sorted by
+/// key with 64 bit FNV-1a, so the hash does not depend on the order of the file or the Rust
+/// version.
+pub fn config_hash(settings: &[(String, String)]) -> u64 {
+    let mut sorted = settings.iter().collect::<Vec<_>>();
+    sorted.sort();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (key, value) in sorted {
+        for byte in key
+            .bytes()
+            .chain([b'='])
+            .chain(value.bytes())
+            .chain([b'\n'])
+        {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Quote and escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_hash() {
+        let settings = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let a = config_hash(&settings(&[("scalefactor", "1"), ("batch", "1")]));
+        assert_eq!(
+            a,
+            config_hash(&settings(&[("batch", "1"), ("scalefactor", "1")]))
+        );
+        assert_ne!(
+            a,
+            config_hash(&settings(&[("batch", "1"), ("scalefactor", "2")]))
+        );
+        assert_ne!(
+            a,
+            config_hash(&settings(&[("batch", "1"), ("scalefactor1", "")]))
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("in/a.las"), "\"in/a.las\"");
+        assert_eq!(json_string("c:\\in \"x\"\n"), "\"c:\\\\in \\\"x\\\"\\n\"");
+    }
+}
@@ -1,133 +1,146 @@
-use image::{Rgb, RgbImage};
+use image::{DynamicImage, Rgba, RgbaImage};
 use log::info;
 use rustc_hash::FxHashMap as HashMap;
 use std::error::Error;
 use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
-use crate::config::Config;
+use crate::config::{Background, Config, ContourSmoothing};
+use crate::contours::{form_lines, segment_distance, Contour};
 use crate::io::bytes::FromToBytes;
+use crate::io::crs::write_prj;
+use crate::io::dxf::write_contours_dxf;
 use crate::io::fs::FileSystem;
+use crate::io::geojson::write_geojson;
 use crate::io::heightmap::HeightMap;
+use crate::io::shp::write_contours_shapefile;
+use crate::io::worldfile::{read_world_file, write_world_file};
 use crate::vec2d::Vec2D;
 
-fn merge_png(
-    fs: &impl FileSystem,
-    config: &Config,
-    png_files: Vec<PathBuf>,
-    outfilename: &str,
-    scale: f64,
-) -> Result<(), Box<dyn Error>> {
-    let batchoutfolder = &config.batchoutfolder;
+/// A raster stitched together from georeferenced tiles.
+pub struct Mosaic {
+    /// RGB, or RGBA with a transparent background.
+    pub image: DynamicImage,
+    /// World coordinates of the upper-left corner, as in the world files of the tiles.
+    pub x: f64,
+    pub y: f64,
+    pub pixel_size: f64,
+}
 
-    let mut xmin = f64::MAX;
-    let mut ymin = f64::MAX;
-    let mut xmax = f64::MIN;
-    let mut ymax = f64::MIN;
-    let mut res = f64::NAN;
-    for png in png_files.iter() {
-        let filename = png.as_path().file_name().unwrap().to_str().unwrap();
-        let full_filename = format!("{}/{}", batchoutfolder, filename);
-        let img = fs
-            .read_image_png(&full_filename)
-            .expect("Opening image failed");
-
-        let width = img.width() as f64;
-        let height = img.height() as f64;
-        let pgw = full_filename.replace(".png", ".pgw");
-        let input = Path::new(&pgw);
-        if fs.exists(input) {
-            let data = fs.read_to_string(input).expect("Can not read input file");
-            let d: Vec<&str> = data.split('\n').collect();
-            let tfw0 = d[0].trim().parse::<f64>().unwrap();
-            let tfw4 = d[4].trim().parse::<f64>().unwrap();
-            let tfw5 = d[5].trim().parse::<f64>().unwrap();
+impl Mosaic {
+    /// Write the mosaic as a PNG together with its world file.
+    pub fn write_png(
+        &self,
+        fs: &impl FileSystem,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        self.image.write_to(
+            &mut BufWriter::new(fs.create(path)?),
+            image::ImageFormat::Png,
+        )?;
+        write_world_file(fs, path, self.x, self.y, self.pixel_size)?;
+        Ok(())
+    }
+}
 
-            if res.is_nan() {
-                res = tfw0;
-            }
-            if tfw4 < xmin {
-                xmin = tfw4;
-            }
-            if (tfw4 + width * res) > xmax {
-                xmax = tfw4 + width * res;
-            }
-            if tfw5 > ymax {
-                ymax = tfw5;
-            }
-            if (tfw5 - height * res) < ymin {
-                ymin = tfw5 - height * res;
-            }
+/// Stitch PNG tiles georeferenced by their world files into one raster, downscaled by `scale`.
+///
+/// The pixel size of the mosaic is that of the first tile times `scale`. Every tile is snapped to
+/// the nearest pixel of that common grid, so tiles whose origins differ by a fraction of a pixel
+/// still line up, and tiles with another pixel size are resized to fit. Where tiles overlap, the
+/// later tile in `tiles` wins. Tiles that are empty or have no world file are skipped, and areas
+/// not covered by any tile are white, or transparent with a transparent `background`.
+pub fn merge_tiles(
+    fs: &impl FileSystem,
+    tiles: &[PathBuf],
+    scale: f64,
+    background: Background,
+) -> Result<Mosaic, Box<dyn Error>> {
+    let mut placed = Vec::with_capacity(tiles.len());
+    for png in tiles {
+        if !fs.exists(png) || fs.file_size(png)? == 0 || !fs.exists(png.with_extension("pgw")) {
+            continue;
         }
+        let (x, y, tile_pixel_size) = read_world_file(fs, png)?;
+        placed.push((png, x, y, tile_pixel_size));
     }
-    let mut im = RgbImage::from_pixel(
-        ((xmax - xmin) / res / scale) as u32,
-        ((ymax - ymin) / res / scale) as u32,
-        Rgb([255, 255, 255]),
-    );
-    for png in png_files.iter() {
-        let filename = png.as_path().file_name().unwrap().to_str().unwrap();
-        let png = format!("{}/{}", batchoutfolder, filename);
-        let pgw = png.replace(".png", ".pgw");
-        let png = Path::new(&png);
-        let pgw = Path::new(&pgw);
-        let filesize = fs.file_size(png).unwrap();
-        if fs.exists(png) && fs.exists(pgw) && filesize > 0 {
-            let img = fs.read_image_png(png).expect("Opening image failed");
-            let width = img.width() as f64;
-            let height = img.height() as f64;
-
-            let data = fs.read_to_string(pgw).expect("Can not read input file");
-            let d: Vec<&str> = data.split('\n').collect();
-            let tfw4 = d[4].trim().parse::<f64>().unwrap();
-            let tfw5 = d[5].trim().parse::<f64>().unwrap();
-
-            let img2 = image::imageops::thumbnail(
-                &img.to_rgb8(),
-                (width / scale + 0.5) as u32,
-                (height / scale + 0.5) as u32,
-            );
-            image::imageops::overlay(
-                &mut im,
-                &img2,
-                ((tfw4 - xmin) / res / scale) as i64,
-                ((-tfw5 + ymax) / res / scale) as i64,
-            );
-        }
+    let Some(&(_, _, _, first_pixel_size)) = placed.first() else {
+        return Err("No georeferenced tiles to merge".into());
+    };
+    let pixel_size = first_pixel_size * scale;
+
+    let xmin = placed.iter().map(|t| t.1).fold(f64::MAX, f64::min);
+    let ymax = placed.iter().map(|t| t.2).fold(f64::MIN, f64::max);
+
+    let mut images = Vec::with_capacity(placed.len());
+    let (mut width, mut height) = (0, 0);
+    for (png, x, y, tile_pixel_size) in placed {
+        let img = fs.read_image_png(png)?;
+        let factor = tile_pixel_size / pixel_size;
+        let img = image::imageops::thumbnail(
+            &img.to_rgba8(),
+            ((img.width() as f64 * factor).round() as u32).max(1),
+            ((img.height() as f64 * factor).round() as u32).max(1),
+        );
+        let col = ((x - xmin) / pixel_size).round() as u32;
+        let row = ((ymax - y) / pixel_size).round() as u32;
+        width = width.max(col + img.width());
+        height = height.max(row + img.height());
+        images.push((img, col, row));
     }
 
-    im.write_to(
-        &mut BufWriter::new(
-            fs.create(format!("{}.jpg", outfilename))
-                .expect("could not save output jpg"),
-        ),
-        image::ImageFormat::Jpeg,
-    )
-    .expect("could not save output jpg");
-
-    im.write_to(
-        &mut BufWriter::new(
-            fs.create(format!("{}.png", outfilename))
-                .expect("could not save output png"),
-        ),
-        image::ImageFormat::Png,
-    )
-    .expect("could not save output Png");
+    let fill = match background {
+        Background::Color(_) => Rgba([255, 255, 255, 255]),
+        Background::Transparent => Rgba([0, 0, 0, 0]),
+    };
+    let mut image = RgbaImage::from_pixel(width, height, fill);
+    for (img, col, row) in images {
+        image::imageops::replace(&mut image, &img, col as i64, row as i64);
+    }
+    let image = match background {
+        Background::Color(_) => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8()),
+        Background::Transparent => DynamicImage::ImageRgba8(image),
+    };
+    Ok(Mosaic {
+        image,
+        x: xmin,
+        y: ymax,
+        pixel_size,
+    })
+}
 
-    let tfw_file = fs
-        .create(format!("{}.pgw", outfilename))
-        .expect("Unable to create file");
-    let mut tfw_out = BufWriter::new(tfw_file);
-    write!(
-        &mut tfw_out,
-        "{}\r\n0\r\n0\r\n{}\r\n{}\r\n{}\r\n",
-        res * scale,
-        -res * scale,
-        xmin,
-        ymax
-    )
-    .expect("Could not write to file");
-    tfw_out.flush().expect("Cannot flush");
+fn merge_png(
+    fs: &impl FileSystem,
+    config: &Config,
+    mut png_files: Vec<PathBuf>,
+    outfilename: &str,
+    scale: f64,
+) -> Result<(), Box<dyn Error>> {
+    // the listing order is arbitrary, sort so that overlaps are resolved the same on every run
+    png_files.sort();
+    let mosaic = merge_tiles(fs, &png_files, scale, config.background)?;
+
+    // JPEG has no alpha channel, so a transparent mosaic is laid on white for it
+    let mut jpg = RgbaImage::from_pixel(
+        mosaic.image.width(),
+        mosaic.image.height(),
+        Rgba([255, 255, 255, 255]),
+    );
+    image::imageops::overlay(&mut jpg, &mosaic.image.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgba8(jpg)
+        .to_rgb8()
+        .write_to(
+            &mut BufWriter::new(
+                fs.create(format!("{}.jpg", outfilename))
+                    .expect("could not save output jpg"),
+            ),
+            image::ImageFormat::Jpeg,
+        )
+        .expect("could not save output jpg");
+
+    mosaic.write_png(fs, format!("{}.png", outfilename))?;
+    write_prj(fs, format!("{}.png", outfilename), config.crs_epsg)?;
     fs.copy(
         Path::new(&format!("{}.pgw", outfilename)),
         Path::new(&format!("{}.jgw", outfilename)),
@@ -551,15 +564,30 @@ pub fn smoothjoin(
         inidotknolls,
         smoothing,
         curviness,
+        contour_smoothing,
+        contour_simplify,
+        min_contour_length,
+        min_ring_area,
         mut indexcontours,
         formline,
         depression_length,
         contour_interval,
+        index_contour_every,
+        contour_geojson,
+        geojson_precision,
+        contour_layered_dxf,
+        contour_shapefile,
+        crs_epsg,
+        formline_by_slope,
+        formline_max_slope,
+        formline_min_distance,
         ..
     } = config;
 
     let halfinterval = contour_interval / 2.0 * scalefactor;
-    if formline > 0.0 {
+    if index_contour_every > 0 {
+        indexcontours = index_contour_every as f64 * contour_interval;
+    } else if formline > 0.0 {
         indexcontours = 5.0 * contour_interval;
     }
 
@@ -575,7 +603,7 @@ pub fn smoothjoin(
     let size = hmap.scale;
     let xmax = (hmap.grid.width() - 1) as u64;
     let ymax = (hmap.grid.height() - 1) as u64;
-    let xyz = hmap.grid;
+    let xyz = &hmap.grid;
 
     let mut steepness = Vec2D::new((xmax + 1) as usize, (ymax + 1) as usize, f64::NAN);
 
@@ -628,6 +656,8 @@ pub fn smoothjoin(
     let knollhead_fp = fs.create(knollhead_output).expect("Unable to create file");
     let mut knollhead_fp = BufWriter::new(knollhead_fp);
 
+    let mut contours = Vec::<Contour>::new();
+
     let mut heads1: HashMap<String, usize> = HashMap::default();
     let mut heads2: HashMap<String, usize> = HashMap::default();
     let mut heads = Vec::<String>::new();
@@ -981,125 +1011,156 @@ pub fn smoothjoin(
                     el_y[l].append(&mut newy);
                     el_x_len = el_x[l].len();
                 }
-                // Smoothing
-                let mut dx: Vec<f64> = vec![f64::NAN; el_x_len];
-                let mut dy: Vec<f64> = vec![f64::NAN; el_x_len];
-
-                for k in 2..(el_x_len - 3) {
-                    dx[k] = (el_x[l][k - 2]
-                        + el_x[l][k - 1]
-                        + el_x[l][k]
-                        + el_x[l][k + 1]
-                        + el_x[l][k + 2]
-                        + el_x[l][k + 3])
-                        / 6.0;
-                    dy[k] = (el_y[l][k - 2]
-                        + el_y[l][k - 1]
-                        + el_y[l][k]
-                        + el_y[l][k + 1]
-                        + el_y[l][k + 2]
-                        + el_y[l][k + 3])
-                        / 6.0;
-                }
+                match contour_smoothing {
+                    ContourSmoothing::Weighted => {
+                        // Smoothing
+                        let mut dx: Vec<f64> = vec![f64::NAN; el_x_len];
+                        let mut dy: Vec<f64> = vec![f64::NAN; el_x_len];
+
+                        for k in 2..(el_x_len - 3) {
+                            dx[k] = (el_x[l][k - 2]
+                                + el_x[l][k - 1]
+                                + el_x[l][k]
+                                + el_x[l][k + 1]
+                                + el_x[l][k + 2]
+                                + el_x[l][k + 3])
+                                / 6.0;
+                            dy[k] = (el_y[l][k - 2]
+                                + el_y[l][k - 1]
+                                + el_y[l][k]
+                                + el_y[l][k + 1]
+                                + el_y[l][k + 2]
+                                + el_y[l][k + 3])
+                                / 6.0;
+                        }
 
-                let mut xa: Vec<f64> = vec![f64::NAN; el_x_len];
-                let mut ya: Vec<f64> = vec![f64::NAN; el_x_len];
-                for k in 1..(el_x_len - 1) {
-                    xa[k] = (el_x[l][k - 1] + el_x[l][k] / (0.01 + smoothing) + el_x[l][k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    ya[k] = (el_y[l][k - 1] + el_y[l][k] / (0.01 + smoothing) + el_y[l][k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                }
+                        let mut xa: Vec<f64> = vec![f64::NAN; el_x_len];
+                        let mut ya: Vec<f64> = vec![f64::NAN; el_x_len];
+                        for k in 1..(el_x_len - 1) {
+                            xa[k] =
+                                (el_x[l][k - 1] + el_x[l][k] / (0.01 + smoothing) + el_x[l][k + 1])
+                                    / (2.0 + 1.0 / (0.01 + smoothing));
+                            ya[k] =
+                                (el_y[l][k - 1] + el_y[l][k] / (0.01 + smoothing) + el_y[l][k + 1])
+                                    / (2.0 + 1.0 / (0.01 + smoothing));
+                        }
 
-                if el_x[l].first() == el_x[l].last() && el_y[l].first() == el_y[l].last() {
-                    let vx = (el_x[l][1] + el_x[l][0] / (0.01 + smoothing) + el_x[l][el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    let vy = (el_y[l][1] + el_y[l][0] / (0.01 + smoothing) + el_y[l][el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    xa[0] = vx;
-                    ya[0] = vy;
-                    xa[el_x_len - 1] = vx;
-                    ya[el_x_len - 1] = vy;
-                } else {
-                    xa[0] = el_x[l][0];
-                    ya[0] = el_y[l][0];
-                    xa[el_x_len - 1] = el_x[l][el_x_len - 1];
-                    ya[el_x_len - 1] = el_y[l][el_x_len - 1];
-                }
-                for k in 1..(el_x_len - 1) {
-                    el_x[l][k] = (xa[k - 1] + xa[k] / (0.01 + smoothing) + xa[k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    el_y[l][k] = (ya[k - 1] + ya[k] / (0.01 + smoothing) + ya[k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                }
-                if xa.first() == xa.last() && ya.first() == ya.last() {
-                    let vx = (xa[1] + xa[0] / (0.01 + smoothing) + xa[el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    let vy = (ya[1] + ya[0] / (0.01 + smoothing) + ya[el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    el_x[l][0] = vx;
-                    el_y[l][0] = vy;
-                    el_x[l][el_x_len - 1] = vx;
-                    el_y[l][el_x_len - 1] = vy;
-                } else {
-                    el_x[l][0] = xa[0];
-                    el_y[l][0] = ya[0];
-                    el_x[l][el_x_len - 1] = xa[el_x_len - 1];
-                    el_y[l][el_x_len - 1] = ya[el_x_len - 1];
-                }
+                        if el_x[l].first() == el_x[l].last() && el_y[l].first() == el_y[l].last() {
+                            let vx = (el_x[l][1]
+                                + el_x[l][0] / (0.01 + smoothing)
+                                + el_x[l][el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            let vy = (el_y[l][1]
+                                + el_y[l][0] / (0.01 + smoothing)
+                                + el_y[l][el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            xa[0] = vx;
+                            ya[0] = vy;
+                            xa[el_x_len - 1] = vx;
+                            ya[el_x_len - 1] = vy;
+                        } else {
+                            xa[0] = el_x[l][0];
+                            ya[0] = el_y[l][0];
+                            xa[el_x_len - 1] = el_x[l][el_x_len - 1];
+                            ya[el_x_len - 1] = el_y[l][el_x_len - 1];
+                        }
+                        for k in 1..(el_x_len - 1) {
+                            el_x[l][k] = (xa[k - 1] + xa[k] / (0.01 + smoothing) + xa[k + 1])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            el_y[l][k] = (ya[k - 1] + ya[k] / (0.01 + smoothing) + ya[k + 1])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                        }
+                        if xa.first() == xa.last() && ya.first() == ya.last() {
+                            let vx = (xa[1] + xa[0] / (0.01 + smoothing) + xa[el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            let vy = (ya[1] + ya[0] / (0.01 + smoothing) + ya[el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            el_x[l][0] = vx;
+                            el_y[l][0] = vy;
+                            el_x[l][el_x_len - 1] = vx;
+                            el_y[l][el_x_len - 1] = vy;
+                        } else {
+                            el_x[l][0] = xa[0];
+                            el_y[l][0] = ya[0];
+                            el_x[l][el_x_len - 1] = xa[el_x_len - 1];
+                            el_y[l][el_x_len - 1] = ya[el_x_len - 1];
+                        }
 
-                for k in 1..(el_x_len - 1) {
-                    xa[k] = (el_x[l][k - 1] + el_x[l][k] / (0.01 + smoothing) + el_x[l][k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    ya[k] = (el_y[l][k - 1] + el_y[l][k] / (0.01 + smoothing) + el_y[l][k + 1])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                }
+                        for k in 1..(el_x_len - 1) {
+                            xa[k] =
+                                (el_x[l][k - 1] + el_x[l][k] / (0.01 + smoothing) + el_x[l][k + 1])
+                                    / (2.0 + 1.0 / (0.01 + smoothing));
+                            ya[k] =
+                                (el_y[l][k - 1] + el_y[l][k] / (0.01 + smoothing) + el_y[l][k + 1])
+                                    / (2.0 + 1.0 / (0.01 + smoothing));
+                        }
 
-                if el_x[l].first() == el_x[l].last() && el_y[l].first() == el_y[l].last() {
-                    let vx = (el_x[l][1] + el_x[l][0] / (0.01 + smoothing) + el_x[l][el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    let vy = (el_y[l][1] + el_y[l][0] / (0.01 + smoothing) + el_y[l][el_x_len - 2])
-                        / (2.0 + 1.0 / (0.01 + smoothing));
-                    xa[0] = vx;
-                    ya[0] = vy;
-                    xa[el_x_len - 1] = vx;
-                    ya[el_x_len - 1] = vy;
-                } else {
-                    xa[0] = el_x[l][0];
-                    ya[0] = el_y[l][0];
-                    xa[el_x_len - 1] = el_x[l][el_x_len - 1];
-                    ya[el_x_len - 1] = el_y[l][el_x_len - 1];
-                }
+                        if el_x[l].first() == el_x[l].last() && el_y[l].first() == el_y[l].last() {
+                            let vx = (el_x[l][1]
+                                + el_x[l][0] / (0.01 + smoothing)
+                                + el_x[l][el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            let vy = (el_y[l][1]
+                                + el_y[l][0] / (0.01 + smoothing)
+                                + el_y[l][el_x_len - 2])
+                                / (2.0 + 1.0 / (0.01 + smoothing));
+                            xa[0] = vx;
+                            ya[0] = vy;
+                            xa[el_x_len - 1] = vx;
+                            ya[el_x_len - 1] = vy;
+                        } else {
+                            xa[0] = el_x[l][0];
+                            ya[0] = el_y[l][0];
+                            xa[el_x_len - 1] = el_x[l][el_x_len - 1];
+                            ya[el_x_len - 1] = el_y[l][el_x_len - 1];
+                        }
 
-                #[allow(clippy::manual_memcpy)]
-                for k in 0..el_x_len {
-                    el_x[l][k] = xa[k];
-                    el_y[l][k] = ya[k];
-                }
+                        #[allow(clippy::manual_memcpy)]
+                        for k in 0..el_x_len {
+                            el_x[l][k] = xa[k];
+                            el_y[l][k] = ya[k];
+                        }
 
-                let mut dx2: Vec<f64> = vec![f64::NAN; el_x_len];
-                let mut dy2: Vec<f64> = vec![f64::NAN; el_x_len];
-                for k in 2..(el_x_len - 3) {
-                    dx2[k] = (el_x[l][k - 2]
-                        + el_x[l][k - 1]
-                        + el_x[l][k]
-                        + el_x[l][k + 1]
-                        + el_x[l][k + 2]
-                        + el_x[l][k + 3])
-                        / 6.0;
-                    dy2[k] = (el_y[l][k - 2]
-                        + el_y[l][k - 1]
-                        + el_y[l][k]
-                        + el_y[l][k + 1]
-                        + el_y[l][k + 2]
-                        + el_y[l][k + 3])
-                        / 6.0;
+                        let mut dx2: Vec<f64> = vec![f64::NAN; el_x_len];
+                        let mut dy2: Vec<f64> = vec![f64::NAN; el_x_len];
+                        for k in 2..(el_x_len - 3) {
+                            dx2[k] = (el_x[l][k - 2]
+                                + el_x[l][k - 1]
+                                + el_x[l][k]
+                                + el_x[l][k + 1]
+                                + el_x[l][k + 2]
+                                + el_x[l][k + 3])
+                                / 6.0;
+                            dy2[k] = (el_y[l][k - 2]
+                                + el_y[l][k - 1]
+                                + el_y[l][k]
+                                + el_y[l][k + 1]
+                                + el_y[l][k + 2]
+                                + el_y[l][k + 3])
+                                / 6.0;
+                        }
+                        for k in 3..(el_x_len - 3) {
+                            let vx = el_x[l][k] + (dx[k] - dx2[k]) * curviness;
+                            let vy = el_y[l][k] + (dy[k] - dy2[k]) * curviness;
+                            el_x[l][k] = vx;
+                            el_y[l][k] = vy;
+                        }
+                    }
+                    ContourSmoothing::None => {}
+                    ContourSmoothing::Chaikin { iterations } => {
+                        chaikin(&mut el_x[l], &mut el_y[l], iterations);
+                        el_x_len = el_x[l].len();
+                    }
+                    ContourSmoothing::MovingAverage { window } => {
+                        moving_average(&mut el_x[l], &mut el_y[l], window);
+                    }
                 }
-                for k in 3..(el_x_len - 3) {
-                    let vx = el_x[l][k] + (dx[k] - dx2[k]) * curviness;
-                    let vy = el_y[l][k] + (dy[k] - dy2[k]) * curviness;
-                    el_x[l][k] = vx;
-                    el_y[l][k] = vy;
+                if contour_simplify > 0.0 {
+                    douglas_peucker(&mut el_x[l], &mut el_y[l], contour_simplify);
+                    el_x_len = el_x[l].len();
+                }
+                if !is_significant(&el_x[l], &el_y[l], min_contour_length, min_ring_area) {
+                    continue;
                 }
 
                 let mut layer = String::from("contour");
@@ -1137,11 +1198,369 @@ pub fn smoothjoin(
                 }
                 fp.write_all(b"SEQEND\r\n  0\r\n")
                     .expect("Unable to write file");
+
+                if contour_geojson || contour_layered_dxf || contour_shapefile {
+                    contours.push(Contour {
+                        points: el_x[l]
+                            .iter()
+                            .copied()
+                            .zip(el_y[l].iter().copied())
+                            .collect(),
+                        elevation: h,
+                        index: layer.contains("_index"),
+                        // the intermediate contours are turned into form lines below
+                        form_line: layer.contains("_intermed"),
+                    });
+                }
             } // -- if not dotkoll
         }
     }
     fp.write_all(b"ENDSEC\r\n  0\r\nEOF\r\n")
         .expect("Unable to write file");
+
+    if formline == 2.0 && formline_by_slope {
+        // keep the intermediate contours only where the regular contours are far apart
+        let (intermediate, regular): (Vec<_>, Vec<_>) =
+            contours.into_iter().partition(|c| c.form_line);
+        let form_lines = form_lines(
+            &intermediate,
+            &regular,
+            &hmap,
+            formline_max_slope,
+            formline_min_distance,
+        );
+        contours = regular;
+        contours.extend(form_lines);
+    } else {
+        for contour in contours.iter_mut() {
+            contour.form_line = false;
+        }
+    }
+
+    if contour_geojson {
+        let geojson_output = tmpfolder.join("contours.geojson");
+        let mut geojson_fp = BufWriter::new(fs.create(geojson_output)?);
+        write_geojson(&mut geojson_fp, &contours, geojson_precision, crs_epsg)?;
+    }
+    if contour_layered_dxf {
+        let dxf_output = tmpfolder.join("contours_layered.dxf");
+        let mut dxf_fp = BufWriter::new(fs.create(dxf_output)?);
+        write_contours_dxf(&mut dxf_fp, &contours)?;
+    }
+    if contour_shapefile {
+        write_contours_shapefile(fs, tmpfolder.join("contours.shp"), &contours, crs_epsg)?;
+    }
     info!("Done");
     Ok(())
 }
+
+/// Smooths a polyline with Chaikin's corner cutting algorithm. Every segment is replaced by two
+/// points at 1/4 and 3/4 of its length. The end points of open lines are kept, and closed lines
+/// (where the first and last point are equal) stay closed.
+fn chaikin(xs: &mut Vec<f64>, ys: &mut Vec<f64>, iterations: u32) {
+    for _ in 0..iterations {
+        let n = xs.len();
+        if n < 3 {
+            return;
+        }
+        let closed = xs[0] == xs[n - 1] && ys[0] == ys[n - 1];
+
+        let mut newx = Vec::with_capacity(2 * n);
+        let mut newy = Vec::with_capacity(2 * n);
+        if !closed {
+            newx.push(xs[0]);
+            newy.push(ys[0]);
+        }
+        for k in 0..n - 1 {
+            newx.push(0.75 * xs[k] + 0.25 * xs[k + 1]);
+            newy.push(0.75 * ys[k] + 0.25 * ys[k + 1]);
+            newx.push(0.25 * xs[k] + 0.75 * xs[k + 1]);
+            newy.push(0.25 * ys[k] + 0.75 * ys[k + 1]);
+        }
+        if closed {
+            newx.push(newx[0]);
+            newy.push(newy[0]);
+        } else {
+            newx.push(xs[n - 1]);
+            newy.push(ys[n - 1]);
+        }
+
+        *xs = newx;
+        *ys = newy;
+    }
+}
+
+/// Smooths a polyline by replacing every vertex with the average of the `window` vertices centered
+/// on it. The end points of open lines are kept, while closed lines wrap around.
+fn moving_average(xs: &mut [f64], ys: &mut [f64], window: usize) {
+    let n = xs.len();
+    let half = window / 2;
+    if n < 3 || half == 0 {
+        return;
+    }
+    let closed = xs[0] == xs[n - 1] && ys[0] == ys[n - 1];
+
+    let (srcx, srcy) = (xs.to_vec(), ys.to_vec());
+    if closed {
+        // the last point duplicates the first one, so wrap around the unique points only
+        let m = n - 1;
+        for k in 0..m {
+            let (mut sx, mut sy) = (0.0, 0.0);
+            for d in 0..=2 * half {
+                let i = (k + m * (half / m + 1) + d - half) % m;
+                sx += srcx[i];
+                sy += srcy[i];
+            }
+            xs[k] = sx / (2 * half + 1) as f64;
+            ys[k] = sy / (2 * half + 1) as f64;
+        }
+        xs[m] = xs[0];
+        ys[m] = ys[0];
+    } else {
+        for k in 1..n - 1 {
+            // shrink the window near the ends so it stays centered on the vertex
+            let r = half.min(k).min(n - 1 - k);
+            let (mut sx, mut sy) = (0.0, 0.0);
+            for i in k - r..=k + r {
+                sx += srcx[i];
+                sy += srcy[i];
+            }
+            xs[k] = sx / (2 * r + 1) as f64;
+            ys[k] = sy / (2 * r + 1) as f64;
+        }
+    }
+}
+
+/// Check that a contour is at least `min_length` meters long and, if it is a closed ring, encloses
+/// at least `min_area` square meters.
+fn is_significant(xs: &[f64], ys: &[f64], min_length: f64, min_area: f64) -> bool {
+    let n = xs.len();
+    let length = (1..n)
+        .map(|k| (xs[k] - xs[k - 1]).hypot(ys[k] - ys[k - 1]))
+        .sum::<f64>();
+    if length < min_length {
+        return false;
+    }
+    if min_area > 0.0 && n > 0 && xs[0] == xs[n - 1] && ys[0] == ys[n - 1] {
+        // shoelace formula
+        let area = (1..n)
+            .map(|k| xs[k - 1] * ys[k] - xs[k] * ys[k - 1])
+            .sum::<f64>()
+            .abs()
+            / 2.0;
+        return area >= min_area;
+    }
+    true
+}
+
+/// Simplifies a polyline with the Douglas-Peucker algorithm, dropping the vertices closer than
+/// `tolerance` to the segment between the vertices kept around them. The end points of open lines
+/// are kept. Closed lines are split at the vertex farthest from their first point so that they
+/// stay closed, and are left as they are if they would collapse to fewer than three corners.
+fn douglas_peucker(xs: &mut Vec<f64>, ys: &mut Vec<f64>, tolerance: f64) {
+    let n = xs.len();
+    if n < 3 {
+        return;
+    }
+    let closed = xs[0] == xs[n - 1] && ys[0] == ys[n - 1];
+
+    let mut keep = vec![false; n];
+    keep[0] = true;
+    keep[n - 1] = true;
+    let mut stack = vec![(0, n - 1)];
+    if closed {
+        let far = (1..n - 1)
+            .max_by(|&a, &b| {
+                let da = (xs[a] - xs[0]).hypot(ys[a] - ys[0]);
+                let db = (xs[b] - xs[0]).hypot(ys[b] - ys[0]);
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        keep[far] = true;
+        stack = vec![(0, far), (far, n - 1)];
+    }
+    while let Some((start, end)) = stack.pop() {
+        let mut max = (0.0, 0);
+        for k in start + 1..end {
+            let d = segment_distance((xs[k], ys[k]), (xs[start], ys[start]), (xs[end], ys[end]));
+            if d > max.0 {
+                max = (d, k);
+            }
+        }
+        if max.0 > tolerance {
+            keep[max.1] = true;
+            stack.push((start, max.1));
+            stack.push((max.1, end));
+        }
+    }
+    if closed && keep.iter().filter(|&&k| k).count() < 4 {
+        return;
+    }
+
+    let mut k = 0;
+    xs.retain(|_| {
+        k += 1;
+        keep[k - 1]
+    });
+    k = 0;
+    ys.retain(|_| {
+        k += 1;
+        keep[k - 1]
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    fn write_tile(fs: &MemoryFileSystem, name: &str, x: f64, y: f64, color: [u8; 3]) -> PathBuf {
+        let path = PathBuf::from(name);
+        RgbImage::from_pixel(10, 10, Rgb(color))
+            .write_to(
+                &mut BufWriter::new(fs.create(&path).unwrap()),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        write_world_file(fs, &path, x, y, 2.0).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_merge_tiles() {
+        let fs = MemoryFileSystem::new();
+        let red = [255, 0, 0];
+        let green = [0, 255, 0];
+        let blue = [0, 0, 255];
+        let black = [0, 0, 0];
+        let tiles = vec![
+            write_tile(&fs, "nw.png", 1000.0, 2000.0, red),
+            // slightly off the grid of the first tile
+            write_tile(&fs, "ne.png", 1020.3, 1999.8, green),
+            write_tile(&fs, "sw.png", 1000.0, 1980.0, blue),
+            // overlaps its neighbors by one pixel
+            write_tile(&fs, "se.png", 1018.0, 1982.0, black),
+        ];
+
+        let mosaic = merge_tiles(&fs, &tiles, 1.0, Background::default()).unwrap();
+        let image = mosaic.image.to_rgb8();
+        assert_eq!(
+            (mosaic.x, mosaic.y, mosaic.pixel_size),
+            (1000.0, 2000.0, 2.0)
+        );
+        assert_eq!(image.dimensions(), (20, 20));
+        assert_eq!(image.get_pixel(0, 0).0, red);
+        assert_eq!(image.get_pixel(19, 0).0, green);
+        assert_eq!(image.get_pixel(10, 0).0, green);
+        assert_eq!(image.get_pixel(0, 19).0, blue);
+        assert_eq!(image.get_pixel(18, 18).0, black);
+        // not covered by any tile
+        assert_eq!(image.get_pixel(19, 19).0, [255, 255, 255]);
+        // the last tile wins in the overlap
+        assert_eq!(image.get_pixel(9, 9).0, black);
+        assert_eq!(image.get_pixel(9, 10).0, black);
+        assert_eq!(image.get_pixel(9, 8).0, red);
+        assert_eq!(image.get_pixel(8, 10).0, blue);
+
+        mosaic.write_png(&fs, "merged.png").unwrap();
+        assert_eq!(
+            read_world_file(&fs, "merged.png").unwrap(),
+            (1000.0, 2000.0, 2.0)
+        );
+
+        let half = merge_tiles(&fs, &tiles, 2.0, Background::default()).unwrap();
+        assert_eq!((half.image.width(), half.image.height()), (10, 10));
+        assert_eq!(half.pixel_size, 4.0);
+        assert_eq!(half.image.to_rgb8().get_pixel(0, 0).0, red);
+        assert_eq!(half.image.to_rgb8().get_pixel(0, 9).0, blue);
+
+        // a transparent background stays transparent where no tile covers the mosaic
+        let transparent = merge_tiles(&fs, &tiles, 1.0, Background::Transparent).unwrap();
+        let image = transparent.image.as_rgba8().unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(19, 19).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_chaikin_right_angle() {
+        let mut xs = vec![0.0, 4.0, 4.0];
+        let mut ys = vec![0.0, 0.0, 4.0];
+        chaikin(&mut xs, &mut ys, 1);
+        assert_eq!(xs, vec![0.0, 1.0, 3.0, 4.0, 4.0, 4.0]);
+        assert_eq!(ys, vec![0.0, 0.0, 0.0, 1.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_chaikin_closed() {
+        let mut xs = vec![0.0, 4.0, 4.0, 0.0, 0.0];
+        let mut ys = vec![0.0, 0.0, 4.0, 4.0, 0.0];
+        chaikin(&mut xs, &mut ys, 2);
+        assert_eq!(xs.len(), 17);
+        assert_eq!(xs.first(), xs.last());
+        assert_eq!(ys.first(), ys.last());
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let mut xs = vec![0.0, 1.0, 2.0, 3.0, 4.0];
+        let mut ys = vec![0.0, 3.0, 0.0, 3.0, 0.0];
+        moving_average(&mut xs, &mut ys, 3);
+        assert_eq!(xs, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(ys, vec![0.0, 1.0, 2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_is_significant() {
+        // a 2-vertex ring around a 1 m segment is 2 m long
+        assert!(!is_significant(
+            &[0.0, 1.0, 0.0],
+            &[0.0, 0.0, 0.0],
+            10.0,
+            0.0
+        ));
+        let (xs, ys): (Vec<f64>, Vec<f64>) = (0..50).map(|k| (k as f64, 0.5 * k as f64)).unzip();
+        assert!(is_significant(&xs, &ys, 10.0, 0.0));
+        assert!(is_significant(&xs, &ys, 10.0, 100.0));
+
+        // a 4 m square ring is 16 m long with an area of 16 m²
+        let (xs, ys) = ([0.0, 4.0, 4.0, 0.0, 0.0], [0.0, 0.0, 4.0, 4.0, 0.0]);
+        assert!(is_significant(&xs, &ys, 10.0, 16.0));
+        assert!(!is_significant(&xs, &ys, 10.0, 20.0));
+        assert!(!is_significant(&xs, &ys, 20.0, 0.0));
+    }
+
+    #[test]
+    fn test_douglas_peucker() {
+        // a near-straight line collapses to its end points
+        let mut xs = vec![0.0, 1.0, 2.0, 3.0, 4.0];
+        let mut ys = vec![0.0, 0.1, -0.1, 0.05, 0.0];
+        douglas_peucker(&mut xs, &mut ys, 0.5);
+        assert_eq!(xs, vec![0.0, 4.0]);
+        assert_eq!(ys, vec![0.0, 0.0]);
+
+        // while a sharp bend is kept
+        let mut xs = vec![0.0, 1.0, 2.0, 2.1, 2.0];
+        let mut ys = vec![0.0, 0.1, 0.0, 1.0, 2.0];
+        douglas_peucker(&mut xs, &mut ys, 0.5);
+        assert_eq!(xs, vec![0.0, 2.0, 2.0]);
+        assert_eq!(ys, vec![0.0, 0.0, 2.0]);
+    }
+
+    #[test]
+    fn test_douglas_peucker_closed() {
+        let mut xs = vec![0.0, 2.0, 4.0, 4.1, 4.0, 2.0, 0.0, -0.1, 0.0];
+        let mut ys = vec![0.0, 0.1, 0.0, 2.0, 4.0, 3.9, 4.0, 2.0, 0.0];
+        douglas_peucker(&mut xs, &mut ys, 0.5);
+        assert_eq!(xs, vec![0.0, 4.0, 4.0, 0.0, 0.0]);
+        assert_eq!(ys, vec![0.0, 0.0, 4.0, 4.0, 0.0]);
+
+        // a ring that would collapse into a line is kept as it is
+        let mut xs = vec![0.0, 1.0, 0.5, 0.0];
+        let mut ys = vec![0.0, 0.0, 0.1, 0.0];
+        douglas_peucker(&mut xs, &mut ys, 0.5);
+        assert_eq!(xs.len(), 4);
+    }
+}
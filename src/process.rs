@@ -1,25 +1,43 @@
-use image::{GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
-use las::{raw::Header, Reader};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use las::raw::Header;
 use log::debug;
+use log::error;
 use log::info;
+use log::warn;
 use rand::distributions;
 use rand::prelude::*;
+use rustc_hash::FxHashMap as HashMap;
 use std::error::Error;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::blocks;
+use crate::cancel::CancellationToken;
 use crate::cliffs;
-use crate::config::Config;
+use crate::config::{Background, Config, DemSmoothing, TileName};
 use crate::contours;
 use crate::crop;
+use crate::density;
+use crate::dsm;
+use crate::error;
+use crate::hillshade;
+use crate::intensity;
+use crate::io::cache::{cache_key, load_or_build};
+use crate::io::crs::{write_prj, InputTransform};
 use crate::io::fs::FileSystem;
+use crate::io::geotiff::write_geotiff;
 use crate::io::heightmap::HeightMap;
-use crate::io::xyz::XyzInternalWriter;
+use crate::io::las::LasPoint;
+use crate::io::worldfile::write_world_file;
+use crate::io::xyz::{XyzInternalReader, XyzInternalWriter, XyzRecord};
 use crate::knolls;
+use crate::manifest::Manifest;
 use crate::merge;
+use crate::progress::{report, report_steps, Stage};
 use crate::render;
 use crate::util::read_lines_no_alloc;
 use crate::util::Timing;
@@ -75,6 +93,156 @@ pub fn process_zip(
     Ok(())
 }
 
+/// How many points to read between progress reports.
+const PROGRESS_POINTS: usize = 1 << 16;
+
+/// Extent, grid size and timings of a processed tile, returned by [`process_tile`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileInfo {
+    /// Bounds of the points used for the tile, after the configured coordinate factors.
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+    /// Size of the heightmap grid in cells.
+    pub width_px: usize,
+    pub height_px: usize,
+    /// Size of a heightmap cell in meters.
+    pub cell_size: f64,
+    /// The total time taken by each pipeline stage, in the order they first ran.
+    pub timings: Vec<(Stage, Duration)>,
+}
+
+/// The smallest width and height in cells of a heightmap that a tile is processed with, as the
+/// knoll, cliff and rendering stages look several cells around each cell.
+const MIN_HEIGHTMAP_SIZE: usize = 8;
+
+/// Grow the `[min_x, min_y, max_x, max_y]` bounds to include the point.
+fn extend_bounds(bounds: &mut [f64; 4], x: f64, y: f64) {
+    bounds[0] = bounds[0].min(x);
+    bounds[1] = bounds[1].min(y);
+    bounds[2] = bounds[2].max(x);
+    bounds[3] = bounds[3].max(y);
+}
+
+/// Place `img` at `(x, y)` on a `width`×`height` image, which crops it to the tile when the
+/// offsets are negative. The uncovered pixels are white, or transparent with a transparent
+/// background, in which case the image stays RGBA.
+fn crop_image(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    x: i64,
+    y: i64,
+    background: Background,
+) -> DynamicImage {
+    match background {
+        Background::Color(_) => {
+            let mut cropped = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+            image::imageops::replace(&mut cropped, &img.to_rgb8(), x, y);
+            DynamicImage::ImageRgb8(cropped)
+        }
+        Background::Transparent => {
+            let mut cropped = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 0]));
+            image::imageops::replace(&mut cropped, &img.to_rgba8(), x, y);
+            DynamicImage::ImageRgba8(cropped)
+        }
+    }
+}
+
+/// Draw the legend of `conf` on a cropped batch tile where each pixel is `meters_per_pixel`
+/// meters, see [`render::add_legend`]. Returns the tile and the number of rows added above it.
+fn add_tile_legend(img: DynamicImage, conf: &Config, meters_per_pixel: f64) -> (DynamicImage, u32) {
+    if conf.legend.is_none() {
+        return (img, 0);
+    }
+    let mut legend = img.to_rgba8();
+    let rows_above = render::add_legend(&mut legend, conf, meters_per_pixel);
+    let img = match img {
+        DynamicImage::ImageRgb8(_) => {
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(legend).to_rgb8())
+        }
+        _ => DynamicImage::ImageRgba8(legend),
+    };
+    (img, rows_above)
+}
+
+/// The `[min_x, min_y, max_x, max_y]` bounds and the number of the points of an internal xyz file.
+fn read_extent(fs: &impl FileSystem, path: &Path) -> std::io::Result<([f64; 4], usize)> {
+    let mut bounds = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    let mut count = 0;
+    let mut reader = XyzInternalReader::new(BufReader::new(fs.open(path)?))?;
+    while let Some(r) = reader.next()? {
+        extend_bounds(&mut bounds, r.x, r.y);
+        count += 1;
+    }
+    Ok((bounds, count))
+}
+
+/// Keeps at most `cap` points of each classification in each `cell_size` square cell. The points
+/// are kept by reservoir sampling, so every point of a cell and class has the same chance of being
+/// kept regardless of where it is in the file (e.g. which flight line it came from), and the
+/// ground estimate of a cell is not biased. Counting the classes separately keeps the few ground
+/// returns under a canopy from being crowded out by the many vegetation returns.
+struct CellDecimator {
+    cell_size: f64,
+    cap: usize,
+    rng: StdRng,
+    /// The number of points seen and the points kept so far in each (cell x, cell y, class).
+    cells: HashMap<(i64, i64, u8), (usize, Vec<XyzRecord>)>,
+}
+
+impl CellDecimator {
+    fn new(cell_size: f64, cap: usize, seed: u64) -> Self {
+        Self {
+            cell_size,
+            cap,
+            rng: StdRng::seed_from_u64(seed),
+            cells: HashMap::default(),
+        }
+    }
+
+    fn push(&mut self, record: XyzRecord) {
+        let key = (
+            (record.x / self.cell_size).floor() as i64,
+            (record.y / self.cell_size).floor() as i64,
+            record.classification,
+        );
+        let (seen, kept) = self.cells.entry(key).or_default();
+        *seen += 1;
+        if kept.len() < self.cap {
+            kept.push(record);
+        } else {
+            // replace a kept point with probability cap / seen
+            let i = self.rng.gen_range(0..*seen);
+            if i < self.cap {
+                kept[i] = record;
+            }
+        }
+    }
+
+    /// The kept points, ordered by cell so that the output does not depend on the hashing.
+    fn into_records(self) -> impl Iterator<Item = XyzRecord> {
+        let mut cells = self.cells.into_iter().collect::<Vec<_>>();
+        cells.sort_by_key(|(key, _)| *key);
+        cells.into_iter().flat_map(|(_, (_, kept))| kept)
+    }
+}
+
+/// The internal record of a point of a LAS/LAZ file, with the coordinate factors, the reprojection
+/// and the classification remapping of the config applied.
+fn las_record(config: &Config, transform: &InputTransform, pt: &LasPoint) -> XyzRecord {
+    let (x, y) = transform.apply(pt.x * config.xfactor, pt.y * config.yfactor);
+    XyzRecord {
+        x,
+        y,
+        z: pt.z * config.zfactor + config.zoff,
+        classification: config.remap_class(pt.classification),
+        number_of_returns: pt.number_of_returns,
+        return_number: pt.return_number,
+    }
+}
+
 pub fn process_tile(
     fs: &impl FileSystem,
     config: &Config,
@@ -82,10 +250,81 @@ pub fn process_tile(
     tmpfolder: &Path,
     input_file: &Path,
     skip_rendering: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<TileInfo, error::Error> {
+    process_tile_inner(
+        fs,
+        config,
+        thread,
+        tmpfolder,
+        input_file,
+        None,
+        skip_rendering,
+        &CancellationToken::new(),
+    )
+}
+
+/// Process a tile like [`process_tile`], but stop with [`error::Error::Cancelled`] soon after
+/// `cancel` is cancelled from another thread. The token is checked between the processing stages
+/// and while reading the points and tracing the contours.
+pub fn process_tile_cancellable(
+    fs: &impl FileSystem,
+    config: &Config,
+    thread: &String,
+    tmpfolder: &Path,
+    input_file: &Path,
+    skip_rendering: bool,
+    cancel: &CancellationToken,
+) -> Result<TileInfo, error::Error> {
+    process_tile_inner(
+        fs,
+        config,
+        thread,
+        tmpfolder,
+        input_file,
+        None,
+        skip_rendering,
+        cancel,
+    )
+}
+
+/// Process a tile like [`process_tile`], but use the given DEM, for example one read with
+/// [`crate::io::geotiff::read_geotiff`], instead of building the heightmap from the ground points.
+/// The points are still used for the vegetation and cliffs. The DEM is cropped to the extent of
+/// the points and resampled to `dem_resolution`.
+pub fn process_tile_with_dem(
+    fs: &impl FileSystem,
+    config: &Config,
+    thread: &String,
+    tmpfolder: &Path,
+    input_file: &Path,
+    dem: &HeightMap,
+    skip_rendering: bool,
+) -> Result<TileInfo, error::Error> {
+    process_tile_inner(
+        fs,
+        config,
+        thread,
+        tmpfolder,
+        input_file,
+        Some(dem),
+        skip_rendering,
+        &CancellationToken::new(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_tile_inner(
+    fs: &impl FileSystem,
+    config: &Config,
+    thread: &String,
+    tmpfolder: &Path,
+    input_file: &Path,
+    dem: Option<&HeightMap>,
+    skip_rendering: bool,
+    cancel: &CancellationToken,
+) -> Result<TileInfo, error::Error> {
     let mut timing = Timing::start_now("process_tile");
-    fs.create_dir_all(tmpfolder)
-        .expect("Could not create tmp folder");
+    fs.create_dir_all(tmpfolder)?;
 
     let &Config {
         pnorthlinesangle,
@@ -94,37 +333,96 @@ pub fn process_tile(
         ..
     } = config;
 
+    cancel.check()?;
+
     timing.start_section("preparing input file");
     info!("Preparing input file");
 
     let filename = input_file
         .file_name()
-        .ok_or_else(|| format!("No extension for input file {}", input_file.display()))?
+        .ok_or_else(|| error::Error::UnsupportedInput(input_file.to_path_buf()))?
         .to_string_lossy()
         .to_lowercase();
 
+    let read_start = Instant::now();
     let target_file = tmpfolder.join("xyztemp.xyz.bin");
-
-    if filename.ends_with(".xyz") {
+    let mut bounds = [f64::MAX, f64::MAX, f64::MIN, f64::MIN];
+    let mut point_count = 0;
+
+    // the points only depend on the input file and the settings below, so they are cached between
+    // runs together with the heightmap, and a cache hit skips decoding the input. The internal
+    // .xyz.bin format is only used for temporary files, so skip those.
+    let points_cache_key = if config.dem_cache && !filename.ends_with(".xyz.bin") {
+        let params = (
+            [
+                config.xfactor,
+                config.yfactor,
+                config.zfactor,
+                config.zoff,
+                config.thinfactor,
+                config.max_points_cell_size,
+            ]
+            .map(f64::to_bits),
+            config.max_points_per_cell,
+            config.random_seed,
+            (config.source_epsg, config.crs_epsg),
+            {
+                let mut remap = config.class_remap.iter().collect::<Vec<_>>();
+                remap.sort();
+                remap
+            },
+        );
+        Some(cache_key(fs, input_file, params)?)
+    } else {
+        None
+    };
+    let cached_points = points_cache_key
+        .map(|key| Path::new(&config.cachefolder).join(format!("points_{:016x}.xyz.bin", key)));
+    // a cached points file that cannot be read (for example truncated) is ignored and replaced,
+    // like the heightmap cache in `load_or_build`
+    let cached_extent = match &cached_points {
+        Some(cached) if fs.exists(cached) => match read_extent(fs, cached) {
+            Ok(extent) => Some(extent),
+            Err(e) => {
+                warn!("Ignoring invalid cache file {}: {}", cached.display(), e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let points_cached = cached_extent.is_some();
+    let is_las = !filename.ends_with(".xyz")
+        && !filename.ends_with(".xyz.bin")
+        && (filename.ends_with(".laz")
+            || filename.ends_with(".las")
+            || crate::io::las::has_las_signature(fs, input_file)?);
+
+    if let (Some(extent), Some(cached)) = (cached_extent, &cached_points) {
+        info!("Reading points from cache {}", cached.display());
+        fs.copy(cached, &target_file)?;
+        (bounds, point_count) = extent;
+    } else if filename.ends_with(".xyz") {
         // if we are here we don't know if the file has at least 6 columns, but we assume that it is in the format
         // x y z classification number_of_returns return_number
 
         info!("Converting points from .xyz to internal binary format");
 
         debug!("Writing records to {:?}", &target_file);
-        let mut writer = XyzInternalWriter::new(BufWriter::new(
-            fs.create(&target_file).expect("Could not create writer"),
-        ));
+        let transform = InputTransform::new(config).map_err(|e| error::Error::Other(e.into()))?;
+        let mut writer = XyzInternalWriter::new(BufWriter::new(fs.create(&target_file)?));
         read_lines_no_alloc(fs, input_file, |line| {
             let mut parts = line.split(' ');
             let x = parts.next().unwrap().parse::<f64>().unwrap();
             let y = parts.next().unwrap().parse::<f64>().unwrap();
+            let (x, y) = transform.apply(x, y);
             let z = parts.next().unwrap().parse::<f64>().unwrap();
 
-            let classification = parts.next().unwrap().parse::<u8>().unwrap();
+            let classification = config.remap_class(parts.next().unwrap().parse::<u8>().unwrap());
             let number_of_returns = parts.next().unwrap().parse::<u8>().unwrap();
             let return_number = parts.next().unwrap().parse::<u8>().unwrap();
 
+            extend_bounds(&mut bounds, x, y);
+            point_count += 1;
             writer
                 .write_record(&crate::io::xyz::XyzRecord {
                     x,
@@ -135,17 +433,20 @@ pub fn process_tile(
                     return_number,
                 })
                 .expect("Could not write record");
-        })
-        .expect("Could not read file");
-        writer.finish().expect("Unable to finish writing");
-    } else if filename.ends_with(".laz") || filename.ends_with(".las") {
+        })?;
+        writer.finish()?;
+    } else if filename.ends_with(".xyz.bin") {
+        // the internal format is only written by pullauta itself, with `max_points_per_cell`
+        // already applied, like in batch mode
+        info!("Copying input file");
+        fs.copy(input_file, &target_file)?;
+        (bounds, point_count) = read_extent(fs, &target_file)?;
+    } else if is_las {
         info!("Converting points from .laz/laz to internal binary format");
         let &Config {
             thinfactor,
-            xfactor,
-            yfactor,
-            zfactor,
-            zoff,
+            max_points_per_cell,
+            max_points_cell_size,
             ..
         } = config;
 
@@ -153,44 +454,141 @@ pub fn process_tile(
             info!("Using thinning factor {}", thinfactor);
         }
 
-        let mut rng = rand::thread_rng();
+        let transform = InputTransform::new(config).map_err(|e| error::Error::Other(e.into()))?;
+        let mut rng = StdRng::seed_from_u64(config.random_seed);
         let randdist = distributions::Bernoulli::new(thinfactor).unwrap();
+        let mut decimator = (max_points_per_cell > 0).then(|| {
+            info!(
+                "Keeping at most {} points per {} m cell",
+                max_points_per_cell, max_points_cell_size
+            );
+            CellDecimator::new(
+                max_points_cell_size,
+                max_points_per_cell,
+                config.random_seed,
+            )
+        });
 
-        let mut reader = Reader::new(BufReader::new(
-            fs.open(input_file).expect("Could not open file"),
-        ))
-        .expect("Could not create reader");
+        let points = crate::io::las::points(fs, input_file, None).map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => error::Error::InvalidLasHeader(e.to_string()),
+            _ => error::Error::Io(e),
+        })?;
 
         debug!("Writing records to {:?}", &target_file);
-        let mut writer = XyzInternalWriter::new(BufWriter::new(
-            fs.create(&target_file).expect("Could not create writer"),
-        ));
+        let mut writer = XyzInternalWriter::new(BufWriter::new(fs.create(&target_file)?));
 
-        for ptu in reader.points() {
-            let pt = ptu.unwrap();
+        let total = points.total() as usize;
+        for (i, ptu) in points.enumerate() {
+            if i % PROGRESS_POINTS == 0 {
+                cancel.check()?;
+                report_steps(Stage::ReadPoints, i, total);
+            }
+            let pt = ptu?;
             if thinfactor == 1.0 || rng.sample(randdist) {
-                writer.write_record(&crate::io::xyz::XyzRecord {
-                    x: pt.x * xfactor,
-                    y: pt.y * yfactor,
-                    z: pt.z * zfactor + zoff,
-                    classification: u8::from(pt.classification),
-                    number_of_returns: pt.number_of_returns,
-                    return_number: pt.return_number,
-                })?;
+                let record = las_record(config, &transform, &pt);
+                match decimator.as_mut() {
+                    Some(decimator) => decimator.push(record),
+                    None => {
+                        extend_bounds(&mut bounds, record.x, record.y);
+                        point_count += 1;
+                        writer.write_record(&record)?;
+                    }
+                }
             }
         }
-        writer.finish().expect("Unable to finish writing");
-    } else if filename.ends_with(".xyz.bin") {
-        info!("Copying input file");
-        fs.copy(input_file, target_file)
-            .expect("Could not copy file");
+        if let Some(decimator) = decimator {
+            for record in decimator.into_records() {
+                extend_bounds(&mut bounds, record.x, record.y);
+                point_count += 1;
+                writer.write_record(&record)?;
+            }
+        }
+        writer.finish()?;
+        report(Stage::ReadPoints, 1.0);
     } else {
-        return Err(format!("Unsupported input file: {}", input_file.display()).into());
+        return Err(error::Error::UnsupportedInput(input_file.to_path_buf()));
+    }
+    if let (false, Some(cached)) = (points_cached, &cached_points) {
+        fs.create_dir_all(&config.cachefolder)?;
+        fs.copy(&target_file, cached)?;
+    }
+
+    if is_las && config.intensity_png && bounds[0] <= bounds[2] {
+        info!("Writing intensity image");
+        intensity::write_intensity_png(
+            fs,
+            config,
+            input_file,
+            bounds,
+            tmpfolder.join("intensity.png"),
+        )?;
     }
 
+    timing.add_stage(Stage::ReadPoints, read_start.elapsed());
     info!("Done");
+    if point_count == 0 {
+        return Err(error::Error::EmptyTile);
+    }
+
+    // the heightmap only depends on the points and the settings below
+    let dem_cache_key = match points_cache_key.filter(|_| dem.is_none()) {
+        Some(points_key) => {
+            let params = (
+                points_key,
+                [
+                    config.scalefactor,
+                    config.dem_binning_cell,
+                    config.dem_resolution,
+                ]
+                .map(f64::to_bits),
+                &config.ground_classes,
+                config.dem_water_class(),
+                config.ground_model,
+                config.dem_fill_radius,
+                config.dem_smoothing_iterations,
+                match config.dem_smoothing {
+                    DemSmoothing::Mean => None,
+                    DemSmoothing::Gaussian { sigma } => Some(sigma.to_bits()),
+                },
+            );
+            Some(cache_key(fs, input_file, params)?)
+        }
+        None => None,
+    };
+    let build_heightmap = || -> Result<HeightMap, Box<dyn Error>> {
+        let build = || {
+            let hmap = match dem {
+                Some(dem) => dem
+                    .crop(bounds[0], bounds[1], bounds[2], bounds[3])
+                    .ok_or(error::Error::DemOutsideTile)?,
+                None => contours::xyz2heightmap(fs, config, tmpfolder, "xyztemp.xyz.bin")?,
+            };
+            let mut hmap = if config.dem_resolution != hmap.scale {
+                info!(
+                    "Resampling the heightmap to {} m cells",
+                    config.dem_resolution
+                );
+                hmap.resample(config.dem_resolution)
+            } else {
+                hmap
+            };
+            if config.dem_smoothing_iterations > 0 {
+                hmap.grid = contours::smooth_dem(
+                    &hmap.grid,
+                    config.dem_smoothing,
+                    config.dem_smoothing_iterations,
+                );
+            }
+            Ok(hmap)
+        };
+        match dem_cache_key {
+            Some(key) => load_or_build(fs, &config.cachefolder, "dem", key, build),
+            None => build(),
+        }
+    };
 
     info!("Knoll detection part 1");
+    cancel.check()?;
     timing.start_section("knoll detection part 1");
 
     let &Config {
@@ -201,31 +599,75 @@ pub fn process_tile(
         ..
     } = config;
 
-    let xyz_03 = contours::xyz2heightmap(
-        fs,
-        config,
-        tmpfolder,
-        "xyztemp.xyz.bin", //point cloud in
-    )
-    .expect("contour generation failed");
-    xyz_03.to_file(fs, tmpfolder.join("xyz_03.hmap")).unwrap();
-
-    if vegeonly || cliffsonly {
-    } else {
-        contours::heightmap2contours(
+    let xyz_03 = timing.time_stage(Stage::Heightmap, build_heightmap)?;
+    if xyz_03.grid.width() < MIN_HEIGHTMAP_SIZE || xyz_03.grid.height() < MIN_HEIGHTMAP_SIZE {
+        return Err(error::Error::InsufficientPoints {
+            points: point_count,
+        });
+    }
+    let mut tile_info = TileInfo {
+        min_x: bounds[0],
+        min_y: bounds[1],
+        max_x: bounds[2],
+        max_y: bounds[3],
+        width_px: xyz_03.grid.width(),
+        height_px: xyz_03.grid.height(),
+        cell_size: xyz_03.scale,
+        timings: Vec::new(),
+    };
+    xyz_03.to_file(fs, tmpfolder.join("xyz_03.hmap"))?;
+    if config.dem_geotiff {
+        write_geotiff(fs, tmpfolder.join("dem.tif"), &xyz_03, config.crs_epsg)?;
+    }
+    if config.dsm {
+        info!("Surface model");
+        let dsm = dsm::xyz2dsm(fs, tmpfolder, "xyztemp.xyz.bin", &xyz_03)?;
+        write_geotiff(fs, tmpfolder.join("dsm.tif"), &dsm, config.crs_epsg)?;
+        write_geotiff(
+            fs,
+            tmpfolder.join("canopy_height.tif"),
+            &dsm::canopy_height(&dsm, &xyz_03),
+            config.crs_epsg,
+        )?;
+    }
+    if config.density {
+        density::write_density_png(
             fs,
+            config,
             tmpfolder,
-            scalefactor * 0.3,
+            "xyztemp.xyz.bin",
             &xyz_03,
-            "contours03.dxf", // dxf curves generated from the heightmap
-        )
-        .expect("contour generation failed");
+            tmpfolder.join("density.png"),
+        )?;
+    }
+    if config.hillshade {
+        hillshade::write_hillshade_png(
+            fs,
+            tmpfolder.join("hillshade.png"),
+            &xyz_03,
+            config.hillshade_azimuth,
+            config.hillshade_altitude,
+            config.crs_epsg,
+        )?;
+    }
+
+    if vegeonly || cliffsonly {
+    } else {
+        timing.time_stage(Stage::Contours, || {
+            contours::heightmap2contours(
+                fs,
+                tmpfolder,
+                scalefactor * 0.3,
+                &xyz_03,
+                "contours03.dxf", // dxf curves generated from the heightmap
+                cancel,
+            )
+        })?;
     }
     drop(xyz_03);
 
     // copy the generated heightmap
-    fs.copy(tmpfolder.join("xyz_03.hmap"), tmpfolder.join("xyz2.hmap"))
-        .expect("Could not copy file");
+    fs.copy(tmpfolder.join("xyz_03.hmap"), tmpfolder.join("xyz2.hmap"))?;
 
     let &Config {
         contour_interval,
@@ -237,137 +679,467 @@ pub fn process_tile(
     if !vegeonly && !cliffsonly {
         if basemapcontours != 0.0 {
             info!("Basemap contours");
-            let xyz2 = HeightMap::from_file(fs, tmpfolder.join("xyz2.hmap"))
-                .expect("could not read xyz2 heightmap");
-            contours::heightmap2contours(
-                fs,
-                tmpfolder,
-                basemapcontours,
-                &xyz2,
-                "basemap.dxf", // generate dxf contours
-            )
-            .expect("contour generation failed");
+            if config.contour_block_size > 0 {
+                timing.time_stage(Stage::Contours, || {
+                    contours::heightmap_file2contours(
+                        fs,
+                        tmpfolder,
+                        basemapcontours,
+                        &tmpfolder.join("xyz2.hmap"),
+                        config.contour_block_size,
+                        "basemap.dxf",
+                        cancel,
+                    )
+                })?;
+            } else {
+                let xyz2 = HeightMap::from_file(fs, tmpfolder.join("xyz2.hmap"))?;
+                timing.time_stage(Stage::Contours, || {
+                    contours::heightmap2contours(
+                        fs,
+                        tmpfolder,
+                        basemapcontours,
+                        &xyz2,
+                        "basemap.dxf", // generate dxf contours
+                        cancel,
+                    )
+                })?;
+            }
         }
         if !skipknolldetection {
             info!("Knoll detection part 2");
+            cancel.check()?;
             timing.start_section("knoll detection part 2");
-            knolls::knolldetector(fs, config, tmpfolder).unwrap();
+            timing.time_stage(Stage::Knolls, || {
+                knolls::knolldetector(fs, config, tmpfolder)
+            })?;
         }
         info!("Contour generation part 1");
+        cancel.check()?;
         timing.start_section("contour generation part 1");
-        knolls::xyzknolls(fs, config, tmpfolder).unwrap(); // modifies the heightmap (but does not change dimensions
+        // modifies the heightmap (but does not change dimensions)
+        timing.time_stage(Stage::Knolls, || knolls::xyzknolls(fs, config, tmpfolder))?;
 
         info!("Contour generation part 2");
+        cancel.check()?;
         timing.start_section("contour generation part 2");
-        if !skipknolldetection {
+        if !skipknolldetection && config.contour_block_size > 0 {
+            timing.time_stage(Stage::Contours, || {
+                contours::heightmap_file2contours(
+                    fs,
+                    tmpfolder,
+                    halfinterval,
+                    &tmpfolder.join("xyz_knolls.hmap"),
+                    config.contour_block_size,
+                    "out.dxf",
+                    cancel,
+                )
+            })?;
+        } else if !skipknolldetection {
             // contours 2.5
-            let xyz_knolls = HeightMap::from_file(fs, tmpfolder.join("xyz_knolls.hmap"))
-                .expect("could not read xyz_knolls heightmap");
-            contours::heightmap2contours(
-                fs,
-                tmpfolder,
-                halfinterval,
-                &xyz_knolls,
-                "out.dxf", // generates dxf curves
-            )
-            .unwrap();
+            let xyz_knolls = HeightMap::from_file(fs, tmpfolder.join("xyz_knolls.hmap"))?;
+            timing.time_stage(Stage::Contours, || {
+                contours::heightmap2contours(
+                    fs,
+                    tmpfolder,
+                    halfinterval,
+                    &xyz_knolls,
+                    "out.dxf", // generates dxf curves
+                    cancel,
+                )
+            })?;
         } else {
-            let hmap = contours::xyz2heightmap(fs, config, tmpfolder, "xyztemp.xyz.bin")
-                .expect("could not generate heightmap");
-            contours::heightmap2contours(
-                fs,
-                tmpfolder,
-                halfinterval,
-                &hmap,
-                "out.dxf", // generate dxf curves
-            )
-            .unwrap();
+            let hmap = timing.time_stage(Stage::Heightmap, build_heightmap)?;
+            timing.time_stage(Stage::Contours, || {
+                contours::heightmap2contours(
+                    fs,
+                    tmpfolder,
+                    halfinterval,
+                    &hmap,
+                    "out.dxf", // generate dxf curves
+                    cancel,
+                )
+            })?;
         }
         info!("Contour generation part 3");
+        cancel.check()?;
         timing.start_section("contour generation part 3");
-        merge::smoothjoin(fs, config, tmpfolder).unwrap();
+        timing.time_stage(Stage::Contours, || merge::smoothjoin(fs, config, tmpfolder))?;
 
         info!("Contour generation part 4");
+        cancel.check()?;
         timing.start_section("contour generation part 4");
-        knolls::dotknolls(fs, config, tmpfolder).unwrap();
+        timing.time_stage(Stage::Knolls, || knolls::dotknolls(fs, config, tmpfolder))?;
     }
 
     if !cliffsonly && !contoursonly {
         info!("Vegetation generation");
+        cancel.check()?;
         timing.start_section("vegetation generation");
-        vegetation::makevege(fs, config, tmpfolder).unwrap();
+        timing.time_stage(Stage::Vegetation, || {
+            vegetation::makevege(fs, config, tmpfolder)
+        })?;
     }
 
     if !vegeonly && !contoursonly {
         info!("Cliff generation");
+        cancel.check()?;
         timing.start_section("cliff generation");
-        cliffs::makecliffs(fs, config, tmpfolder).unwrap();
+        timing.time_stage(Stage::Cliffs, || cliffs::makecliffs(fs, config, tmpfolder))?;
     }
     if !vegeonly && !contoursonly && !cliffsonly && config.detectbuildings {
         info!("Detecting buildings");
+        cancel.check()?;
         timing.start_section("detecting buildings");
-        blocks::blocks(fs, tmpfolder).unwrap();
+        timing.time_stage(Stage::Buildings, || blocks::blocks(fs, tmpfolder))?;
     }
     if !skip_rendering && !vegeonly && !contoursonly && !cliffsonly {
         info!("Rendering png map with depressions");
+        cancel.check()?;
         timing.start_section("rendering png map with depressions");
-        render::render(
-            fs,
-            config,
-            thread,
-            tmpfolder,
-            pnorthlinesangle,
-            pnorthlineswidth,
-            false,
-        )
-        .unwrap();
+        timing.time_stage(Stage::Render, || {
+            render::render(
+                fs,
+                config,
+                thread,
+                tmpfolder,
+                pnorthlinesangle,
+                pnorthlineswidth,
+                false,
+            )
+        })?;
 
         info!("Rendering png map without depressions");
+        cancel.check()?;
         timing.start_section("rendering png map without depressions");
-        render::render(
-            fs,
-            config,
-            thread,
-            tmpfolder,
-            pnorthlinesangle,
-            pnorthlineswidth,
-            true,
-        )
-        .unwrap();
+        timing.time_stage(Stage::Render, || {
+            render::render(
+                fs,
+                config,
+                thread,
+                tmpfolder,
+                pnorthlinesangle,
+                pnorthlineswidth,
+                true,
+            )
+        })?;
     } else if contoursonly {
         info!("Rendering formlines");
+        cancel.check()?;
         timing.start_section("rendering formlines");
         let mut img = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0]));
-        render::draw_curves(fs, config, &mut img, tmpfolder, false, false).unwrap();
+        timing.time_stage(Stage::Render, || {
+            render::draw_curves(fs, config, &mut img, tmpfolder, false, false)
+        })?;
     } else {
         info!("Skipped rendering");
     }
     info!("All done!");
-    Ok(())
+    timing.end_section();
+    tile_info.timings = timing.stages().to_vec();
+    Ok(tile_info)
+}
+
+/// Serializes the check-and-create of the output file that workers use to claim a tile.
+static TILE_CLAIM_LOCK: Mutex<()> = Mutex::new(());
+
+/// Rough estimate of the peak memory in bytes needed to process a tile of the given size in
+/// meters, including the `tile_buffer` margin that is read around each tile. Dominated by the
+/// heightmap grids and the rendered 600 dpi images.
+pub fn estimate_tile_memory(conf: &Config, width: f64, height: f64) -> usize {
+    let width = width + 2.0 * conf.tile_buffer;
+    let height = height + 2.0 * conf.tile_buffer;
+
+    let cell = 2.0 * conf.scalefactor;
+    let cells = (width / cell).ceil() * (height / cell).ceil();
+    // accumulated heights and the averaged heightmap
+    let grid_bytes =
+        cells as usize * (std::mem::size_of::<(f64, usize)>() + std::mem::size_of::<f64>());
+
+    let pixels_per_meter = conf.pixels_per_meter;
+    let pixels = (width * pixels_per_meter).ceil() * (height * pixels_per_meter).ceil();
+    // a few RGBA images are alive at the same time during vegetation and rendering
+    let image_bytes = pixels as usize * std::mem::size_of::<Rgba<u8>>() * 3;
+
+    grid_bytes + image_bytes
+}
+
+/// Split the extent `[min_x, min_y, max_x, max_y]` of the input file `name` into the output tiles
+/// of the batch mode: the cells of a grid of `tile_size` meters aligned to multiples of
+/// `tile_size`, clipped to the extent and named `<name>_<min x>_<min y>` by the corner of their
+/// cell. With `tile_size` 0 the whole file is a single tile named `name`.
+pub fn split_tiles(name: &str, bounds: [f64; 4], tile_size: f64) -> Vec<(String, [f64; 4])> {
+    if tile_size <= 0.0 {
+        return vec![(name.to_string(), bounds)];
+    }
+    let [min_x, min_y, max_x, max_y] = bounds;
+    let first = |min: f64| (min / tile_size).floor() as i64;
+    let last = |min: f64, max: f64| ((max / tile_size).ceil() as i64 - 1).max(first(min));
+
+    let mut tiles = Vec::new();
+    for j in first(min_y)..=last(min_y, max_y) {
+        for i in first(min_x)..=last(min_x, max_x) {
+            let (x0, y0) = (i as f64 * tile_size, j as f64 * tile_size);
+            tiles.push((
+                format!("{}_{}_{}", name, x0, y0),
+                [
+                    x0.max(min_x),
+                    y0.max(min_y),
+                    (x0 + tile_size).min(max_x),
+                    (y0 + tile_size).min(max_y),
+                ],
+            ));
+        }
+    }
+    tiles
+}
+
+/// The name of the tile with `bounds` of the input file `name` from the `template`, see
+/// [`split_tiles`]. The coordinates are those of the corner of the tile grid, or of the file when it
+/// is not split.
+pub fn expand_tile_name(
+    template: &TileName,
+    name: &str,
+    bounds: [f64; 4],
+    tile_size: f64,
+    scale: f64,
+) -> String {
+    let origin = |min: f64| {
+        if tile_size > 0.0 {
+            (min / tile_size).floor() * tile_size
+        } else {
+            min
+        }
+    };
+    template.expand(name, origin(bounds[0]), origin(bounds[1]), scale)
+}
+
+/// Number of tiles to process concurrently. `threads` is the requested number of workers, 0 means
+/// one per core. If `memory_budget` (in bytes) is non-zero, the number of workers is limited so
+/// that the estimated memory of all concurrent tiles fits in the budget. At least one worker is
+/// always used.
+pub fn batch_worker_count(threads: usize, memory_budget: usize, tile_memory: usize) -> usize {
+    let mut workers = if threads == 0 {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    } else {
+        threads
+    };
+    if memory_budget > 0 && tile_memory > 0 {
+        workers = workers.min(memory_budget / tile_memory);
+    }
+    workers.max(1)
+}
+
+/// Expected work for one input tile, see [`plan`].
+#[derive(Debug)]
+pub struct TilePlan {
+    pub input: PathBuf,
+    /// Sizing of the tile, or why it cannot be processed.
+    pub estimate: Result<TileEstimate, error::Error>,
+}
+
+/// Output size and memory estimate of a tile, computed from its LAS header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileEstimate {
+    /// Size of the heightmap grid in cells.
+    pub width_px: usize,
+    pub height_px: usize,
+    /// Estimated peak memory needed to process the tile, see [`estimate_tile_memory`].
+    pub size_bytes: usize,
+}
+
+/// Check the inputs without processing them: each LAS/LAZ header is read and validated, and the
+/// output grid size and memory use are estimated. Nothing is written.
+pub fn plan(fs: &impl FileSystem, inputs: &[PathBuf], conf: &Config) -> Vec<TilePlan> {
+    inputs
+        .iter()
+        .map(|input| TilePlan {
+            input: input.clone(),
+            estimate: estimate_tile(fs, input, conf),
+        })
+        .collect()
+}
+
+fn estimate_tile(
+    fs: &impl FileSystem,
+    input: &Path,
+    conf: &Config,
+) -> Result<TileEstimate, error::Error> {
+    let header = read_las_header(fs, input)?;
+    let points = header
+        .large_file
+        .map(|l| l.number_of_point_records)
+        .filter(|&n| n > 0)
+        .unwrap_or(header.number_of_point_records as u64);
+    if points == 0 {
+        return Err(error::Error::EmptyTile);
+    }
+    // the size of uncompressed point data is known up front, so truncated files can be caught
+    if header.point_data_record_format & 0x80 == 0 {
+        let expected =
+            header.offset_to_point_data as u64 + points * header.point_data_record_length as u64;
+        let size = fs.file_size(input)?;
+        if size < expected {
+            return Err(error::Error::InvalidLasHeader(format!(
+                "file is truncated, expected {} bytes but found {}",
+                expected, size
+            )));
+        }
+    }
+
+    let width = (header.max_x - header.min_x) * conf.xfactor;
+    let height = (header.max_y - header.min_y) * conf.yfactor;
+    Ok(TileEstimate {
+        width_px: (width / conf.dem_resolution).ceil() as usize + 1,
+        height_px: (height / conf.dem_resolution).ceil() as usize + 1,
+        size_bytes: estimate_tile_memory(conf, width, height),
+    })
+}
+
+/// Read the header of a LAS/LAZ file.
+fn read_las_header(fs: &impl FileSystem, path: &Path) -> Result<Header, error::Error> {
+    Header::read_from(&mut fs.open(path)?)
+        .map_err(|e| error::Error::InvalidLasHeader(e.to_string()))
+}
+
+/// The estimated peak memory use of the largest tile of the input folder. Files whose header
+/// cannot be read are logged and left out of the estimate.
+fn max_tile_memory(conf: &Config, fs: &impl FileSystem) -> usize {
+    let mut tile_memory = 0;
+    for path in fs.list(&conf.lazfolder).unwrap() {
+        if matches!(path.extension(), Some(e) if e == "laz" || e == "las") {
+            let header = match read_las_header(fs, &path) {
+                Ok(header) => header,
+                Err(e) => {
+                    error!("Could not read the header of {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let (mut width, mut height) =
+                (header.max_x - header.min_x, header.max_y - header.min_y);
+            if conf.tile_size > 0.0 {
+                width = width.min(conf.tile_size);
+                height = height.min(conf.tile_size);
+            }
+            tile_memory = tile_memory.max(estimate_tile_memory(conf, width, height));
+        }
+    }
+    tile_memory
+}
+
+/// Process all tiles in the input folder with a pool of `threads` worker threads (0 means one per
+/// core), limited by the configured memory budget. Each worker uses its own temp folder, and the
+/// tiles are claimed by creating their output file so every tile is processed once.
+pub fn batch_process_parallel<F: FileSystem + Sync>(conf: &Config, fs: &F, threads: usize) {
+    let tile_memory = max_tile_memory(conf, fs);
+    let workers = batch_worker_count(threads, conf.memory_budget_mb * 1024 * 1024, tile_memory);
+    info!(
+        "Processing tiles with {} worker(s), estimated {} MB per tile",
+        workers,
+        tile_memory / 1024 / 1024
+    );
+    if workers == 1 {
+        batch_process(conf, fs, &String::new());
+        return;
+    }
+
+    let start = SystemTime::now();
+    let mut manifest = Manifest::default();
+    std::thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|i| {
+                scope.spawn(move || {
+                    info!("Starting thread");
+                    let manifest = batch_process_tiles(conf, fs, &format!("{}", i + 1));
+                    info!("Thread complete");
+                    manifest
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            manifest.merge(handle.join().unwrap());
+        }
+    });
+    if conf.manifest {
+        write_manifest(fs, conf, manifest, start, "");
+    }
 }
 
+/// Process all tiles in the input folder, see [`batch_process_tiles`], and write the manifest of
+/// the run to the output folder if enabled. Workers started as separate processes write their own
+/// `manifest<thread>.json`.
 pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
+    let start = SystemTime::now();
+    let manifest = batch_process_tiles(conf, fs, thread);
+    if conf.manifest {
+        write_manifest(fs, conf, manifest, start, thread);
+    }
+}
+
+/// Write the manifest of a batch run that started at `start`, with the files written to the
+/// output folder since then as its outputs.
+fn write_manifest(
+    fs: &impl FileSystem,
+    conf: &Config,
+    mut manifest: Manifest,
+    start: SystemTime,
+    thread: &str,
+) {
+    let path = Path::new(&conf.batchoutfolder).join(format!("manifest{}.json", thread));
+    manifest.inputs.sort();
+    manifest.inputs.dedup();
+    manifest.skipped.sort();
+    manifest.skipped.dedup();
+    manifest
+        .collect_outputs(fs, &conf.batchoutfolder, start)
+        .expect("Could not list output folder");
+    manifest.outputs.retain(|p| *p != path);
+    manifest
+        .write(fs, conf, path)
+        .expect("Could not write manifest");
+}
+
+/// Process the tiles of the input folder that are not claimed yet by another worker, and return
+/// the input files and stage timings of the processed ones and the input files that could not be
+/// read.
+fn batch_process_tiles(conf: &Config, fs: &impl FileSystem, thread: &String) -> Manifest {
     let &Config {
         vegeonly,
         cliffsonly,
         contoursonly,
         savetempfolders,
         savetempfiles,
-        scalefactor,
+        pixels_per_meter,
         vege_bitmode,
-        zoff,
         thinfactor,
+        random_seed,
+        tile_buffer,
+        tile_size,
         ..
     } = conf;
 
     let Config {
         lazfolder,
         batchoutfolder,
+        crs_epsg,
         ..
     } = conf;
 
-    let mut rng = rand::thread_rng();
+    // the tiles are rendered with their buffer, so the legend is drawn after they are cropped
+    let render_conf = Config {
+        legend: None,
+        ..conf.clone()
+    };
+
     let randdist = distributions::Bernoulli::new(thinfactor).unwrap();
+    let transform = match InputTransform::new(conf) {
+        Ok(transform) => transform,
+        Err(e) => {
+            error!("{}", e);
+            return Manifest::default();
+        }
+    };
 
     fs.create_dir_all(batchoutfolder)
         .expect("Could not create output folder");
@@ -384,33 +1156,75 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
         }
     }
 
-    for laz_path in &laz_files {
-        let laz = laz_path.file_name().unwrap().to_str().unwrap();
+    // the extent of each input file after the transform, to find the files overlapping a tile and
+    // its buffer. The files whose header cannot be read are skipped.
+    let mut manifest = Manifest::default();
+    let mut laz_bounds = Vec::new();
+    laz_files.retain(|path| match read_las_header(fs, path) {
+        Ok(header) => {
+            laz_bounds.push(transform.apply_bounds([
+                header.min_x * conf.xfactor,
+                header.min_y * conf.yfactor,
+                header.max_x * conf.xfactor,
+                header.max_y * conf.yfactor,
+            ]));
+            true
+        }
+        Err(e) => {
+            error!("Skipping {}: {}", path.display(), e);
+            manifest.skipped.push(path.clone());
+            false
+        }
+    });
+    let tiles = laz_files
+        .iter()
+        .zip(&laz_bounds)
+        .flat_map(|(path, &bounds)| {
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            split_tiles(file_name, bounds, tile_size)
+                .into_iter()
+                .map(move |(name, bounds)| {
+                    let name = match &conf.tile_name {
+                        Some(template) => expand_tile_name(
+                            template,
+                            file_name,
+                            bounds,
+                            tile_size,
+                            conf.scalefactor,
+                        ),
+                        None => name,
+                    };
+                    (path, name, bounds)
+                })
+        })
+        .collect::<Vec<_>>();
+
+    for (laz_path, laz, [minx, miny, maxx, maxy]) in &tiles {
+        let (minx, miny, maxx, maxy) = (*minx, *miny, *maxx, *maxy);
         let outfile = format!("{}/{}.png", batchoutfolder, laz);
-        if fs.exists(&outfile) {
-            info!("Skipping {}.png it exists already in output folder.", laz);
-            continue;
+        {
+            // claim the tile by creating the output file, the lock makes sure that two worker
+            // threads in this process cannot both claim the same tile
+            let _claim = TILE_CLAIM_LOCK.lock().unwrap();
+            if fs.exists(&outfile) {
+                info!("Skipping {}.png it exists already in output folder.", laz);
+                continue;
+            }
+            fs.create(&outfile).unwrap();
         }
 
         info!("{} -> {}.png", laz, laz);
-        fs.create(&outfile).unwrap();
 
         let headerfile = PathBuf::from(format!("header{}.xyz", thread));
         if fs.exists(&headerfile) {
             fs.remove_file(&headerfile).unwrap();
         }
 
-        let mut file = fs.open(format!("{}/{}", lazfolder, laz)).unwrap();
-        let header = Header::read_from(&mut file).unwrap();
-        let minx = header.min_x;
-        let miny = header.min_y;
-        let maxx = header.max_x;
-        let maxy = header.max_y;
-
-        let minx2 = minx - 127.0;
-        let miny2 = miny - 127.0;
-        let maxx2 = maxx + 127.0;
-        let maxy2 = maxy + 127.0;
+        // read the points of the neighboring tiles within the buffer, it is cropped off below
+        let minx2 = minx - tile_buffer;
+        let miny2 = miny - tile_buffer;
+        let maxx2 = maxx + tile_buffer;
+        let maxy2 = maxy + tile_buffer;
 
         let tmp_filename = PathBuf::from(format!("temp{}.xyz.bin", thread));
         debug!("Writing records to {:?}", &tmp_filename);
@@ -418,51 +1232,101 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
             fs.create(&tmp_filename).expect("Could not create writer"),
         ));
 
-        for laz_p in &laz_files {
-            let laz = laz_p.as_path().file_name().unwrap().to_str().unwrap();
-            let mut file = fs.open(format!("{}/{}", lazfolder, laz)).unwrap();
-            let header = Header::read_from(&mut file).unwrap();
-            if header.max_x > minx2
-                && header.min_x < maxx2
-                && header.max_y > miny2
-                && header.min_y < maxy2
-            {
-                let mut reader =
-                    Reader::new(BufReader::new(fs.open(laz_p).expect("Could not open file")))
-                        .expect("Could not create reader");
-                for ptu in reader.points() {
-                    let pt = ptu.unwrap();
-                    if pt.x > minx2
-                        && pt.x < maxx2
-                        && pt.y > miny2
-                        && pt.y < maxy2
-                        && (thinfactor == 1.0 || rng.sample(randdist))
+        // seed for each tile, so the thinning does not depend on which worker processes it
+        let mut rng = StdRng::seed_from_u64(random_seed);
+        let mut decimator = (conf.max_points_per_cell > 0).then(|| {
+            CellDecimator::new(
+                conf.max_points_cell_size,
+                conf.max_points_per_cell,
+                random_seed,
+            )
+        });
+        // an input file that cannot be read fails the tile, like the errors of process_tile below
+        let mut read_error = None;
+        'inputs: for (laz_index, (laz_p, bounds)) in laz_files.iter().zip(&laz_bounds).enumerate() {
+            report_steps(Stage::ReadPoints, laz_index, laz_files.len());
+            if bounds[2] > minx2 && bounds[0] < maxx2 && bounds[3] > miny2 && bounds[1] < maxy2 {
+                let points = match crate::io::las::points(fs, laz_p, None) {
+                    Ok(points) => points,
+                    Err(e) => {
+                        read_error = Some((laz_p, e));
+                        break;
+                    }
+                };
+                for ptu in points {
+                    let pt = match ptu {
+                        Ok(pt) => pt,
+                        Err(e) => {
+                            read_error = Some((laz_p, e));
+                            break 'inputs;
+                        }
+                    };
+                    // the buffered tile is in the target coordinates, so check it after the
+                    // transform, including the min edge and excluding the max edge
+                    let record = las_record(conf, &transform, &pt);
+                    if record.x < minx2
+                        || record.x >= maxx2
+                        || record.y < miny2
+                        || record.y >= maxy2
                     {
-                        writer
-                            .write_record(&crate::io::xyz::XyzRecord {
-                                x: pt.x,
-                                y: pt.y,
-                                z: pt.z + zoff,
-                                classification: u8::from(pt.classification),
-                                number_of_returns: pt.number_of_returns,
-                                return_number: pt.return_number,
-                            })
-                            .expect("Could not write record");
+                        continue;
+                    }
+                    if thinfactor == 1.0 || rng.sample(randdist) {
+                        match decimator.as_mut() {
+                            Some(decimator) => decimator.push(record),
+                            None => writer
+                                .write_record(&record)
+                                .expect("Could not write record"),
+                        }
                     }
                 }
             }
         }
+        if let Some((laz_p, e)) = read_error {
+            // keep the empty output file so the tile is not retried by the other workers
+            error!(
+                "Skipping {}: could not read {}: {}. Remove {} to retry.",
+                laz,
+                laz_p.display(),
+                e,
+                outfile
+            );
+            manifest.skipped.push(laz_p.clone());
+            continue;
+        }
+        if let Some(decimator) = decimator {
+            for record in decimator.into_records() {
+                writer
+                    .write_record(&record)
+                    .expect("Could not write record");
+            }
+        }
         writer.finish().expect("Unable to finish writing");
+        report(Stage::ReadPoints, 1.0);
 
         let tmpfolder = PathBuf::from(format!("temp{}", thread));
-        if zip_files.is_empty() {
-            process_tile(fs, conf, thread, &tmpfolder, &tmp_filename, false).unwrap();
-        } else {
-            process_tile(fs, conf, thread, &tmpfolder, &tmp_filename, true).unwrap();
-            if !vegeonly && !cliffsonly && !contoursonly {
-                process_zip(fs, conf, thread, &tmpfolder, &zip_files).unwrap();
+        let skip_rendering = !zip_files.is_empty();
+        match process_tile(
+            fs,
+            &render_conf,
+            thread,
+            &tmpfolder,
+            &tmp_filename,
+            skip_rendering,
+        ) {
+            Ok(info) => {
+                manifest.inputs.push(laz_path.to_path_buf());
+                manifest.add_timings(info.timings.iter().map(|&(stage, d)| (stage.name(), d)));
+            }
+            Err(e) => {
+                // keep the empty output file so the tile is not retried by the other workers
+                error!("Skipping {}: {}. Remove {} to retry.", laz, e, outfile);
+                continue;
             }
         }
+        if skip_rendering && !vegeonly && !cliffsonly && !contoursonly {
+            process_zip(fs, &render_conf, thread, &tmpfolder, &zip_files).unwrap();
+        }
 
         // crop
         let tfw_in = PathBuf::from(format!("pullautus{}.pgw", thread));
@@ -511,6 +1375,33 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
             let dx = minx - tfw4;
             let dy = -maxy + tfw5;
 
+            // the legend is drawn on the cropped tile, so that it is not cut off with the buffer
+            let mut rows_above = 0;
+            for name in ["pullautus", "pullautus_depr"] {
+                let orig_img = fs
+                    .read_image_png(format!("{}{}.png", name, thread))
+                    .expect("Opening image failed");
+                let img = crop_image(
+                    &orig_img,
+                    ((maxx - minx) * pixels_per_meter + 2.0) as u32,
+                    ((maxy - miny) * pixels_per_meter + 2.0) as u32,
+                    (-dx * pixels_per_meter) as i64,
+                    (-dy * pixels_per_meter) as i64,
+                    conf.background,
+                );
+                let (img, rows) = add_tile_legend(img, conf, tfw0);
+                rows_above = rows;
+
+                img.write_to(
+                    &mut BufWriter::new(
+                        fs.create(format!("{}{}.png", name, thread))
+                            .expect("could not save output png"),
+                    ),
+                    image::ImageFormat::Png,
+                )
+                .expect("could not save output png");
+            }
+
             let pgw_file_out = fs.create(&tfw_in).expect("Unable to create file");
             let mut pgw_file_out = BufWriter::new(pgw_file_out);
             write!(
@@ -521,65 +1412,19 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
                 tfw2,
                 tfw3,
                 minx + tfw0 / 2.0,
-                maxy - tfw0 / 2.0
+                maxy - tfw0 / 2.0 + rows_above as f64 * tfw0
             )
             .expect("Unable to write to file");
 
             pgw_file_out.flush().unwrap();
+            // close the file before copying it
+            drop(pgw_file_out);
             fs.copy(
                 Path::new(&format!("pullautus{}.pgw", thread)),
                 Path::new(&format!("pullautus_depr{}.pgw", thread)),
             )
             .expect("Could not copy file");
 
-            let orig_img = fs
-                .read_image_png(format!("pullautus{}.png", thread))
-                .expect("Opening image failed");
-            let mut img = RgbImage::from_pixel(
-                ((maxx - minx) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
-                ((maxy - miny) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
-                Rgb([255, 255, 255]),
-            );
-            image::imageops::overlay(
-                &mut img,
-                &orig_img.to_rgb8(),
-                (-dx * 600.0 / 254.0 / scalefactor) as i64,
-                (-dy * 600.0 / 254.0 / scalefactor) as i64,
-            );
-
-            img.write_to(
-                &mut BufWriter::new(
-                    fs.create(format!("pullautus{}.png", thread))
-                        .expect("could not save output png"),
-                ),
-                image::ImageFormat::Png,
-            )
-            .expect("could not save output png");
-
-            let orig_img = fs
-                .read_image_png(format!("pullautus_depr{}.png", thread))
-                .expect("Opening image failed");
-            let mut img = RgbImage::from_pixel(
-                ((maxx - minx) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
-                ((maxy - miny) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
-                Rgb([255, 255, 255]),
-            );
-            image::imageops::overlay(
-                &mut img,
-                &orig_img.to_rgb8(),
-                (-dx * 600.0 / 254.0 / scalefactor) as i64,
-                (-dy * 600.0 / 254.0 / scalefactor) as i64,
-            );
-
-            img.write_to(
-                &mut BufWriter::new(
-                    fs.create(format!("pullautus_depr{}.png", thread))
-                        .expect("could not save output png"),
-                ),
-                image::ImageFormat::Png,
-            )
-            .expect("could not save output png");
-
             fs.copy(format!("pullautus{}.png", thread), &outfile)
                 .expect("Could not copy file to output folder");
             fs.copy(
@@ -597,6 +1442,13 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
                 format!("{}/{}_depr.pgw", batchoutfolder, laz),
             )
             .expect("Could not copy file to output folder");
+            write_prj(fs, &outfile, *crs_epsg).expect("Unable to write prj file");
+            write_prj(
+                fs,
+                format!("{}/{}_depr.png", batchoutfolder, laz),
+                *crs_epsg,
+            )
+            .expect("Unable to write prj file");
         }
 
         if savetempfiles {
@@ -673,15 +1525,15 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
                 orig_img_reader.no_limits();
                 let orig_img = orig_img_reader.decode().unwrap();
                 let mut img = RgbaImage::from_pixel(
-                    ((maxx - minx) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
-                    ((maxy - miny) * 600.0 / 254.0 / scalefactor + 2.0) as u32,
+                    ((maxx - minx) * pixels_per_meter + 2.0) as u32,
+                    ((maxy - miny) * pixels_per_meter + 2.0) as u32,
                     Rgba([255, 255, 255, 0]),
                 );
                 image::imageops::overlay(
                     &mut img,
                     &orig_img,
-                    (-dx * 600.0 / 254.0 / scalefactor) as i64,
-                    (-dy * 600.0 / 254.0 / scalefactor) as i64,
+                    (-dx * pixels_per_meter) as i64,
+                    (-dy * pixels_per_meter) as i64,
                 );
 
                 img.write_to(
@@ -716,19 +1568,20 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
                 )
                 .expect("could not save output png");
 
-                let pgw_file_out = fs
-                    .create(format!("{}/{}_vege.pgw", batchoutfolder, laz))
-                    .expect("Unable to create file");
-                let mut pgw_file_out = BufWriter::new(pgw_file_out);
-                write!(
-                    &mut pgw_file_out,
-                    "1.0\r\n0.0\r\n0.0\r\n-1.0\r\n{}\r\n{}\r\n",
+                write_world_file(
+                    fs,
+                    format!("{}/{}_vege.png", batchoutfolder, laz),
                     minx + 0.5,
-                    maxy - 0.5
+                    maxy - 0.5,
+                    1.0,
                 )
                 .expect("Unable to write to file");
-
-                pgw_file_out.flush().unwrap();
+                write_prj(
+                    fs,
+                    format!("{}/{}_vege.png", batchoutfolder, laz),
+                    *crs_epsg,
+                )
+                .expect("Unable to write prj file");
 
                 if vege_bitmode {
                     let mut orig_img_reader = image::ImageReader::new(BufReader::new(
@@ -796,6 +1649,14 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
                         format!("{}/{}_undergrowth_bit.pgw", batchoutfolder, laz),
                     )
                     .expect("Could not copy file");
+                    for suffix in ["vege_bit", "undergrowth_bit"] {
+                        write_prj(
+                            fs,
+                            format!("{}/{}_{}.png", batchoutfolder, laz, suffix),
+                            *crs_epsg,
+                        )
+                        .expect("Unable to write prj file");
+                    }
                 }
             }
 
@@ -857,6 +1718,39 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
             .unwrap();
         }
 
+        for contour_file in [
+            "contours.geojson",
+            "contours_layered.dxf",
+            "contours.shp",
+            "contours.shx",
+            "contours.dbf",
+            "contours.prj",
+            "dem.tif",
+            "dsm.tif",
+            "canopy_height.tif",
+            "density.png",
+            "density.pgw",
+            "density.prj",
+            "hillshade.png",
+            "hillshade.pgw",
+            "hillshade.prj",
+            "intensity.png",
+            "intensity.pgw",
+            "intensity.prj",
+            "canopy.png",
+            "canopy.pgw",
+            "canopy.prj",
+        ] {
+            let contour_path = PathBuf::from(format!("temp{}/{}", thread, contour_file));
+            if fs.exists(&contour_path) {
+                fs.copy(
+                    &contour_path,
+                    format!("{}/{}_{}", batchoutfolder, laz, contour_file),
+                )
+                .expect("Could not copy file to output folder");
+            }
+        }
+
         if savetempfolders {
             fs.create_dir_all(format!("temp_{}_dir", laz))
                 .expect("Could not create output folder");
@@ -869,4 +1763,1033 @@ pub fn batch_process(conf: &Config, fs: &impl FileSystem, thread: &String) {
             }
         }
     }
+    manifest
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+    use std::sync::{Arc, Mutex};
+
+    use las::{point::Classification, Builder, Point, Writer};
+
+    use crate::io::fs::memory::MemoryFileSystem;
+    use crate::vec2d::Vec2D;
+
+    use super::*;
+
+    /// Write a small tile of gently sloping ground points starting at (x0, y0).
+    fn write_tile(fs: &MemoryFileSystem, path: &str, x0: f64, y0: f64) {
+        write_tile_with_class(fs, path, x0, y0, Classification::Ground);
+    }
+
+    fn write_tile_with_class(
+        fs: &MemoryFileSystem,
+        path: &str,
+        x0: f64,
+        y0: f64,
+        classification: Classification,
+    ) {
+        write_grid_tile(fs, path, x0, y0, 60, classification, |x, y| {
+            100.0 + 0.1 * x + 0.05 * y + (0.3 * x).sin()
+        });
+    }
+
+    /// Write a grid of `n` × `n` points at 1.5 m spacing from `(x0, y0)`, with the elevation
+    /// `z(dx, dy)` at the offset `(dx, dy)` from that corner.
+    fn write_grid_tile(
+        fs: &MemoryFileSystem,
+        path: &str,
+        x0: f64,
+        y0: f64,
+        n: usize,
+        classification: Classification,
+        z: impl Fn(f64, f64) -> f64,
+    ) {
+        let points = (0..n).flat_map(|i| (0..n).map(move |j| (i as f64 * 1.5, j as f64 * 1.5)));
+        write_points(
+            fs,
+            path,
+            points.map(|(x, y)| (x0 + x, y0 + y, z(x, y), classification)),
+        );
+    }
+
+    /// Write the points `(x, y, z, classification)` as single returns to a LAS file.
+    fn write_points(
+        fs: &MemoryFileSystem,
+        path: &str,
+        points: impl IntoIterator<Item = (f64, f64, f64, Classification)>,
+    ) {
+        let mut builder = Builder::from((1, 2));
+        builder.point_format = las::point::Format::new(1).unwrap();
+        let mut writer =
+            Writer::new(Cursor::new(Vec::new()), builder.into_header().unwrap()).unwrap();
+        for (x, y, z, classification) in points {
+            writer
+                .write_point(Point {
+                    x,
+                    y,
+                    z,
+                    classification,
+                    return_number: 1,
+                    number_of_returns: 1,
+                    gps_time: Some(0.0),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let data = writer.into_inner().unwrap().into_inner();
+        fs.create(path).unwrap().write_all(&data).unwrap();
+    }
+
+    /// The vertices `(x, y, h)` of each polyline in a dxf file.
+    fn read_polylines(fs: &MemoryFileSystem, path: &str) -> Vec<Vec<(f64, f64, f64)>> {
+        let data = fs.read_to_string(path).unwrap();
+        data.split("POLYLINE")
+            .skip(1)
+            .map(|poly| {
+                poly.split("VERTEX")
+                    .skip(1)
+                    .map(|vertex| {
+                        let lines = vertex.lines().map(str::trim).collect::<Vec<_>>();
+                        let value = |code: &str| -> f64 {
+                            let i = lines.iter().position(|l| *l == code).unwrap();
+                            lines[i + 1].parse().unwrap()
+                        };
+                        (value("10"), value("20"), value("30"))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn read_outputs(fs: &MemoryFileSystem, folder: &str) -> Vec<(String, Vec<u8>)> {
+        let mut outputs = fs
+            .list(folder)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let mut data = Vec::new();
+                fs.open(&path).unwrap().read_to_end(&mut data).unwrap();
+                (
+                    path.file_name().unwrap().to_str().unwrap().to_string(),
+                    data,
+                )
+            })
+            .collect::<Vec<_>>();
+        outputs.sort();
+        outputs
+    }
+
+    #[test]
+    fn test_tile_without_ground_points() {
+        let fs = MemoryFileSystem::new();
+        write_tile_with_class(&fs, "trees.las", 0.0, 0.0, Classification::HighVegetation);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let result = process_tile(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("trees.las"),
+            false,
+        );
+        assert!(matches!(result, Err(error::Error::EmptyTile)));
+    }
+
+    #[test]
+    fn test_class_remap_makes_ground() {
+        let fs = MemoryFileSystem::new();
+        write_tile_with_class(&fs, "tile.las", 0.0, 0.0, Classification::Unclassified);
+        let process = |conf: &Config| {
+            process_tile(
+                &fs,
+                conf,
+                &String::new(),
+                Path::new("temp"),
+                Path::new("tile.las"),
+                true,
+            )
+        };
+
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        assert!(matches!(process(&conf), Err(error::Error::EmptyTile)));
+
+        let conf = Config::from_toml_str("class_remap = \"1:2\"").unwrap();
+        let info = process(&conf).unwrap();
+        let hmap = HeightMap::from_file(&fs, Path::new("temp").join("xyz_03.hmap")).unwrap();
+        assert_eq!(info.width_px, hmap.grid.width());
+        assert!(!hmap.grid.is_any_nan());
+        // the surface of the points, 100 m at the origin
+        assert!((hmap.grid[(5, 5)] - 100.0).abs() < 5.0);
+    }
+
+    /// A file system that counts how many times each file is opened for reading.
+    #[derive(Debug, Default)]
+    struct CountingFileSystem {
+        inner: MemoryFileSystem,
+        opens: Mutex<HashMap<PathBuf, usize>>,
+    }
+
+    impl CountingFileSystem {
+        fn opens(&self, path: &str) -> usize {
+            *self
+                .opens
+                .lock()
+                .unwrap()
+                .get(Path::new(path))
+                .unwrap_or(&0)
+        }
+    }
+
+    impl FileSystem for CountingFileSystem {
+        fn create_dir_all(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+            self.inner.create_dir_all(path)
+        }
+        fn list(&self, path: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+            self.inner.list(path)
+        }
+        fn exists(&self, path: impl AsRef<Path>) -> bool {
+            self.inner.exists(path)
+        }
+        fn open(
+            &self,
+            path: impl AsRef<Path>,
+        ) -> std::io::Result<impl std::io::Read + std::io::Seek + Send + 'static> {
+            *self
+                .opens
+                .lock()
+                .unwrap()
+                .entry(path.as_ref().to_path_buf())
+                .or_default() += 1;
+            self.inner.open(path)
+        }
+        fn create(&self, path: impl AsRef<Path>) -> std::io::Result<impl Write + std::io::Seek> {
+            self.inner.create(path)
+        }
+        fn read_to_string(&self, path: impl AsRef<Path>) -> std::io::Result<String> {
+            self.inner.read_to_string(path)
+        }
+        fn remove_file(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+            self.inner.remove_file(path)
+        }
+        fn file_size(&self, path: impl AsRef<Path>) -> std::io::Result<u64> {
+            self.inner.file_size(path)
+        }
+        fn modified(&self, path: impl AsRef<Path>) -> std::io::Result<SystemTime> {
+            self.inner.modified(path)
+        }
+        fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> std::io::Result<()> {
+            self.inner.copy(from, to)
+        }
+    }
+
+    #[test]
+    fn test_second_run_reads_points_from_cache() {
+        let fs = CountingFileSystem::default();
+        write_tile(&fs.inner, "tile.las", 1000.0, 2000.0);
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.dem_cache = true;
+        let process = || {
+            process_tile(
+                &fs,
+                &conf,
+                &String::new(),
+                Path::new("temp"),
+                Path::new("tile.las"),
+                true,
+            )
+            .unwrap()
+        };
+
+        let heightmap = || HeightMap::from_file(&fs.inner, "temp/xyz_03.hmap").unwrap();
+
+        let first = process();
+        assert_eq!(fs.opens("tile.las"), 1);
+        let hmap = heightmap();
+        let second = process();
+        assert_eq!(fs.opens("tile.las"), 1);
+        assert_eq!((first.min_x, first.max_y), (second.min_x, second.max_y));
+        assert_eq!(heightmap(), hmap);
+        assert_eq!(
+            read_extent(&fs.inner, Path::new("temp/xyztemp.xyz.bin"))
+                .unwrap()
+                .1,
+            60 * 60
+        );
+
+        // a truncated points cache is decoded again instead of failing the tile
+        let cached = fs
+            .inner
+            .list("cache")
+            .unwrap()
+            .into_iter()
+            .find(|path| path.to_string_lossy().ends_with(".xyz.bin"))
+            .unwrap();
+        fs.inner.create(&cached).unwrap().write_all(b"XYZ").unwrap();
+        let third = process();
+        assert_eq!(fs.opens("tile.las"), 2);
+        assert_eq!((first.min_x, first.max_y), (third.min_x, third.max_y));
+        assert_eq!(heightmap(), hmap);
+    }
+
+    #[test]
+    fn test_crop_image() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba([255, 0, 0, if x < 2 { 0 } else { 255 }])
+        }));
+
+        let cropped = crop_image(&img, 3, 2, -1, -1, Background::Transparent);
+        let cropped = cropped.as_rgba8().unwrap();
+        assert_eq!(cropped.dimensions(), (3, 2));
+        assert_eq!(cropped.get_pixel(0, 0).0[3], 0);
+        assert_eq!(cropped.get_pixel(1, 0).0, [255, 0, 0, 255]);
+
+        // a larger canvas leaves the uncovered pixels transparent
+        let padded = crop_image(&img, 5, 5, 0, 0, Background::Transparent);
+        assert_eq!(padded.as_rgba8().unwrap().get_pixel(4, 4).0, [0, 0, 0, 0]);
+
+        let opaque = crop_image(&img, 5, 5, 0, 0, Background::default());
+        let opaque = opaque.as_rgb8().unwrap();
+        assert_eq!(opaque.get_pixel(3, 0).0, [255, 0, 0]);
+        assert_eq!(opaque.get_pixel(4, 4).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_cell_decimator() {
+        let decimate = |seed| {
+            let mut decimator = CellDecimator::new(1.0, 1, seed);
+            for i in 0..5 {
+                decimator.push(XyzRecord {
+                    x: 10.1 + 0.1 * i as f64,
+                    y: 20.5,
+                    z: i as f64,
+                    classification: 2,
+                    number_of_returns: 1,
+                    return_number: 1,
+                });
+            }
+            // alone in its cell
+            decimator.push(XyzRecord {
+                x: 11.5,
+                y: 20.5,
+                z: 9.0,
+                classification: 2,
+                number_of_returns: 1,
+                return_number: 1,
+            });
+            decimator.into_records().map(|r| r.z).collect::<Vec<_>>()
+        };
+
+        let kept = decimate(7);
+        assert_eq!(kept.len(), 2);
+        assert!(kept[0] < 5.0);
+        assert_eq!(kept[1], 9.0);
+        assert_eq!(decimate(7), kept);
+    }
+
+    #[test]
+    fn test_tile_with_too_few_points() {
+        let fs = MemoryFileSystem::new();
+        write_points(&fs, "empty.las", []);
+        write_points(
+            &fs,
+            "single.las",
+            [(1000.0, 2000.0, 100.0, Classification::Ground)],
+        );
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let process = |input: &str| {
+            process_tile(
+                &fs,
+                &conf,
+                &String::new(),
+                Path::new("temp"),
+                Path::new(input),
+                false,
+            )
+        };
+        assert!(matches!(process("empty.las"), Err(error::Error::EmptyTile)));
+        assert!(matches!(
+            process("single.las"),
+            Err(error::Error::InsufficientPoints { points: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_tile_with_invalid_header() {
+        let fs = MemoryFileSystem::new();
+        fs.create("broken.las")
+            .unwrap()
+            .write_all(b"LASF but not much else")
+            .unwrap();
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let result = process_tile(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("broken.las"),
+            false,
+        );
+        assert!(matches!(result, Err(error::Error::InvalidLasHeader(_))));
+    }
+
+    #[test]
+    fn test_tile_info_matches_las_header() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let header = Header::read_from(&mut fs.open("tile.las").unwrap()).unwrap();
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let info = process_tile(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("tile.las"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(info.min_x, header.min_x);
+        assert_eq!(info.min_y, header.min_y);
+        assert_eq!(info.max_x, header.max_x);
+        assert_eq!(info.max_y, header.max_y);
+        assert_eq!(info.cell_size, conf.dem_resolution);
+        assert!(info.width_px > 0 && info.height_px > 0);
+    }
+
+    #[test]
+    fn test_tile_stage_timings() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let info = process_tile(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("tile.las"),
+            false,
+        )
+        .unwrap();
+        let stages = info
+            .timings
+            .iter()
+            .map(|(stage, _)| *stage)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            vec![
+                Stage::ReadPoints,
+                Stage::Heightmap,
+                Stage::Contours,
+                Stage::Knolls,
+                Stage::Vegetation,
+                Stage::Cliffs,
+                Stage::Render
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel_tile() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        let cancel = CancellationToken::new();
+
+        let start = std::time::Instant::now();
+        let cancelled = std::thread::scope(|s| {
+            let worker = s.spawn(|| {
+                let result = process_tile_cancellable(
+                    &fs,
+                    &conf,
+                    &String::new(),
+                    Path::new("temp"),
+                    Path::new("tile.las"),
+                    false,
+                    &cancel,
+                );
+                matches!(result, Err(error::Error::Cancelled))
+            });
+            cancel.cancel();
+            worker.join().unwrap()
+        });
+        assert!(cancelled);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_cancel_tile_during_stage() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        let cancel = CancellationToken::new();
+
+        // cancel from the progress handler once the contours are partway traced, only for our own
+        // worker as other tests may run the pipeline at the same time
+        let _lock = crate::progress::TEST_HANDLER_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let cancelled_at = Arc::new(Mutex::new(None));
+        let (token, recorded) = (cancel.clone(), cancelled_at.clone());
+        crate::progress::set_progress_handler(move |stage, fraction| {
+            if std::thread::current().name() == Some("cancel-during-stage")
+                && stage == Stage::Contours
+                && fraction > 0.0
+                && fraction < 1.0
+                && !token.is_cancelled()
+            {
+                *recorded.lock().unwrap() = Some((stage, fraction));
+                token.cancel();
+            }
+        });
+        let cancelled = std::thread::Builder::new()
+            .name("cancel-during-stage".to_string())
+            .spawn(move || {
+                let process = || {
+                    let result = process_tile_cancellable(
+                        &fs,
+                        &conf,
+                        &String::new(),
+                        Path::new("temp"),
+                        Path::new("tile.las"),
+                        false,
+                        &cancel,
+                    );
+                    matches!(result, Err(error::Error::Cancelled))
+                };
+                // few threads, so that the contours are traced in several chunks on any machine
+                #[cfg(feature = "rayon")]
+                let cancelled = rayon::ThreadPoolBuilder::new()
+                    .num_threads(2)
+                    .thread_name(|_| "cancel-during-stage".to_string())
+                    .build()
+                    .unwrap()
+                    .install(process);
+                #[cfg(not(feature = "rayon"))]
+                let cancelled = process();
+                cancelled
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        crate::progress::clear_progress_handler();
+
+        assert!(cancelled);
+        let (stage, fraction) = cancelled_at.lock().unwrap().expect("not cancelled");
+        assert_eq!(stage, Stage::Contours);
+        assert!(fraction < 1.0);
+    }
+
+    #[test]
+    fn test_plan() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let mut data = Vec::new();
+        fs.open("tile.las").unwrap().read_to_end(&mut data).unwrap();
+        fs.create("truncated.las")
+            .unwrap()
+            .write_all(&data[..data.len() / 2])
+            .unwrap();
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+
+        let plans = plan(
+            &fs,
+            &[
+                PathBuf::from("tile.las"),
+                PathBuf::from("missing.las"),
+                PathBuf::from("truncated.las"),
+            ],
+            &conf,
+        );
+        assert_eq!(plans.len(), 3);
+        let estimate = plans[0].estimate.as_ref().unwrap();
+        // the fixture covers 88.5 m in both directions
+        let cells = (88.5 / conf.dem_resolution).ceil() as usize + 1;
+        assert_eq!((estimate.width_px, estimate.height_px), (cells, cells));
+        assert_eq!(estimate.size_bytes, estimate_tile_memory(&conf, 88.5, 88.5));
+        assert!(matches!(plans[1].estimate, Err(error::Error::Io(_))));
+        assert!(matches!(
+            plans[2].estimate,
+            Err(error::Error::InvalidLasHeader(_))
+        ));
+        assert!(!fs.exists("temp"));
+    }
+
+    #[test]
+    fn test_contours_continue_across_tile_seam() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        let surface = |x: f64, y: f64| 100.0 + 3.0 * (x / 15.0).sin() + 0.15 * y;
+        // the points within [x0, x0 + 90] × [0, 90], so the tiles share one continuous surface
+        for (path, x0) in [("in/a.las", 0.0), ("in/b.las", 90.0)] {
+            write_grid_tile(&fs, path, x0, 0.0, 61, Classification::Ground, |x, y| {
+                surface(x0 + x, y)
+            });
+        }
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        conf.savetempfiles = true;
+        batch_process(&conf, &fs, &String::new());
+
+        let a = read_polylines(&fs, "out/a.las_contours.dxf");
+        let b = read_polylines(&fs, "out/b.las_contours.dxf");
+        // the contours of the west tile that end at the seam continue in the east tile, which
+        // includes the last vertex before the seam
+        let seam_ends = a
+            .iter()
+            .flat_map(|poly| [poly[0], poly[poly.len() - 1]])
+            .filter(|&(x, _, _)| x > 87.0)
+            .collect::<Vec<_>>();
+        assert!(!seam_ends.is_empty());
+        for (x, y, h) in seam_ends {
+            assert!(
+                b.iter()
+                    .flatten()
+                    .any(|&(bx, by, bh)| bh == h && (bx - x).abs() < 1e-6 && (by - y).abs() < 1e-6),
+                "contour {} ending at ({}, {}) does not continue in the east tile",
+                h,
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_tile_name() {
+        let template: TileName = "tile_{x}_{y}".parse().unwrap();
+        let names = split_tiles("a.las", [950.0, 1000.0, 1038.5, 1088.5], 100.0)
+            .into_iter()
+            .map(|(_, bounds)| expand_tile_name(&template, "a.las", bounds, 100.0, 1.0))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["tile_900_1000", "tile_1000_1000"]);
+
+        // without tile_size the corner of the file
+        let template: TileName = "{name}_{x}_{y}_{scale}".parse().unwrap();
+        assert_eq!(
+            expand_tile_name(
+                &template,
+                "a.las",
+                [950.5, 1000.0, 1038.5, 1088.5],
+                0.0,
+                1.5
+            ),
+            "a_950_1000_1.5"
+        );
+    }
+
+    #[test]
+    fn test_split_tiles() {
+        let bounds = [950.0, 1000.0, 1038.5, 1088.5];
+        assert_eq!(
+            split_tiles("a.las", bounds, 0.0),
+            vec![("a.las".to_string(), bounds)]
+        );
+        assert_eq!(
+            split_tiles("a.las", bounds, 100.0),
+            vec![
+                (
+                    "a.las_900_1000".to_string(),
+                    [950.0, 1000.0, 1000.0, 1088.5]
+                ),
+                (
+                    "a.las_1000_1000".to_string(),
+                    [1000.0, 1000.0, 1038.5, 1088.5]
+                ),
+            ]
+        );
+        // an extent ending on a tile edge does not add an empty tile
+        assert_eq!(
+            split_tiles("a.las", [0.0, 0.0, 100.0, 50.0], 100.0).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_batch_splits_large_file() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        // spans x 950..1038.5, across the 100 m tile edge at x 1000
+        write_tile(&fs, "in/big.las", 950.0, 1000.0);
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        conf.tile_size = 100.0;
+        batch_process(&conf, &fs, &String::new());
+
+        let pngs = read_outputs(&fs, "out")
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.ends_with(".png"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pngs,
+            [
+                "big.las_1000_1000.png",
+                "big.las_1000_1000_depr.png",
+                "big.las_900_1000.png",
+                "big.las_900_1000_depr.png",
+            ]
+        );
+
+        let origin = |path: &str| {
+            let lines = fs
+                .read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|l| l.parse::<f64>().unwrap())
+                .collect::<Vec<_>>();
+            (lines[4] - lines[0] / 2.0, lines[5] + lines[0] / 2.0)
+        };
+        assert_eq!(origin("out/big.las_900_1000.pgw"), (950.0, 1088.5));
+        assert_eq!(origin("out/big.las_1000_1000.pgw"), (1000.0, 1088.5));
+    }
+
+    #[test]
+    fn test_batch_draws_legend_on_cropped_tile() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        batch_process(&conf, &fs, &String::new());
+        let plain = fs.read_image_png("out/a.las.png").unwrap().to_rgb8();
+        let plain_pgw = fs.read_to_string("out/a.las.pgw").unwrap();
+
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+        conf.legend = Some(crate::config::Corner::TopLeft);
+        conf.legend_margin = true;
+        batch_process(&conf, &fs, &String::new());
+        let map = fs.read_image_png("out/a.las.png").unwrap().to_rgb8();
+
+        // the strip is added above the cropped tile and the legend is drawn on it
+        assert_eq!(
+            map.dimensions(),
+            (plain.width(), plain.height() + render::LEGEND_HEIGHT)
+        );
+        assert!((0..render::LEGEND_HEIGHT)
+            .flat_map(|y| (0..map.width()).map(move |x| (x, y)))
+            .any(|(x, y)| map.get_pixel(x, y).0 == [0, 0, 0]));
+        assert_eq!(
+            image::imageops::crop_imm(
+                &map,
+                0,
+                render::LEGEND_HEIGHT,
+                plain.width(),
+                plain.height()
+            )
+            .to_image(),
+            plain
+        );
+
+        let origin_y = |pgw: &str| pgw.lines().nth(5).unwrap().parse::<f64>().unwrap();
+        let pixel_size = plain_pgw.lines().next().unwrap().parse::<f64>().unwrap();
+        let pgw = fs.read_to_string("out/a.las.pgw").unwrap();
+        assert!(
+            (origin_y(&pgw) - origin_y(&plain_pgw) - render::LEGEND_HEIGHT as f64 * pixel_size)
+                .abs()
+                < 1e-9
+        );
+    }
+
+    #[cfg(feature = "reproject")]
+    #[test]
+    fn test_batch_reprojects_points() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        // on the central meridian of UTM zone 34N, six degrees west of that of TM35FIN
+        write_tile(&fs, "in/a.las", 500000.0, 2000000.0);
+
+        let mut conf =
+            Config::from_toml_str("source_epsg = 32634\ncrs_epsg = 3067\ntile_buffer = 0").unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        batch_process(&conf, &fs, &String::new());
+
+        let transform = crate::io::reproject::Transform::new(32634, 3067).unwrap();
+        // the tile is rotated by the meridian convergence, its extent is that of its corners
+        let corners = [(0.0, 0.0), (88.5, 0.0), (0.0, 88.5), (88.5, 88.5)]
+            .map(|(dx, dy)| transform.transform(500000.0 + dx, 2000000.0 + dy));
+        let min_x = corners.iter().map(|c| c.0).fold(f64::MAX, f64::min);
+        let max_y = corners.iter().map(|c| c.1).fold(f64::MIN, f64::max);
+        let lines = fs
+            .read_to_string("out/a.las.pgw")
+            .unwrap()
+            .lines()
+            .map(|l| l.parse::<f64>().unwrap())
+            .collect::<Vec<_>>();
+        let origin = (lines[4] - lines[0] / 2.0, lines[5] + lines[0] / 2.0);
+        assert!(
+            (origin.0 - min_x).abs() < 1.0 && (origin.1 - max_y).abs() < 1.0,
+            "{:?} {:?}",
+            origin,
+            (min_x, max_y)
+        );
+        assert!(fs.exists("out/a.las.prj"));
+    }
+
+    #[test]
+    fn test_batch_decimates_points() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 900.0, 2100.0);
+
+        let mut conf =
+            Config::from_toml_str("max_points_per_cell = 1\nmax_points_cell_size = 3").unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        batch_process(&conf, &fs, &String::new());
+        assert!(fs.exists("out/a.las.png"));
+
+        // one of the four points in each 3 m cell of the 1.5 m grid
+        let mut reader =
+            XyzInternalReader::new(BufReader::new(fs.open("temp.xyz.bin").unwrap())).unwrap();
+        let mut cells = Vec::new();
+        while let Some(r) = reader.next().unwrap() {
+            cells.push(((r.x / 3.0).floor() as i64, (r.y / 3.0).floor() as i64));
+        }
+        let count = cells.len();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(count, 30 * 30);
+        assert_eq!(cells.len(), count);
+    }
+
+    #[test]
+    fn test_batch_manifest() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        conf.manifest = true;
+        batch_process(&conf, &fs, &String::new());
+
+        let manifest = fs.read_to_string("out/manifest.json").unwrap();
+        for key in [
+            "version",
+            "config_hash",
+            "config",
+            "crs_epsg",
+            "inputs",
+            "outputs",
+            "skipped",
+            "timings",
+        ] {
+            assert!(manifest.contains(&format!("\"{}\": ", key)), "no {}", key);
+        }
+        assert!(manifest.contains("\"inputs\": [\"in/a.las\"]"));
+        assert!(manifest.contains("\"knolls\": "));
+
+        let outputs = manifest
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("\"outputs\": "))
+            .unwrap()
+            .trim_end_matches(',');
+        let written = read_outputs(&fs, "out")
+            .into_iter()
+            .map(|(name, _)| format!("\"out/{}\"", name))
+            .filter(|name| name != "\"out/manifest.json\"")
+            .collect::<Vec<_>>();
+        assert!(written.contains(&"\"out/a.las.png\"".to_string()));
+        assert_eq!(outputs, format!("[{}]", written.join(", ")));
+    }
+
+    #[test]
+    fn test_batch_skips_unreadable_files() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+        fs.create("in/broken.las")
+            .unwrap()
+            .write_all(b"LASF but not much else")
+            .unwrap();
+        // a valid header, but the points are cut off
+        write_tile(&fs, "in/truncated.las", 5000.0, 2000.0);
+        let mut data = Vec::new();
+        fs.open("in/truncated.las")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data.truncate(data.len() / 2);
+        fs.create("in/truncated.las")
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        conf.batchoutfolder = "out".to_string();
+        conf.manifest = true;
+        batch_process(&conf, &fs, &String::new());
+
+        let manifest = fs.read_to_string("out/manifest.json").unwrap();
+        assert!(manifest.contains("\"inputs\": [\"in/a.las\"]"));
+        assert!(manifest.contains("\"skipped\": [\"in/broken.las\", \"in/truncated.las\"]"));
+        assert!(fs.exists("out/a.las.png"));
+    }
+
+    #[test]
+    fn test_thinning_is_reproducible() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.thinfactor = 0.5;
+        conf.cliff_thin = 0.5;
+
+        for folder in ["temp1", "temp2"] {
+            process_tile(
+                &fs,
+                &conf,
+                &String::new(),
+                Path::new(folder),
+                Path::new("tile.las"),
+                true,
+            )
+            .unwrap();
+        }
+        let first = read_outputs(&fs, "temp1");
+        let second = read_outputs(&fs, "temp2");
+        assert!(first.iter().any(|(name, _)| name == "c2g.dxf"));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_tile_with_external_dem() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        // a ramp rising 0.2 m per meter to the east, unlike the ground points of the tile
+        let ramp = |x: f64| 0.2 * x - 100.0;
+        let dem = HeightMap {
+            xoffset: 900.0,
+            yoffset: 1900.0,
+            scale: 1.0,
+            grid: Vec2D::from_fn(300, 300, |x, _| ramp(900.0 + x as f64)),
+        };
+
+        let info = process_tile_with_dem(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("tile.las"),
+            &dem,
+            true,
+        )
+        .unwrap();
+        assert_eq!(info.cell_size, conf.dem_resolution);
+
+        let contours = read_polylines(&fs, "temp/out2.dxf");
+        let mut heights = contours.iter().map(|poly| poly[0].2).collect::<Vec<_>>();
+        heights.sort_by(f64::total_cmp);
+        heights.dedup();
+        let interval = conf.contour_interval / 2.0;
+        let expected = (41..=47).map(|i| i as f64 * interval).collect::<Vec<_>>();
+        assert_eq!(heights, expected);
+        for &(x, _, h) in contours.iter().flatten() {
+            if x > 1005.0 && x < 1083.0 {
+                assert!((h - ramp(x)).abs() < 0.5, "contour {} at x = {}", h, x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tile_with_dem_elsewhere() {
+        let fs = MemoryFileSystem::new();
+        write_tile(&fs, "tile.las", 1000.0, 2000.0);
+        let conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        let dem = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 1.0,
+            grid: Vec2D::new(100, 100, 100.0),
+        };
+
+        let result = process_tile_with_dem(
+            &fs,
+            &conf,
+            &String::new(),
+            Path::new("temp"),
+            Path::new("tile.las"),
+            &dem,
+            true,
+        );
+        assert!(matches!(result, Err(error::Error::DemOutsideTile)));
+    }
+
+    #[test]
+    fn test_worker_count() {
+        assert_eq!(batch_worker_count(4, 0, 100), 4);
+        assert_eq!(batch_worker_count(4, 250, 100), 2);
+        // always at least one worker, even if a single tile does not fit
+        assert_eq!(batch_worker_count(4, 50, 100), 1);
+        assert!(batch_worker_count(0, 0, 100) >= 1);
+    }
+
+    #[test]
+    fn test_tile_memory_skips_invalid_header() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+        fs.create("in/broken.las")
+            .unwrap()
+            .write_all(b"LASF but not much else")
+            .unwrap();
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+        let header = Header::read_from(&mut fs.open("in/a.las").unwrap()).unwrap();
+        assert_eq!(
+            max_tile_memory(&conf, &fs),
+            estimate_tile_memory(
+                &conf,
+                header.max_x - header.min_x,
+                header.max_y - header.min_y
+            )
+        );
+    }
+
+    #[test]
+    fn test_parallel_batch_matches_sequential() {
+        let fs = MemoryFileSystem::new();
+        fs.create_dir_all("in").unwrap();
+        write_tile(&fs, "in/a.las", 1000.0, 2000.0);
+        write_tile(&fs, "in/b.las", 1090.0, 2000.0);
+
+        let mut conf = Config::from_file(Path::new("pullauta.default.ini")).unwrap();
+        conf.lazfolder = "in".to_string();
+
+        conf.batchoutfolder = "sequential".to_string();
+        batch_process(&conf, &fs, &String::new());
+
+        conf.batchoutfolder = "parallel".to_string();
+        batch_process_parallel(&conf, &fs, 2);
+
+        let sequential = read_outputs(&fs, "sequential");
+        let parallel = read_outputs(&fs, "parallel");
+        assert!(sequential.iter().any(|(name, _)| name == "a.las.png"));
+        assert!(sequential.iter().any(|(name, _)| name == "b.las.png"));
+        assert_eq!(
+            sequential.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            parallel.iter().map(|(name, _)| name).collect::<Vec<_>>()
+        );
+        for ((name, a), (_, b)) in sequential.iter().zip(parallel.iter()) {
+            assert!(a == b, "{} differs", name);
+        }
+    }
 }
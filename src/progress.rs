@@ -0,0 +1,133 @@
+use std::sync::RwLock;
+
+/// A stage of the pipeline, that reports its progress and is timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Reading the points of the input LAS/LAZ file.
+    ReadPoints,
+    /// Building the ground heightmap from the points.
+    Heightmap,
+    /// Tracing the contours from the heightmap.
+    Contours,
+    /// Detecting the knolls and adjusting the heightmap around them.
+    Knolls,
+    /// Generating the vegetation.
+    Vegetation,
+    /// Generating the cliffs.
+    Cliffs,
+    /// Detecting the buildings.
+    Buildings,
+    /// Rendering the map image.
+    Render,
+}
+
+impl Stage {
+    /// The name of the stage, as written to the manifest.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::ReadPoints => "read points",
+            Stage::Heightmap => "heightmap",
+            Stage::Contours => "contours",
+            Stage::Knolls => "knolls",
+            Stage::Vegetation => "vegetation",
+            Stage::Cliffs => "cliffs",
+            Stage::Buildings => "buildings",
+            Stage::Render => "render",
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(Stage, f32) + Send + Sync>;
+
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Held by the tests that install a handler, as it is shared by the whole process.
+#[cfg(test)]
+pub(crate) static TEST_HANDLER_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Install a process-wide handler that is called with the stage and its completion fraction
+/// between 0.0 and 1.0. Replaces any previously installed handler. Without a handler, reporting is
+/// a no-op.
+///
+/// The handler is called on the thread doing the work, so when tiles are processed in parallel
+/// each worker reports its own progress and they can be told apart with [`std::thread::current`].
+pub fn set_progress_handler(handler: impl Fn(Stage, f32) + Send + Sync + 'static) {
+    *HANDLER.write().expect("progress handler lock poisoned") = Some(Box::new(handler));
+}
+
+/// Remove the installed progress handler, if any.
+pub fn clear_progress_handler() {
+    *HANDLER.write().expect("progress handler lock poisoned") = None;
+}
+
+/// Report the progress of a stage to the installed handler. The fraction is clamped to 0.0..=1.0.
+pub(crate) fn report(stage: Stage, fraction: f32) {
+    if let Some(handler) = HANDLER
+        .read()
+        .expect("progress handler lock poisoned")
+        .as_ref()
+    {
+        handler(stage, fraction.clamp(0.0, 1.0));
+    }
+}
+
+/// Report the progress of a stage as `done` out of `total` steps.
+pub(crate) fn report_steps(stage: Stage, done: usize, total: usize) {
+    if total > 0 {
+        report(stage, done as f32 / total as f32);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, ThreadId};
+
+    use super::*;
+
+    #[test]
+    fn test_handler_called_on_reporting_thread() {
+        // a failing test holding the lock poisons it, which does not matter to the others
+        let _lock = TEST_HANDLER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let calls: Arc<Mutex<Vec<(ThreadId, Stage, f32)>>> = Arc::default();
+        let recorded = calls.clone();
+        set_progress_handler(move |stage, fraction| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((thread::current().id(), stage, fraction));
+        });
+
+        let workers = (0..2)
+            .map(|i| {
+                thread::spawn(move || {
+                    report_steps(Stage::Heightmap, i + 1, 4);
+                    report(Stage::Render, 2.0);
+                    thread::current().id()
+                })
+            })
+            .collect::<Vec<_>>();
+        let ids = workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .collect::<Vec<_>>();
+        clear_progress_handler();
+
+        // other tests may run the pipeline at the same time, only look at our own threads
+        let calls = calls.lock().unwrap();
+        for (i, id) in ids.iter().enumerate() {
+            let own = calls
+                .iter()
+                .filter(|(t, _, _)| t == id)
+                .map(|&(_, stage, fraction)| (stage, fraction))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                own,
+                vec![
+                    (Stage::Heightmap, (i + 1) as f32 / 4.0),
+                    (Stage::Render, 1.0)
+                ]
+            );
+        }
+    }
+}
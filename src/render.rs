@@ -1,10 +1,19 @@
-use crate::config::Config;
+use crate::config::{Background, Config, Corner};
+use crate::contours::{Contour, FormLineSelector};
 use crate::io::bytes::FromToBytes;
+use crate::io::crs::write_prj;
 use crate::io::fs::FileSystem;
 use crate::io::heightmap::HeightMap;
+use crate::io::worldfile::{read_world_file, write_world_file};
+use crate::progress::{report, report_steps, Stage};
 use image::ImageBuffer;
 use image::Rgba;
-use imageproc::drawing::{draw_filled_circle_mut, draw_line_segment_mut};
+use image::RgbaImage;
+use imageproc::drawing::{
+    draw_filled_circle_mut, draw_filled_rect_mut, draw_line_segment_mut, draw_polygon_mut,
+};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
 use log::info;
 use rustc_hash::FxHashMap as HashMap;
 use std::error::Error;
@@ -14,6 +23,9 @@ use std::io::BufReader;
 use std::io::{BufWriter, Write};
 use std::path::Path;
 
+/// The number of progress steps reported while rendering.
+const RENDER_STEPS: usize = 8;
+
 pub fn render(
     fs: &impl FileSystem,
     config: &Config,
@@ -26,6 +38,7 @@ pub fn render(
     info!("Rendering...");
 
     let scalefactor = config.scalefactor;
+    let pixels_per_meter = config.pixels_per_meter;
 
     let angle = -angle_deg / 180.0 * PI;
 
@@ -66,11 +79,11 @@ pub fn render(
 
     let eastoff = -((x0 - (-angle).tan() * y0)
         - ((x0 - (-angle).tan() * y0) / (250.0 / angle.cos())).floor() * (250.0 / angle.cos()))
-        / 254.0
-        * 600.0;
+        * pixels_per_meter
+        * scalefactor;
 
-    let new_width = (w as f64 * 600.0 / 254.0 / scalefactor) as u32;
-    let new_height = (h as f64 * 600.0 / 254.0 / scalefactor) as u32;
+    let new_width = raster_size(w as f64, pixels_per_meter);
+    let new_height = raster_size(h as f64, pixels_per_meter);
     let mut img = image::imageops::resize(
         &img,
         new_width,
@@ -104,29 +117,31 @@ pub fn render(
         image::imageops::overlay(&mut img, &low, 0, 0);
     }
 
+    report_steps(Stage::Render, 1, RENDER_STEPS);
     // north lines ----------------
     if angle != 999.0 {
-        let mut i: f64 = eastoff - 600.0 * 250.0 / 254.0 / angle.cos() * 100.0 / scalefactor;
-        while i < w as f64 * 5.0 * 600.0 / 254.0 / scalefactor {
+        let mut i: f64 = eastoff - 250.0 * pixels_per_meter / angle.cos() * 100.0;
+        while i < w as f64 * 5.0 * pixels_per_meter {
             for m in 0..nwidth {
                 draw_line_segment_mut(
                     &mut img,
                     (i as f32 + m as f32, 0.0),
                     (
-                        (i as f32
-                            + (angle.tan() * (h as f64) * 600.0 / 254.0 / scalefactor) as f32)
+                        (i as f32 + (angle.tan() * (h as f64) * pixels_per_meter) as f32)
                             + m as f32,
-                        (h as f32 * 600.0 / 254.0 / scalefactor as f32),
+                        (h as f32 * pixels_per_meter as f32),
                     ),
                     Rgba([0, 0, 200, 255]),
                 );
             }
-            i += 600.0 * 250.0 / 254.0 / angle.cos() / scalefactor;
+            i += 250.0 * pixels_per_meter / angle.cos();
         }
     }
 
+    report_steps(Stage::Render, 2, RENDER_STEPS);
     draw_curves(fs, config, &mut img, tmpfolder, nodepressions, true).unwrap();
 
+    report_steps(Stage::Render, 3, RENDER_STEPS);
     // dotknolls----------
     let input = tmpfolder.join("dotknolls.dxf");
     let data = fs.read_to_string(input).expect("Can not read input file");
@@ -141,14 +156,10 @@ pub fn render(
             for (i, v) in val.iter().enumerate() {
                 let vt = v.trim_end();
                 if vt == " 10" {
-                    x = (val[i + 1].trim().parse::<f64>().unwrap() - x0) * 600.0
-                        / 254.0
-                        / scalefactor;
+                    x = (val[i + 1].trim().parse::<f64>().unwrap() - x0) * pixels_per_meter;
                 }
                 if vt == " 20" {
-                    y = (y0 - val[i + 1].trim().parse::<f64>().unwrap()) * 600.0
-                        / 254.0
-                        / scalefactor;
+                    y = (y0 - val[i + 1].trim().parse::<f64>().unwrap()) * pixels_per_meter;
                 }
             }
             if layer == "dotknoll" {
@@ -158,6 +169,7 @@ pub fn render(
             }
         }
     }
+    report_steps(Stage::Render, 4, RENDER_STEPS);
     // blocks -------------
     let blocks_file = tmpfolder.join("blocks.png");
     if fs.exists(&blocks_file) {
@@ -193,6 +205,7 @@ pub fn render(
         }
         image::imageops::overlay(&mut img, &blockpurple_thumb, 0, 0);
     }
+    report_steps(Stage::Render, 5, RENDER_STEPS);
     // blueblack -------------
     let blueblack_file = tmpfolder.join("blueblack.png");
     if fs.exists(&blueblack_file) {
@@ -230,6 +243,7 @@ pub fn render(
             ("cliff4", Rgba([100, 100, 0, 255])),
         ]);
     }
+    report_steps(Stage::Render, 6, RENDER_STEPS);
     let input = tmpfolder.join("c2g.dxf");
     let data = fs.read_to_string(input).expect("Can not read input file");
     let data: Vec<&str> = data.split("POLYLINE").collect();
@@ -257,20 +271,12 @@ pub fn render(
             for (i, v) in r.iter().enumerate() {
                 if i > 0 {
                     let val = v.trim_end().split('\n').collect::<Vec<&str>>();
-                    x.push(
-                        (val[xline].trim().parse::<f64>().unwrap() - x0) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
-                    y.push(
-                        (y0 - val[yline].trim().parse::<f64>().unwrap()) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
+                    x.push((val[xline].trim().parse::<f64>().unwrap() - x0) * pixels_per_meter);
+                    y.push((y0 - val[yline].trim().parse::<f64>().unwrap()) * pixels_per_meter);
                 }
             }
         }
-        let last_idx = x.len() - 1;
+        let last_idx = x.len().saturating_sub(1);
         if x.first() != x.last() || y.first() != y.last() {
             let dist = ((x[0] - x[last_idx]).powi(2) + (y[0] - y[last_idx]).powi(2)).sqrt();
             if dist > 0.0 {
@@ -341,20 +347,12 @@ pub fn render(
             for (i, v) in r.iter().enumerate() {
                 if i > 0 {
                     let val = v.trim_end().split('\n').collect::<Vec<&str>>();
-                    x.push(
-                        (val[xline].trim().parse::<f64>().unwrap() - x0) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
-                    y.push(
-                        (y0 - val[yline].trim().parse::<f64>().unwrap()) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
+                    x.push((val[xline].trim().parse::<f64>().unwrap() - x0) * pixels_per_meter);
+                    y.push((y0 - val[yline].trim().parse::<f64>().unwrap()) * pixels_per_meter);
                 }
             }
         }
-        let last_idx = x.len() - 1;
+        let last_idx = x.len().saturating_sub(1);
         if x.first() != x.last() || y.first() != y.last() {
             let dist = ((x[0] - x[last_idx]).powi(2) + (y[0] - y[last_idx]).powi(2)).sqrt();
             if dist > 0.0 {
@@ -398,6 +396,7 @@ pub fn render(
             }
         }
     }
+    report_steps(Stage::Render, 7, RENDER_STEPS);
     // high -------------
     let high_file = tmpfolder.join("high.png");
     if fs.exists(&high_file) {
@@ -416,6 +415,12 @@ pub fn render(
         image::imageops::overlay(&mut img, &high_thumb, 0, 0);
     }
 
+    let mut rows_above = 0;
+    if config.legend.is_some() {
+        let (_, _, pixel_size) = read_world_file(fs, tmpfolder.join("vegetation.png"))?;
+        rows_above = add_legend(&mut img, config, pixel_size / pixels_per_meter);
+    }
+
     let filename = if nodepressions {
         format!("pullautus{}", thread)
     } else {
@@ -431,26 +436,231 @@ pub fn render(
     )
     .expect("could not write image");
 
-    let file_in = tmpfolder.join("vegetation.pgw");
-    let pgw_file_out = fs
-        .create(format!("{}.pgw", filename))
-        .expect("Unable to create file");
-    let mut pgw_file_out = BufWriter::new(pgw_file_out);
-
-    if let Ok(lines) = fs.open(file_in) {
-        for (i, line) in BufReader::new(lines).lines().enumerate() {
-            let ip = line.unwrap_or(String::new());
-            let x: f64 = ip.parse::<f64>().unwrap();
-            if i == 0 || i == 3 {
-                write!(&mut pgw_file_out, "{}\r\n", x / 600.0 * 254.0 * scalefactor)
-                    .expect("Unable to write to file");
-            } else {
-                write!(&mut pgw_file_out, "{}\r\n", ip).expect("Unable to write to file");
+    scale_world_file(
+        fs,
+        tmpfolder.join("vegetation.png"),
+        format!("{}.png", filename),
+        pixels_per_meter,
+        rows_above,
+    )?;
+    write_prj(fs, format!("{}.png", filename), config.crs_epsg)?;
+    report(Stage::Render, 1.0);
+    info!("Done");
+    Ok(())
+}
+
+/// The number of pixels needed to cover `extent` meters at `pixels_per_meter`.
+pub fn raster_size(extent: f64, pixels_per_meter: f64) -> u32 {
+    (extent * pixels_per_meter) as u32
+}
+
+/// Write the world file of a rendered map from the world file of the (1 pixel per meter)
+/// vegetation image it was rendered from, with `rows_above` rows added above the map.
+fn scale_world_file(
+    fs: &impl FileSystem,
+    from_png: impl AsRef<Path>,
+    to_png: impl AsRef<Path>,
+    pixels_per_meter: f64,
+    rows_above: u32,
+) -> std::io::Result<()> {
+    let (x, y, pixel_size) = read_world_file(fs, from_png)?;
+    let pixel_size = pixel_size / pixels_per_meter;
+    write_world_file(
+        fs,
+        to_png,
+        x,
+        y + rows_above as f64 * pixel_size,
+        pixel_size,
+    )
+}
+
+/// The glyphs of the legend text, 5 pixels high and 3 wide unless given otherwise, with the rows
+/// from the top and the bits from the left.
+const LEGEND_GLYPHS: [(char, u32, [u8; 5]); 15] = [
+    ('0', 3, [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', 3, [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', 3, [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', 3, [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', 3, [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', 3, [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', 3, [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', 3, [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', 3, [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', 3, [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('.', 3, [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (' ', 3, [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('k', 3, [0b100, 0b101, 0b110, 0b101, 0b101]),
+    ('m', 5, [0b00000, 0b11110, 0b10101, 0b10101, 0b10101]),
+    ('N', 4, [0b1001, 0b1101, 0b1011, 0b1001, 0b1001]),
+];
+/// The size in pixels of each pixel of the legend glyphs.
+const LEGEND_FONT_SCALE: u32 = 3;
+/// The space in pixels around and between the parts of the legend.
+const LEGEND_PADDING: u32 = 8;
+const LEGEND_BAR_HEIGHT: u32 = 8;
+const LEGEND_ARROW_WIDTH: u32 = 18;
+const LEGEND_ARROW_HEIGHT: u32 = 24;
+/// The height in pixels of the legend, and of the strip added for it with `legend_margin`.
+pub const LEGEND_HEIGHT: u32 = 3 * LEGEND_PADDING + 5 * LEGEND_FONT_SCALE + LEGEND_ARROW_HEIGHT;
+
+/// The longest round length (1, 2 or 5 times a power of ten) not longer than `max`.
+fn round_length(max: f64) -> f64 {
+    let power = 10f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|m| m * power)
+        .find(|&length| length <= max)
+        .unwrap_or(power)
+}
+
+/// The glyphs of the characters of `text` that have one, with their widths.
+fn legend_glyphs(text: &str) -> impl Iterator<Item = (u32, [u8; 5])> + '_ {
+    text.chars().filter_map(|c| {
+        LEGEND_GLYPHS
+            .iter()
+            .find(|(g, _, _)| *g == c)
+            .map(|&(_, width, rows)| (width, rows))
+    })
+}
+
+/// The width in pixels of `text` drawn with [`draw_legend_text`].
+fn legend_text_width(text: &str) -> u32 {
+    legend_glyphs(text)
+        .map(|(width, _)| (width + 1) * LEGEND_FONT_SCALE)
+        .sum::<u32>()
+        .saturating_sub(LEGEND_FONT_SCALE)
+}
+
+/// Draw `text` with the legend glyphs, with its top left corner at `(x, y)`.
+fn draw_legend_text(img: &mut RgbaImage, text: &str, x: u32, y: u32, color: Rgba<u8>) {
+    let mut gx = x;
+    for (width, rows) in legend_glyphs(text) {
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..width {
+                if bits >> (width - 1 - col) & 1 != 0 {
+                    draw_filled_rect_mut(
+                        img,
+                        Rect::at(
+                            (gx + col * LEGEND_FONT_SCALE) as i32,
+                            (y + row as u32 * LEGEND_FONT_SCALE) as i32,
+                        )
+                        .of_size(LEGEND_FONT_SCALE, LEGEND_FONT_SCALE),
+                        color,
+                    );
+                }
             }
         }
+        gx += (width + 1) * LEGEND_FONT_SCALE;
     }
-    info!("Done");
-    Ok(())
+}
+
+/// Draw the legend of `config`, if any, on the map where each pixel is `meters_per_pixel` meters,
+/// on the strip added for it with `legend_margin`. Returns the number of rows added above the map.
+pub fn add_legend(img: &mut RgbaImage, config: &Config, meters_per_pixel: f64) -> u32 {
+    let Some(corner) = config.legend else {
+        return 0;
+    };
+    let mut rows_above = 0;
+    if config.legend_margin {
+        let color = match config.background {
+            Background::Color([r, g, b]) => Rgba([r, g, b, 255]),
+            Background::Transparent => Rgba([0, 0, 0, 0]),
+        };
+        (*img, rows_above) = add_legend_margin(img, corner, color);
+    }
+    draw_legend(img, corner, meters_per_pixel);
+    rows_above
+}
+
+/// Add a strip of `color` [`LEGEND_HEIGHT`] pixels high for the legend to the top or bottom edge of
+/// the map, on the side of `corner`. Returns the new image and the number of rows added above the
+/// map.
+pub fn add_legend_margin(img: &RgbaImage, corner: Corner, color: Rgba<u8>) -> (RgbaImage, u32) {
+    let above = match corner {
+        Corner::TopLeft | Corner::TopRight => LEGEND_HEIGHT,
+        Corner::BottomLeft | Corner::BottomRight => 0,
+    };
+    let mut result = RgbaImage::from_pixel(img.width(), img.height() + LEGEND_HEIGHT, color);
+    image::imageops::replace(&mut result, img, 0, above as i64);
+    (result, above)
+}
+
+/// Draw a scale bar and a north arrow on a white box in the `corner` of the map, where each pixel
+/// is `meters_per_pixel` meters. The scale bar is a round number of meters, at most a quarter of
+/// the width of the map, in four alternating black and white parts. Nothing is drawn if the map
+/// is too small for the legend.
+pub fn draw_legend(img: &mut RgbaImage, corner: Corner, meters_per_pixel: f64) {
+    let black = Rgba([0, 0, 0, 255]);
+    let white = Rgba([255, 255, 255, 255]);
+    let (p, s) = (LEGEND_PADDING, LEGEND_FONT_SCALE);
+
+    let length = round_length(img.width() as f64 / 4.0 * meters_per_pixel);
+    let bar_width = ((length / meters_per_pixel).round() as u32).max(4);
+    let label = if length >= 1000.0 {
+        format!("{} km", length / 1000.0)
+    } else {
+        format!("{} m", length)
+    };
+    let label_width = legend_text_width(&label);
+    let width = 3 * p + bar_width.max(label_width) + LEGEND_ARROW_WIDTH;
+    if width > img.width() || LEGEND_HEIGHT > img.height() {
+        return;
+    }
+    let x0 = match corner {
+        Corner::TopLeft | Corner::BottomLeft => 0,
+        Corner::TopRight | Corner::BottomRight => img.width() - width,
+    };
+    let y0 = match corner {
+        Corner::TopLeft | Corner::TopRight => 0,
+        Corner::BottomLeft | Corner::BottomRight => img.height() - LEGEND_HEIGHT,
+    };
+    draw_filled_rect_mut(
+        img,
+        Rect::at(x0 as i32, y0 as i32).of_size(width, LEGEND_HEIGHT),
+        white,
+    );
+
+    // the scale bar at the bottom with its length above it
+    let bar_y = y0 + LEGEND_HEIGHT - p - LEGEND_BAR_HEIGHT;
+    draw_legend_text(img, &label, x0 + p, bar_y - p - 5 * s, black);
+    draw_filled_rect_mut(
+        img,
+        Rect::at((x0 + p) as i32, bar_y as i32).of_size(bar_width, LEGEND_BAR_HEIGHT),
+        black,
+    );
+    for part in [1, 3] {
+        let (from, to) = (bar_width * part / 4, bar_width * (part + 1) / 4);
+        if to - from > 2 {
+            draw_filled_rect_mut(
+                img,
+                Rect::at((x0 + p + from) as i32, (bar_y + 1) as i32)
+                    .of_size(to - from - 1, LEGEND_BAR_HEIGHT - 2),
+                white,
+            );
+        }
+    }
+
+    // the north arrow with an N above it
+    let ax = x0 + 2 * p + bar_width.max(label_width);
+    draw_legend_text(
+        img,
+        "N",
+        ax + (LEGEND_ARROW_WIDTH - 3 * s) / 2,
+        y0 + p,
+        black,
+    );
+    let ay = y0 + LEGEND_HEIGHT - p - LEGEND_ARROW_HEIGHT;
+    let point = |x: u32, y: u32| Point::new((ax + x) as i32, (ay + y) as i32);
+    draw_polygon_mut(
+        img,
+        &[
+            point(LEGEND_ARROW_WIDTH / 2, 0),
+            point(LEGEND_ARROW_WIDTH - 1, LEGEND_ARROW_HEIGHT - 1),
+            point(LEGEND_ARROW_WIDTH / 2, LEGEND_ARROW_HEIGHT * 2 / 3),
+            point(0, LEGEND_ARROW_HEIGHT - 1),
+        ],
+        black,
+    );
 }
 
 pub fn draw_curves(
@@ -464,6 +674,7 @@ pub fn draw_curves(
     // Drawing curves --------------
     let &Config {
         scalefactor,
+        pixels_per_meter,
         mut formlinesteepness,
         formline,
         formlineaddition,
@@ -471,6 +682,7 @@ pub fn draw_curves(
         gaplength,
         minimumgap,
         label_depressions,
+        contour_antialias,
         ..
     } = config;
     formlinesteepness *= scalefactor;
@@ -601,6 +813,31 @@ pub fn draw_curves(
     let data = fs.read_to_string(input).expect("Can not read input file");
     let data: Vec<&str> = data.split("POLYLINE").collect();
 
+    // select the form lines like the vector outputs, see `merge::smoothjoin`
+    let form_line_selector = if formline == 2.0 && config.formline_by_slope {
+        let hmap = HeightMap::from_file(fs, tmpfolder.join("xyz_knolls.hmap"))?;
+        let regular = data
+            .iter()
+            .skip(1)
+            .map(|rec| read_polyline(rec))
+            .filter(|(layer, _)| !layer.contains("intermed"))
+            .map(|(_, points)| Contour {
+                points,
+                elevation: 0.0,
+                index: false,
+                form_line: false,
+            })
+            .collect::<Vec<_>>();
+        Some(FormLineSelector::new(
+            &regular,
+            &hmap,
+            config.formline_max_slope,
+            config.formline_min_distance,
+        ))
+    } else {
+        None
+    };
+
     // only create the file if condition is met
     let mut fp = if formline == 2.0 && !nodepressions {
         let output = tmpfolder.join("formlines.dxf");
@@ -617,37 +854,13 @@ pub fn draw_curves(
     for (j, rec) in data.iter().enumerate() {
         let mut x = Vec::<f64>::new();
         let mut y = Vec::<f64>::new();
-        let mut xline = 0;
-        let mut yline = 0;
         let mut layer = "";
         if j > 0 {
-            let r = rec.split("VERTEX").collect::<Vec<&str>>();
-            let apu = r[1];
-            let val = apu.split('\n').collect::<Vec<&str>>();
-            layer = val[2].trim();
-            for (i, v) in val.iter().enumerate() {
-                let vt = v.trim_end();
-                if vt == " 10" {
-                    xline = i + 1;
-                }
-                if vt == " 20" {
-                    yline = i + 1;
-                }
-            }
-            for (i, v) in r.iter().enumerate() {
-                if i > 0 {
-                    let val = v.trim_end().split('\n').collect::<Vec<&str>>();
-                    x.push(
-                        (val[xline].trim().parse::<f64>().unwrap() - x0) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
-                    y.push(
-                        (y0 - val[yline].trim().parse::<f64>().unwrap()) * 600.0
-                            / 254.0
-                            / scalefactor,
-                    );
-                }
+            let points;
+            (layer, points) = read_polyline(rec);
+            for (px, py) in points {
+                x.push((px - x0) * pixels_per_meter);
+                y.push((y0 - py) * pixels_per_meter);
             }
         }
         let mut color = Rgba([200, 0, 200, 255]); // purple
@@ -675,14 +888,17 @@ pub fn draw_curves(
             let mut smallringtest = false;
             let mut help = vec![false; x.len()];
             let mut help2 = vec![false; x.len()];
-            if curvew == 1.5 {
+            if let (Some(selector), true) = (&form_line_selector, curvew == 1.5) {
+                for i in 0..x.len() {
+                    help2[i] =
+                        selector.keep(x[i] / pixels_per_meter + x0, -y[i] / pixels_per_meter + y0);
+                }
+            } else if curvew == 1.5 {
                 for i in 0..x.len() {
                     help[i] = false;
                     help2[i] = true;
-                    let xx = (((x[i] / 600.0 * 254.0 * scalefactor + x0) - xstart) / size).floor()
-                        as usize;
-                    let yy = (((-y[i] / 600.0 * 254.0 * scalefactor + y0) - ystart) / size).floor()
-                        as usize;
+                    let xx = (((x[i] / pixels_per_meter + x0) - xstart) / size).floor() as usize;
+                    let yy = (((-y[i] / pixels_per_meter + y0) - ystart) / size).floor() as usize;
                     if curvew != 1.5
                         || formline == 0.0
                         || steepness.get(&(xx, yy)).unwrap_or(&0.0) < &formlinesteepness
@@ -803,19 +1019,32 @@ pub fn draw_curves(
             };
 
             for i in 1..x.len() {
-                if curvew != 1.5 || formline == 0.0 || help2[i] || smallringtest {
+                // the selected form lines are the runs of kept vertices
+                let keep = help2[i] && (form_line_selector.is_none() || help2[i - 1]);
+                if curvew != 1.5 || formline == 0.0 || keep || smallringtest {
                     if let (Some(fp), true) = (fp.as_mut(), curvew == 1.5) {
                         if !formlinestart {
                             write!(fp, "POLYLINE\r\n 66\r\n1\r\n  8\r\n{}\r\n  0\r\n", f_label)
                                 .expect("Could not write file");
+                            // the selected runs start at the vertex before, like the vector outputs
+                            if form_line_selector.is_some() {
+                                write!(
+                                    fp,
+                                    "VERTEX\r\n  8\r\n{}\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\n",
+                                    f_label,
+                                    x[i - 1] / pixels_per_meter + x0,
+                                    -y[i - 1] / pixels_per_meter + y0
+                                )
+                                .expect("Could not write file");
+                            }
                             formlinestart = true;
                         }
                         write!(
                             fp,
                             "VERTEX\r\n  8\r\n{}\r\n 10\r\n{}\r\n 20\r\n{}\r\n  0\r\n",
                             f_label,
-                            x[i] / 600.0 * 254.0 * scalefactor + x0,
-                            -y[i] / 600.0 * 254.0 * scalefactor + y0
+                            x[i] / pixels_per_meter + x0,
+                            -y[i] / pixels_per_meter + y0
                         )
                         .expect("Could not write file");
                     }
@@ -861,60 +1090,38 @@ pub fn draw_curves(
                             if gap > 0.0 {
                                 gap -= step;
                                 if gap < 0.0 && onegapdone && step > 0.0 {
-                                    let mut n = -curvew - 0.5;
-                                    while n < curvew + 0.5 {
-                                        let mut m = -curvew - 0.5;
-                                        while m < curvew + 0.5 {
-                                            draw_line_segment_mut(
-                                                canvas,
-                                                (
-                                                    ((-x[i - 1] * gap + (step + gap) * x[i]) / step
-                                                        + n)
-                                                        as f32,
-                                                    ((-y[i - 1] * gap + (step + gap) * y[i]) / step
-                                                        + m)
-                                                        as f32,
-                                                ),
-                                                ((x[i] + n) as f32, (y[i] + m) as f32),
-                                                color,
-                                            );
-                                            m += 1.0;
-                                        }
-                                        n += 1.0;
-                                    }
-                                    gap = 0.0;
-                                }
-                            } else {
-                                let mut n = -curvew - 0.5;
-                                while n < curvew + 0.5 {
-                                    let mut m = -curvew - 0.5;
-                                    while m < curvew + 0.5 {
-                                        draw_line_segment_mut(
-                                            canvas,
-                                            ((x[i - 1] + n) as f32, (y[i - 1] + m) as f32),
-                                            ((x[i] + n) as f32, (y[i] + m) as f32),
-                                            color,
-                                        );
-                                        m += 1.0;
-                                    }
-                                    n += 1.0;
-                                }
-                            }
-                        } else {
-                            let mut n = -curvew;
-                            while n < curvew {
-                                let mut m = -curvew;
-                                while m < curvew {
-                                    draw_line_segment_mut(
+                                    draw_thick_line(
                                         canvas,
-                                        ((x[i - 1] + n) as f32, (y[i - 1] + m) as f32),
-                                        ((x[i] + n) as f32, (y[i] + m) as f32),
+                                        (
+                                            (-x[i - 1] * gap + (step + gap) * x[i]) / step,
+                                            (-y[i - 1] * gap + (step + gap) * y[i]) / step,
+                                        ),
+                                        (x[i], y[i]),
+                                        curvew + 0.5,
+                                        contour_antialias,
                                         color,
                                     );
-                                    m += 1.0;
+                                    gap = 0.0;
                                 }
-                                n += 1.0;
+                            } else {
+                                draw_thick_line(
+                                    canvas,
+                                    (x[i - 1], y[i - 1]),
+                                    (x[i], y[i]),
+                                    curvew + 0.5,
+                                    contour_antialias,
+                                    color,
+                                );
                             }
+                        } else {
+                            draw_thick_line(
+                                canvas,
+                                (x[i - 1], y[i - 1]),
+                                (x[i], y[i]),
+                                curvew,
+                                contour_antialias,
+                                color,
+                            );
                         }
                     }
                 } else if let (Some(fp), true) = (fp.as_mut(), formlinestart) {
@@ -935,3 +1142,193 @@ pub fn draw_curves(
     }
     Ok(())
 }
+
+/// Read the layer and the vertices of a DXF polyline, the text after a `POLYLINE` marker.
+fn read_polyline(rec: &str) -> (&str, Vec<(f64, f64)>) {
+    let r = rec.split("VERTEX").collect::<Vec<&str>>();
+    let val = r[1].split('\n').collect::<Vec<&str>>();
+    let layer = val[2].trim();
+    let mut xline = 0;
+    let mut yline = 0;
+    for (i, v) in val.iter().enumerate() {
+        let vt = v.trim_end();
+        if vt == " 10" {
+            xline = i + 1;
+        }
+        if vt == " 20" {
+            yline = i + 1;
+        }
+    }
+    let points = r[1..]
+        .iter()
+        .map(|v| {
+            let val = v.trim_end().split('\n').collect::<Vec<&str>>();
+            (
+                val[xline].trim().parse::<f64>().unwrap(),
+                val[yline].trim().parse::<f64>().unwrap(),
+            )
+        })
+        .collect();
+    (layer, points)
+}
+
+/// Draw a line from `from` to `to` that extends `half` pixels to each side. The hard edged line is
+/// drawn with a square brush of 1 pixel lines, while the anti-aliased one blends each pixel with
+/// `color` by the fraction of it that the line covers, see [`draw_line_segment_aa`].
+fn draw_thick_line(
+    canvas: &mut RgbaImage,
+    from: (f64, f64),
+    to: (f64, f64),
+    half: f64,
+    antialias: bool,
+    color: Rgba<u8>,
+) {
+    if antialias {
+        let from = (from.0 as f32, from.1 as f32);
+        let to = (to.0 as f32, to.1 as f32);
+        draw_line_segment_aa(canvas, from, to, 2.0 * half as f32, color);
+        return;
+    }
+    let mut n = -half;
+    while n < half {
+        let mut m = -half;
+        while m < half {
+            draw_line_segment_mut(
+                canvas,
+                ((from.0 + n) as f32, (from.1 + m) as f32),
+                ((to.0 + n) as f32, (to.1 + m) as f32),
+                color,
+            );
+            m += 1.0;
+        }
+        n += 1.0;
+    }
+}
+
+/// Draw an anti-aliased line segment `width` pixels wide. The coverage of each pixel is estimated
+/// from the distance of its center to the segment, so pixels well inside the line get `color`,
+/// pixels on its edges are blended with it and the ends are rounded. Overlapping segments of a
+/// polyline are blended twice, which makes the edge pixels at the vertices slightly darker.
+pub fn draw_line_segment_aa(
+    canvas: &mut RgbaImage,
+    from: (f32, f32),
+    to: (f32, f32),
+    width: f32,
+    color: Rgba<u8>,
+) {
+    let half = width / 2.0;
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let len2 = dx * dx + dy * dy;
+    let x_range = from.0.min(to.0) - half - 1.0..=from.0.max(to.0) + half + 1.0;
+    let y_range = from.1.min(to.1) - half - 1.0..=from.1.max(to.1) + half + 1.0;
+    let x_end = (x_range.end().ceil().max(0.0) as u32).min(canvas.width());
+    let y_end = (y_range.end().ceil().max(0.0) as u32).min(canvas.height());
+    for py in y_range.start().floor().max(0.0) as u32..y_end {
+        for px in x_range.start().floor().max(0.0) as u32..x_end {
+            let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+            let t = if len2 > 0.0 {
+                (((cx - from.0) * dx + (cy - from.1) * dy) / len2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = (cx - from.0 - t * dx).hypot(cy - from.1 - t * dy);
+            let coverage = (half + 0.5 - distance).clamp(0.0, 1.0);
+            if coverage <= 0.0 {
+                continue;
+            }
+            let alpha = coverage * color[3] as f32 / 255.0;
+            let pixel = canvas.get_pixel_mut(px, py);
+            for c in 0..3 {
+                pixel[c] =
+                    (pixel[c] as f32 + (color[c] as f32 - pixel[c] as f32) * alpha).round() as u8;
+            }
+            pixel[3] = pixel[3].max((alpha * 255.0).round() as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::io::fs::memory::MemoryFileSystem;
+
+    use super::*;
+
+    #[test]
+    fn test_output_resolution() {
+        // a 600 m tile rendered at 0.2 m per pixel
+        let pixels_per_meter = 1.0 / 0.2;
+        assert_eq!(raster_size(600.0, pixels_per_meter), 3000);
+
+        let fs = MemoryFileSystem::new();
+        write_world_file(&fs, "vegetation.png", 500000.0, 6700600.0, 1.0).unwrap();
+        scale_world_file(&fs, "vegetation.png", "pullautus.png", pixels_per_meter, 0).unwrap();
+        assert_eq!(
+            read_world_file(&fs, "pullautus.png").unwrap(),
+            (500000.0, 6700600.0, 0.2)
+        );
+    }
+
+    #[test]
+    fn test_draw_legend() {
+        assert_eq!(round_length(180.0), 100.0);
+        assert_eq!(round_length(499.0), 200.0);
+        assert_eq!(round_length(5.0), 5.0);
+
+        let map = RgbaImage::from_fn(800, 600, |x, y| {
+            Rgba([(x % 256) as u8, (y % 256) as u8, 90, 255])
+        });
+        let mut legend = map.clone();
+        draw_legend(&mut legend, Corner::BottomRight, 0.5);
+
+        // a 100 m bar of 200 pixels, the label and the arrow beside it
+        let width = 3 * LEGEND_PADDING + 200 + LEGEND_ARROW_WIDTH;
+        let mut changed = 0;
+        for (x, y, pixel) in legend.enumerate_pixels() {
+            if pixel != map.get_pixel(x, y) {
+                assert!(x >= 800 - width && y >= 600 - LEGEND_HEIGHT, "({x}, {y})");
+                changed += 1;
+            }
+        }
+        assert!(changed > (width * LEGEND_HEIGHT / 2) as usize);
+        let bar_y = 600 - LEGEND_PADDING - LEGEND_BAR_HEIGHT;
+        assert_eq!(
+            *legend.get_pixel(800 - width + LEGEND_PADDING, bar_y),
+            Rgba([0, 0, 0, 255])
+        );
+
+        // on a margin the map itself is untouched
+        let (mut margin, above) = add_legend_margin(&map, Corner::TopLeft, Rgba([255; 4]));
+        assert_eq!(
+            (above, margin.height()),
+            (LEGEND_HEIGHT, 600 + LEGEND_HEIGHT)
+        );
+        draw_legend(&mut margin, Corner::TopLeft, 0.5);
+        for (x, y, pixel) in map.enumerate_pixels() {
+            assert_eq!(pixel, margin.get_pixel(x, y + above));
+        }
+
+        // too small for the legend
+        let mut small = RgbaImage::new(50, 40);
+        draw_legend(&mut small, Corner::TopLeft, 1.0);
+        assert_eq!(small, RgbaImage::new(50, 40));
+    }
+
+    #[test]
+    fn test_draw_line_segment_aa() {
+        let white = Rgba([255, 255, 255, 255]);
+        let black = Rgba([0, 0, 0, 255]);
+        let mut aa = RgbaImage::from_pixel(20, 20, white);
+        draw_line_segment_aa(&mut aa, (2.0, 3.0), (17.0, 15.0), 2.0, black);
+
+        // the diagonal line has partially covered gray pixels along its edges
+        let gray = aa.pixels().filter(|p| p[0] > 0 && p[0] < 255).count();
+        assert!(gray > 10, "only {} gray pixels", gray);
+        assert_eq!(aa.get_pixel(9, 9)[0], 0);
+        assert_eq!(*aa.get_pixel(18, 2), white);
+
+        // while the hard edged line is only on or off
+        let mut hard = RgbaImage::from_pixel(20, 20, white);
+        draw_thick_line(&mut hard, (2.0, 3.0), (17.0, 15.0), 1.0, false, black);
+        assert!(hard.pixels().all(|p| p[0] == 0 || p[0] == 255));
+    }
+}
@@ -23,7 +23,7 @@ pub fn render(
     config: &Config,
     tmpfolder: &Path,
 ) -> Result<(), Box<dyn Error>> {
-    let scalefactor = config.scalefactor;
+    let pixels_per_meter = config.pixels_per_meter;
 
     let vectorconf = &config.vectorconf;
     let mtkskip = &config.mtkskiplayers;
@@ -64,8 +64,8 @@ pub fn render(
     let w = img.width() as f64;
     let h = img.height() as f64;
 
-    let outw = w * 600.0 / 254.0 / scalefactor;
-    let outh = h * 600.0 / 254.0 / scalefactor;
+    let outw = w * pixels_per_meter;
+    let outh = h * pixels_per_meter;
 
     // TODO: only allocate the canvas that are actually used... in a lazy way
     let mut imgbrown = Canvas::new(outw as i32, outh as i32);
@@ -534,8 +534,8 @@ pub fn render(
                             let x = point.x;
                             let y = point.y;
                             poly.push((
-                                (600.0 / 254.0 / scalefactor * (x - x0)).floor() as f32,
-                                (600.0 / 254.0 / scalefactor * (y0 - y)).floor() as f32,
+                                (pixels_per_meter * (x - x0)).floor() as f32,
+                                (pixels_per_meter * (y0 - y)).floor() as f32,
                             ));
                         }
                     }
@@ -632,12 +632,12 @@ pub fn render(
                             let x = point.x;
                             let y = point.y;
                             poly.push((
-                                (600.0 / 254.0 / scalefactor * (x - x0)).floor() as f32,
-                                (600.0 / 254.0 / scalefactor * (y0 - y)).floor() as f32,
+                                (pixels_per_meter * (x - x0)).floor() as f32,
+                                (pixels_per_meter * (y0 - y)).floor() as f32,
                             ));
                             polyborder.push((
-                                (600.0 / 254.0 / scalefactor * (x - x0)).floor() as f32,
-                                (600.0 / 254.0 / scalefactor * (y0 - y)).floor() as f32,
+                                (pixels_per_meter * (x - x0)).floor() as f32,
+                                (pixels_per_meter * (y0 - y)).floor() as f32,
                             ));
                         }
                         polys.push(poly);
@@ -689,9 +689,9 @@ pub fn render(
 
     let mut i = 0.0_f32;
     imgmarsh.set_transparent_color();
-    while i < ((h * 600.0 / 254.0 / scalefactor + 500.0) as f32) {
+    while i < ((h * pixels_per_meter + 500.0) as f32) {
         i += 14.0;
-        let wd = (w * 600.0 / 254.0 / scalefactor + 2.0) as f32;
+        let wd = (w * pixels_per_meter + 2.0) as f32;
         imgmarsh.draw_filled_polygon(&[vec![
             (-1.0, i),
             (wd, i),
@@ -0,0 +1,290 @@
+//! A triangulated irregular network (TIN) of ground points, used as an alternative to averaging
+//! the points in grid cells when building the heightmap.
+
+use crate::vec2d::Vec2D;
+
+const NONE: usize = usize::MAX;
+
+/// A triangle of the triangulation being built, with its vertices in counter-clockwise order and
+/// the neighbor across the edge opposite each vertex.
+#[derive(Debug, Clone, Copy)]
+struct Triangle {
+    vertices: [usize; 3],
+    neighbors: [usize; 3],
+    alive: bool,
+}
+
+/// A Delaunay triangulation of points with elevations.
+#[derive(Debug, Clone)]
+pub struct Tin {
+    points: Vec<(f64, f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`, positive if it is counter-clockwise.
+fn orient(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Positive if `p` is inside the circumcircle of the counter-clockwise triangle `a`, `b`, `c`.
+fn in_circle(a: (f64, f64), b: (f64, f64), c: (f64, f64), p: (f64, f64)) -> f64 {
+    let (ax, ay) = (a.0 - p.0, a.1 - p.1);
+    let (bx, by) = (b.0 - p.0, b.1 - p.1);
+    let (cx, cy) = (c.0 - p.0, c.1 - p.1);
+    (ax * ax + ay * ay) * (bx * cy - cx * by) - (bx * bx + by * by) * (ax * cy - cx * ay)
+        + (cx * cx + cy * cy) * (ax * by - bx * ay)
+}
+
+impl Tin {
+    /// Triangulate the points with the Bowyer-Watson algorithm. Of the points with the same `x`
+    /// and `y` only the first is used.
+    pub fn new(mut points: Vec<(f64, f64, f64)>) -> Tin {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        points.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+        if points.len() < 3 {
+            return Tin {
+                points,
+                triangles: Vec::new(),
+            };
+        }
+
+        let (mut xmin, mut ymin, mut xmax, mut ymax) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        for &(x, y, _) in &points {
+            xmin = xmin.min(x);
+            ymin = ymin.min(y);
+            xmax = xmax.max(x);
+            ymax = ymax.max(y);
+        }
+
+        // insert the points row by row, alternating the direction, so that each point is close to
+        // the previous one and the walk to the triangle containing it is short
+        let spacing = ((xmax - xmin) * (ymax - ymin) / points.len() as f64)
+            .sqrt()
+            .max(f64::MIN_POSITIVE);
+        let row_height = 4.0 * spacing;
+        let key = |&(x, y, _): &(f64, f64, f64)| {
+            let row = ((y - ymin) / row_height) as i64;
+            (row, if row % 2 == 0 { x } else { -x })
+        };
+        points.sort_by(|a, b| {
+            let (ka, kb) = (key(a), key(b));
+            ka.0.cmp(&kb.0).then(ka.1.total_cmp(&kb.1))
+        });
+
+        // work relative to the corner of the points for precision, with a super triangle around
+        // all of them as the last three vertices
+        let mut coords = points
+            .iter()
+            .map(|&(x, y, _)| (x - xmin, y - ymin))
+            .collect::<Vec<_>>();
+        let size = (xmax - xmin).max(ymax - ymin).max(1.0) * 100.0;
+        let n = coords.len();
+        coords.extend([(-size, -size), (size, -size), (0.0, size)]);
+
+        let mut triangles = vec![Triangle {
+            vertices: [n, n + 1, n + 2],
+            neighbors: [NONE; 3],
+            alive: true,
+        }];
+        let mut last = 0;
+        let mut bad = Vec::new();
+        let mut stack = Vec::new();
+        let mut boundary = Vec::new();
+        for i in 0..n {
+            let p = coords[i];
+            let start = locate(&coords, &triangles, last, p);
+
+            // the cavity of the triangles whose circumcircle contains the point
+            bad.clear();
+            stack.clear();
+            stack.push(start);
+            triangles[start].alive = false;
+            while let Some(t) = stack.pop() {
+                bad.push(t);
+                for nb in triangles[t].neighbors {
+                    if nb == NONE || !triangles[nb].alive {
+                        continue;
+                    }
+                    let [a, b, c] = triangles[nb].vertices;
+                    if in_circle(coords[a], coords[b], coords[c], p) > 0.0 {
+                        triangles[nb].alive = false;
+                        stack.push(nb);
+                    }
+                }
+            }
+
+            // the edges around the cavity, with the triangle outside each edge and the index of
+            // the edge in that triangle
+            boundary.clear();
+            for &t in &bad {
+                let tri = triangles[t];
+                for k in 0..3 {
+                    let nb = tri.neighbors[k];
+                    if nb != NONE && !triangles[nb].alive {
+                        continue;
+                    }
+                    let edge = (tri.vertices[(k + 1) % 3], tri.vertices[(k + 2) % 3]);
+                    let outer_edge = if nb == NONE {
+                        NONE
+                    } else {
+                        triangles[nb]
+                            .neighbors
+                            .iter()
+                            .position(|&x| x == t)
+                            .unwrap()
+                    };
+                    boundary.push((edge, nb, outer_edge));
+                }
+            }
+
+            // connect the point to each boundary edge, reusing the slots of the removed triangles
+            let mut new = Vec::with_capacity(boundary.len());
+            for (j, &((a, b), nb, outer_edge)) in boundary.iter().enumerate() {
+                let triangle = Triangle {
+                    vertices: [i, a, b],
+                    neighbors: [nb, NONE, NONE],
+                    alive: true,
+                };
+                let t = match bad.get(j) {
+                    Some(&t) => {
+                        triangles[t] = triangle;
+                        t
+                    }
+                    None => {
+                        triangles.push(triangle);
+                        triangles.len() - 1
+                    }
+                };
+                if nb != NONE {
+                    triangles[nb].neighbors[outer_edge] = t;
+                }
+                new.push((a, b, t));
+            }
+            for &(a, b, t) in &new {
+                // across the edge from `b` to the point is the new triangle starting at `b`, and
+                // across the edge from the point to `a` the new triangle ending at `a`
+                triangles[t].neighbors[1] = new.iter().find(|n| n.0 == b).unwrap().2;
+                triangles[t].neighbors[2] = new.iter().find(|n| n.1 == a).unwrap().2;
+            }
+            last = new[0].2;
+        }
+
+        let triangles = triangles
+            .into_iter()
+            .filter(|t| t.alive && t.vertices.iter().all(|&v| v < n))
+            .map(|t| t.vertices)
+            .collect();
+        Tin { points, triangles }
+    }
+
+    /// The number of triangles.
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// Whether there are no triangles, when there are less than three distinct points.
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// Sample the surface at the centers of the cells of a `w` x `h` grid, where the center of the
+    /// cell `(0, 0)` is at `(xoffset, yoffset)`. The cells outside the triangulation are NaN.
+    pub fn rasterize(
+        &self,
+        xoffset: f64,
+        yoffset: f64,
+        scale: f64,
+        w: usize,
+        h: usize,
+    ) -> Vec2D<f64> {
+        let mut grid = Vec2D::new(w, h, f64::NAN);
+        let cell = |v: f64, offset: f64| (v - offset) / scale;
+        for &[a, b, c] in &self.triangles {
+            let (pa, pb, pc) = (self.points[a], self.points[b], self.points[c]);
+            let area = orient((pa.0, pa.1), (pb.0, pb.1), (pc.0, pc.1));
+            if area <= 0.0 {
+                continue;
+            }
+
+            let x0 = cell(pa.0.min(pb.0).min(pc.0), xoffset).ceil().max(0.0) as usize;
+            let x1 = cell(pa.0.max(pb.0).max(pc.0), xoffset).floor();
+            let y0 = cell(pa.1.min(pb.1).min(pc.1), yoffset).ceil().max(0.0) as usize;
+            let y1 = cell(pa.1.max(pb.1).max(pc.1), yoffset).floor();
+            if x1 < 0.0 || y1 < 0.0 {
+                continue;
+            }
+            let x1 = (x1 as usize).min(w.saturating_sub(1));
+            let y1 = (y1 as usize).min(h.saturating_sub(1));
+
+            // allow for rounding on the edges, the cells there get the same value from either side
+            let eps = -1e-9 * area;
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    let p = (xoffset + x as f64 * scale, yoffset + y as f64 * scale);
+                    let wa = orient((pb.0, pb.1), (pc.0, pc.1), p);
+                    let wb = orient((pc.0, pc.1), (pa.0, pa.1), p);
+                    let wc = orient((pa.0, pa.1), (pb.0, pb.1), p);
+                    if wa >= eps && wb >= eps && wc >= eps {
+                        grid[(x, y)] = (wa * pa.2 + wb * pb.2 + wc * pc.2) / area;
+                    }
+                }
+            }
+        }
+        grid
+    }
+}
+
+/// Find the triangle containing `p` by walking from the triangle `start` towards it.
+fn locate(coords: &[(f64, f64)], triangles: &[Triangle], start: usize, p: (f64, f64)) -> usize {
+    let mut t = start;
+    'walk: for _ in 0..triangles.len() {
+        let tri = &triangles[t];
+        for k in 0..3 {
+            let a = coords[tri.vertices[(k + 1) % 3]];
+            let b = coords[tri.vertices[(k + 2) % 3]];
+            if orient(a, b, p) < 0.0 && tri.neighbors[k] != NONE {
+                t = tri.neighbors[k];
+                continue 'walk;
+            }
+        }
+        return t;
+    }
+
+    // the walk went around in circles because of rounding, fall back to checking every triangle
+    triangles
+        .iter()
+        .position(|tri| {
+            let [a, b, c] = tri.vertices.map(|v| coords[v]);
+            tri.alive && orient(a, b, p) >= 0.0 && orient(b, c, p) >= 0.0 && orient(c, a, p) >= 0.0
+        })
+        .unwrap_or(t)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_tin_is_delaunay() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let points = (0..400)
+            .map(|_| {
+                let x = rng.gen_range(0.0..100.0);
+                let y = rng.gen_range(0.0..50.0);
+                (500000.0 + x, 6700000.0 + y, x + y)
+            })
+            .collect::<Vec<_>>();
+        let tin = Tin::new(points);
+        assert!(tin.len() > 700);
+
+        for &[a, b, c] in &tin.triangles {
+            let [a, b, c] = [a, b, c].map(|v| (tin.points[v].0, tin.points[v].1));
+            assert!(orient(a, b, c) > 0.0);
+            for p in &tin.points {
+                assert!(in_circle(a, b, c, (p.0, p.1)) <= 1e-3);
+            }
+        }
+    }
+}
@@ -2,12 +2,13 @@ use std::{
     fmt::Debug,
     io::{self, BufRead, BufReader},
     path::Path,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use log::debug;
 
 use crate::io::fs::FileSystem;
+use crate::progress::Stage;
 
 /// Iterates over the lines in a file and calls the callback with a &str reference to each line.
 /// This function does not allocate new strings for each line, as opposed to using
@@ -66,11 +67,15 @@ where
 
 /// Helper struct to time operations. Keeps track of the total time taken until the object is
 /// dropped, as well as timing between individual sub-sections of the operation.
-/// Timing information is printed using debug level log messages.
+/// Timing information is printed using debug level log messages, and the time taken by each
+/// finished section is kept, see [`Timing::sections`]. Independently of the sections, the total
+/// time taken by each pipeline [`Stage`] can be recorded, see [`Timing::stages`].
 pub struct Timing {
     name: &'static str,
     start: Instant,
     current_section: Option<TimingSection>,
+    sections: Vec<(&'static str, Duration)>,
+    stages: Vec<(Stage, Duration)>,
 }
 
 struct TimingSection {
@@ -86,6 +91,8 @@ impl Timing {
             name,
             start: Instant::now(),
             current_section: None,
+            sections: Vec::new(),
+            stages: Vec::new(),
         }
     }
 
@@ -110,11 +117,38 @@ impl Timing {
                 s.name,
                 now - s.start
             );
+            self.sections.push((s.name, now - s.start));
             Some(now)
         } else {
             None
         }
     }
+
+    /// The name and time taken of each finished section, in the order they were started.
+    pub fn sections(&self) -> &[(&'static str, Duration)] {
+        &self.sections
+    }
+
+    /// Add `duration` to the time taken by `stage`.
+    pub fn add_stage(&mut self, stage: Stage, duration: Duration) {
+        match self.stages.iter_mut().find(|(s, _)| *s == stage) {
+            Some((_, total)) => *total += duration,
+            None => self.stages.push((stage, duration)),
+        }
+    }
+
+    /// Run `f` and add the time it takes to the time taken by `stage`.
+    pub fn time_stage<R>(&mut self, stage: Stage, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        self.add_stage(stage, start.elapsed());
+        result
+    }
+
+    /// The total time taken by each stage, in the order they first ran.
+    pub fn stages(&self) -> &[(Stage, Duration)] {
+        &self.stages
+    }
 }
 
 impl Drop for Timing {
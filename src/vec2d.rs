@@ -1,5 +1,26 @@
 use crate::io::bytes::FromToBytes;
 
+/// The magic number that identifies a serialized Vec2D.
+const VEC2D_MAGIC: &[u8] = b"V2DB";
+/// The version of the serialized Vec2D format, bump when the layout changes.
+const VEC2D_VERSION: u8 = 1;
+/// Upper bound on the number of elements to allocate up front when deserializing, so that a
+/// corrupt header cannot trigger a huge allocation before any element has been read.
+const MAX_PREALLOCATED_ELEMENTS: usize = 1 << 20;
+/// The (dx,dy) offsets of the edge neighbors of a cell.
+const NEIGHBORS4: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+/// The (dx,dy) offsets of the edge and corner neighbors of a cell.
+const NEIGHBORS8: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
 /// Vector for storing 2-dimensional grid-like data in a contigous memory block, removes one layer of indirection.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Vec2D<T> {
@@ -20,6 +41,47 @@ impl<T> Vec2D<T> {
         }
     }
 
+    /// Create a grid by calling `f(x, y)` for each cell. The cells are visited in storage order,
+    /// i.e. column by column: all `y` for `x = 0`, then all `y` for `x = 1`, and so on.
+    pub fn from_fn<F: FnMut(usize, usize) -> T>(w: usize, h: usize, mut f: F) -> Vec2D<T> {
+        let data = (0..w)
+            .flat_map(|x| (0..h).map(move |y| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Vec2D { data, w, h }
+    }
+
+    /// Create a grid from its rows, so that `rows[y][x]` becomes the cell `(x, y)`. Fails if the rows
+    /// are not all of the same length.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Vec2D<T>, RaggedError> {
+        let h = rows.len();
+        let w = check_lengths(&rows)?;
+        let mut rows = rows.into_iter().map(Vec::into_iter).collect::<Vec<_>>();
+        let mut data = Vec::with_capacity(w * h);
+        for _ in 0..w {
+            for row in rows.iter_mut() {
+                data.extend(row.next());
+            }
+        }
+        Ok(Vec2D {
+            data: data.into(),
+            w,
+            h,
+        })
+    }
+
+    /// Create a grid from its columns, so that `cols[x][y]` becomes the cell `(x, y)`. Fails if the
+    /// columns are not all of the same length.
+    pub fn from_cols(cols: Vec<Vec<T>>) -> Result<Vec2D<T>, RaggedError> {
+        let w = cols.len();
+        let h = check_lengths(&cols)?;
+        Ok(Vec2D {
+            data: cols.into_iter().flatten().collect(),
+            w,
+            h,
+        })
+    }
+
     pub fn width(&self) -> usize {
         self.w
     }
@@ -27,170 +89,2131 @@ impl<T> Vec2D<T> {
         self.h
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut T)> + '_ {
-        let h = self.h;
-        self.data.iter_mut().enumerate().map(move |(i, v)| {
-            let x = i / h;
-            let y = i % h;
-            (x, y, v)
-        })
+    /// The `(width, height)` of the grid.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.w, self.h)
     }
-}
 
-impl<T: Copy> Vec2D<T> {
-    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
-        self.data.iter().enumerate().map(move |(i, v)| {
-            let x = i / self.h;
-            let y = i % self.h;
-            (x, y, *v)
-        })
+    /// The number of rows, `height`, as in [`Vec2D::from_rows`].
+    pub fn rows(&self) -> usize {
+        self.h
     }
-}
 
-impl Vec2D<f64> {
-    pub fn is_any_nan(&self) -> bool {
-        self.data.iter().any(|x| x.is_nan())
+    /// The number of columns, `width`, as in [`Vec2D::from_cols`].
+    pub fn cols(&self) -> usize {
+        self.w
     }
-}
 
-impl<T> std::ops::Index<(usize, usize)> for Vec2D<T> {
-    type Output = T;
+    /// The number of cells, `width * height`, which is the same as [`Vec2D::len`].
+    pub fn area(&self) -> usize {
+        self.w * self.h
+    }
 
-    /// Index is (x,y)
-    fn index(&self, index: (usize, usize)) -> &T {
-        if index.0 >= self.w || index.1 >= self.h {
+    /// The total number of cells, `width * height`.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The size of the cell data in bytes, excluding any heap memory owned by the cells themselves.
+    pub fn size_bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<T>()
+    }
+
+    /// The cells as one contiguous slice in column-major order, i.e. the cell at (x,y) is at
+    /// index `x * height + y`.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// The cells as one contiguous mutable slice, in the same order as [`Vec2D::as_slice`].
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// Get a reference to the element at (x,y), or `None` if the index is out of bounds.
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        if x >= self.w || y >= self.h {
+            return None;
+        }
+        // SAFETY: the index is checked to be within bounds
+        Some(unsafe { self.data.get_unchecked(x * self.h + y) })
+    }
+
+    /// Get a mutable reference to the element at (x,y), or `None` if the index is out of bounds.
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        if x >= self.w || y >= self.h {
+            return None;
+        }
+        // SAFETY: the index is checked to be within bounds
+        Some(unsafe { self.data.get_unchecked_mut(x * self.h + y) })
+    }
+
+    /// Get a reference to the element at (x,y) without checking the bounds, for hot loops where
+    /// the index is known to be within bounds. The bounds are still checked in debug builds.
+    ///
+    /// # Safety
+    ///
+    /// `x` must be less than `width()` and `y` less than `height()`.
+    pub unsafe fn index_unchecked(&self, x: usize, y: usize) -> &T {
+        debug_assert!(
+            x < self.w && y < self.h,
+            "index out of bounds: ({}, {})",
+            x,
+            y
+        );
+        // SAFETY: the caller guarantees the index is within bounds
+        unsafe { self.data.get_unchecked(x * self.h + y) }
+    }
+
+    /// Get a mutable reference to the element at (x,y) without checking the bounds, see
+    /// [`Vec2D::index_unchecked`].
+    ///
+    /// # Safety
+    ///
+    /// `x` must be less than `width()` and `y` less than `height()`.
+    pub unsafe fn index_unchecked_mut(&mut self, x: usize, y: usize) -> &mut T {
+        debug_assert!(
+            x < self.w && y < self.h,
+            "index out of bounds: ({}, {})",
+            x,
+            y
+        );
+        // SAFETY: the caller guarantees the index is within bounds
+        unsafe { self.data.get_unchecked_mut(x * self.h + y) }
+    }
+
+    /// Get the column at `x` as a contiguous slice of length `height()`, indexed by y.
+    pub fn column(&self, x: usize) -> &[T] {
+        if x >= self.w {
             panic!(
-                "index out of bounds: the len is ({}, {}) but the index is ({}, {})",
-                self.w, self.h, index.0, index.1
+                "column out of bounds: the width is {} but the column is {}",
+                self.w, x
             );
         }
-        // SAFETY: the index is checked to be within bounds
-        unsafe { self.data.get_unchecked(index.0 * self.h + index.1) }
+        &self.data[x * self.h..(x + 1) * self.h]
     }
-}
 
-impl<T> std::ops::IndexMut<(usize, usize)> for Vec2D<T> {
-    /// Index is (x,y)
-    fn index_mut(&mut self, index: (usize, usize)) -> &mut T {
-        if index.0 >= self.w || index.1 >= self.h {
+    /// Get the column at `x` as a contiguous mutable slice of length `height()`, indexed by y.
+    pub fn column_mut(&mut self, x: usize) -> &mut [T] {
+        if x >= self.w {
             panic!(
-                "index out of bounds: the len is ({}, {}) but the index is ({}, {})",
-                self.w, self.h, index.0, index.1
+                "column out of bounds: the width is {} but the column is {}",
+                self.w, x
             );
         }
-        // SAFETY: the index is checked to be within bounds
-        unsafe { self.data.get_unchecked_mut(index.0 * self.h + index.1) }
+        &mut self.data[x * self.h..(x + 1) * self.h]
     }
-}
 
-/// Implement the FromToBytes trait for Vec2D<T> where T implements FromToBytes.
-impl<T: FromToBytes> FromToBytes for Vec2D<T> {
-    fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
-        let w = usize::from_bytes(reader)?;
-        let h = usize::from_bytes(reader)?;
+    /// Iterate over the row at `y`, ordered by x. Rows are strided in memory, so unlike
+    /// [`Vec2D::column`] this cannot be a slice.
+    pub fn row(&self, y: usize) -> impl Iterator<Item = &T> + '_ {
+        if y >= self.h {
+            panic!(
+                "row out of bounds: the height is {} but the row is {}",
+                self.h, y
+            );
+        }
+        self.data.iter().skip(y).step_by(self.h)
+    }
+
+    /// Iterate mutably over the row at `y`, ordered by x.
+    pub fn row_mut(&mut self, y: usize) -> impl Iterator<Item = &mut T> + '_ {
+        if y >= self.h {
+            panic!(
+                "row out of bounds: the height is {} but the row is {}",
+                self.h, y
+            );
+        }
+        self.data.iter_mut().skip(y).step_by(self.h)
+    }
+
+    /// Create a new grid of the same dimensions by applying `f` to each element.
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Vec2D<U> {
+        Vec2D {
+            data: self.data.iter().map(f).collect(),
+            w: self.w,
+            h: self.h,
+        }
+    }
+
+    /// Copy the rectangular window `[x0, x0 + w) × [y0, y0 + h)` into a new grid.
+    pub fn subgrid(&self, x0: usize, y0: usize, w: usize, h: usize) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        if x0 + w > self.w || y0 + h > self.h {
+            panic!(
+                "subgrid out of bounds: the len is ({}, {}) but the window is ({}..{}, {}..{})",
+                self.w,
+                self.h,
+                x0,
+                x0 + w,
+                y0,
+                y0 + h
+            );
+        }
 
         let mut data = Vec::with_capacity(w * h);
-        for _ in 0..w * h {
-            data.push(T::from_bytes(reader)?);
+        for x in x0..x0 + w {
+            data.extend_from_slice(&self.data[x * self.h + y0..x * self.h + y0 + h]);
         }
 
-        Ok(Vec2D {
+        Vec2D {
             data: data.into(),
             w,
             h,
-        })
+        }
     }
 
-    fn to_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        self.w.to_bytes(writer)?;
-        self.h.to_bytes(writer)?;
+    /// Grow the grid to `new_w` x `new_h`, keeping every cell at the same `(x, y)` and setting the
+    /// new cells to `fill`. The counterpart of [`Vec2D::subgrid`].
+    pub fn padded(&self, new_w: usize, new_h: usize, fill: T) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        if new_w < self.w || new_h < self.h {
+            panic!(
+                "padded size ({}, {}) is smaller than the len ({}, {})",
+                new_w, new_h, self.w, self.h
+            );
+        }
 
-        for item in self.data.iter() {
-            item.to_bytes(writer)?;
+        let mut data = Vec::with_capacity(new_w * new_h);
+        for x in 0..self.w {
+            data.extend_from_slice(self.column(x));
+            data.resize(data.len() + new_h - self.h, fill.clone());
         }
+        data.resize(new_w * new_h, fill);
 
-        Ok(())
+        Vec2D {
+            data: data.into(),
+            w: new_w,
+            h: new_h,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Create a new grid with the axes swapped, i.e. `result[(y, x)] == self[(x, y)]`.
+    pub fn transpose(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        // each column of the result is a row of the source
+        let mut data = Vec::with_capacity(self.w * self.h);
+        for y in 0..self.h {
+            data.extend(self.row(y).cloned());
+        }
 
-    #[test]
-    fn test_new() {
-        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
-        assert_eq!(vec2d.w, 3);
-        assert_eq!(vec2d.h, 2);
-        assert_eq!(vec2d.data, vec![0; 6].into());
+        Vec2D {
+            data: data.into(),
+            w: self.h,
+            h: self.w,
+        }
     }
 
-    #[test]
-    fn test_index() {
-        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        assert_eq!(vec2d[(0, 0)], 1);
-        assert_eq!(vec2d[(2, 1)], 1);
+    /// Create a copy of the grid mirrored along the y-axis, i.e. `result[(x, h - 1 - y)] == self[(x, y)]`.
+    /// Useful for converting between bottom-left (LAS) and top-left (PNG) origins.
+    pub fn flip_y(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        let mut data = Vec::with_capacity(self.w * self.h);
+        for x in 0..self.w {
+            data.extend(self.column(x).iter().rev().cloned());
+        }
+
+        Vec2D {
+            data: data.into(),
+            w: self.w,
+            h: self.h,
+        }
     }
 
-    #[test]
-    fn test_index_skewed_0() {
-        let vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
-        assert_eq!(vec2d[(9, 0)], 1);
-        assert_eq!(vec2d[(9, 1)], 1);
+    /// Create a copy of the grid mirrored along the x-axis, i.e. `result[(w - 1 - x, y)] == self[(x, y)]`.
+    pub fn flip_x(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        let mut data = Vec::with_capacity(self.w * self.h);
+        for x in (0..self.w).rev() {
+            data.extend_from_slice(self.column(x));
+        }
+
+        Vec2D {
+            data: data.into(),
+            w: self.w,
+            h: self.h,
+        }
     }
 
-    #[test]
-    fn test_index_skewed_1() {
-        let vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
-        assert_eq!(vec2d[(0, 9)], 1);
-        assert_eq!(vec2d[(2, 9)], 1);
+    /// Create a copy of the grid rotated by 180 degrees, i.e.
+    /// `result[(w - 1 - x, h - 1 - y)] == self[(x, y)]`.
+    pub fn rotate_180(&self) -> Vec2D<T>
+    where
+        T: Clone,
+    {
+        // with column-major storage this is just the data in reverse
+        Vec2D {
+            data: self.data.iter().rev().cloned().collect(),
+            w: self.w,
+            h: self.h,
+        }
     }
 
-    #[test]
-    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (3, 0)")]
-    fn test_index_out_of_bounds_0() {
-        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        let _ = vec2d[(3, 0)];
+    /// Overwrite every element with a clone of `value`, keeping the existing allocation.
+    pub fn fill(&mut self, value: T)
+    where
+        T: Clone,
+    {
+        self.data.fill(value);
     }
-    #[test]
-    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (0, 2)")]
-    fn test_index_out_of_bounds_1() {
-        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        let _ = vec2d[(0, 2)];
+
+    /// Overwrite every element with a freshly computed value from `f`, keeping the existing
+    /// allocation. Elements are visited in the internal (column-major) order.
+    pub fn fill_with<F: FnMut() -> T>(&mut self, f: F) {
+        self.data.fill_with(f);
     }
 
-    #[test]
-    fn test_index_mut() {
-        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        vec2d[(1, 1)] = 5;
-        assert_eq!(vec2d[(1, 1)], 5);
+    /// Copy every element into `dst`, keeping the allocation of `dst`.
+    ///
+    /// # Panics
+    /// If the grids have different dimensions.
+    pub fn clone_into(&self, dst: &mut Vec2D<T>)
+    where
+        T: Clone,
+    {
+        assert_eq!(
+            self.dimensions(),
+            dst.dimensions(),
+            "cannot clone into a grid of different dimensions"
+        );
+        dst.data.clone_from_slice(&self.data);
     }
 
-    #[test]
-    fn test_index_mut_skewed_0() {
-        let mut vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
-        vec2d[(9, 1)] = 5;
-        assert_eq!(vec2d[(9, 1)], 5);
+    /// Swap the elements at `a` and `b`, both indexed as (x,y).
+    pub fn swap(&mut self, a: (usize, usize), b: (usize, usize)) {
+        let (a, b) = (self.flat_index(a), self.flat_index(b));
+        self.data.swap(a, b);
     }
 
-    #[test]
-    fn test_index_mut_skewed_1() {
-        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
-        vec2d[(2, 9)] = 5;
-        assert_eq!(vec2d[(2, 9)], 5);
+    /// Replace the element at `p`, indexed as (x,y), with `v` and return the previous element.
+    pub fn replace(&mut self, p: (usize, usize), v: T) -> T {
+        let i = self.flat_index(p);
+        std::mem::replace(&mut self.data[i], v)
     }
 
-    #[test]
-    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (3, 0)")]
-    fn test_index_mut_out_of_bounds_0() {
-        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        vec2d[(3, 0)] = 5;
+    /// The number of cells for which `pred` returns true.
+    pub fn count<F: Fn(&T) -> bool>(&self, pred: F) -> usize {
+        self.data.iter().filter(|v| pred(v)).count()
     }
 
-    #[test]
-    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (0, 2)")]
-    fn test_index_mut_out_of_bounds_1() {
-        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
-        vec2d[(0, 2)] = 5;
+    /// The index in `data` of the element at (x,y), panicking like the index operators if it is out
+    /// of bounds.
+    fn flat_index(&self, index: (usize, usize)) -> usize {
+        if index.0 >= self.w || index.1 >= self.h {
+            panic!(
+                "index out of bounds: the len is ({}, {}) but the index is ({}, {})",
+                self.w, self.h, index.0, index.1
+            );
+        }
+        index.0 * self.h + index.1
+    }
+
+    /// Iterate over the cells in the internal column-major order, yielding `(i, x, y, &T)` where
+    /// `i` is the flat index of the cell in [`Vec2D::as_slice`], i.e. `x * height + y`.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, usize, usize, &T)> + '_ {
+        let h = self.h;
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(i, v)| (i, i / h, i % h, v))
+    }
+
+    /// Iterate over only the cells for which `pred` is true, yielding `(x, y, &T)` in the internal
+    /// column-major order like [`Vec2D::iter_indexed`]. Meant for sparse grids such as masks,
+    /// where most cells are background.
+    pub fn iter_where<'a, F: Fn(&T) -> bool + 'a>(
+        &'a self,
+        pred: F,
+    ) -> impl Iterator<Item = (usize, usize, &'a T)> + 'a {
+        let h = self.h;
+        self.data
+            .iter()
+            .enumerate()
+            .filter(move |(_, v)| pred(v))
+            .map(move |(i, v)| (i / h, i % h, v))
+    }
+
+    /// Iterate over the cells of this grid and `other` together in the internal column-major order,
+    /// yielding `(x, y, &T, &U)`.
+    ///
+    /// # Panics
+    /// If the grids have different dimensions.
+    pub fn zip<'a, U>(
+        &'a self,
+        other: &'a Vec2D<U>,
+    ) -> impl Iterator<Item = (usize, usize, &'a T, &'a U)> + 'a {
+        assert_eq!(
+            self.dimensions(),
+            other.dimensions(),
+            "cannot zip grids of different dimensions"
+        );
+        let h = self.h;
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .enumerate()
+            .map(move |(i, (a, b))| (i / h, i % h, a, b))
+    }
+
+    /// Draw the grid as text for debugging, one line per row with `y = 0` first, each cell drawn
+    /// as the character returned by `cell`.
+    pub fn to_ascii_art(&self, cell: impl Fn(&T) -> char) -> String {
+        let mut art = String::with_capacity((self.w + 1) * self.h);
+        for y in 0..self.h {
+            art.extend(self.row(y).map(&cell));
+            art.push('\n');
+        }
+        art
+    }
+
+    /// Iterate over the in-bounds edge neighbors (left, right, down, up) of (x,y), yielding
+    /// `(x, y, &T)` for each.
+    pub fn neighbors4(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize, &T)> + '_ {
+        self.neighbors(x, y, &NEIGHBORS4)
+    }
+
+    /// Like [`Vec2D::neighbors4`], but also yielding the in-bounds diagonal neighbors of (x,y).
+    pub fn neighbors8(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize, &T)> + '_ {
+        self.neighbors(x, y, &NEIGHBORS8)
+    }
+
+    /// Iterate over the cells on the edges of the grid, each once, yielding `(x, y, &T)` in the
+    /// internal (column-major) order: the whole first and last columns, and the first and last
+    /// rows of the columns in between.
+    pub fn border_cells(&self) -> impl Iterator<Item = (usize, usize, &T)> + '_ {
+        let (w, h) = (self.w, self.h);
+        (0..w).flat_map(move |x| {
+            let step = if x == 0 || x + 1 == w {
+                1
+            } else {
+                h.max(2) - 1
+            };
+            (0..h)
+                .step_by(step)
+                .map(move |y| (x, y, &self.data[x * h + y]))
+        })
+    }
+
+    fn neighbors<'a>(
+        &'a self,
+        x: usize,
+        y: usize,
+        offsets: &'static [(isize, isize)],
+    ) -> impl Iterator<Item = (usize, usize, &'a T)> + 'a {
+        offsets.iter().filter_map(move |&(dx, dy)| {
+            let nx = x.checked_add_signed(dx)?;
+            let ny = y.checked_add_signed(dy)?;
+            self.get(nx, ny).map(|v| (nx, ny, v))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut T)> + '_ {
+        let h = self.h;
+        self.data.iter_mut().enumerate().map(move |(i, v)| {
+            let x = i / h;
+            let y = i % h;
+            (x, y, v)
+        })
+    }
+
+    /// Mutable variant of [`Vec2D::iter_rows`], visiting cells in row-major order (all `x` for
+    /// `y = 0`, then all `x` for `y = 1`, ...). Note that this differs from [`Vec2D::iter_mut`],
+    /// which follows the internal column-major layout.
+    pub fn iter_rows_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut T)> + '_ {
+        let w = self.w;
+        let n = self.w * self.h;
+        // each column is a contiguous chunk, so keep one iterator per column and advance them in turn
+        let mut columns: Vec<_> = self
+            .data
+            .chunks_mut(self.h.max(1))
+            .map(|c| c.iter_mut())
+            .collect();
+        let mut i = 0;
+        std::iter::from_fn(move || {
+            if i >= n {
+                return None;
+            }
+            let x = i % w;
+            let y = i / w;
+            i += 1;
+            columns[x].next().map(|v| (x, y, v))
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> Vec2D<T> {
+    /// Parallel version of [`Vec2D::iter_mut`], yielding `(x, y, &mut T)` in the internal
+    /// column-major order.
+    pub fn par_iter_mut(
+        &mut self,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = (usize, usize, &mut T)> + '_ {
+        use rayon::prelude::*;
+
+        let h = self.h;
+        self.data.par_iter_mut().enumerate().map(move |(i, v)| {
+            let x = i / h;
+            let y = i % h;
+            (x, y, v)
+        })
+    }
+}
+
+impl<T: Copy> Vec2D<T> {
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        self.data.iter().enumerate().map(move |(i, v)| {
+            let x = i / self.h;
+            let y = i % self.h;
+            (x, y, *v)
+        })
+    }
+
+    /// Iterate over the cells in row-major order, i.e. all `x` for `y = 0`, then all `x` for
+    /// `y = 1`, and so on. Note that this differs from [`Vec2D::iter`], which follows the
+    /// internal column-major layout and is therefore cheaper.
+    pub fn iter_rows(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        (0..self.h).flat_map(move |y| (0..self.w).map(move |x| (x, y, self.data[x * self.h + y])))
+    }
+}
+
+impl Vec2D<f64> {
+    pub fn is_any_nan(&self) -> bool {
+        self.data.iter().any(|x| x.is_nan())
+    }
+
+    /// Iterate over the cells that are not NaN, i.e. have data, see [`Vec2D::iter_where`].
+    pub fn iter_valid(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.iter_where(|v| !v.is_nan()).map(|(x, y, &v)| (x, y, v))
+    }
+
+    /// The number of NaN cells, i.e. cells without data.
+    pub fn count_nan(&self) -> usize {
+        self.count(|v| v.is_nan())
+    }
+
+    /// The smallest value of the grid, skipping NaN cells. `None` if every cell is NaN.
+    pub fn min_ignore_nan(&self) -> Option<f64> {
+        self.data
+            .iter()
+            .copied()
+            .filter(|v| !v.is_nan())
+            .reduce(f64::min)
+    }
+
+    /// The largest value of the grid, skipping NaN cells. `None` if every cell is NaN.
+    pub fn max_ignore_nan(&self) -> Option<f64> {
+        self.data
+            .iter()
+            .copied()
+            .filter(|v| !v.is_nan())
+            .reduce(f64::max)
+    }
+
+    /// The mean of the grid, skipping NaN cells. `None` if every cell is NaN.
+    pub fn mean_ignore_nan(&self) -> Option<f64> {
+        let (sum, count) = self
+            .data
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// Whether the grids have the same dimensions and every pair of cells differs by at most
+    /// `epsilon`. NaN (no data) only equals NaN.
+    pub fn approx_eq(&self, other: &Vec2D<f64>, epsilon: f64) -> bool {
+        self.w == other.w
+            && self.h == other.h
+            && self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .all(|(&a, &b)| a == b || (a.is_nan() && b.is_nan()) || (a - b).abs() <= epsilon)
+    }
+
+    /// Sample the grid at fractional coordinates using bilinear interpolation between the four
+    /// surrounding cells. Coordinates outside `[0, w-1] × [0, h-1]` are clamped to the edges.
+    /// Returns NaN if any of the contributing cells is NaN, or if the grid is empty.
+    pub fn sample_bilinear(&self, x: f64, y: f64) -> f64 {
+        if self.w == 0 || self.h == 0 {
+            return f64::NAN;
+        }
+
+        let x = x.clamp(0.0, (self.w - 1) as f64);
+        let y = y.clamp(0.0, (self.h - 1) as f64);
+
+        let x0 = x.floor() as usize;
+        let y0 = y.floor() as usize;
+        let x1 = (x0 + 1).min(self.w - 1);
+        let y1 = (y0 + 1).min(self.h - 1);
+        let fx = x - x0 as f64;
+        let fy = y - y0 as f64;
+
+        let v00 = self[(x0, y0)];
+        let v10 = self[(x1, y0)];
+        let v01 = self[(x0, y1)];
+        let v11 = self[(x1, y1)];
+
+        // make sure no-data propagates even if the cell has zero weight
+        if v00.is_nan() || v10.is_nan() || v01.is_nan() || v11.is_nan() {
+            return f64::NAN;
+        }
+
+        let top = v00 * (1.0 - fx) + v10 * fx;
+        let bottom = v01 * (1.0 - fx) + v11 * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Resample the grid so that each output cell covers `factor` input cells along each axis.
+    /// With `factor > 1` the grid is made coarser by averaging the valid (non-NaN) cells of each
+    /// block, leaving the output cell NaN if the whole block is NaN. With `factor < 1` the grid is
+    /// made finer using [`Vec2D::sample_bilinear`]. Output cell `(0, 0)` starts at input cell
+    /// `(0, 0)` in both cases.
+    pub fn resample(&self, factor: f64) -> Vec2D<f64> {
+        assert!(factor > 0.0, "resample factor must be positive");
+        if factor == 1.0 || self.w == 0 || self.h == 0 {
+            return self.clone();
+        }
+
+        if factor < 1.0 {
+            let w = ((self.w - 1) as f64 / factor).floor() as usize + 1;
+            let h = ((self.h - 1) as f64 / factor).floor() as usize + 1;
+            let mut result = Vec2D::new(w, h, 0.0);
+            for (x, y, v) in result.iter_mut() {
+                *v = self.sample_bilinear(x as f64 * factor, y as f64 * factor);
+            }
+            return result;
+        }
+
+        // block `i` covers the input cells `[i * factor, (i + 1) * factor)`, at least one cell
+        let block = |i: usize, len: usize| {
+            let start = (i as f64 * factor).floor() as usize;
+            let end = (((i + 1) as f64 * factor).floor() as usize).clamp(start + 1, len);
+            start..end
+        };
+        let w = (self.w as f64 / factor).ceil() as usize;
+        let h = (self.h as f64 / factor).ceil() as usize;
+        let mut result = Vec2D::new(w, h, f64::NAN);
+        for (x, y, v) in result.iter_mut() {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for xx in block(x, self.w) {
+                for &value in &self.column(xx)[block(y, self.h)] {
+                    if !value.is_nan() {
+                        sum += value;
+                        count += 1;
+                    }
+                }
+            }
+            if count > 0 {
+                *v = sum / count as f64;
+            }
+        }
+        result
+    }
+
+    /// Apply a 3x3 kernel to every cell and return the result as a new grid. The kernel is
+    /// indexed as `kernel[dy + 1][dx + 1]`, so it reads like the neighborhood it is applied to.
+    /// Neighbors outside the grid are replaced by the nearest edge cell, and NaN cells propagate
+    /// to every output cell they contribute to.
+    pub fn convolve3x3(&self, kernel: &[[f64; 3]; 3]) -> Vec2D<f64> {
+        let mut result = Vec2D::new(self.w, self.h, 0.0);
+        if self.w == 0 || self.h == 0 {
+            return result;
+        }
+
+        for x in 0..self.w {
+            for y in 0..self.h {
+                let mut sum = 0.0;
+                for (j, row) in kernel.iter().enumerate() {
+                    let yy = (y + j).saturating_sub(1).min(self.h - 1);
+                    for (i, k) in row.iter().enumerate() {
+                        let xx = (x + i).saturating_sub(1).min(self.w - 1);
+                        sum += k * self[(xx, yy)];
+                    }
+                }
+                result[(x, y)] = sum;
+            }
+        }
+        result
+    }
+}
+
+/// Accumulates values per cell to compute their mean, for example the elevations of the points
+/// binned into each cell of a heightmap.
+#[derive(Debug, Clone)]
+pub struct AccumGrid {
+    sum: Vec2D<f64>,
+    count: Vec2D<u32>,
+}
+
+impl AccumGrid {
+    pub fn new(w: usize, h: usize) -> AccumGrid {
+        AccumGrid {
+            sum: Vec2D::new(w, h, 0.0),
+            count: Vec2D::new(w, h, 0),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.sum.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.sum.height()
+    }
+
+    /// Add a value to the cell at (x,y).
+    pub fn add(&mut self, x: usize, y: usize, z: f64) {
+        self.sum[(x, y)] += z;
+        self.count[(x, y)] += 1;
+    }
+
+    /// The number of values added to the cell at (x,y).
+    pub fn count(&self, x: usize, y: usize) -> u32 {
+        self.count[(x, y)]
+    }
+
+    /// The mean of the values added to each cell, NaN for cells without any values.
+    pub fn finalize(self) -> Vec2D<f64> {
+        let mut mean = self.sum;
+        for (v, &count) in mean.as_mut_slice().iter_mut().zip(self.count.as_slice()) {
+            *v = if count > 0 {
+                *v / count as f64
+            } else {
+                f64::NAN
+            };
+        }
+        mean
+    }
+}
+
+/// A grid with an explicit no-data value, for cell types that have no NaN to mark missing data,
+/// such as classification or vegetation grids. The no-data value is stored with the grid so that
+/// outputs can record it instead of mixing it up with valid values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoDataGrid<T> {
+    pub grid: Vec2D<T>,
+    pub nodata: T,
+}
+
+impl<T: Copy + PartialEq> NoDataGrid<T> {
+    /// Create a grid with every cell set to no-data.
+    pub fn new(w: usize, h: usize, nodata: T) -> NoDataGrid<T> {
+        NoDataGrid {
+            grid: Vec2D::new(w, h, nodata),
+            nodata,
+        }
+    }
+
+    /// Check if the cell at (x,y) is no-data.
+    pub fn is_nodata(&self, x: usize, y: usize) -> bool {
+        self.grid[(x, y)] == self.nodata
+    }
+
+    /// The value of the cell at (x,y), or `None` if it is no-data.
+    pub fn get_valid(&self, x: usize, y: usize) -> Option<T> {
+        let value = self.grid[(x, y)];
+        (value != self.nodata).then_some(value)
+    }
+}
+
+impl<T: FromToBytes> FromToBytes for NoDataGrid<T> {
+    fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let nodata = T::from_bytes(reader)?;
+        let grid = Vec2D::from_bytes(reader)?;
+        Ok(NoDataGrid { grid, nodata })
+    }
+
+    fn to_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.nodata.to_bytes(writer)?;
+        self.grid.to_bytes(writer)
+    }
+}
+
+/// Which neighbors of a cell belong to the same connected component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Cells sharing an edge.
+    Four,
+    /// Cells sharing an edge or a corner.
+    Eight,
+}
+
+/// Label the connected components of the `true` cells of a grid. Returns a grid where each cell
+/// holds the label of its component, numbered from 1 in storage order, with 0 for the `false`
+/// cells, and the number of components.
+pub fn label_components(grid: &Vec2D<bool>, connectivity: Connectivity) -> (Vec2D<u32>, usize) {
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let (w, h) = grid.dimensions();
+    // the neighbors that come before a cell in storage order
+    let previous: &[(isize, isize)] = match connectivity {
+        Connectivity::Four => &[(-1, 0), (0, -1)],
+        Connectivity::Eight => &[(-1, -1), (-1, 0), (-1, 1), (0, -1)],
+    };
+
+    let mut parents: Vec<usize> = (0..grid.len()).collect();
+    for (i, x, y, &set) in grid.iter_indexed() {
+        if !set {
+            continue;
+        }
+        for &(dx, dy) in previous {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx < 0 || ny < 0 || ny >= h as isize {
+                continue;
+            }
+            let j = nx as usize * h + ny as usize;
+            if grid.as_slice()[j] {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut labels = Vec2D::new(w, h, 0u32);
+    let mut count = 0;
+    for i in 0..grid.len() {
+        if !grid.as_slice()[i] {
+            continue;
+        }
+        let root = find(&mut parents, i);
+        // roots come first in storage order, so they are labeled before the rest of their component
+        let label = if root == i {
+            count += 1;
+            count as u32
+        } else {
+            labels.as_slice()[root]
+        };
+        labels.as_mut_slice()[i] = label;
+    }
+    (labels, count)
+}
+
+/// The Euclidean distance, in cells, from each cell of a grid to the nearest `true` cell, or
+/// infinity if there is none. This is the exact transform of Felzenszwalb and Huttenlocher, a
+/// pass along the columns and then one along the rows, so it takes linear time.
+pub fn distance_transform(grid: &Vec2D<bool>) -> Vec2D<f64> {
+    /// Stands for the cells without a `true` cell in their column, kept finite to avoid NaNs.
+    const FAR: f64 = 1e20;
+
+    // the lower envelope of the parabolas rooted at each cell of `f`, sampled at each cell
+    fn transform(f: &[f64], d: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+        let mut k = 0;
+        v[0] = 0;
+        z[0] = f64::NEG_INFINITY;
+        z[1] = f64::INFINITY;
+        for q in 1..f.len() {
+            let parabola = |p: usize| f[p] + (p * p) as f64;
+            let mut s;
+            loop {
+                s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f64;
+                if s > z[k] {
+                    break;
+                }
+                k -= 1;
+            }
+            k += 1;
+            v[k] = q;
+            z[k] = s;
+            z[k + 1] = f64::INFINITY;
+        }
+        k = 0;
+        for (q, d) in d.iter_mut().enumerate() {
+            while z[k + 1] < q as f64 {
+                k += 1;
+            }
+            let dq = q as f64 - v[k] as f64;
+            *d = dq * dq + f[v[k]];
+        }
+    }
+
+    let (w, h) = grid.dimensions();
+    if w == 0 || h == 0 {
+        return Vec2D::new(w, h, f64::INFINITY);
+    }
+    let n = w.max(h);
+    let (mut f, mut d) = (vec![0.0; n], vec![0.0; n]);
+    let (mut v, mut z) = (vec![0; n], vec![0.0; n + 1]);
+
+    let mut squared = Vec2D::new(w, h, 0.0);
+    for x in 0..w {
+        for y in 0..h {
+            f[y] = if grid[(x, y)] { 0.0 } else { FAR };
+        }
+        transform(&f[..h], &mut d[..h], &mut v, &mut z);
+        for y in 0..h {
+            squared[(x, y)] = d[y];
+        }
+    }
+    for y in 0..h {
+        for x in 0..w {
+            f[x] = squared[(x, y)];
+        }
+        transform(&f[..w], &mut d[..w], &mut v, &mut z);
+        for x in 0..w {
+            squared[(x, y)] = d[x];
+        }
+    }
+
+    Vec2D::from_fn(w, h, |x, y| {
+        let d = squared[(x, y)];
+        if d >= FAR {
+            f64::INFINITY
+        } else {
+            d.sqrt()
+        }
+    })
+}
+
+impl<T> std::ops::Index<(usize, usize)> for Vec2D<T> {
+    type Output = T;
+
+    /// Index is (x,y)
+    fn index(&self, index: (usize, usize)) -> &T {
+        if index.0 >= self.w || index.1 >= self.h {
+            panic!(
+                "index out of bounds: the len is ({}, {}) but the index is ({}, {})",
+                self.w, self.h, index.0, index.1
+            );
+        }
+        // SAFETY: the index is checked to be within bounds
+        unsafe { self.data.get_unchecked(index.0 * self.h + index.1) }
+    }
+}
+
+impl<T> std::ops::IndexMut<(usize, usize)> for Vec2D<T> {
+    /// Index is (x,y)
+    fn index_mut(&mut self, index: (usize, usize)) -> &mut T {
+        if index.0 >= self.w || index.1 >= self.h {
+            panic!(
+                "index out of bounds: the len is ({}, {}) but the index is ({}, {})",
+                self.w, self.h, index.0, index.1
+            );
+        }
+        // SAFETY: the index is checked to be within bounds
+        unsafe { self.data.get_unchecked_mut(index.0 * self.h + index.1) }
+    }
+}
+
+/// The error of [`Vec2D::from_rows`] and [`Vec2D::from_cols`] for rows or columns of different
+/// lengths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaggedError {
+    /// The index of the first row or column with a different length than the first one.
+    pub index: usize,
+    pub len: usize,
+    pub expected: usize,
+}
+
+impl std::fmt::Display for RaggedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "row or column {} has {} elements but the first one has {}",
+            self.index, self.len, self.expected
+        )
+    }
+}
+
+impl std::error::Error for RaggedError {}
+
+/// The common length of `lines`, 0 if there are none.
+fn check_lengths<T>(lines: &[Vec<T>]) -> Result<usize, RaggedError> {
+    let expected = lines.first().map_or(0, Vec::len);
+    match lines.iter().position(|line| line.len() != expected) {
+        Some(index) => Err(RaggedError {
+            index,
+            len: lines[index].len(),
+            expected,
+        }),
+        None => Ok(expected),
+    }
+}
+
+/// Read and validate the serialized header of a Vec2D, returning `(w, h, w * h)`.
+fn read_header<R: std::io::Read>(reader: &mut R) -> std::io::Result<(usize, usize, usize)> {
+    // read and check the magic number and version
+    let mut buff = [0; VEC2D_MAGIC.len() + 1];
+    reader.read_exact(&mut buff)?;
+    if &buff[..VEC2D_MAGIC.len()] != VEC2D_MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "invalid magic number",
+        ));
+    }
+    let version = buff[VEC2D_MAGIC.len()];
+    if version != VEC2D_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "unsupported Vec2D format version {}, expected {}",
+                version, VEC2D_VERSION
+            ),
+        ));
+    }
+
+    let w = usize::from_bytes(reader)?;
+    let h = usize::from_bytes(reader)?;
+    let len = w.checked_mul(h).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid Vec2D dimensions ({}, {})", w, h),
+        )
+    })?;
+    Ok((w, h, len))
+}
+
+impl<T: FromToBytes> Vec2D<T> {
+    /// Deserialize a Vec2D of known dimensions directly into the pre-allocated `buf`, avoiding the
+    /// intermediate `Vec` used by [`FromToBytes::from_bytes`]. The dimensions in the stream must
+    /// match `w` and `h`, and `buf` must hold exactly `w * h` elements.
+    pub fn from_bytes_into<R: std::io::Read>(
+        reader: &mut R,
+        w: usize,
+        h: usize,
+        mut buf: Box<[T]>,
+    ) -> std::io::Result<Self> {
+        if w.checked_mul(h) != Some(buf.len()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "buffer of length {} does not fit dimensions ({}, {})",
+                    buf.len(),
+                    w,
+                    h
+                ),
+            ));
+        }
+
+        let (read_w, read_h, _) = read_header(reader)?;
+        if read_w != w || read_h != h {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "expected Vec2D dimensions ({}, {}) but found ({}, {})",
+                    w, h, read_w, read_h
+                ),
+            ));
+        }
+
+        for item in buf.iter_mut() {
+            *item = T::from_bytes(reader)?;
+        }
+
+        Ok(Vec2D { data: buf, w, h })
+    }
+}
+
+/// Reads a serialized Vec2D as strips of whole columns, so that only one strip of `columns`
+/// columns is in memory at a time. Consecutive strips overlap by one column.
+pub struct ColumnStrips<R, T> {
+    reader: R,
+    w: usize,
+    h: usize,
+    columns: usize,
+    next_x: usize,
+    carry: Vec<T>,
+}
+
+impl<R: std::io::Read, T: FromToBytes + Clone> ColumnStrips<R, T> {
+    /// Read the header of the serialized Vec2D, `columns` must be at least 2.
+    pub fn new(mut reader: R, columns: usize) -> std::io::Result<Self> {
+        if columns < 2 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("a strip needs at least 2 columns, got {}", columns),
+            ));
+        }
+        let (w, h, _) = read_header(&mut reader)?;
+        Ok(ColumnStrips {
+            reader,
+            w,
+            h,
+            columns,
+            next_x: 0,
+            carry: Vec::new(),
+        })
+    }
+
+    /// The dimensions of the whole grid.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.w, self.h)
+    }
+
+    /// Read the next strip, returning the x index of its first column in the whole grid, or `None`
+    /// after the last column.
+    pub fn next_strip(&mut self) -> std::io::Result<Option<(usize, Vec2D<T>)>> {
+        if self.next_x >= self.w {
+            return Ok(None);
+        }
+        let x0 = self.next_x;
+        let w = self.columns.min(self.w - x0);
+        let mut data = Vec::with_capacity(w * self.h);
+        data.append(&mut self.carry);
+        while data.len() < w * self.h {
+            data.push(T::from_bytes(&mut self.reader)?);
+        }
+        self.carry = data[(w - 1) * self.h..].to_vec();
+        self.next_x = if x0 + w == self.w { self.w } else { x0 + w - 1 };
+        Ok(Some((
+            x0,
+            Vec2D {
+                data: data.into(),
+                w,
+                h: self.h,
+            },
+        )))
+    }
+}
+
+/// Implement the FromToBytes trait for Vec2D<T> where T implements FromToBytes.
+impl<T: FromToBytes> FromToBytes for Vec2D<T> {
+    fn from_bytes<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let (w, h, len) = read_header(reader)?;
+
+        // grow the buffer as elements are read, a truncated stream then fails with
+        // UnexpectedEof instead of allocating memory for data that does not exist
+        let mut data = Vec::with_capacity(len.min(MAX_PREALLOCATED_ELEMENTS));
+        for _ in 0..len {
+            data.push(T::from_bytes(reader)?);
+        }
+
+        Ok(Vec2D {
+            data: data.into(),
+            w,
+            h,
+        })
+    }
+
+    fn to_bytes<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(VEC2D_MAGIC)?;
+        writer.write_all(&[VEC2D_VERSION])?;
+        self.w.to_bytes(writer)?;
+        self.h.to_bytes(writer)?;
+
+        for item in self.data.iter() {
+            item.to_bytes(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        assert_eq!(vec2d.w, 3);
+        assert_eq!(vec2d.h, 2);
+        assert_eq!(vec2d.data, vec![0; 6].into());
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let vec2d = Vec2D::from_fn(3, 2, |x, y| x * 10 + y);
+        assert_eq!(vec2d.dimensions(), (3, 2));
+        assert_eq!(vec2d[(0, 0)], 0);
+        assert_eq!(vec2d[(0, 1)], 1);
+        assert_eq!(vec2d[(2, 0)], 20);
+        assert_eq!(vec2d[(2, 1)], 21);
+        assert_eq!(vec2d[(1, 1)], 11);
+
+        // called in storage order
+        let mut calls = Vec::new();
+        Vec2D::from_fn(2, 3, |x, y| calls.push((x, y)));
+        assert_eq!(calls, vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn test_as_slice() {
+        let mut vec2d = Vec2D::from_fn(3, 2, |x, y| x * 10 + y);
+        assert_eq!(vec2d.as_slice(), &[0, 1, 10, 11, 20, 21]);
+
+        vec2d.as_mut_slice()[2 * 2 + 1] = 99;
+        assert_eq!(vec2d[(2, 1)], 99);
+    }
+
+    #[test]
+    fn test_index() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        assert_eq!(vec2d[(0, 0)], 1);
+        assert_eq!(vec2d[(2, 1)], 1);
+    }
+
+    #[test]
+    fn test_index_skewed_0() {
+        let vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
+        assert_eq!(vec2d[(9, 0)], 1);
+        assert_eq!(vec2d[(9, 1)], 1);
+    }
+
+    #[test]
+    fn test_index_skewed_1() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
+        assert_eq!(vec2d[(0, 9)], 1);
+        assert_eq!(vec2d[(2, 9)], 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (3, 0)")]
+    fn test_index_out_of_bounds_0() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        let _ = vec2d[(3, 0)];
+    }
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (0, 2)")]
+    fn test_index_out_of_bounds_1() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        let _ = vec2d[(0, 2)];
+    }
+
+    #[test]
+    fn test_index_mut() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d[(1, 1)] = 5;
+        assert_eq!(vec2d[(1, 1)], 5);
+    }
+
+    #[test]
+    fn test_index_mut_skewed_0() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
+        vec2d[(9, 1)] = 5;
+        assert_eq!(vec2d[(9, 1)], 5);
+    }
+
+    #[test]
+    fn test_index_mut_skewed_1() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
+        vec2d[(2, 9)] = 5;
+        assert_eq!(vec2d[(2, 9)], 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (3, 0)")]
+    fn test_index_mut_out_of_bounds_0() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d[(3, 0)] = 5;
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (0, 2)")]
+    fn test_index_mut_out_of_bounds_1() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d[(0, 2)] = 5;
+    }
+
+    #[test]
+    fn test_get() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d[(2, 1)] = 5;
+        assert_eq!(vec2d.get(0, 0), Some(&1));
+        assert_eq!(vec2d.get(2, 1), Some(&5));
+        assert_eq!(vec2d.get(3, 0), None);
+        assert_eq!(vec2d.get(0, 2), None);
+    }
+
+    #[test]
+    fn test_get_skewed() {
+        let vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
+        assert_eq!(vec2d.get(9, 1), Some(&1));
+        assert_eq!(vec2d.get(1, 9), None);
+
+        let vec2d: Vec2D<i32> = Vec2D::new(2, 10, 1);
+        assert_eq!(vec2d.get(1, 9), Some(&1));
+        assert_eq!(vec2d.get(9, 1), None);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
+        *vec2d.get_mut(2, 9).unwrap() = 5;
+        assert_eq!(vec2d[(2, 9)], 5);
+        assert_eq!(vec2d.get_mut(3, 0), None);
+        assert_eq!(vec2d.get_mut(0, 10), None);
+    }
+
+    #[test]
+    fn test_index_unchecked() {
+        let mut vec2d: Vec2D<i32> = Vec2D::from_fn(3, 4, |x, y| (x * 10 + y) as i32);
+        // SAFETY: both indices are within bounds
+        unsafe {
+            assert_eq!(*vec2d.index_unchecked(2, 3), 23);
+            *vec2d.index_unchecked_mut(1, 2) = 5;
+        }
+        assert_eq!(vec2d[(1, 2)], 5);
+        assert_eq!(vec2d.get(1, 2), Some(&5));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_index_unchecked_out_of_bounds_in_debug() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 4, 1);
+        // SAFETY: not safe, but the bounds are checked in debug builds
+        unsafe {
+            vec2d.index_unchecked(0, 4);
+        }
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 10, 1);
+        vec2d[(2, 9)] = 5;
+        vec2d.fill(7);
+        assert!(vec2d.data.iter().all(|&v| v == 7));
+        assert_eq!(vec2d.width(), 3);
+        assert_eq!(vec2d.height(), 10);
+    }
+
+    #[test]
+    fn test_fill_empty() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(0, 5, 1);
+        vec2d.fill(7);
+        assert!(vec2d.data.is_empty());
+    }
+
+    #[test]
+    fn test_fill_with() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        let mut i = 0;
+        vec2d.fill_with(|| {
+            i += 1;
+            i
+        });
+        assert_eq!(vec2d.data, vec![1, 2, 3, 4, 5, 6].into());
+        assert_eq!(vec2d[(2, 1)], 6);
+    }
+
+    #[test]
+    fn test_clone_into() {
+        let vec2d = Vec2D::from_fn(3, 2, |x, y| x * 10 + y);
+        let mut dst = Vec2D::new(3, 2, 0);
+        let ptr = dst.as_slice().as_ptr();
+        vec2d.clone_into(&mut dst);
+        assert_eq!(dst, vec2d);
+        assert_eq!(dst.as_slice().as_ptr(), ptr);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot clone into a grid of different dimensions")]
+    fn test_clone_into_different_dimensions() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d.clone_into(&mut Vec2D::new(2, 3, 0));
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut vec2d = Vec2D::from_fn(3, 2, |x, y| x * 10 + y);
+        vec2d.swap((0, 1), (2, 0));
+        assert_eq!(vec2d[(0, 1)], 20);
+        assert_eq!(vec2d[(2, 0)], 1);
+
+        // swapping a cell with itself does nothing
+        let before = vec2d.clone();
+        vec2d.swap((1, 1), (1, 1));
+        assert_eq!(vec2d, before);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (0, 2)")]
+    fn test_swap_out_of_bounds() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d.swap((0, 0), (0, 2));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut vec2d = Vec2D::from_fn(3, 2, |x, y| x * 10 + y);
+        assert_eq!(vec2d.replace((2, 1), 99), 21);
+        assert_eq!(vec2d.replace((2, 1), 7), 99);
+        assert_eq!(vec2d[(2, 1)], 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds: the len is (3, 2) but the index is (3, 0)")]
+    fn test_replace_out_of_bounds() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 1);
+        vec2d.replace((3, 0), 0);
+    }
+
+    #[test]
+    fn test_label_components_connectivity() {
+        // two diagonal pixels
+        let mut grid = Vec2D::new(3, 3, false);
+        grid[(0, 0)] = true;
+        grid[(1, 1)] = true;
+
+        let (labels, count) = label_components(&grid, Connectivity::Four);
+        assert_eq!(count, 2);
+        assert_ne!(labels[(0, 0)], labels[(1, 1)]);
+        assert_eq!(labels[(2, 2)], 0);
+
+        let (labels, count) = label_components(&grid, Connectivity::Eight);
+        assert_eq!(count, 1);
+        assert_eq!(labels[(0, 0)], 1);
+        assert_eq!(labels[(1, 1)], 1);
+        assert_eq!(labels[(1, 0)], 0);
+    }
+
+    #[test]
+    fn test_distance_transform() {
+        let mut grid = Vec2D::new(7, 5, false);
+        grid[(1, 1)] = true;
+        grid[(5, 4)] = true;
+
+        let distance = distance_transform(&grid);
+        for (x, y, d) in distance.iter() {
+            let nearest = [(1.0, 1.0), (5.0, 4.0)]
+                .iter()
+                .map(|&(sx, sy): &(f64, f64)| (x as f64 - sx).hypot(y as f64 - sy))
+                .fold(f64::INFINITY, f64::min);
+            assert!((d - nearest).abs() < 1e-9, "({x}, {y}): {d} != {nearest}");
+        }
+
+        let none = distance_transform(&Vec2D::new(3, 2, false));
+        assert!(none.iter().all(|(_, _, d)| d == f64::INFINITY));
+    }
+
+    #[test]
+    fn test_label_components_merges_branches() {
+        // a U shape whose arms are only joined at the bottom, plus a separate dot
+        let mut grid = Vec2D::new(5, 4, false);
+        for y in 0..4 {
+            grid[(0, y)] = true;
+            grid[(2, y)] = true;
+        }
+        grid[(1, 0)] = true;
+        grid[(4, 3)] = true;
+
+        let (labels, count) = label_components(&grid, Connectivity::Four);
+        assert_eq!(count, 2);
+        assert!((0..4).all(|y| labels[(0, y)] == 1 && labels[(2, y)] == 1));
+        assert_eq!(labels[(4, 3)], 2);
+    }
+
+    #[test]
+    fn test_column_strips() {
+        let grid = Vec2D::from_fn(7, 3, |x, y| (x * 10 + y) as f64);
+        let mut bytes = Vec::new();
+        grid.to_bytes(&mut bytes).unwrap();
+
+        let mut strips = ColumnStrips::<_, f64>::new(bytes.as_slice(), 3).unwrap();
+        assert_eq!(strips.dimensions(), (7, 3));
+        let mut starts = Vec::new();
+        while let Some((x0, strip)) = strips.next_strip().unwrap() {
+            starts.push((x0, strip.width()));
+            assert_eq!(strip, grid.subgrid(x0, 0, strip.width(), 3));
+        }
+        assert_eq!(starts, vec![(0, 3), (2, 3), (4, 3)]);
+
+        assert!(ColumnStrips::<_, f64>::new(bytes.as_slice(), 1).is_err());
+    }
+
+    #[test]
+    fn test_from_rows_and_cols() {
+        let grid = Vec2D::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+        assert_eq!(grid.dimensions(), (3, 2));
+        assert_eq!(grid[(0, 0)], 1);
+        assert_eq!(grid[(2, 0)], 3);
+        assert_eq!(grid[(2, 1)], 6);
+        assert_eq!(
+            Vec2D::from_cols(vec![vec![1, 4], vec![2, 5], vec![3, 6]]).unwrap(),
+            grid
+        );
+
+        assert_eq!(
+            Vec2D::from_rows(vec![vec![1, 2, 3], vec![4, 5]]),
+            Err(RaggedError {
+                index: 1,
+                len: 2,
+                expected: 3
+            })
+        );
+        assert!(Vec2D::from_cols(vec![vec![1], vec![2, 3]]).is_err());
+        assert_eq!(Vec2D::<i32>::from_rows(Vec::new()).unwrap().area(), 0);
+    }
+
+    #[test]
+    fn test_to_ascii_art() {
+        let mask = Vec2D::from_rows(vec![
+            vec![true, false, false, false],
+            vec![false, true, true, false],
+            vec![false, false, false, true],
+        ])
+        .unwrap();
+        assert_eq!(
+            mask.to_ascii_art(|&m| if m { '#' } else { '.' }),
+            "#...\n.##.\n...#\n"
+        );
+        assert_eq!(Vec2D::new(0, 2, 0).to_ascii_art(|_| '?'), "\n\n");
+    }
+
+    #[test]
+    fn test_zip() {
+        let a = Vec2D::from_fn(4, 3, |x, y| x * 10 + y);
+        let b = Vec2D::from_fn(4, 3, |x, y| (x as f64, y as f64));
+        let zipped: Vec<_> = a.zip(&b).collect();
+        assert_eq!(zipped.len(), 12);
+        for (x, y, &va, &vb) in zipped {
+            assert_eq!(va, a[(x, y)]);
+            assert_eq!(vb, (x as f64, y as f64));
+        }
+        assert_eq!(a.zip(&b).nth(5).map(|(x, y, _, _)| (x, y)), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_iter_where() {
+        let v = Vec2D::from_fn(4, 3, |x, y| x * 10 + y);
+        let even: Vec<_> = v.iter_where(|&c| c % 2 == 0).collect();
+        assert_eq!(
+            even,
+            vec![
+                (0, 0, &0),
+                (0, 2, &2),
+                (1, 0, &10),
+                (1, 2, &12),
+                (2, 0, &20),
+                (2, 2, &22),
+                (3, 0, &30),
+                (3, 2, &32)
+            ]
+        );
+        // the same cells and order as filtering all of them
+        let filtered: Vec<_> = v
+            .iter_indexed()
+            .filter(|(_, _, _, &c)| c % 2 == 0)
+            .map(|(_, x, y, c)| (x, y, c))
+            .collect();
+        assert_eq!(even, filtered);
+        assert_eq!(v.iter_where(|&c| c > 100).count(), 0);
+
+        let mut grid = Vec2D::new(3, 2, f64::NAN);
+        grid[(2, 0)] = 1.5;
+        grid[(0, 1)] = -2.0;
+        assert_eq!(
+            grid.iter_valid().collect::<Vec<_>>(),
+            vec![(0, 1, -2.0), (2, 0, 1.5)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot zip grids of different dimensions")]
+    fn test_zip_different_dimensions() {
+        let a = Vec2D::new(4, 3, 0);
+        let b = Vec2D::new(3, 4, 0);
+        let _ = a.zip(&b);
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let v = Vec2D::from_fn(3, 3, |x, y| x * 10 + y);
+
+        let corner: Vec<_> = v.neighbors4(0, 0).collect();
+        assert_eq!(corner, vec![(1, 0, &10), (0, 1, &1)]);
+        assert_eq!(v.neighbors8(0, 0).count(), 3);
+        assert_eq!(v.neighbors4(2, 1).count(), 3);
+        assert_eq!(v.neighbors8(2, 1).count(), 5);
+
+        assert_eq!(v.neighbors4(1, 1).count(), 4);
+        let center: Vec<_> = v.neighbors8(1, 1).map(|(_, _, &value)| value).collect();
+        assert_eq!(center, vec![0, 10, 20, 1, 21, 2, 12, 22]);
+
+        assert_eq!(Vec2D::new(1, 1, 0).neighbors8(0, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_border_cells() {
+        let v = Vec2D::from_fn(3, 3, |x, y| x * 10 + y);
+        let border: Vec<_> = v
+            .border_cells()
+            .map(|(x, y, &value)| (x, y, value))
+            .collect();
+        assert_eq!(
+            border,
+            vec![
+                (0, 0, 0),
+                (0, 1, 1),
+                (0, 2, 2),
+                (1, 0, 10),
+                (1, 2, 12),
+                (2, 0, 20),
+                (2, 1, 21),
+                (2, 2, 22)
+            ]
+        );
+
+        // a single row or column is all border
+        assert_eq!(Vec2D::new(4, 1, 0).border_cells().count(), 4);
+        assert_eq!(Vec2D::new(1, 4, 0).border_cells().count(), 4);
+        assert_eq!(Vec2D::new(5, 4, 0).border_cells().count(), 14);
+        assert_eq!(Vec2D::new(0, 0, 0).border_cells().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_indexed() {
+        let vec2d = Vec2D::from_fn(7, 3, |x, y| x * 10 + y);
+        let mut count = 0;
+        for (i, x, y, v) in vec2d.iter_indexed() {
+            assert_eq!(i, x * 3 + y);
+            assert_eq!(*v, x * 10 + y);
+            assert_eq!(vec2d.as_slice()[i], *v);
+            count += 1;
+        }
+        assert_eq!(count, 21);
+    }
+
+    #[test]
+    fn test_iter_rows() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        vec2d[(1, 0)] = 1;
+        vec2d[(2, 0)] = 2;
+        vec2d[(0, 1)] = 3;
+        vec2d[(1, 1)] = 4;
+        vec2d[(2, 1)] = 5;
+        let visited: Vec<_> = vec2d.iter_rows().collect();
+        assert_eq!(
+            visited,
+            vec![
+                (0, 0, 0),
+                (1, 0, 1),
+                (2, 0, 2),
+                (0, 1, 3),
+                (1, 1, 4),
+                (2, 1, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iter_rows_mut() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        let mut visited = vec![];
+        for (i, (x, y, v)) in vec2d.iter_rows_mut().enumerate() {
+            visited.push((x, y));
+            *v = i as i32;
+        }
+        assert_eq!(
+            visited,
+            vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]
+        );
+        assert_eq!(vec2d[(2, 0)], 2);
+        assert_eq!(vec2d[(0, 1)], 3);
+        assert_eq!(vec2d[(2, 1)], 5);
+    }
+
+    #[test]
+    fn test_column() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        vec2d[(1, 0)] = 1;
+        vec2d[(1, 1)] = 2;
+        assert_eq!(vec2d.column(1), &[1, 2]);
+        assert_eq!(vec2d.column(2), &[0, 0]);
+
+        vec2d.column_mut(2).copy_from_slice(&[3, 4]);
+        assert_eq!(vec2d[(2, 0)], 3);
+        assert_eq!(vec2d[(2, 1)], 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "column out of bounds: the width is 3 but the column is 3")]
+    fn test_column_out_of_bounds() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        let _ = vec2d.column(3);
+    }
+
+    #[test]
+    fn test_row() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        vec2d[(0, 1)] = 1;
+        vec2d[(2, 1)] = 2;
+        assert_eq!(vec2d.row(1).copied().collect::<Vec<_>>(), vec![1, 0, 2]);
+        assert_eq!(vec2d.row(0).copied().collect::<Vec<_>>(), vec![0, 0, 0]);
+
+        for v in vec2d.row_mut(0) {
+            *v = 5;
+        }
+        assert_eq!(vec2d[(2, 0)], 5);
+        assert_eq!(vec2d[(2, 1)], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "row out of bounds: the height is 2 but the row is 2")]
+    fn test_row_out_of_bounds() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        let _ = vec2d.row(2);
+    }
+
+    #[test]
+    fn test_map() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(10, 2, 1);
+        vec2d[(9, 1)] = 5;
+        let mapped: Vec2D<i64> = vec2d.map(|v| *v as i64 * 2);
+        assert_eq!(mapped.width(), 10);
+        assert_eq!(mapped.height(), 2);
+        assert_eq!(mapped[(9, 1)], 10);
+        assert_eq!(mapped[(9, 0)], 2);
+        assert_eq!(mapped[(0, 1)], 2);
+    }
+
+    #[test]
+    fn test_subgrid() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(4, 3, 0);
+        for (x, y, v) in vec2d.iter_mut() {
+            *v = (x * 10 + y) as i32;
+        }
+        let sub = vec2d.subgrid(1, 1, 3, 2);
+        assert_eq!(sub.width(), 3);
+        assert_eq!(sub.height(), 2);
+        assert_eq!(sub[(0, 0)], 11);
+        assert_eq!(sub[(2, 1)], 32);
+        assert_eq!(sub[(1, 0)], 21);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "subgrid out of bounds: the len is (4, 3) but the window is (2..5, 0..1)"
+    )]
+    fn test_subgrid_out_of_bounds() {
+        let vec2d: Vec2D<i32> = Vec2D::new(4, 3, 0);
+        let _ = vec2d.subgrid(2, 0, 3, 1);
+    }
+
+    #[test]
+    fn test_padded() {
+        let vec2d = Vec2D::from_fn(3, 2, |x, y| (x * 10 + y) as f64);
+        let padded = vec2d.padded(4, 5, f64::NAN);
+        assert_eq!(padded.dimensions(), (4, 5));
+        for (x, y, v) in padded.iter() {
+            if x < 3 && y < 2 {
+                assert_eq!(v, vec2d[(x, y)]);
+            } else {
+                assert!(v.is_nan());
+            }
+        }
+        assert_eq!(vec2d.padded(3, 2, 0.0), vec2d);
+    }
+
+    #[test]
+    #[should_panic(expected = "padded size (2, 5) is smaller than the len (3, 2)")]
+    fn test_padded_smaller() {
+        let vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        let _ = vec2d.padded(2, 5, 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_iter_mut() {
+        use rayon::prelude::*;
+
+        let mut vec2d: Vec2D<usize> = Vec2D::new(37, 11, 0);
+        vec2d.par_iter_mut().for_each(|(x, y, v)| *v = x * 100 + y);
+        for (x, y, v) in vec2d.iter() {
+            assert_eq!(v, x * 100 + y);
+        }
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let vec2d = Vec2D::new(7, 2, 0u8);
+        assert_eq!(vec2d.dimensions(), (7, 2));
+        assert_eq!(vec2d.transpose().dimensions(), (2, 7));
+        assert_eq!(vec2d.area(), 14);
+        assert_eq!(Vec2D::new(0, 5, 0u8).area(), 0);
+    }
+
+    #[test]
+    fn test_count() {
+        let vec2d = Vec2D::from_fn(4, 3, |x, y| x * 10 + y);
+        assert_eq!(vec2d.count(|&v| v > 20), 5);
+        assert_eq!(vec2d.count(|_| false), 0);
+
+        let mut vec2d = Vec2D::new(3, 2, 1.5);
+        assert_eq!(vec2d.count_nan(), 0);
+        vec2d[(0, 1)] = f64::NAN;
+        vec2d[(2, 0)] = f64::NAN;
+        assert_eq!(vec2d.count_nan(), 2);
+    }
+
+    #[test]
+    fn test_reductions_ignore_nan() {
+        let mut vec2d: Vec2D<f64> = Vec2D::from_fn(3, 2, |x, y| (x * 10 + y) as f64);
+        vec2d[(0, 0)] = f64::NAN;
+        vec2d[(2, 1)] = f64::NAN;
+        assert_eq!(vec2d.min_ignore_nan(), Some(1.0));
+        assert_eq!(vec2d.max_ignore_nan(), Some(20.0));
+        assert_eq!(
+            vec2d.mean_ignore_nan(),
+            Some((1.0 + 10.0 + 11.0 + 20.0) / 4.0)
+        );
+
+        vec2d.fill(f64::NAN);
+        assert_eq!(vec2d.min_ignore_nan(), None);
+        assert_eq!(vec2d.max_ignore_nan(), None);
+        assert_eq!(vec2d.mean_ignore_nan(), None);
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let mut a = Vec2D::from_fn(3, 2, |x, y| (x * 10 + y) as f64 + 0.5);
+        a[(1, 1)] = f64::NAN;
+        let b = a.map(|v| v + 1e-9);
+        assert!(a.approx_eq(&b, 1e-6));
+        assert!(!a.approx_eq(&b, 1e-12));
+
+        let mut c = a.clone();
+        c[(0, 0)] = f64::NAN;
+        assert!(!a.approx_eq(&c, 1e-6));
+        assert!(!a.approx_eq(&a.transpose(), 1e-6));
+    }
+
+    #[test]
+    fn test_accum_grid() {
+        let mut accum = AccumGrid::new(3, 2);
+        accum.add(1, 0, 5.0);
+        accum.add(2, 1, 1.0);
+        accum.add(2, 1, 2.0);
+        accum.add(2, 1, 6.0);
+        assert_eq!(accum.count(0, 0), 0);
+        assert_eq!(accum.count(2, 1), 3);
+
+        let mean = accum.finalize();
+        assert_eq!(mean.dimensions(), (3, 2));
+        assert!(mean[(0, 0)].is_nan());
+        assert!(mean[(0, 1)].is_nan());
+        assert_eq!(mean[(1, 0)], 5.0);
+        assert_eq!(mean[(2, 1)], 3.0);
+    }
+
+    #[test]
+    fn test_nodata_grid_u8() {
+        // 0 is a valid class here, so use 255 for no-data
+        let mut grid = NoDataGrid::new(3, 2, 255u8);
+        grid.grid[(0, 0)] = 0;
+        grid.grid[(2, 1)] = 3;
+        assert!(!grid.is_nodata(0, 0));
+        assert!(grid.is_nodata(1, 0));
+        assert_eq!(grid.get_valid(0, 0), Some(0));
+        assert_eq!(grid.get_valid(1, 1), None);
+
+        let mut buff = Vec::new();
+        grid.to_bytes(&mut buff).unwrap();
+        let read = NoDataGrid::<u8>::from_bytes(&mut buff.as_slice()).unwrap();
+        assert_eq!(read, grid);
+        assert!(read.is_nodata(1, 0));
+    }
+
+    #[test]
+    fn test_nodata_grid_i16() {
+        let mut grid = NoDataGrid::new(2, 2, i16::MIN);
+        grid.grid[(1, 0)] = -5;
+        grid.grid[(0, 1)] = 0;
+
+        let mut buff = Vec::new();
+        grid.to_bytes(&mut buff).unwrap();
+        let read = NoDataGrid::<i16>::from_bytes(&mut buff.as_slice()).unwrap();
+        assert_eq!(read.nodata, i16::MIN);
+        assert_eq!(read.get_valid(1, 0), Some(-5));
+        assert_eq!(read.get_valid(0, 1), Some(0));
+        assert!(read.is_nodata(0, 0));
+        assert!(read.is_nodata(1, 1));
+    }
+
+    #[test]
+    fn test_sample_bilinear() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 2, 0.0);
+        vec2d[(0, 0)] = 1.0;
+        vec2d[(1, 0)] = 2.0;
+        vec2d[(2, 0)] = 3.0;
+        vec2d[(0, 1)] = 5.0;
+        vec2d[(1, 1)] = 6.0;
+        vec2d[(2, 1)] = 7.0;
+
+        // cell centers
+        assert_eq!(vec2d.sample_bilinear(0.0, 0.0), 1.0);
+        assert_eq!(vec2d.sample_bilinear(2.0, 1.0), 7.0);
+        assert_eq!(vec2d.sample_bilinear(1.0, 1.0), 6.0);
+
+        // midpoints
+        assert_eq!(vec2d.sample_bilinear(0.5, 0.0), 1.5);
+        assert_eq!(vec2d.sample_bilinear(1.0, 0.5), 4.0);
+        assert_eq!(vec2d.sample_bilinear(1.5, 0.5), 4.5);
+
+        // clamped to the edges
+        assert_eq!(vec2d.sample_bilinear(-0.5, 0.0), 1.0);
+        assert_eq!(vec2d.sample_bilinear(2.5, 1.5), 7.0);
+    }
+
+    #[test]
+    fn test_sample_bilinear_nan() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 2, 1.0);
+        vec2d[(1, 1)] = f64::NAN;
+        assert!(vec2d.sample_bilinear(0.5, 0.5).is_nan());
+        assert!(vec2d.sample_bilinear(1.0, 0.0).is_nan());
+        assert_eq!(vec2d.sample_bilinear(2.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_resample_down() {
+        let vec2d: Vec2D<f64> = Vec2D::new(4, 4, 7.5);
+        let coarse = vec2d.resample(4.0);
+        assert_eq!(coarse.dimensions(), (1, 1));
+        assert_eq!(coarse[(0, 0)], 7.5);
+
+        // partial blocks at the edges average what is there
+        let mut vec2d: Vec2D<f64> = Vec2D::new(5, 2, 0.0);
+        for (x, _, v) in vec2d.iter_mut() {
+            *v = x as f64;
+        }
+        let coarse = vec2d.resample(2.0);
+        assert_eq!(coarse.dimensions(), (3, 1));
+        assert_eq!(coarse.column(0), &[0.5]);
+        assert_eq!(coarse.column(1), &[2.5]);
+        assert_eq!(coarse.column(2), &[4.0]);
+    }
+
+    #[test]
+    fn test_resample_down_nan() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(4, 2, 1.0);
+        vec2d[(0, 0)] = f64::NAN;
+        vec2d[(1, 1)] = 4.0;
+        vec2d[(2, 0)] = f64::NAN;
+        vec2d[(3, 0)] = f64::NAN;
+        vec2d[(2, 1)] = f64::NAN;
+        vec2d[(3, 1)] = f64::NAN;
+
+        let coarse = vec2d.resample(2.0);
+        // NaN cells are left out of the average
+        assert_eq!(coarse[(0, 0)], 2.0);
+        // a block without any valid cells stays NaN
+        assert!(coarse[(1, 0)].is_nan());
+    }
+
+    #[test]
+    fn test_resample_up() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(2, 2, 0.0);
+        vec2d[(1, 0)] = 2.0;
+        vec2d[(0, 1)] = 4.0;
+        vec2d[(1, 1)] = 6.0;
+
+        let fine = vec2d.resample(0.5);
+        assert_eq!(fine.dimensions(), (3, 3));
+        assert_eq!(fine[(0, 0)], 0.0);
+        assert_eq!(fine[(1, 0)], 1.0);
+        assert_eq!(fine[(1, 1)], 3.0);
+        assert_eq!(fine[(2, 2)], 6.0);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 2, 1.0);
+        vec2d[(2, 1)] = 5.0;
+
+        let mut buff = Vec::new();
+        vec2d.to_bytes(&mut buff).unwrap();
+        assert_eq!(&buff[..4], VEC2D_MAGIC);
+        assert_eq!(
+            Vec2D::<f64>::from_bytes(&mut buff.as_slice()).unwrap(),
+            vec2d
+        );
+    }
+
+    #[test]
+    fn test_bytes_invalid_magic() {
+        let mut buff = Vec::new();
+        Vec2D::new(3, 2, 1.0).to_bytes(&mut buff).unwrap();
+        buff[0] = b'X';
+        let err = Vec2D::<f64>::from_bytes(&mut buff.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_bytes_invalid_version() {
+        let mut buff = Vec::new();
+        Vec2D::new(3, 2, 1.0).to_bytes(&mut buff).unwrap();
+        buff[VEC2D_MAGIC.len()] = VEC2D_VERSION + 1;
+        let err = Vec2D::<f64>::from_bytes(&mut buff.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_bytes_overflowing_dimensions() {
+        let mut buff = Vec::new();
+        buff.extend_from_slice(VEC2D_MAGIC);
+        buff.push(VEC2D_VERSION);
+        usize::MAX.to_bytes(&mut buff).unwrap();
+        2usize.to_bytes(&mut buff).unwrap();
+        let err = Vec2D::<f64>::from_bytes(&mut buff.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_bytes_truncated() {
+        let mut buff = Vec::new();
+        buff.extend_from_slice(VEC2D_MAGIC);
+        buff.push(VEC2D_VERSION);
+        (1usize << 40).to_bytes(&mut buff).unwrap();
+        (1usize << 20).to_bytes(&mut buff).unwrap();
+        1.0f64.to_bytes(&mut buff).unwrap();
+        let err = Vec2D::<f64>::from_bytes(&mut buff.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        for (x, y, v) in vec2d.iter_mut() {
+            *v = (x * 10 + y) as i32;
+        }
+        let transposed = vec2d.transpose();
+        assert_eq!(transposed.width(), 2);
+        assert_eq!(transposed.height(), 3);
+        for (x, y, v) in vec2d.iter() {
+            assert_eq!(transposed[(y, x)], v);
+        }
+    }
+
+    fn corners() -> Vec2D<i32> {
+        let mut vec2d: Vec2D<i32> = Vec2D::new(3, 2, 0);
+        vec2d[(0, 0)] = 1;
+        vec2d[(2, 0)] = 2;
+        vec2d[(0, 1)] = 3;
+        vec2d[(2, 1)] = 4;
+        vec2d
+    }
+
+    #[test]
+    fn test_flip_y() {
+        let flipped = corners().flip_y();
+        assert_eq!(flipped.width(), 3);
+        assert_eq!(flipped.height(), 2);
+        assert_eq!(flipped[(0, 0)], 3);
+        assert_eq!(flipped[(2, 0)], 4);
+        assert_eq!(flipped[(0, 1)], 1);
+        assert_eq!(flipped[(2, 1)], 2);
+    }
+
+    #[test]
+    fn test_flip_x() {
+        let flipped = corners().flip_x();
+        assert_eq!(flipped[(0, 0)], 2);
+        assert_eq!(flipped[(2, 0)], 1);
+        assert_eq!(flipped[(0, 1)], 4);
+        assert_eq!(flipped[(2, 1)], 3);
+    }
+
+    #[test]
+    fn test_rotate_180() {
+        let rotated = corners().rotate_180();
+        assert_eq!(rotated[(0, 0)], 4);
+        assert_eq!(rotated[(2, 0)], 3);
+        assert_eq!(rotated[(0, 1)], 2);
+        assert_eq!(rotated[(2, 1)], 1);
+        assert_eq!(rotated, corners().flip_x().flip_y());
+    }
+
+    #[test]
+    fn test_convolve3x3_identity() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 2, 0.0);
+        for (x, y, v) in vec2d.iter_mut() {
+            *v = (x * 10 + y) as f64;
+        }
+        let kernel = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]];
+        assert_eq!(vec2d.convolve3x3(&kernel), vec2d);
+    }
+
+    #[test]
+    fn test_convolve3x3_edges() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 3, 0.0);
+        vec2d[(0, 0)] = 9.0;
+
+        // box blur, the corner is replicated into the 5 neighbors outside the grid
+        let kernel = [[1.0 / 9.0; 3]; 3];
+        let result = vec2d.convolve3x3(&kernel);
+        assert!((result[(0, 0)] - 9.0 * 4.0 / 9.0).abs() < 1e-9);
+        assert!((result[(1, 1)] - 1.0).abs() < 1e-9);
+        assert_eq!(result[(2, 2)], 0.0);
+
+        // pick the left neighbor, which is the cell itself on the left edge
+        let kernel = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.0]];
+        let result = vec2d.convolve3x3(&kernel);
+        assert_eq!(result[(0, 0)], 9.0);
+        assert_eq!(result[(1, 0)], 9.0);
+        assert_eq!(result[(2, 0)], 0.0);
+    }
+
+    #[test]
+    fn test_convolve3x3_nan() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(4, 4, 1.0);
+        vec2d[(0, 0)] = f64::NAN;
+        let kernel = [[1.0; 3]; 3];
+        let result = vec2d.convolve3x3(&kernel);
+        assert!(result[(0, 0)].is_nan());
+        assert!(result[(1, 1)].is_nan());
+        assert_eq!(result[(2, 2)], 9.0);
+    }
+
+    #[test]
+    fn test_len() {
+        let vec2d: Vec2D<f64> = Vec2D::new(10, 3, 0.0);
+        assert_eq!(vec2d.len(), 30);
+        assert_eq!(vec2d.area(), 30);
+        assert_eq!((vec2d.cols(), vec2d.rows()), (10, 3));
+        assert!(!vec2d.is_empty());
+        assert_eq!(vec2d.size_bytes(), 240);
+
+        let vec2d = Vec2D::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+        assert_eq!((vec2d.cols(), vec2d.rows()), (3, 2));
+        assert_eq!(vec2d.transpose().rows(), 3);
+
+        let vec2d: Vec2D<u8> = Vec2D::new(0, 3, 0);
+        assert_eq!(vec2d.len(), 0);
+        assert_eq!(vec2d.area(), 0);
+        assert_eq!((vec2d.cols(), vec2d.rows()), (0, 3));
+        assert!(vec2d.is_empty());
+        assert_eq!(vec2d.size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_from_bytes_into() {
+        let mut vec2d: Vec2D<f64> = Vec2D::new(3, 2, 1.0);
+        vec2d[(2, 1)] = 5.0;
+        let mut buff = Vec::new();
+        vec2d.to_bytes(&mut buff).unwrap();
+
+        let buf = vec![0.0; 6].into_boxed_slice();
+        let read = Vec2D::from_bytes_into(&mut buff.as_slice(), 3, 2, buf).unwrap();
+        assert_eq!(read, vec2d);
+
+        let buf = vec![0.0; 6].into_boxed_slice();
+        let err = Vec2D::from_bytes_into(&mut buff.as_slice(), 2, 3, buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let buf = vec![0.0; 5].into_boxed_slice();
+        let err = Vec2D::from_bytes_into(&mut buff.as_slice(), 3, 2, buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
     }
 }
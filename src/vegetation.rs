@@ -6,14 +6,43 @@ use log::info;
 use rustc_hash::FxHashMap as HashMap;
 use std::error::Error;
 use std::f32::consts::SQRT_2;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
 
-use crate::config::{Config, Zone};
+use crate::config::{Background, Config, Zone};
 use crate::io::bytes::FromToBytes;
+use crate::io::crs::write_prj;
 use crate::io::fs::FileSystem;
 use crate::io::heightmap::HeightMap;
-use crate::io::xyz::XyzInternalReader;
+use crate::io::worldfile::write_world_file;
+use crate::io::xyz::{XyzInternalReader, XyzRecord};
+use crate::vec2d::{NoDataGrid, Vec2D};
+use crate::water;
+
+/// The green class of the blocks at the edges that are not classified.
+const GREEN_NO_DATA: u8 = u8::MAX;
+
+/// Whether a non-ground return `hh` meters above the ground adds to the green density: it must be
+/// within `vege_height_band` if one is set, and not a building if `vege_exclude_buildings` is set
+/// (the `buildingsclass`, or the ASPRS class 6 when that is not set).
+fn counts_for_green(config: &Config, hh: f64, classification: u8) -> bool {
+    if let Some((low, high)) = config.vege_height_band {
+        if hh < low || hh > high {
+            return false;
+        }
+    }
+    if config.vege_exclude_buildings {
+        let buildings = if config.buildings == 0 {
+            6
+        } else {
+            config.buildings
+        };
+        if classification == buildings {
+            return false;
+        }
+    }
+    true
+}
 
 pub fn makevege(
     fs: &impl FileSystem,
@@ -44,7 +73,6 @@ pub fn makevege(
         pointvolumeexponent,
         greenhigh,
         topweight,
-        greentone,
         vegezoffset: zoffset,
         uglimit,
         uglimit2,
@@ -179,7 +207,7 @@ pub fn makevege(
                     } else {
                         *ghit.entry((xx, yyy)).or_insert(0) += 1;
                     }
-                } else {
+                } else if counts_for_green(config, hh, r3) {
                     let mut last = 1.0;
                     if r4 == r5 {
                         last = lastfactor;
@@ -219,21 +247,11 @@ pub fn makevege(
     let wy = (xmax - xmin).floor() / 3.0;
     let hy = (ymax - ymin).floor() / 3.0;
 
-    let scalefactor = config.scalefactor;
+    let pixels_per_meter = config.pixels_per_meter;
 
     let img_width = (w * block) as u32;
     let img_height = (h * block) as u32;
 
-    let greens = (0..greenshades.len())
-        .map(|i| {
-            Rgb([
-                (greentone - greentone / (greenshades.len() - 1) as f64 * i as f64) as u8,
-                (254.0 - (74.0 / (greenshades.len() - 1) as f64) * i as f64) as u8,
-                (greentone - greentone / (greenshades.len() - 1) as f64 * i as f64) as u8,
-            ])
-        })
-        .collect::<Vec<_>>();
-
     let mut aveg = 0;
     let mut avecount = 0;
 
@@ -271,7 +289,8 @@ pub fn makevege(
         }
     }
 
-    let mut imggr1 = RgbImage::from_pixel(img_width, img_height, Rgb([255, 255, 255]));
+    // the green shade of each block, 0 for no green and i + 1 for greenshades[i]
+    let mut classes = NoDataGrid::new(w as usize, h as usize, GREEN_NO_DATA);
     for x in 2..w as usize {
         for y in 2..h as usize {
             let roof = *top.get(&(x as u64, y as u64)).unwrap_or(&0.0)
@@ -308,31 +327,41 @@ pub fn makevege(
                         / (ghit2 as f64 + greenhit2 + highit2 as f64 + 1.0))
                 * (1.0 - pointvolumefactor * firsthit2 as f64 / (aveg + 0.00001))
                     .powf(pointvolumeexponent);
-            if thevalue > 0.0 {
-                let mut greenshade = 0;
-                for (i, &shade) in greenshades.iter().enumerate() {
-                    if thevalue > greenlimit * shade {
-                        greenshade = i + 1;
-                    }
-                }
-                if greenshade > 0 {
-                    draw_filled_rect_mut(
-                        &mut imggr1,
-                        Rect::at(
-                            ((x as f64 + 0.5) * block) as i32 - addition,
-                            (((h - y as f64) - 0.5) * block) as i32 - addition,
-                        )
-                        .of_size(
-                            (block as i32 + addition) as u32,
-                            (block as i32 + addition) as u32,
-                        ),
-                        greens[greenshade - 1],
-                    );
-                }
-            }
+            classes.grid[(x, y)] = if thevalue > 0.0 {
+                green_class(thevalue, greenlimit, greenshades).map_or(0, |i| i as u8 + 1)
+            } else {
+                0
+            };
         }
     }
 
+    if config.vege_despeckle_window > 1 {
+        classes = despeckle(
+            &classes,
+            config.vege_despeckle_window,
+            config.vege_despeckle_min_cells,
+        );
+    }
+
+    let mut imggr1 = RgbImage::from_pixel(img_width, img_height, Rgb(background_fill(config)));
+    for (x, y, &class) in classes
+        .grid
+        .iter_where(|&class| class != 0 && class != GREEN_NO_DATA)
+    {
+        draw_filled_rect_mut(
+            &mut imggr1,
+            Rect::at(
+                ((x as f64 + 0.5) * block) as i32 - addition,
+                (((h - y as f64) - 0.5) * block) as i32 - addition,
+            )
+            .of_size(
+                (block as i32 + addition) as u32,
+                (block as i32 + addition) as u32,
+            ),
+            Rgb(greenshades[class as usize - 1].1),
+        );
+    }
+
     let proceed_yellows: bool = config.proceed_yellows;
     let med: u32 = config.med;
     let med2 = config.med2;
@@ -370,8 +399,7 @@ pub fn makevege(
         )
         .expect("could not save output png");
 
-    let mut img = DynamicImage::ImageRgb8(imggr1);
-    image::imageops::overlay(&mut img, &DynamicImage::ImageRgba8(imgye2), 0, 0);
+    let img = vegetation_image(imggr1, &imgye2, config.background);
 
     img.write_to(
         &mut BufWriter::new(
@@ -392,7 +420,7 @@ pub fn makevege(
         let mut g_img = g_img.to_rgb8();
         for pixel in g_img.pixels_mut() {
             let mut found = false;
-            for (idx, color) in greens.iter().enumerate() {
+            for (idx, (_, color)) in greenshades.iter().enumerate() {
                 let c = idx as u8 + 2;
                 if pixel[0] == color[0] && pixel[1] == color[1] && pixel[2] == color[2] {
                     *pixel = Rgb([c, c, c]);
@@ -492,6 +520,30 @@ pub fn makevege(
         }
     }
 
+    if config.water_detection {
+        let counts = water::ground_counts(fs, config, &xyz_file_in, &hmap)?;
+        let mask = water::detect_water(
+            &hmap,
+            &counts,
+            config.water_max_slope,
+            config.water_max_density,
+            config.water_min_area,
+        );
+        // cover the whole cell around the cell center
+        let half = (size / 2.0).ceil() as i32;
+        for (x, y, is_water) in mask.iter() {
+            if is_water {
+                let (x, y) = (xstart + x as f64 * size, ystart + y as f64 * size);
+                draw_filled_rect_mut(
+                    &mut imgwater,
+                    Rect::at((x - xmin) as i32 - half, (ymax - y) as i32 - half)
+                        .of_size(2 * half as u32 + 1, 2 * half as u32 + 1),
+                    blue,
+                );
+            }
+        }
+    }
+
     imgwater
         .write_to(
             &mut BufWriter::new(
@@ -505,7 +557,7 @@ pub fn makevege(
     drop(imgwater); // explicitly drop imgwater to free memory
 
     let underg = Rgba([64, 121, 0, 255]);
-    let tmpfactor = (600.0 / 254.0 / scalefactor) as f32;
+    let tmpfactor = pixels_per_meter as f32;
 
     let bf32 = block as f32;
     let hf32 = h as f32;
@@ -514,13 +566,13 @@ pub fn makevege(
     let mut x = 0.0_f32;
 
     let mut imgug = RgbaImage::from_pixel(
-        (w * block * 600.0 / 254.0 / scalefactor) as u32,
-        (h * block * 600.0 / 254.0 / scalefactor) as u32,
+        (w * block * pixels_per_meter) as u32,
+        (h * block * pixels_per_meter) as u32,
         Rgba([255, 255, 255, 0]),
     );
     let mut img_ug_bit = GrayImage::from_pixel(
-        (w * block * 600.0 / 254.0 / scalefactor) as u32,
-        (h * block * 600.0 / 254.0 / scalefactor) as u32,
+        (w * block * pixels_per_meter) as u32,
+        (h * block * pixels_per_meter) as u32,
         Luma([0x00]),
     );
     loop {
@@ -659,31 +711,389 @@ pub fn makevege(
         )
         .expect("could not save output png");
 
-    let mut writer = BufWriter::new(
-        fs.create(tmpfolder.join("undergrowth.pgw"))
-            .expect("cannot create pgw file"),
-    );
-    write!(
-        &mut writer,
-        "{}\r\n0.0\r\n0.0\r\n{}\r\n{}\r\n{}\r\n",
-        1.0 / tmpfactor,
-        -1.0 / tmpfactor,
+    write_world_file(
+        fs,
+        tmpfolder.join("undergrowth.png"),
         xmin,
         ymax,
+        (1.0 / tmpfactor) as f64,
     )
     .expect("Cannot write pgw file");
+    write_world_file(fs, tmpfolder.join("vegetation.png"), xmin, ymax, 1.0)
+        .expect("Cannot write pgw file");
 
-    let mut writer = BufWriter::new(
-        fs.create(tmpfolder.join("vegetation.pgw"))
-            .expect("cannot create pgw file"),
-    );
-    write!(
-        &mut writer,
-        "1.0\r\n0.0\r\n0.0\r\n-1.0\r\n{}\r\n{}\r\n",
-        xmin, ymax
-    )
-    .expect("Cannot write pgw file");
+    if config.vege_canopy {
+        info!("Classifying the canopy");
+        let mut reader = XyzInternalReader::new(BufReader::new(fs.open(&xyz_file_in)?))?;
+        let mut read_error = None;
+        let records = std::iter::from_fn(|| match reader.next() {
+            Ok(r) => r.map(|r| XyzRecord {
+                z: r.z - zoffset,
+                ..r
+            }),
+            Err(e) => {
+                read_error = Some(e);
+                None
+            }
+        });
+        let classes = canopy_classes(
+            records,
+            &hmap,
+            config.canopy_rough_height,
+            config.canopy_forest_height,
+            config.canopy_min_cover,
+        );
+        if let Some(e) = read_error {
+            return Err(e.into());
+        }
+        write_canopy_png(
+            fs,
+            tmpfolder.join("canopy.png"),
+            &classes,
+            &hmap,
+            config.crs_epsg,
+        )?;
+    }
 
     info!("Done");
     Ok(())
 }
+
+/// Classify a vegetation value into one of the green shades. `shades` are ordered
+/// `(threshold, color)` pairs where the threshold is relative to `limit`. The value gets the color
+/// of the last shade whose threshold it strictly exceeds, or `None` if it exceeds none of them.
+pub fn green_shade(value: f64, limit: f64, shades: &[(f64, [u8; 3])]) -> Option<[u8; 3]> {
+    green_class(value, limit, shades).map(|i| shades[i].1)
+}
+
+/// Like [`green_shade`], but returns the index of the shade instead of its color.
+pub fn green_class(value: f64, limit: f64, shades: &[(f64, [u8; 3])]) -> Option<usize> {
+    let mut class = None;
+    for (i, &(threshold, _)) in shades.iter().enumerate() {
+        if value > limit * threshold {
+            class = Some(i);
+        }
+    }
+    class
+}
+
+/// Remove specks from a class grid: every 8-connected area of a single class smaller than
+/// `min_cells` is replaced, cell by cell, by the most common class among the other cells in the
+/// `window`×`window` neighborhood. Cells of other small areas and no-data cells do not count,
+/// and a tie keeps the original class. Larger areas, such as genuine small clearings, are kept
+/// as they are.
+pub fn despeckle(classes: &NoDataGrid<u8>, window: usize, min_cells: usize) -> NoDataGrid<u8> {
+    let grid = &classes.grid;
+    let (w, h) = grid.dimensions();
+
+    // label the connected areas and find the small ones
+    let mut labels = Vec2D::new(w, h, usize::MAX);
+    let mut small = Vec::new();
+    let mut stack = Vec::new();
+    for (x, y, class) in grid.iter() {
+        if labels[(x, y)] != usize::MAX || class == classes.nodata {
+            continue;
+        }
+        let label = small.len();
+        labels[(x, y)] = label;
+        stack.push((x, y));
+        let mut size = 0;
+        while let Some((cx, cy)) = stack.pop() {
+            size += 1;
+            for nx in cx.saturating_sub(1)..(cx + 2).min(w) {
+                for ny in cy.saturating_sub(1)..(cy + 2).min(h) {
+                    if labels[(nx, ny)] == usize::MAX && grid[(nx, ny)] == class {
+                        labels[(nx, ny)] = label;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+        }
+        small.push(size < min_cells);
+    }
+
+    let half = window / 2;
+    let mut result = classes.clone();
+    for (x, y, class) in grid.iter() {
+        if class == classes.nodata || !small[labels[(x, y)]] {
+            continue;
+        }
+        let mut counts = [0usize; 256];
+        for nx in x.saturating_sub(half)..(x + half + 1).min(w) {
+            for ny in y.saturating_sub(half)..(y + half + 1).min(h) {
+                let neighbor = grid[(nx, ny)];
+                if neighbor != classes.nodata && !small[labels[(nx, ny)]] {
+                    counts[neighbor as usize] += 1;
+                }
+            }
+        }
+        let mut best = class;
+        for (candidate, &count) in counts.iter().enumerate() {
+            if count > counts[best as usize] {
+                best = candidate as u8;
+            }
+        }
+        result.grid[(x, y)] = best;
+    }
+    result
+}
+
+/// The class of a cell by the height of its vegetation above the ground, see [`canopy_classes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanopyClass {
+    Open,
+    Rough,
+    Forest,
+}
+
+impl CanopyClass {
+    /// The color of the class in the canopy image.
+    pub fn color(self) -> Rgb<u8> {
+        match self {
+            CanopyClass::Open => Rgb([255, 255, 255]),
+            CanopyClass::Rough => Rgb([197, 255, 184]),
+            CanopyClass::Forest => Rgb([61, 255, 23]),
+        }
+    }
+}
+
+/// Classify each cell of the heightmap `dem` by the height above the ground of all the returns
+/// binned into it: forest if at least `min_cover` of its returns are `forest_height` or more above
+/// the ground, else rough if at least `min_cover` of its returns are `rough_height` or more above
+/// the ground, else open. The ground under each return is interpolated from the heightmap, so two
+/// cells with the same number of returns differ by how high they are. Cells without returns are
+/// open and returns outside the grid are ignored.
+pub fn canopy_classes(
+    records: impl IntoIterator<Item = XyzRecord>,
+    dem: &HeightMap,
+    rough_height: f64,
+    forest_height: f64,
+    min_cover: f64,
+) -> Vec2D<CanopyClass> {
+    let (w, h) = dem.grid.dimensions();
+    // per cell: all returns, returns at least rough_height and at least forest_height high
+    let mut counts = Vec2D::new(w, h, [0u32; 3]);
+    for r in records {
+        let fx = (r.x - dem.xoffset) / dem.scale;
+        let fy = (r.y - dem.yoffset) / dem.scale;
+        let (idx_x, idx_y) = (fx.floor(), fy.floor());
+        if idx_x < 0.0 || idx_y < 0.0 || idx_x >= w as f64 || idx_y >= h as f64 {
+            continue;
+        }
+        let height = r.z - dem.grid.sample_bilinear(fx, fy);
+        let count = &mut counts[(idx_x as usize, idx_y as usize)];
+        count[0] += 1;
+        if height >= rough_height {
+            count[1] += 1;
+        }
+        if height >= forest_height {
+            count[2] += 1;
+        }
+    }
+
+    counts.map(|&[total, rough, forest]| {
+        let cover = |n: u32| total > 0 && n as f64 >= min_cover * total as f64;
+        if cover(forest) {
+            CanopyClass::Forest
+        } else if cover(rough) {
+            CanopyClass::Rough
+        } else {
+            CanopyClass::Open
+        }
+    })
+}
+
+/// Write the canopy classes of the cells of `dem` as a north-up PNG with one pixel per cell, a
+/// world file and a `.prj` if `crs_epsg` is given.
+pub fn write_canopy_png(
+    fs: &impl FileSystem,
+    path: impl AsRef<Path>,
+    classes: &Vec2D<CanopyClass>,
+    dem: &HeightMap,
+    crs_epsg: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let (w, h) = classes.dimensions();
+    let img = RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        classes[(x as usize, h - 1 - y as usize)].color()
+    });
+    img.write_to(
+        &mut BufWriter::new(fs.create(path)?),
+        image::ImageFormat::Png,
+    )?;
+    write_world_file(
+        fs,
+        path,
+        dem.xoffset,
+        dem.yoffset + dem.scale * (h as f64 - 1.0),
+        dem.scale,
+    )?;
+    write_prj(fs, path, crs_epsg)?;
+    Ok(())
+}
+
+/// The color the green image is filled with before the greens are drawn. A transparent background
+/// is filled with white, which [`vegetation_image`] then makes transparent.
+fn background_fill(config: &Config) -> [u8; 3] {
+    match config.background {
+        Background::Color(color) => color,
+        Background::Transparent => [255, 255, 255],
+    }
+}
+
+/// Lay the yellows over the greens to make the vegetation image the maps are rendered on. With a
+/// transparent background the pixels without greens, including those without elevation data, are
+/// made transparent and the image is RGBA.
+fn vegetation_image(greens: RgbImage, yellows: &RgbaImage, background: Background) -> DynamicImage {
+    let mut img = match background {
+        Background::Color(_) => DynamicImage::ImageRgb8(greens),
+        Background::Transparent => {
+            let mut img = DynamicImage::ImageRgb8(greens).to_rgba8();
+            for pixel in img.pixels_mut() {
+                if pixel.0 == [255, 255, 255, 255] {
+                    pixel[3] = 0;
+                }
+            }
+            DynamicImage::ImageRgba8(img)
+        }
+    };
+    image::imageops::overlay(&mut img, yellows, 0, 0);
+    img
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LIGHT: [u8; 3] = [200, 254, 200];
+    const MEDIUM: [u8; 3] = [100, 217, 100];
+    const DARK: [u8; 3] = [0, 180, 0];
+
+    #[test]
+    fn test_green_shade() {
+        let shades = [(0.2, LIGHT), (0.5, MEDIUM), (1.0, DARK)];
+        let limit = 0.5;
+
+        // one column per band, the value is exactly on the threshold on the first row
+        let mut densities = Vec2D::new(4, 2, 0.0);
+        for (x, limit_factor) in [0.0, 0.2, 0.5, 1.0].into_iter().enumerate() {
+            densities[(x, 0)] = limit * limit_factor;
+            densities[(x, 1)] = limit * limit_factor + 0.01;
+        }
+        let colors = densities.map(|&v| green_shade(v, limit, &shades));
+
+        // exactly on a threshold stays in the band below
+        assert_eq!(colors[(0, 0)], None);
+        assert_eq!(colors[(1, 0)], None);
+        assert_eq!(colors[(2, 0)], Some(LIGHT));
+        assert_eq!(colors[(3, 0)], Some(MEDIUM));
+
+        assert_eq!(colors[(0, 1)], None);
+        assert_eq!(colors[(1, 1)], Some(LIGHT));
+        assert_eq!(colors[(2, 1)], Some(MEDIUM));
+        assert_eq!(colors[(3, 1)], Some(DARK));
+    }
+
+    #[test]
+    fn test_green_shade_unused_shades() {
+        // a very high threshold disables the darker shades, like 99 in the default config
+        let shades = [(0.2, LIGHT), (99.0, MEDIUM), (99.0, DARK)];
+        assert_eq!(green_shade(50.0, 1.0, &shades), Some(LIGHT));
+    }
+
+    #[test]
+    fn test_canopy_height_decides_class() {
+        let dem = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid: Vec2D::from_fn(3, 1, |x, _| 100.0 + x as f64),
+        };
+        // the same number of returns in the first two cells, at 3 m and at 0.3 m above the ground
+        let record = |x: f64, height: f64| XyzRecord {
+            x,
+            y: 0.5,
+            z: 100.0 + x / 2.0 + height,
+            classification: 5,
+            number_of_returns: 1,
+            return_number: 1,
+        };
+        let records = (0..10).flat_map(|i| {
+            let dx = 0.1 * i as f64;
+            [record(0.5 + dx, 3.0), record(2.5 + dx, 0.3)]
+        });
+        let classes = canopy_classes(records, &dem, 0.5, 2.0, 0.3);
+        assert_eq!(classes[(0, 0)], CanopyClass::Forest);
+        assert_eq!(classes[(1, 0)], CanopyClass::Open);
+        assert_eq!(classes[(2, 0)], CanopyClass::Open);
+
+        // a third of the returns between 0.5 m and 2 m
+        let records = (0..9).map(|i| record(0.5 + 0.1 * i as f64, if i < 3 { 1.0 } else { 0.0 }));
+        let classes = canopy_classes(records, &dem, 0.5, 2.0, 0.3);
+        assert_eq!(classes[(0, 0)], CanopyClass::Rough);
+    }
+
+    #[test]
+    fn test_height_band_excludes_high_returns() {
+        let config = Config::from_toml_str("vege_height_band = [1.5, 30.0]").unwrap();
+        assert!(counts_for_green(&config, 10.0, 5));
+        // likely a bird or noise
+        assert!(!counts_for_green(&config, 40.0, 5));
+        assert!(!counts_for_green(&config, 1.0, 3));
+
+        let config = Config::from_toml_str("vege_exclude_buildings = true").unwrap();
+        assert!(counts_for_green(&config, 40.0, 5));
+        assert!(!counts_for_green(&config, 10.0, 6));
+        let config =
+            Config::from_toml_str("vege_exclude_buildings = true\nbuildingsclass = 9").unwrap();
+        assert!(counts_for_green(&config, 10.0, 6));
+        assert!(!counts_for_green(&config, 10.0, 9));
+    }
+
+    #[test]
+    fn test_despeckle() {
+        let mut classes = NoDataGrid::new(9, 9, GREEN_NO_DATA);
+        classes.grid.fill(0);
+        classes.grid[(0, 0)] = GREEN_NO_DATA;
+        // a single stray green cell
+        classes.grid[(1, 1)] = 2;
+        // a 3x3 green block
+        for x in 5..8 {
+            for y in 4..7 {
+                classes.grid[(x, y)] = 1;
+            }
+        }
+
+        let result = despeckle(&classes, 3, 4);
+        assert_eq!(result.grid[(1, 1)], 0);
+        for x in 5..8 {
+            for y in 4..7 {
+                assert_eq!(result.grid[(x, y)], 1);
+            }
+        }
+        assert!(result.is_nodata(0, 0));
+        assert_eq!(result.grid.iter().filter(|&(_, _, c)| c == 1).count(), 9);
+        assert_eq!(result.grid.iter().filter(|&(_, _, c)| c == 2).count(), 0);
+
+        // a small block survives with a lower threshold
+        let result = despeckle(&classes, 3, 1);
+        assert_eq!(result, classes);
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let mut greens = RgbImage::from_pixel(3, 2, Rgb([255, 255, 255]));
+        greens.put_pixel(1, 1, Rgb(DARK));
+        let mut yellows = RgbaImage::from_pixel(3, 2, Rgba([255, 255, 255, 0]));
+        yellows.put_pixel(2, 0, Rgba([255, 219, 166, 255]));
+
+        let img = vegetation_image(greens.clone(), &yellows, Background::Transparent).to_rgba8();
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(*img.get_pixel(1, 1), Rgba([0, 180, 0, 255]));
+        assert_eq!(*img.get_pixel(2, 0), Rgba([255, 219, 166, 255]));
+
+        let img = vegetation_image(greens, &yellows, Background::default());
+        assert!(matches!(img, DynamicImage::ImageRgb8(_)));
+        assert_eq!(img.to_rgba8().get_pixel(0, 0)[3], 255);
+    }
+}
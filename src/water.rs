@@ -0,0 +1,147 @@
+use std::{error::Error, io::BufReader, path::Path};
+
+use crate::cliffs::slope_mask;
+use crate::config::Config;
+use crate::io::fs::FileSystem;
+use crate::io::heightmap::HeightMap;
+use crate::io::xyz::XyzInternalReader;
+use crate::vec2d::{label_components, Connectivity, Vec2D};
+
+/// Count the ground points of an internal xyz file in each cell of the heightmap grid.
+pub fn ground_counts(
+    fs: &impl FileSystem,
+    config: &Config,
+    xyz_file_in: &Path,
+    hmap: &HeightMap,
+) -> Result<Vec2D<u32>, Box<dyn Error>> {
+    let (w, h) = hmap.grid.dimensions();
+    let mut counts = Vec2D::new(w, h, 0);
+    let mut reader = XyzInternalReader::new(BufReader::new(fs.open(xyz_file_in)?))?;
+    while let Some(r) = reader.next()? {
+        if !config.ground_classes.contains(&r.classification) {
+            continue;
+        }
+        let idx_x = ((r.x - hmap.xoffset) / hmap.scale).floor();
+        let idx_y = ((r.y - hmap.yoffset) / hmap.scale).floor();
+        if idx_x < 0.0 || idx_y < 0.0 || idx_x >= w as f64 || idx_y >= h as f64 {
+            continue;
+        }
+        counts[(idx_x as usize, idx_y as usize)] += 1;
+    }
+    Ok(counts)
+}
+
+/// Find the water areas of a heightmap: connected (8-connected) areas of at least `min_area`
+/// square meters where the slope is below `max_slope` (rise/run, see [`slope_mask`]) and there are
+/// fewer than `max_density` ground points per square meter, as the laser is rarely reflected back
+/// from water. Only areas that are not higher than the cells around them are kept, so flat hill
+/// tops with sparse returns are not mistaken for lakes. `counts` is the number of ground points in
+/// each cell, see [`ground_counts`].
+pub fn detect_water(
+    hmap: &HeightMap,
+    counts: &Vec2D<u32>,
+    max_slope: f64,
+    max_density: f64,
+    min_area: f64,
+) -> Vec2D<bool> {
+    let grid = &hmap.grid;
+    let (w, h) = grid.dimensions();
+    let cell_area = hmap.scale * hmap.scale;
+
+    let steep = slope_mask(hmap, max_slope, 0.0);
+    let mut candidates = Vec2D::new(w, h, false);
+    for (x, y, v) in candidates.iter_mut() {
+        *v = !steep[(x, y)]
+            && !grid[(x, y)].is_nan()
+            && (counts[(x, y)] as f64) < max_density * cell_area;
+    }
+
+    let (labels, count) = label_components(&candidates, Connectivity::Eight);
+    // per component: number of cells, sum of the elevations and the lowest cell around it
+    let mut cells = vec![0usize; count + 1];
+    let mut sum = vec![0.0; count + 1];
+    let mut shore = vec![f64::INFINITY; count + 1];
+    for (x, y, &label) in labels.iter_where(|&label| label != 0) {
+        let label = label as usize;
+        cells[label] += 1;
+        sum[label] += grid[(x, y)];
+        for nx in x.saturating_sub(1)..(x + 2).min(w) {
+            for ny in y.saturating_sub(1)..(y + 2).min(h) {
+                let z = grid[(nx, ny)];
+                if labels[(nx, ny)] as usize != label && !z.is_nan() {
+                    shore[label] = shore[label].min(z);
+                }
+            }
+        }
+    }
+    let is_water = (0..=count)
+        .map(|label| {
+            label > 0
+                && cells[label] as f64 * cell_area >= min_area
+                && sum[label] / cells[label] as f64 <= shore[label]
+        })
+        .collect::<Vec<_>>();
+
+    let mut mask = Vec2D::new(w, h, false);
+    for (x, y, v) in mask.iter_mut() {
+        *v = is_water[labels[(x, y)] as usize];
+    }
+    mask
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 40 x 40 heightmap of 2 m cells around a round flat area of radius 16 m at `center_z`,
+    /// with the terrain around it at `z(distance from the edge of the flat area)`. The flat area
+    /// has no ground points and the rest has 4 points per cell.
+    fn basin(center_z: f64, z: impl Fn(f64) -> f64) -> (HeightMap, Vec2D<u32>) {
+        let mut grid = Vec2D::new(40, 40, center_z);
+        let mut counts = Vec2D::new(40, 40, 0);
+        for (x, y, v) in grid.iter_mut() {
+            let r = 2.0 * ((x as f64 - 20.0).powi(2) + (y as f64 - 20.0).powi(2)).sqrt();
+            if r > 16.0 {
+                *v = z(r - 16.0);
+                counts[(x, y)] = 4;
+            }
+        }
+        let hmap = HeightMap {
+            xoffset: 0.0,
+            yoffset: 0.0,
+            scale: 2.0,
+            grid,
+        };
+        (hmap, counts)
+    }
+
+    #[test]
+    fn test_flat_basin_is_water() {
+        let (hmap, counts) = basin(10.0, |d| 10.0 + 0.5 * d);
+        let mask = detect_water(&hmap, &counts, 0.1, 0.5, 100.0);
+        assert!(mask[(20, 20)]);
+        assert!(mask[(25, 20)]);
+        assert!(!mask[(0, 0)]);
+        assert!(!mask[(20, 35)]);
+        // only the cells of the flat area are water
+        for (x, y, v) in mask.iter() {
+            if v {
+                assert_eq!(counts[(x, y)], 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flat_hill_top_is_not_water() {
+        let (hmap, counts) = basin(10.0, |d| 10.0 - 0.5 * d);
+        let mask = detect_water(&hmap, &counts, 0.1, 0.5, 100.0);
+        assert!(mask.iter().all(|(_, _, v)| !v));
+    }
+
+    #[test]
+    fn test_small_pond_is_not_water() {
+        let (hmap, counts) = basin(10.0, |d| 10.0 + 0.5 * d);
+        let mask = detect_water(&hmap, &counts, 0.1, 0.5, 1000.0);
+        assert!(mask.iter().all(|(_, _, v)| !v));
+    }
+}